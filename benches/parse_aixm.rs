@@ -0,0 +1,45 @@
+//! Parse throughput of an AIXM dataset, e.g. the DFS Waypoints:
+//!
+//! ```sh
+//! AIXM_BENCH_FILE=ED_Waypoints_2025-02-20_2025-03-20_revision.xml cargo bench
+//! ```
+//!
+//! The single pass over the members is compared to deserializing the whole
+//! document from a reader, the scan alone shows how much of it is spent
+//! finding the members rather than deserializing them.
+
+use std::{env, fs, hint::black_box};
+
+use airac_aixm_updater_core::aixm_parse::{Members, parse_dataset};
+use aixm::MessageAixmBasicMessage;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+#[expect(clippy::print_stderr, reason = "only way to tell why nothing ran")]
+fn parse(c: &mut Criterion) {
+    let Ok(path) = env::var("AIXM_BENCH_FILE") else {
+        eprintln!("AIXM_BENCH_FILE not set, skipping");
+        return;
+    };
+    let data = fs::read(&path).expect("AIXM_BENCH_FILE not readable");
+    let xml = std::str::from_utf8(&data).expect("AIXM_BENCH_FILE not UTF-8");
+
+    let mut group = c.benchmark_group("parse_aixm");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+    group.bench_function("parse_dataset", |b| {
+        b.iter(|| parse_dataset(black_box(xml)).unwrap());
+    });
+    group.bench_function("scan_members", |b| {
+        b.iter(|| Members::new(black_box(xml)).map(Result::unwrap).count());
+    });
+    // previous implementation, as baseline
+    group.bench_function("from_reader", |b| {
+        b.iter(|| {
+            quick_xml::de::from_reader::<_, MessageAixmBasicMessage>(black_box(&*data)).unwrap()
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
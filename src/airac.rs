@@ -0,0 +1,184 @@
+//! AIRAC cycles: 28 days each, counted from a known cycle start and numbered
+//! within the year of their effective date, the same in every country.
+
+use std::fmt;
+
+use chrono::{Datelike as _, NaiveDate, TimeDelta, Utc};
+
+const CYCLE_DAYS: i64 = 28;
+
+/// Start of AIRAC 2501, checked at compile time.
+const AIRAC_2501: NaiveDate = NaiveDate::from_ymd_opt(2025, 1, 23).unwrap();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AiracCycle {
+    effective: NaiveDate,
+}
+
+impl AiracCycle {
+    /// Cycle in effect on `date`.
+    pub fn containing(date: NaiveDate) -> Self {
+        let cycles = (date - AIRAC_2501).num_days().div_euclid(CYCLE_DAYS);
+        Self {
+            effective: AIRAC_2501 + TimeDelta::days(cycles * CYCLE_DAYS),
+        }
+    }
+
+    /// Cycle in effect today (UTC).
+    pub fn current() -> Self {
+        Self::containing(Utc::now().date_naive())
+    }
+
+    /// Cycle identified by `ident` (`YYNN`), `None` if malformed or the year
+    /// has fewer cycles.
+    pub fn from_ident(ident: &str) -> Option<Self> {
+        if ident.len() != 4 || !ident.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let year = 2000 + ident[..2].parse::<i32>().ok()?;
+        let number = ident[2..].parse::<i64>().ok()?.checked_sub(1)?;
+
+        let mut first = Self::containing(NaiveDate::from_ymd_opt(year, 1, 1)?);
+        if first.effective.year() < year {
+            first = first.next();
+        }
+        let cycle = first.offset(number);
+        (cycle.effective.year() == year).then_some(cycle)
+    }
+
+    /// Cycle given as `YYNN` or as a date (`YYYY-MM-DD`) within it.
+    pub fn parse(cycle: &str) -> Option<Self> {
+        Self::from_ident(cycle).or_else(|| {
+            NaiveDate::parse_from_str(cycle, "%Y-%m-%d")
+                .ok()
+                .map(Self::containing)
+        })
+    }
+
+    /// Cycle `cycles` after this one, or before if negative.
+    #[must_use]
+    pub fn offset(self, cycles: i64) -> Self {
+        Self {
+            effective: self.effective + TimeDelta::days(cycles * CYCLE_DAYS),
+        }
+    }
+
+    #[must_use]
+    pub fn next(self) -> Self {
+        self.offset(1)
+    }
+
+    #[must_use]
+    pub fn previous(self) -> Self {
+        self.offset(-1)
+    }
+
+    /// First day of the cycle.
+    pub fn effective(self) -> NaiveDate {
+        self.effective
+    }
+
+    /// First day of the next cycle, i.e. the end of this one (exclusive).
+    pub fn expires(self) -> NaiveDate {
+        self.next().effective
+    }
+
+    /// Number of the cycle within its year, starting at 1.
+    pub fn number(self) -> u32 {
+        self.effective.ordinal0() / 28 + 1
+    }
+
+    /// Identifier `YYNN`, e.g. `2503`.
+    pub fn ident(self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for AiracCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}{:02}",
+            self.effective.year().rem_euclid(100),
+            self.number()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn containing_starts_on_the_effective_date() {
+        assert_eq!(AiracCycle::containing(date(2025, 1, 23)).ident(), "2501");
+        assert_eq!(AiracCycle::containing(date(2025, 2, 19)).ident(), "2501");
+        assert_eq!(AiracCycle::containing(date(2025, 2, 20)).ident(), "2502");
+        // before the reference cycle
+        let cycle = AiracCycle::containing(date(2025, 1, 22));
+        assert_eq!(cycle.ident(), "2413");
+        assert_eq!(cycle.effective(), date(2024, 12, 26));
+        assert_eq!(cycle.expires(), date(2025, 1, 23));
+    }
+
+    #[test]
+    fn from_ident_finds_the_effective_date() {
+        assert_eq!(
+            AiracCycle::from_ident("2501").map(AiracCycle::effective),
+            Some(date(2025, 1, 23))
+        );
+        assert_eq!(
+            AiracCycle::from_ident("2401").map(AiracCycle::effective),
+            Some(date(2024, 1, 25))
+        );
+        assert_eq!(
+            AiracCycle::from_ident("2513").map(AiracCycle::effective),
+            Some(date(2025, 12, 25))
+        );
+    }
+
+    #[test]
+    fn fourteenth_cycle_only_in_years_that_have_one() {
+        assert_eq!(
+            AiracCycle::from_ident("2001").map(AiracCycle::effective),
+            Some(date(2020, 1, 2))
+        );
+        assert_eq!(
+            AiracCycle::from_ident("2014").map(AiracCycle::effective),
+            Some(date(2020, 12, 31))
+        );
+        assert_eq!(AiracCycle::from_ident("2514"), None);
+        assert_eq!(AiracCycle::from_ident("2114"), None);
+    }
+
+    #[test]
+    fn malformed_idents_are_rejected() {
+        for ident in ["", "251", "25011", "25a1", "2500", "-501"] {
+            assert_eq!(AiracCycle::from_ident(ident), None, "{ident}");
+        }
+    }
+
+    #[test]
+    fn number_counts_within_the_year() {
+        assert_eq!(AiracCycle::containing(date(2025, 1, 23)).number(), 1);
+        assert_eq!(AiracCycle::containing(date(2025, 12, 31)).number(), 13);
+        assert_eq!(AiracCycle::containing(date(2020, 12, 31)).number(), 14);
+        // the cycle in effect on new year belongs to the previous year
+        assert_eq!(AiracCycle::containing(date(2021, 1, 1)).ident(), "2014");
+    }
+
+    #[test]
+    fn parse_accepts_idents_and_dates() {
+        assert_eq!(AiracCycle::parse("2503"), AiracCycle::from_ident("2503"));
+        assert_eq!(
+            AiracCycle::parse("2025-03-25"),
+            Some(AiracCycle::containing(date(2025, 3, 20)))
+        );
+        assert_eq!(AiracCycle::parse("2025-03-25").unwrap().ident(), "2503");
+        assert_eq!(AiracCycle::parse("March"), None);
+    }
+}
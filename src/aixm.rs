@@ -1,109 +1,539 @@
-use aixm::{Member, MessageAixmBasicMessage};
-use itertools::Itertools as _;
-use quick_xml::DeError;
-use snafu::{OptionExt, ResultExt as _};
+//! Downloading and loading of the AIXM datasets. Downloads resume and are
+//! cached on disk. A cancelled parse of a local dataset file keeps a
+//! checkpoint at the member it stopped after, so the next run of the process
+//! reading the same file resumes there. The checkpoints stay in memory, as
+//! the `aixm` types of the members read so far cannot be written to disk.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+use aixm::Member;
+use chrono::NaiveDate;
+use reqwest::{
+    Client, StatusCode,
+    header::{HeaderMap, RANGE},
+};
+use snafu::{OptionExt, ResultExt as _, ensure};
 use tokio::{
+    fs,
+    io::AsyncReadExt as _,
     sync::mpsc,
     task::{JoinSet, spawn_blocking},
+    time::sleep,
 };
-use tracing::error;
+use tracing::{debug, error};
 
 use crate::error::{
-    AiracUpdaterResult, DatasetNotFoundSnafu, DecodeDatasetSnafu, DeserializeDatasetSnafu,
-    FetchDatasetSnafu,
+    AiracUpdaterResult, CancelledSnafu, DatasetNotUtf8Snafu, DatasetServerErrorSnafu,
+    DecodeDatasetSnafu, DeserializeDatasetSnafu, Error, FetchDatasetSnafu,
+    LocalDatasetNotFoundSnafu, MalformedDatasetSnafu, OpenAixmSnafu, ReadAixmSnafu,
 };
 use crate::{
     Message,
-    aixm_dfs::{fetch_dfs_datasets, get_dataset_url},
+    aixm_airspaces::AixmAirspace,
+    aixm_dfs::effective_from_filename,
+    aixm_frequencies::{ServiceFrequency, TowerPosition},
+    aixm_ils::Localizer,
+    aixm_military::MilitaryEntity,
+    aixm_parse::{
+        ParseCheckpoint, ParseProgress, check_well_formed, normalize_prefixes, resume_parse_dataset,
+    },
+    aixm_routes::RouteSegment,
+    cancel::CancelToken,
+    dataset_archive::unpack_dataset,
+    dataset_cache::CacheEntry,
+    network,
+    pipeline::Stage,
+    provider::{Amendments, DatasetProvider},
 };
 
-pub(crate) async fn load_aixm_files(tx: mpsc::Sender<Message>) -> AiracUpdaterResult<Vec<Member>> {
+pub const DFS_DATASETS: &[&str] = &[
+    "ED AirportHeliport",
+    "ED Navaids",
+    "ED Routes",
+    "ED Runway",
+    "ED Waypoints",
+];
+
+/// Airspace boundaries, classes and temporary airspaces, not loaded by
+/// default as only some features need it
+pub const AIRSPACE_DATASET: &str = "ED Airspace";
+
+/// Checkpoints of the local dataset files whose parse was cancelled, by path.
+static PARSE_CHECKPOINTS: LazyLock<Mutex<HashMap<PathBuf, ParseCheckpoint>>> =
+    LazyLock::new(Mutex::default);
+
+/// Members of a dataset, with the route segments, service frequencies, tower
+/// positions, airspaces and localizers the `aixm` crate does not cover, all
+/// read in one pass with [`parse_dataset`](crate::aixm_parse::parse_dataset).
+#[derive(Debug, Default)]
+pub struct AixmDataset {
+    pub name: String,
+    pub members: Vec<Member>,
+    pub route_segments: Vec<RouteSegment>,
+    pub frequencies: Vec<ServiceFrequency>,
+    pub towers: Vec<TowerPosition>,
+    pub airspaces: Vec<AixmAirspace>,
+    /// Entities AIXM marks as military only
+    pub military: Vec<MilitaryEntity>,
+    pub localizers: Vec<Localizer>,
+    /// Number of members per feature type, see [`parse_dataset`](crate::aixm_parse::parse_dataset)
+    pub member_counts: BTreeMap<String, usize>,
+    /// Effective date of the release, if its file name has one
+    pub effective: Option<NaiveDate>,
+}
+
+/// Loads the given datasets of an amendment from `provider`, `0` being the
+/// current one, or of their own amendment if overridden.
+pub async fn load_aixm_files(
+    provider: &impl DatasetProvider,
+    amdt: impl Into<Amendments>,
+    datasets: &[String],
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<AixmDataset>> {
+    let amdts = amdt.into();
+    let mut dataset_urls = vec![];
+    for (amdt, datasets) in amdts.group(datasets) {
+        if amdt != amdts.amdt {
+            tx.send(Message::info(format!(
+                "Using AMDT {amdt} instead of {} for {}",
+                amdts.amdt,
+                datasets.join(", ")
+            )))
+            .await?;
+        }
+        dataset_urls.extend(provider.dataset_urls(amdt, &datasets).await?);
+    }
+
+    let mut join_set = JoinSet::new();
+    for (dataset, dataset_url) in dataset_urls {
+        tx.send(Message::stage(&dataset, Stage::Queued)).await?;
+        tx.send(Message::debug(format!(
+            "{dataset} from {}: {dataset_url}",
+            provider.name()
+        )))
+        .await?;
+        let tx = tx.clone();
+        join_set.spawn(async move {
+            let result = fetch_and_load_dataset(dataset_url, dataset.clone(), tx.clone()).await;
+            tx.send(Message::stage(
+                &dataset,
+                if result.is_ok() {
+                    Stage::Done
+                } else {
+                    Stage::Failed
+                },
+            ))
+            .await?;
+            result
+        });
+    }
+
+    Ok(collect_loaded(join_set, &tx).await)
+}
+
+/// Loads the given datasets from downloaded DFS files in `dir` instead, named
+/// like the releases, e.g. `ED_Waypoints_2025-02-20_2025-03-20_revision.xml`.
+/// Of several files of a dataset, the last one by name is used. A parse
+/// stopped by `cancel` is resumed by the next call reading the same file.
+pub async fn load_local_aixm_files(
+    dir: &Path,
+    datasets: &[String],
+    cancel: &CancelToken,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<AixmDataset>> {
+    let mut file_names = vec![];
+    let mut entries = fs::read_dir(dir)
+        .await
+        .context(OpenAixmSnafu { filename: dir })?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context(ReadAixmSnafu { filename: dir })?
+    {
+        if let Some(file_name) = entry.file_name().to_str() {
+            file_names.push(file_name.to_string());
+        }
+    }
+
     let mut join_set = JoinSet::new();
-    let dataset_metadata = fetch_dfs_datasets().await?;
-    for dataset in &[
-        "ED AirportHeliport",
-        "ED Navaids",
-        "ED Routes",
-        "ED Runway",
-        "ED Waypoints",
-        // "../sectors/aixm/ED_AirportHeliport_2025-02-20_2025-03-20_revision.xml",
-        // "../sectors/aixm/ED_Navaids_2025-02-20_2025-03-20_revision.xml",
-        // "../sectors/aixm/ED_Routes_2025-02-20_2025-03-20_revision.xml",
-        // "../sectors/aixm/ED_Runway_2025-02-20_2025-03-20_revision.xml",
-        // "../sectors/aixm/ED_Waypoints_2025-02-20_2025-03-20_revision.xml",
-    ] {
-        // let path = PathBuf::from(file_path);
-        // join_set.spawn(load_aixm_file(path, tx.clone()));
-
-        let dataset_url = get_dataset_url(&dataset_metadata, 0, dataset, "AIXM 5.1").context(
-            DatasetNotFoundSnafu {
-                dataset: (*dataset).to_string(),
-            },
-        )?;
-        join_set.spawn(fetch_and_load_dfs_dataset(dataset_url, dataset, tx.clone()));
+    for dataset in datasets {
+        let prefix = format!("{}_", dataset.replace(' ', "_"));
+        let path = file_names
+            .iter()
+            .filter(|file_name| {
+                file_name.starts_with(&prefix)
+                    && Path::new(file_name)
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("xml"))
+            })
+            .max()
+            .map(|file_name| dir.join(file_name))
+            .context(LocalDatasetNotFoundSnafu { dataset, dir })?;
+        tx.send(Message::stage(dataset, Stage::Queued)).await?;
+        let dataset = dataset.clone();
+        let cancel = cancel.clone();
+        let tx = tx.clone();
+        join_set.spawn(async move {
+            let result = read_and_load_aixm_file(path, &dataset, cancel, tx.clone()).await;
+            tx.send(Message::stage(
+                &dataset,
+                if result.is_ok() {
+                    Stage::Done
+                } else {
+                    Stage::Failed
+                },
+            ))
+            .await?;
+            result
+        });
     }
 
-    Ok(join_set
+    Ok(collect_loaded(join_set, &tx).await)
+}
+
+/// Datasets that loaded, the others are reported.
+async fn collect_loaded(
+    join_set: JoinSet<AiracUpdaterResult<AixmDataset>>,
+    tx: &mpsc::Sender<Message>,
+) -> Vec<AixmDataset> {
+    join_set
         .join_all()
         .await
         .into_iter()
         .filter_map(|res| match res {
             Err(e) => {
-                if let Err(e) = tx.blocking_send(Message::error(e.to_string())) {
+                if let Err(e) = tx.blocking_send(Message::failure(&e)) {
                     error!("{e}");
                 }
                 None
             }
             Ok(aixm) => Some(aixm),
         })
-        .concat())
+        .collect()
+}
+
+async fn read_and_load_aixm_file(
+    path: PathBuf,
+    dataset: &str,
+    cancel: CancelToken,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<AixmDataset> {
+    tx.send(Message::info(format!(
+        "Reading AIXM: {dataset} from {}",
+        path.display()
+    )))
+    .await?;
+    let mut data = vec![];
+    fs::File::open(&path)
+        .await
+        .context(OpenAixmSnafu { filename: &path })?
+        .read_to_end(&mut data)
+        .await
+        .context(ReadAixmSnafu { filename: &path })?;
+    let data = unpack(data, None, dataset).await?;
+    let data = verify_well_formed(data, dataset).await?;
+    let effective = path
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .and_then(effective_from_filename);
+    let mut aixm = load_aixm_data(data, dataset, Some(Resumable { path, cancel }), tx).await?;
+    aixm.effective = effective;
+    Ok(aixm)
+}
+
+async fn fetch_and_load_dataset(
+    dataset_url: impl AsRef<str>,
+    dataset_name: String,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<AixmDataset> {
+    let data = fetch_dataset(&dataset_url, &dataset_name, tx.clone()).await?;
+    let mut aixm = load_aixm_data(data, &dataset_name, None, tx.clone()).await?;
+    aixm.effective = effective_from_filename(dataset_url.as_ref());
+    Ok(aixm)
 }
 
-async fn fetch_and_load_dfs_dataset(
+pub async fn fetch_dataset(
     dataset_url: impl AsRef<str>,
     dataset_name: &str,
     tx: mpsc::Sender<Message>,
-) -> AiracUpdaterResult<Vec<Member>> {
+) -> AiracUpdaterResult<Vec<u8>> {
+    network::check_request(dataset_url.as_ref())?;
+    tx.send(Message::stage(dataset_name, Stage::Downloading))
+        .await?;
     tx.send(Message::info(format!("Fetching AIXM: {dataset_name}")))
         .await?;
-    let data = reqwest::get(dataset_url.as_ref())
-        .await
-        .context(FetchDatasetSnafu {
-            dataset: dataset_name.to_string(),
-        })?
-        .bytes()
-        .await
-        .context(DecodeDatasetSnafu {
-            dataset: dataset_name.to_string(),
-        })?;
+    // the URL contains the amendment and the release file name
+    let cache = CacheEntry::new(dataset_url.as_ref());
+    let client = Client::new();
+    let retry = network::retry();
+    let mut download = Download::default();
+    let mut attempt = 1;
+    loop {
+        let result = download
+            .attempt(&client, dataset_url.as_ref(), dataset_name, cache.as_ref())
+            .await;
+        match result {
+            Ok(()) => break,
+            Err(e) if attempt < retry.attempts && is_transient(&e) => {
+                let delay = retry.delay(attempt);
+                let resume = if download.body.is_empty() {
+                    String::new()
+                } else {
+                    format!(", resuming after {} bytes", download.body.len())
+                };
+                tx.send(Message::warn(format!(
+                    "{e}, retrying in {delay:?}{resume} (attempt {} of {})",
+                    attempt + 1,
+                    retry.attempts
+                )))
+                .await?;
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let (status, headers) = download.response.unwrap_or_default();
+    let cached = match &cache {
+        Some(cache) if status == StatusCode::NOT_MODIFIED => cache.read().await,
+        _ => None,
+    };
+    if let Some(data) = cached {
+        tx.send(Message::info(format!(
+            "AIXM not modified, using cached: {dataset_name}"
+        )))
+        .await?;
+        return Ok(data);
+    }
+
     tx.send(Message::info(format!("Fetched AIXM: {dataset_name}")))
         .await?;
-    load_aixm_data(data.to_vec(), dataset_name, tx.clone()).await
+    let data = unpack(download.body, Some(headers.clone()), dataset_name).await?;
+    // never cache an error page or a truncated body
+    let data = verify_well_formed(data, dataset_name).await?;
+    if let Some(cache) = cache.as_ref().filter(|_| status.is_success()) {
+        if let Err(e) = cache.store(&data, &headers).await {
+            tx.send(Message::warn(e.to_string())).await?;
+        }
+    }
+    Ok(data)
+}
+
+/// Connection errors, interrupted bodies and server errors, which are worth
+/// another attempt.
+fn is_transient(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::FetchDataset { .. } | Error::DecodeDataset { .. } | Error::DatasetServerError { .. }
+    )
+}
+
+/// Dataset download over several attempts, keeping what was received to
+/// resume an interrupted body with a range request.
+#[derive(Default)]
+struct Download {
+    /// Status and headers of the response the body started with
+    response: Option<(StatusCode, HeaderMap)>,
+    body: Vec<u8>,
+}
+
+impl Download {
+    async fn attempt(
+        &mut self,
+        client: &Client,
+        url: &str,
+        dataset: &str,
+        cache: Option<&CacheEntry>,
+    ) -> AiracUpdaterResult {
+        let resume_from = self
+            .response
+            .as_ref()
+            .is_some_and(|(status, _)| *status == StatusCode::OK)
+            .then_some(self.body.len())
+            .filter(|received| *received > 0);
+        let mut request = client.get(url);
+        if let Some(received) = resume_from {
+            request = request.header(RANGE, format!("bytes={received}-"));
+        } else if let Some(cache) = cache {
+            request = cache.conditional(request).await;
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .context(FetchDatasetSnafu { dataset })?;
+        let status = response.status();
+        ensure!(
+            !status.is_server_error() && status != StatusCode::TOO_MANY_REQUESTS,
+            DatasetServerErrorSnafu {
+                dataset,
+                status: status.as_u16()
+            }
+        );
+        // starting over if the server ignored the range
+        if resume_from.is_none() || status != StatusCode::PARTIAL_CONTENT {
+            self.response = Some((status, response.headers().clone()));
+            self.body.clear();
+        }
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .context(DecodeDatasetSnafu { dataset })?
+        {
+            self.body.extend_from_slice(&chunk);
+        }
+
+        Ok(())
+    }
+}
+
+/// Extracts the XML of a zipped dataset with [`unpack_dataset`] on a blocking
+/// thread.
+async fn unpack(
+    data: Vec<u8>,
+    headers: Option<HeaderMap>,
+    dataset: &str,
+) -> AiracUpdaterResult<Vec<u8>> {
+    let dataset = dataset.to_string();
+    spawn_blocking(move || unpack_dataset(data, headers.as_ref(), &dataset)).await?
+}
+
+/// Checks `data` with [`check_well_formed`] on a blocking thread, as it has to
+/// scan the whole dataset.
+async fn verify_well_formed<T: AsRef<[u8]> + Send + 'static>(
+    data: T,
+    dataset: &str,
+) -> AiracUpdaterResult<T> {
+    let (data, checked) = spawn_blocking(move || {
+        let checked = check_well_formed(data.as_ref());
+        (data, checked)
+    })
+    .await?;
+    if let Err(reason) = checked {
+        return MalformedDatasetSnafu { dataset, reason }.fail();
+    }
+    Ok(data)
+}
+
+/// Local dataset file whose parse stops with a checkpoint once cancelled.
+struct Resumable {
+    path: PathBuf,
+    cancel: CancelToken,
+}
+
+impl Resumable {
+    fn take_checkpoint(&self) -> Option<ParseCheckpoint> {
+        match PARSE_CHECKPOINTS.lock() {
+            Ok(mut checkpoints) => checkpoints.remove(&self.path),
+            Err(e) => {
+                error!("{e}");
+                None
+            }
+        }
+    }
+
+    fn keep_checkpoint(&self, checkpoint: ParseCheckpoint) {
+        match PARSE_CHECKPOINTS.lock() {
+            Ok(mut checkpoints) => {
+                checkpoints.insert(self.path.clone(), checkpoint);
+            }
+            Err(e) => error!("{e}"),
+        }
+    }
 }
 
 async fn load_aixm_data(
     data: Vec<u8>,
     dataset: &str,
+    resumable: Option<Resumable>,
     tx: mpsc::Sender<Message>,
-) -> AiracUpdaterResult<Vec<Member>> {
+) -> AiracUpdaterResult<AixmDataset> {
+    tx.send(Message::stage(dataset, Stage::Parsing)).await?;
     tx.send(Message::info(format!("Loading AIXM: {dataset}",)))
         .await?;
 
-    let aixm_data = spawn_blocking(move || {
-        Ok::<_, DeError>(
-            quick_xml::de::from_reader::<_, MessageAixmBasicMessage>(&*data)?
-                .message_has_member
-                .into_iter()
-                .map(|m| m.member)
-                .collect(),
-        )
-    })
-    .await?
-    .context(DeserializeDatasetSnafu {
-        dataset: dataset.to_string(),
-    });
-    tx.send(Message::info(format!("Loaded AIXM: {dataset}",)))
+    let name = dataset.to_string();
+    let blocking_tx = tx.clone();
+    let (aixm_data, skipped_segments, skipped_airspaces, skipped_localizers) =
+        spawn_blocking(move || {
+            let xml = std::str::from_utf8(&data).context(DatasetNotUtf8Snafu { dataset: &name })?;
+            let xml = normalize_prefixes(xml);
+            let checkpoint = resumable
+                .as_ref()
+                .and_then(Resumable::take_checkpoint)
+                .filter(|checkpoint| checkpoint.matches(&xml));
+            if let Some(checkpoint) = &checkpoint {
+                if let Err(e) = blocking_tx.blocking_send(Message::info(format!(
+                    "Resuming {name} after {} members at byte {}",
+                    checkpoint.members(),
+                    checkpoint.offset()
+                ))) {
+                    error!("{e}");
+                }
+            }
+            let progress = resume_parse_dataset(&xml, checkpoint, || {
+                resumable
+                    .as_ref()
+                    .is_some_and(|resumable| resumable.cancel.is_cancelled())
+            })
+            .context(DeserializeDatasetSnafu { dataset: &name })?;
+            let parsed = match progress {
+                ParseProgress::Done(parsed) => parsed,
+                ParseProgress::Interrupted(checkpoint) => {
+                    debug!(
+                        "{name} cancelled after {} members, keeping a checkpoint",
+                        checkpoint.members()
+                    );
+                    if let Some(resumable) = &resumable {
+                        resumable.keep_checkpoint(checkpoint);
+                    }
+                    return CancelledSnafu.fail();
+                }
+            };
+            AiracUpdaterResult::Ok((
+                AixmDataset {
+                    name,
+                    members: parsed.members,
+                    route_segments: parsed.route_segments,
+                    frequencies: parsed.services.frequencies,
+                    towers: parsed.services.towers,
+                    airspaces: parsed.airspaces,
+                    military: parsed.military,
+                    localizers: parsed.localizers,
+                    member_counts: parsed.member_counts,
+                    effective: None,
+                },
+                parsed.skipped_segments,
+                parsed.skipped_airspaces,
+                parsed.skipped_localizers,
+            ))
+        })
+        .await??;
+    tx.send(Message::loaded(dataset, aixm_data.member_counts.clone()))
+        .await?;
+    if skipped_segments > 0 {
+        tx.send(Message::warn(format!(
+            "Skipped {skipped_segments} route segments in {dataset} without a known route or course"
+        )))
         .await?;
+    }
+    if skipped_airspaces > 0 {
+        tx.send(Message::warn(format!(
+            "Skipped {skipped_airspaces} airspaces in {dataset} without supported geometry"
+        )))
+        .await?;
+    }
+    if skipped_localizers > 0 {
+        tx.send(Message::warn(format!(
+            "Skipped {skipped_localizers} localizers in {dataset} without frequency, position or true bearing"
+        )))
+        .await?;
+    }
 
-    aixm_data
+    Ok(aixm_data)
 }
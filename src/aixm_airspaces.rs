@@ -0,0 +1,251 @@
+use geo::{Contains as _, LineString, Point, Polygon};
+use quick_xml::DeError;
+use serde::Deserialize;
+
+use crate::{error::AiracUpdaterResult, geometry::gml_points};
+
+pub(crate) const TEMPDELTA: &str = "TEMPDELTA";
+
+// Only the parts of the AIXM airspace model needed for the boundaries and the
+// temporary airspace overlay, the `aixm` crate does not cover airspaces.
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AirspaceMessage {
+    #[serde(rename = "message:hasMember", default)]
+    pub(crate) members: Vec<HasMember>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HasMember {
+    #[serde(rename = "aixm:Airspace")]
+    pub(crate) airspace: Option<Airspace>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Airspace {
+    #[serde(rename = "aixm:timeSlice", default)]
+    pub(crate) time_slices: Vec<TimeSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimeSlice {
+    #[serde(rename = "aixm:AirspaceTimeSlice")]
+    pub(crate) airspace: AirspaceTimeSlice,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AirspaceTimeSlice {
+    #[serde(rename = "gml:validTime")]
+    pub(crate) valid_time: Option<ValidTime>,
+    #[serde(rename = "aixm:interpretation")]
+    pub(crate) interpretation: String,
+    #[serde(rename = "aixm:type")]
+    pub(crate) airspace_type: Option<String>,
+    #[serde(rename = "aixm:designator")]
+    pub(crate) designator: Option<String>,
+    #[serde(rename = "aixm:name")]
+    pub(crate) name: Option<String>,
+    #[serde(rename = "aixm:class", default)]
+    classes: Vec<AirspaceClass>,
+    #[serde(rename = "aixm:geometryComponent", default)]
+    geometry_components: Vec<GeometryComponent>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ValidTime {
+    #[serde(rename = "gml:TimePeriod")]
+    pub(crate) period: TimePeriod,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimePeriod {
+    #[serde(rename = "gml:beginPosition")]
+    pub(crate) begin: Option<TimePosition>,
+    #[serde(rename = "gml:endPosition")]
+    pub(crate) end: Option<TimePosition>,
+}
+
+/// Empty with `indeterminatePosition="unknown"` for open ends.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimePosition {
+    #[serde(rename = "$text", default)]
+    pub(crate) value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AirspaceClass {
+    #[serde(rename = "aixm:AirspaceLayerClass")]
+    layer: Option<AirspaceLayerClass>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AirspaceLayerClass {
+    #[serde(rename = "aixm:classification")]
+    classification: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeometryComponent {
+    #[serde(rename = "aixm:AirspaceGeometryComponent")]
+    component: AirspaceGeometryComponent,
+}
+
+#[derive(Debug, Deserialize)]
+struct AirspaceGeometryComponent {
+    #[serde(rename = "aixm:theAirspaceVolume")]
+    volume: TheAirspaceVolume,
+}
+
+#[derive(Debug, Deserialize)]
+struct TheAirspaceVolume {
+    #[serde(rename = "aixm:AirspaceVolume")]
+    volume: AirspaceVolume,
+}
+
+#[derive(Debug, Deserialize)]
+struct AirspaceVolume {
+    #[serde(rename = "aixm:horizontalProjection")]
+    horizontal_projection: Option<HorizontalProjection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HorizontalProjection {
+    #[serde(rename = "aixm:Surface")]
+    surface: Surface,
+}
+
+#[derive(Debug, Deserialize)]
+struct Surface {
+    #[serde(rename = "@srsName")]
+    srs_name: Option<String>,
+    #[serde(rename = "gml:patches")]
+    patches: Patches,
+}
+
+#[derive(Debug, Deserialize)]
+struct Patches {
+    #[serde(rename = "gml:PolygonPatch", default)]
+    polygons: Vec<PolygonPatch>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolygonPatch {
+    #[serde(rename = "gml:exterior")]
+    exterior: Exterior,
+}
+
+#[derive(Debug, Deserialize)]
+struct Exterior {
+    #[serde(rename = "gml:LinearRing")]
+    ring: Option<LinearRing>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinearRing {
+    #[serde(rename = "gml:posList")]
+    pos_list: Option<String>,
+    #[serde(rename = "gml:pos", default)]
+    pos: Vec<String>,
+}
+
+impl LinearRing {
+    fn points(&self, srs_name: Option<&str>) -> AiracUpdaterResult<Vec<Point>> {
+        gml_points(self.pos_list.as_deref(), &self.pos, srs_name)
+    }
+}
+
+impl AirspaceTimeSlice {
+    /// Exterior rings of the horizontal projections given as polygons, other
+    /// geometry (e.g. arcs or references to borders) is not supported.
+    pub(crate) fn outlines(&self) -> Vec<Vec<Point>> {
+        self.geometry_components
+            .iter()
+            .filter_map(|component| {
+                component
+                    .component
+                    .volume
+                    .volume
+                    .horizontal_projection
+                    .as_ref()
+            })
+            .flat_map(|projection| {
+                let srs_name = projection.surface.srs_name.as_deref();
+                projection
+                    .surface
+                    .patches
+                    .polygons
+                    .iter()
+                    .filter_map(move |polygon| {
+                        polygon.exterior.ring.as_ref()?.points(srs_name).ok()
+                    })
+            })
+            .filter(|outline| outline.len() > 1)
+            .collect()
+    }
+}
+
+/// Boundary of a permanent airspace, e.g. a CTR, TMA or FIR.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AixmAirspace {
+    pub designator: String,
+    pub airspace_type: Option<String>,
+    /// ICAO airspace class, e.g. `D`, of the first layer that has one
+    pub class: Option<String>,
+    pub outlines: Vec<Vec<Point>>,
+}
+
+impl AixmAirspace {
+    pub fn contains(&self, point: Point) -> bool {
+        self.outlines
+            .iter()
+            .any(|outline| Polygon::new(LineString::from(outline.clone()), vec![]).contains(&point))
+    }
+}
+
+/// Airspaces of a dataset with a designator and a horizontal projection, read
+/// member by member.
+#[derive(Debug, Default)]
+pub struct AirspaceCollector {
+    airspaces: Vec<AixmAirspace>,
+    /// Airspaces with unsupported geometry
+    skipped: usize,
+}
+
+impl AirspaceCollector {
+    /// Reads an `Airspace` feature. Temporary time slices are ignored.
+    pub fn add(&mut self, xml: &str) -> Result<(), DeError> {
+        let Some(time_slice) = quick_xml::de::from_str::<Airspace>(xml)?
+            .time_slices
+            .into_iter()
+            .map(|time_slice| time_slice.airspace)
+            .find(|time_slice| time_slice.interpretation != TEMPDELTA)
+        else {
+            return Ok(());
+        };
+        let Some(designator) = time_slice.designator.clone() else {
+            return Ok(());
+        };
+        let outlines = time_slice.outlines();
+        if outlines.is_empty() {
+            self.skipped += 1;
+            return Ok(());
+        }
+        let class = time_slice
+            .classes
+            .iter()
+            .find_map(|class| class.layer.as_ref()?.classification.clone());
+        self.airspaces.push(AixmAirspace {
+            designator,
+            airspace_type: time_slice.airspace_type,
+            class,
+            outlines,
+        });
+        Ok(())
+    }
+
+    /// Airspaces read, with the number of skipped ones with unsupported
+    /// geometry.
+    pub fn finish(self) -> (Vec<AixmAirspace>, usize) {
+        (self.airspaces, self.skipped)
+    }
+}
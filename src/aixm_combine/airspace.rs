@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use itertools::Itertools as _;
+
+use crate::{aixm_airspaces::AixmAirspace, aixm_index::AixmIndex, geometry::to_sct_coordinates};
+
+use super::sct::section_lines;
+
+const ARTCC_SECTIONS: [&str; 3] = ["[ARTCC]", "[ARTCC HIGH]", "[ARTCC LOW]"];
+
+/// `[ARTCC …]` section with the boundaries named like an AIXM airspace
+/// regenerated.
+pub(super) struct ArtccSection {
+    pub(super) header: &'static str,
+    pub(super) lines: Vec<String>,
+    /// Boundaries whose lines changed
+    pub(super) updated: Vec<String>,
+}
+
+/// Name of the boundary of an `[ARTCC …]` line, everything before the two
+/// coordinate pairs, which may also be fix or navaid names.
+fn boundary_name(line: &str) -> Option<String> {
+    let tokens = line.split(';').next()?.split_whitespace().collect_vec();
+    (tokens.len() > 4).then(|| tokens[..tokens.len() - 4].join(" "))
+}
+
+fn render_boundary(name: &str, airspace: &AixmAirspace) -> Vec<String> {
+    airspace
+        .outlines
+        .iter()
+        .flat_map(|outline| outline.iter().tuple_windows())
+        .map(|(from, to)| {
+            let (from_lat, from_lon) = to_sct_coordinates(*from);
+            let (to_lat, to_lon) = to_sct_coordinates(*to);
+            format!("{name} {from_lat} {from_lon} {to_lat} {to_lon}")
+        })
+        .collect()
+}
+
+/// Replaces the lines of every boundary of the rendered .sct named like the
+/// designator of an AIXM airspace with its AIXM outline, at the position of
+/// its first line. Other boundaries and comments are kept as they are, and
+/// sections without changed boundaries are not returned.
+pub(super) fn update_artcc_sections(sct: &str, aixm: &AixmIndex) -> Vec<ArtccSection> {
+    let airspaces = aixm
+        .airspaces()
+        .iter()
+        .map(|airspace| (airspace.designator.to_ascii_uppercase(), airspace))
+        .collect::<HashMap<_, _>>();
+
+    let mut sections = vec![];
+    for header in ARTCC_SECTIONS {
+        let mut lines = vec![];
+        let mut boundaries = HashMap::<String, (Vec<String>, Vec<String>)>::new();
+        for line in section_lines(sct, header) {
+            let Some((name, airspace)) = boundary_name(&line).and_then(|name| {
+                let airspace = airspaces.get(&name.to_ascii_uppercase())?;
+                Some((name, airspace))
+            }) else {
+                lines.push(line);
+                continue;
+            };
+            let (previous, _) = boundaries.entry(name.clone()).or_insert_with(|| {
+                let rendered = render_boundary(&name, airspace);
+                lines.extend(rendered.iter().cloned());
+                (vec![], rendered)
+            });
+            previous.push(line.split_whitespace().join(" "));
+        }
+
+        let updated = boundaries
+            .into_iter()
+            .filter(|(_, (previous, rendered))| previous != rendered)
+            .map(|(name, _)| name)
+            .sorted()
+            .collect_vec();
+        if !updated.is_empty() {
+            sections.push(ArtccSection {
+                header,
+                lines,
+                updated,
+            });
+        }
+    }
+    sections
+}
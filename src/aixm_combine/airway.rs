@@ -0,0 +1,65 @@
+use itertools::Itertools as _;
+
+use crate::{
+    aixm_index::AixmIndex,
+    aixm_routes::{AirwayLevel, SegmentPath},
+    geometry::to_sct_coordinates,
+};
+
+/// `[LOW AIRWAY]` and `[HIGH AIRWAY]` lines of the AIXM route segments.
+pub(super) struct Airways {
+    pub(super) low: Vec<String>,
+    pub(super) high: Vec<String>,
+    /// Segments between designated points missing in AIXM
+    pub(super) unresolved: usize,
+}
+
+/// Renders the airways from the AIXM route segments, `None` if there are none
+/// (i.e. "ED Routes" is not loaded) to keep the existing ones.
+pub(super) fn render_airways(aixm: &AixmIndex) -> Option<Airways> {
+    if aixm.route_segments().is_empty() {
+        return None;
+    }
+
+    let fixes = aixm.designated_point_positions();
+    let mut airways = Airways {
+        low: vec![],
+        high: vec![],
+        unresolved: 0,
+    };
+    for segment in aixm
+        .route_segments()
+        .iter()
+        .sorted_by(|a, b| a.designator.cmp(&b.designator))
+    {
+        let points = match &segment.path {
+            SegmentPath::Curve(points) => points.clone(),
+            SegmentPath::Fixes(start, end) => {
+                let (Some(start), Some(end)) = (fixes.get(start.as_str()), fixes.get(end.as_str()))
+                else {
+                    airways.unresolved += 1;
+                    continue;
+                };
+                vec![*start, *end]
+            }
+        };
+        for (from, to) in points.into_iter().tuple_windows() {
+            let (from_lat, from_lon) = to_sct_coordinates(from);
+            let (to_lat, to_lon) = to_sct_coordinates(to);
+            let line = format!(
+                "{} {from_lat} {from_lon} {to_lat} {to_lon}",
+                segment.designator
+            );
+            match segment.level {
+                AirwayLevel::Lower => airways.low.push(line),
+                AirwayLevel::Upper => airways.high.push(line),
+                AirwayLevel::Both => {
+                    airways.low.push(line.clone());
+                    airways.high.push(line);
+                }
+            }
+        }
+    }
+
+    Some(airways)
+}
@@ -0,0 +1,550 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+use chrono::{SecondsFormat, Utc};
+use geo::{Distance as _, Geodesic, Point};
+use itertools::Itertools as _;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use crate::{
+    Message,
+    airac::AiracCycle,
+    aixm_frequencies::normalize_call_sign,
+    aixm_index::AixmIndex,
+    error::{AiracUpdaterResult, EsePositionsChangedSnafu},
+    geometry::{from_sct_coordinates, to_sct_coordinates},
+    report::{Change, ChangeAction, MOVED_THRESHOLD},
+};
+
+use super::{CombineOptions, EntityKind};
+
+const HEADER_KEYS: &[&str] = &[
+    "; AIRAC-CYCLE:",
+    "; AIRAC-EFFECTIVE:",
+    "; GENERATED-BY:",
+    "; GENERATED-AT:",
+];
+
+/// Replaces the generated header block at the top of the .ese with `cycle`
+/// and generation metadata, e.g.
+///
+/// ```text
+/// ; AIRAC-CYCLE: 2504
+/// ; AIRAC-EFFECTIVE: 2025-04-17
+/// ; GENERATED-BY: airac-aixm-updater 0.1.1
+/// ; GENERATED-AT: 2025-04-10T18:00:00Z
+/// ```
+pub fn update_header(raw: &mut Vec<u8>, cycle: AiracCycle) {
+    let newline: &[u8] = if raw.windows(2).any(|w| w == b"\r\n") {
+        b"\r\n"
+    } else {
+        b"\n"
+    };
+
+    let mut header_len = 0;
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        if HEADER_KEYS
+            .iter()
+            .any(|key| line.starts_with(key.as_bytes()))
+        {
+            header_len += line.len();
+        } else {
+            break;
+        }
+    }
+
+    let mut header = vec![];
+    for line in [
+        format!("{} {cycle}", HEADER_KEYS[0]),
+        format!(
+            "{} {}",
+            HEADER_KEYS[1],
+            cycle.effective().format("%Y-%m-%d")
+        ),
+        format!(
+            "{} {} {}",
+            HEADER_KEYS[2],
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+        format!(
+            "{} {}",
+            HEADER_KEYS[3],
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+    ] {
+        header.extend_from_slice(line.as_bytes());
+        header.extend_from_slice(newline);
+    }
+
+    raw.splice(..header_len, header);
+}
+
+/// Lines of the `[POSITIONS]` section, without comments and blank lines.
+pub fn positions_lines(raw: &[u8]) -> Vec<String> {
+    let mut in_positions = false;
+    let mut lines = vec![];
+    for line in raw.split(|b| *b == b'\n') {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end();
+        if line.starts_with('[') {
+            in_positions = line.eq_ignore_ascii_case("[POSITIONS]");
+        } else if in_positions && !line.trim_start().is_empty() && !line.starts_with(';') {
+            lines.push(line.to_string());
+        }
+    }
+
+    lines
+}
+
+/// `[POSITIONS]` line without its frequency, the only field updated from AIXM.
+fn without_frequency(line: &str) -> String {
+    line.split(':')
+        .enumerate()
+        .map(|(i, field)| if i == 2 { "" } else { field })
+        .join(":")
+}
+
+/// Fails unless the `[POSITIONS]` section of `raw` still consists of the
+/// `original` lines, i.e. identifiers and squawk ranges are unchanged and in
+/// the same order. Only the frequencies may have been updated.
+pub fn verify_positions(path: &Path, original: &[String], raw: &[u8]) -> AiracUpdaterResult {
+    let current = positions_lines(raw);
+    if let Some(i) = (0..original.len().max(current.len())).find(|i| {
+        original.get(*i).map(|line| without_frequency(line))
+            != current.get(*i).map(|line| without_frequency(line))
+    }) {
+        return EsePositionsChangedSnafu {
+            path,
+            line: original
+                .get(i)
+                .or(current.get(i))
+                .cloned()
+                .unwrap_or_default(),
+        }
+        .fail();
+    }
+
+    Ok(())
+}
+
+/// Refreshes the .ese from AIXM: free text labels named like a navaid or fix
+/// are moved to its AIXM position and position frequencies are updated from
+/// the service of the same call sign. All other lines are kept byte for byte.
+pub fn update_from_aixm(
+    path: &Path,
+    raw: &mut Vec<u8>,
+    aixm: &AixmIndex,
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: &mpsc::Sender<Message>,
+) {
+    let mut locations = HashMap::<String, Vec<(EntityKind, Point, &str)>>::new();
+    for (kind, designator, position, dataset) in aixm.locations() {
+        let kind = match kind {
+            "Airport" => EntityKind::Airport,
+            "VOR" => EntityKind::Vor,
+            "NDB" => EntityKind::Ndb,
+            _ => EntityKind::Fix,
+        };
+        if !aixm.entity_types().contains(kind) {
+            continue;
+        }
+        // labels follow the adjustments of their entity
+        let Some(position) = options.position(kind, &designator, position, tx) else {
+            continue;
+        };
+        locations
+            .entry(designator)
+            .or_default()
+            .push((kind, position, dataset));
+    }
+
+    let frequencies = frequencies_by_call_sign(aixm);
+
+    let mut section = String::new();
+    let mut updated_frequencies = vec![];
+    let mut updated = Vec::with_capacity(raw.len());
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end();
+        if text.starts_with('[') {
+            section = text.to_ascii_uppercase();
+        } else if !text.starts_with(';') {
+            match section.as_str() {
+                "[FREETEXT]" => {
+                    if let Some(moved) =
+                        move_freetext(line, text, &locations, options.distances.label, changes)
+                    {
+                        updated.extend_from_slice(&moved);
+                        continue;
+                    }
+                }
+                "[POSITIONS]" if !frequencies.is_empty() => {
+                    if let Some((retuned, update)) =
+                        update_frequency(line, text, &frequencies, options.frequency_tolerance)
+                    {
+                        updated.extend_from_slice(&retuned);
+                        updated_frequencies.push(update);
+                        continue;
+                    }
+                }
+                _ => (),
+            }
+        }
+        updated.extend_from_slice(line);
+    }
+
+    for update in &updated_frequencies {
+        if let Err(e) = tx.blocking_send(Message::info(format!("{}: {update}", path.display()))) {
+            error!("{e}");
+        }
+    }
+
+    *raw = updated;
+}
+
+/// Waypoints of the SID and STAR routes
+/// (`SID:<airport>:<runway>:<name>:<waypoints>`).
+pub(super) fn procedure_waypoints(raw: &[u8]) -> BTreeSet<String> {
+    let mut section = String::new();
+    let mut waypoints = BTreeSet::new();
+    for line in raw.split(|b| *b == b'\n') {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end();
+        if text.starts_with('[') {
+            section = text.to_ascii_uppercase();
+        } else if section == "[SIDSSTARS]" && !text.starts_with(';') {
+            if let Some(route) = text.splitn(5, ':').nth(4) {
+                waypoints.extend(route.split_whitespace().map(ToString::to_string));
+            }
+        }
+    }
+    waypoints
+}
+
+/// SID/STAR waypoints of an .ese none of the AIXM airports, navaids and fixes
+/// is named like, whether the updated entity types include them or not.
+pub(super) fn unknown_waypoints(raw: &[u8], aixm: &AixmIndex) -> BTreeSet<String> {
+    let known = aixm
+        .locations()
+        .map(|(_, designator, ..)| designator)
+        .collect::<HashSet<_>>();
+    procedure_waypoints(raw)
+        .into_iter()
+        .filter(|waypoint| !known.contains(waypoint))
+        .collect()
+}
+
+/// AIXM frequencies by normalised call sign.
+fn frequencies_by_call_sign(aixm: &AixmIndex) -> HashMap<&str, Vec<&str>> {
+    let mut frequencies = HashMap::<&str, Vec<&str>>::new();
+    for frequency in aixm.frequencies() {
+        frequencies
+            .entry(frequency.call_sign.as_str())
+            .or_default()
+            .push(frequency.frequency.as_str());
+    }
+    frequencies
+}
+
+/// See [`EuroscopeFile::ambiguous_frequencies`](super::EuroscopeFile::ambiguous_frequencies).
+pub(super) fn ambiguous_frequencies(raw: &[u8], aixm: &AixmIndex) -> Vec<(String, String, String)> {
+    let frequencies = frequencies_by_call_sign(aixm);
+    positions_lines(raw)
+        .iter()
+        .filter_map(|line| {
+            let fields = line.splitn(4, ':').collect::<Vec<_>>();
+            let [name, call_sign, frequency, _] = fields.as_slice() else {
+                return None;
+            };
+            let aixm_frequencies = frequencies.get(normalize_call_sign(call_sign).as_str())?;
+            (aixm_frequencies.len() > 1 && !aixm_frequencies.contains(&frequency.trim())).then(
+                || {
+                    (
+                        (*name).to_string(),
+                        (*call_sign).to_string(),
+                        aixm_frequencies.join(", "),
+                    )
+                },
+            )
+        })
+        .collect()
+}
+
+/// `<lat>:<lon>:<group>:<text>` line with the coordinates replaced by the
+/// AIXM position of the closest navaid or fix named `<text>`, if it moved.
+fn move_freetext(
+    line: &[u8],
+    text: &str,
+    locations: &HashMap<String, Vec<(EntityKind, Point, &str)>>,
+    max_distance: f64,
+    changes: &mut Vec<Change>,
+) -> Option<Vec<u8>> {
+    let fields = text.splitn(4, ':').collect::<Vec<_>>();
+    let [lat, lon, _, label] = fields.as_slice() else {
+        return None;
+    };
+    let current = from_sct_coordinates(lat, lon)?;
+    let (kind, position, dataset, distance) = locations
+        .get(label.trim())?
+        .iter()
+        .filter(|(kind, _, _)| *kind != EntityKind::Airport)
+        .map(|(kind, position, dataset)| {
+            (
+                *kind,
+                *position,
+                *dataset,
+                Geodesic.distance(current, *position),
+            )
+        })
+        .min_by(|a, b| a.3.total_cmp(&b.3))?;
+    if !(MOVED_THRESHOLD..max_distance).contains(&distance) {
+        return None;
+    }
+
+    changes.push(Change::moved(
+        kind,
+        label.trim(),
+        current,
+        position,
+        distance,
+        dataset,
+    ));
+    let (new_lat, new_lon) = to_sct_coordinates(position);
+    // keep everything after the coordinates as is, whatever its encoding
+    let rest_start = line
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b':')
+        .nth(1)?
+        .0;
+    let mut moved = format!("{new_lat}:{new_lon}").into_bytes();
+    moved.extend_from_slice(&line[rest_start..]);
+    Some(moved)
+}
+
+/// Moves the free text labels of a move rejected in review back to their
+/// previous position.
+pub(super) fn revert_label_move(raw: &mut Vec<u8>, change: &Change) {
+    let ChangeAction::Moved { from, .. } = change.action else {
+        return;
+    };
+    let (lat, lon) = to_sct_coordinates(change.position);
+    let (from_lat, from_lon) = to_sct_coordinates(from);
+    let mut section = String::new();
+    let mut reverted = Vec::with_capacity(raw.len());
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end();
+        if text.starts_with('[') {
+            section = text.to_ascii_uppercase();
+        } else if section == "[FREETEXT]" {
+            let fields = text.splitn(4, ':').collect::<Vec<_>>();
+            if let [line_lat, line_lon, _, label] = fields.as_slice() {
+                if *line_lat == lat && *line_lon == lon && label.trim() == change.designator {
+                    // the coordinates are ASCII, so their length is the same in bytes
+                    let rest_start = line_lat.len() + 1 + line_lon.len();
+                    reverted.extend_from_slice(format!("{from_lat}:{from_lon}").as_bytes());
+                    reverted.extend_from_slice(&line[rest_start..]);
+                    continue;
+                }
+            }
+        }
+        reverted.extend_from_slice(line);
+    }
+    *raw = reverted;
+}
+
+/// `<name>:<call sign>:<frequency>:...` position line with the frequency
+/// replaced by the AIXM one of its call sign, if that differs by more than
+/// `tolerance` MHz and is unambiguous, together with a description of the
+/// update.
+fn update_frequency(
+    line: &[u8],
+    text: &str,
+    frequencies: &HashMap<&str, Vec<&str>>,
+    tolerance: f64,
+) -> Option<(Vec<u8>, String)> {
+    let fields = text.splitn(4, ':').collect::<Vec<_>>();
+    let [name, call_sign, frequency, _] = fields.as_slice() else {
+        return None;
+    };
+    let [aixm_frequency] = frequencies
+        .get(normalize_call_sign(call_sign).as_str())?
+        .as_slice()
+    else {
+        // several frequencies of a call sign cannot be told apart
+        return None;
+    };
+    if *aixm_frequency == frequency.trim()
+        || frequency
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .zip(aixm_frequency.parse::<f64>().ok())
+            .is_some_and(|(current, aixm)| (current - aixm).abs() <= tolerance)
+    {
+        return None;
+    }
+
+    // keep everything but the frequency as is, whatever its encoding
+    let mut colons = line
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b':')
+        .map(|(i, _)| i);
+    let start = colons.nth(1)? + 1;
+    let end = colons.next()?;
+    let mut retuned = line[..start].to_vec();
+    retuned.extend_from_slice(aixm_frequency.as_bytes());
+    retuned.extend_from_slice(&line[end..]);
+    Some((
+        retuned,
+        format!(
+            "{name} ({call_sign}) frequency {} -> {aixm_frequency}",
+            frequency.trim()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::NaiveDate;
+    use vatsim_parser::ese::Ese;
+
+    use super::*;
+    use crate::{
+        aixm::AixmDataset,
+        aixm_combine::{EuroscopeFile, RunwayRename},
+        aixm_frequencies::ServiceFrequency,
+        error::Error,
+    };
+
+    const ESE: &str = "\
+[POSITIONS]
+; Munich
+EDDM_TWR:Muenchen Tower:118.705:MT:T:EDDM:TWR:-:-:2001:2077:N048.21.14.000:E011.47.10.000
+EDDM_GND:Muenchen Ground:121.780:MG:G:EDDM:GND:-:-:2001:2077:N048.21.14.000:E011.47.10.000
+
+[SIDSSTARS]
+SID:EDDM:26L:KIRDI1S:DM060 KIRDI
+";
+
+    /// AIXM of AIRAC 2504 with the given call signs and frequencies
+    fn aixm(frequencies: &[(&str, &str)]) -> AixmIndex {
+        AixmIndex::new([AixmDataset {
+            name: "ED Services".to_string(),
+            frequencies: frequencies
+                .iter()
+                .map(|(call_sign, frequency)| ServiceFrequency {
+                    call_sign: (*call_sign).to_string(),
+                    frequency: (*frequency).to_string(),
+                })
+                .collect(),
+            effective: NaiveDate::from_ymd_opt(2025, 4, 17),
+            ..AixmDataset::default()
+        }])
+    }
+
+    /// `ESE` as loaded, with `renames` applied and combined with `aixm`, as
+    /// written and parsed again. `None` if it is left untouched.
+    fn update(aixm: &AixmIndex, renames: &[RunwayRename]) -> Option<String> {
+        let mut file = EuroscopeFile::Ese {
+            path: PathBuf::from("EDMM.ese"),
+            content: Box::new(Ese::parse(ESE.as_bytes()).unwrap()),
+            raw: ESE.as_bytes().to_vec(),
+            changed: false,
+            positions: positions_lines(ESE.as_bytes()),
+        };
+        file.apply_runway_renames(renames);
+        let (tx, _rx) = mpsc::channel(16);
+        let file = file.combine_with_aixm(aixm, &CombineOptions::default(), &mut vec![], tx);
+        let written = file.serialize()?;
+
+        Ese::parse(&written).unwrap();
+        verify_positions(
+            Path::new("EDMM.ese"),
+            &positions_lines(ESE.as_bytes()),
+            &written,
+        )
+        .unwrap();
+        Some(String::from_utf8(written).unwrap())
+    }
+
+    #[test]
+    fn unchanged_ese_is_left_untouched() {
+        assert_eq!(update(&aixm(&[("MUENCHEN TOWER", "118.705")]), &[]), None);
+    }
+
+    #[test]
+    fn frequency_is_updated_with_the_header() {
+        let written = update(&aixm(&[("MUENCHEN TOWER", "118.700")]), &[]).unwrap();
+        let mut lines = written.lines();
+        assert_eq!(lines.next(), Some("; AIRAC-CYCLE: 2504"));
+        assert_eq!(lines.next(), Some("; AIRAC-EFFECTIVE: 2025-04-17"));
+
+        let positions = positions_lines(written.as_bytes());
+        assert_eq!(
+            positions,
+            [
+                positions_lines(ESE.as_bytes())[0].replace(":118.705:", ":118.700:"),
+                positions_lines(ESE.as_bytes())[1].clone(),
+            ]
+        );
+        assert!(written.ends_with(ESE.split_once("\n\n").unwrap().1));
+    }
+
+    #[test]
+    fn header_is_replaced_not_repeated() {
+        let aixm = aixm(&[("MUENCHEN TOWER", "118.700")]);
+        let mut raw = update(&aixm, &[]).unwrap().into_bytes();
+        update_header(&mut raw, AiracCycle::parse("2505").unwrap());
+
+        let written = String::from_utf8(raw).unwrap();
+        assert_eq!(written.matches("; AIRAC-CYCLE:").count(), 1);
+        assert!(written.starts_with("; AIRAC-CYCLE: 2505\n; AIRAC-EFFECTIVE: 2025-05-15\n"));
+        assert!(written.contains("\n[POSITIONS]\n; Munich\n"));
+    }
+
+    #[test]
+    fn renamed_runway_is_written_to_procedures() {
+        let written = update(
+            &aixm(&[]),
+            &[RunwayRename {
+                airport: "EDDM".to_string(),
+                from: "26L".to_string(),
+                to: "26R".to_string(),
+            }],
+        )
+        .unwrap();
+        assert!(written.contains("\nSID:EDDM:26R:KIRDI1S:DM060 KIRDI\n"));
+        assert_eq!(
+            procedure_waypoints(written.as_bytes()),
+            procedure_waypoints(ESE.as_bytes())
+        );
+        assert_eq!(
+            positions_lines(written.as_bytes()),
+            positions_lines(ESE.as_bytes())
+        );
+    }
+
+    #[test]
+    fn changed_positions_are_rejected() {
+        let edited = ESE.replace(":MG:G:", ":MG:A:");
+        assert!(matches!(
+            verify_positions(
+                Path::new("EDMM.ese"),
+                &positions_lines(ESE.as_bytes()),
+                edited.as_bytes(),
+            ),
+            Err(Error::EsePositionsChanged { line, .. })
+                if line == positions_lines(ESE.as_bytes())[1]
+        ));
+    }
+}
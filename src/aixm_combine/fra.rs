@@ -0,0 +1,62 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::aixm_index::AixmIndex;
+
+pub const FRA_FILE_NAME: &str = "isec_fra.txt";
+
+/// Flags per point usage, as read by the FRA plugin.
+const USAGE_FLAGS: &[(&str, char)] = &[
+    ("FRA_ENTRY", 'E'),
+    ("FRA_EXIT", 'X'),
+    ("FRA_INTERMEDIATE", 'I'),
+    ("FRA_ARRIVAL", 'A'),
+    ("FRA_DEPARTURE", 'D'),
+    ("HOLDING", 'H'),
+];
+
+/// Collects the FRA and en-route holding flags of all designated points from
+/// their AIXM point type (e.g. `OTHER:FRA_ENTRY`), by designator.
+pub fn classify_points(aixm: &AixmIndex) -> BTreeMap<String, String> {
+    let mut points = BTreeMap::<String, String>::new();
+    for (_, aixm_fix) in aixm.designated_points() {
+        let time_slice = &aixm_fix.aixm_time_slice.aixm_designated_point_time_slice;
+        let Some(point_type) = &time_slice.aixm_type else {
+            continue;
+        };
+        let point_type = point_type.to_ascii_uppercase();
+
+        for (usage, flag) in USAGE_FLAGS {
+            if point_type.contains(usage) {
+                let flags = points
+                    .entry(time_slice.aixm_designator.clone())
+                    .or_default();
+                if !flags.contains(*flag) {
+                    flags.push(*flag);
+                }
+            }
+        }
+    }
+
+    points
+}
+
+/// Serializes the flags as `DESIGNATOR:FLAGS` lines, restricted to the points
+/// present in the isec.txt.
+pub fn serialize_points<'a>(
+    points: &BTreeMap<String, String>,
+    isec_designators: impl Iterator<Item = &'a str>,
+) -> Vec<u8> {
+    let isec_designators = isec_designators.collect::<HashSet<_>>();
+    let mut content = format!(
+        "; FRA and en-route holding points, generated by {} {}\r\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    for (designator, flags) in points {
+        if isec_designators.contains(designator.as_str()) {
+            content.push_str(&format!("{designator}:{flags}\r\n"));
+        }
+    }
+
+    content.into_bytes()
+}
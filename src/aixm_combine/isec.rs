@@ -1,40 +1,79 @@
-use aixm::{AixmDesignatedPoint, LocationType, Member};
-use geo::{Distance, Geodesic, point};
+use std::{collections::HashMap, sync::LazyLock};
+
+use aixm::AixmDesignatedPoint;
+use geo::{Distance, Geodesic};
+use regex::Regex;
 use tokio::sync::mpsc;
 use tracing::error;
 use vatsim_parser::{adaptation::locations::Fix, isec::IsecMap};
 
-use crate::Message;
+use crate::{
+    Message,
+    aixm_index::AixmIndex,
+    geometry::GmlPos,
+    report::{Change, ChangeAction, MOVED_THRESHOLD},
+};
+
+use super::{AixmUpdateExt, CombineOptions, EntityKind, sct::skip_invalid};
 
-use super::AixmUpdateExt;
+static ISEC_LINE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?<name>[^;\s]\S*)(?<sep>\s+)(?<lat>[-+]?\d+(?:\.\d+)?)\s+(?<lon>[-+]?\d+(?:\.\d+)?)",
+    )
+    .expect("valid regex")
+});
 
 impl AixmUpdateExt for IsecMap {
-    fn update_from_aixm(mut self, aixm: &[Member], tx: mpsc::Sender<Message>) -> Self {
-        for data in aixm {
-            if let Member::DesignatedPoint(aixm_fix) = data {
-                update_fixes(&mut self, aixm_fix, tx.clone());
-            }
+    fn update_from_aixm(
+        mut self,
+        aixm: &AixmIndex,
+        options: &CombineOptions,
+        changes: &mut Vec<Change>,
+        tx: mpsc::Sender<Message>,
+    ) -> Self {
+        if !aixm.entity_types().fixes {
+            return self;
+        }
+        for aixm_fix in aixm.designated_points() {
+            update_fixes(
+                &mut self,
+                aixm_fix,
+                options.distances.fix,
+                options,
+                changes,
+                tx.clone(),
+            );
         }
 
         self
     }
 }
 
-fn update_fixes(isecs: &mut IsecMap, aixm_fix: &AixmDesignatedPoint, tx: mpsc::Sender<Message>) {
-    let (lat, lng) = (match &aixm_fix
-        .aixm_time_slice
-        .aixm_designated_point_time_slice
-        .aixm_location
-        .location
-    {
-        LocationType::ElevatedPoint(ep) => &ep.gml_pos,
-        LocationType::Point(p) => &p.gml_pos,
-    })
-    .split_once(' ')
-    .unwrap();
-    let coordinate = point! {
-        x: lng.parse().unwrap(),
-        y: lat.parse().unwrap(),
+fn update_fixes(
+    isecs: &mut IsecMap,
+    (dataset, aixm_fix): (&str, &AixmDesignatedPoint),
+    max_distance: f64,
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: mpsc::Sender<Message>,
+) {
+    let time_slice = &aixm_fix.aixm_time_slice.aixm_designated_point_time_slice;
+    let Some(coordinate) = skip_invalid(
+        GmlPos::from(&time_slice.aixm_location.location).to_point(),
+        EntityKind::Fix,
+        &time_slice.aixm_designator,
+        dataset,
+        &tx,
+    ) else {
+        return;
+    };
+    let Some(coordinate) = options.position(
+        EntityKind::Fix,
+        &time_slice.aixm_designator,
+        coordinate,
+        &tx,
+    ) else {
+        return;
     };
     if let Some(fix) = isecs
         .get_vec_mut(
@@ -50,10 +89,21 @@ fn update_fixes(isecs: &mut IsecMap, aixm_fix: &AixmDesignatedPoint, tx: mpsc::S
                     .aixm_designated_point_time_slice
                     .aixm_designator
                     == fix.designator
-                    && Geodesic.distance(coordinate, fix.coordinate) < 1000.0
+                    && Geodesic.distance(coordinate, fix.coordinate) < max_distance
             })
         })
     {
+        let distance = Geodesic.distance(fix.coordinate, coordinate);
+        if distance >= MOVED_THRESHOLD {
+            changes.push(Change::moved(
+                EntityKind::Fix,
+                &fix.designator,
+                fix.coordinate,
+                coordinate,
+                distance,
+                dataset,
+            ));
+        }
         fix.coordinate = coordinate;
     } else if aixm_fix
         .aixm_time_slice
@@ -78,6 +128,15 @@ fn update_fixes(isecs: &mut IsecMap, aixm_fix: &AixmDesignatedPoint, tx: mpsc::S
         ))) {
             error!("{e}");
         }
+        changes.push(Change::added(
+            EntityKind::Fix,
+            &aixm_fix
+                .aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_designator,
+            coordinate,
+            dataset,
+        ));
         isecs.insert(
             aixm_fix
                 .aixm_time_slice
@@ -95,3 +154,153 @@ fn update_fixes(isecs: &mut IsecMap, aixm_fix: &AixmDesignatedPoint, tx: mpsc::S
         );
     }
 }
+
+/// Undoes a fix addition or move rejected in review.
+pub(super) fn revert_change(isecs: &mut IsecMap, change: &Change) {
+    let Some(fixes) = isecs.get_vec_mut(&change.designator) else {
+        return;
+    };
+    match change.action {
+        ChangeAction::Added => fixes.retain(|fix| fix.coordinate != change.position),
+        ChangeAction::Moved { from, .. } => {
+            if let Some(fix) = fixes
+                .iter_mut()
+                .find(|fix| fix.coordinate == change.position)
+            {
+                fix.coordinate = from;
+            }
+        }
+        ChangeAction::Candidate | ChangeAction::Removed => (),
+    }
+    if fixes.is_empty() {
+        isecs.remove(&change.designator);
+    }
+}
+
+/// Coordinate formatted like `written`, with as many decimals
+fn format_like(written: &str, value: f64) -> String {
+    let decimals = written
+        .split_once('.')
+        .map_or(0, |(_, decimals)| decimals.len());
+    format!("{value:.decimals$}")
+}
+
+/// isec.txt as read with the coordinates of moved fixes replaced in place and
+/// new fixes appended. Comments, separators, extra columns and lines in an
+/// unknown format are kept as they were.
+pub(super) fn render(original: &str, isecs: &IsecMap) -> String {
+    // the n-th line of a name is the n-th fix of that name
+    let mut occurrences = HashMap::<&str, usize>::new();
+    let mut separator = "\t";
+    let mut lines = vec![];
+    for line in original.lines() {
+        let line = line.trim_end_matches('\r');
+        // comments and other lines without coordinates are no fix
+        let Some((name, captures)) = ISEC_LINE_RE
+            .captures(line)
+            .and_then(|captures| Some((captures.name("name")?.as_str(), captures)))
+        else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let occurrence = occurrences.entry(name).or_default();
+        let fix = isecs.get_vec(name).and_then(|fixes| fixes.get(*occurrence));
+        *occurrence += 1;
+
+        let Some(fix) = fix else {
+            lines.push(line.to_string());
+            continue;
+        };
+        separator = captures.name("sep").map_or(separator, |sep| sep.as_str());
+        let (Some(lat), Some(lon)) = (captures.name("lat"), captures.name("lon")) else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let new_lat = format_like(lat.as_str(), fix.coordinate.y());
+        let new_lon = format_like(lon.as_str(), fix.coordinate.x());
+        let unchanged = lat
+            .as_str()
+            .parse::<f64>()
+            .is_ok_and(|written| format_like(lat.as_str(), written) == new_lat)
+            && lon
+                .as_str()
+                .parse::<f64>()
+                .is_ok_and(|written| format_like(lon.as_str(), written) == new_lon);
+        if unchanged {
+            lines.push(line.to_string());
+        } else {
+            lines.push(format!(
+                "{}{new_lat}{}{new_lon}{}",
+                &line[..lat.start()],
+                &line[lat.end()..lon.start()],
+                &line[lon.end()..],
+            ));
+        }
+    }
+
+    let mut added = isecs
+        .iter_all()
+        .flat_map(|(name, fixes)| {
+            let written = occurrences.get(name.as_str()).copied().unwrap_or(0);
+            fixes.iter().skip(written)
+        })
+        .map(|fix| {
+            format!(
+                "{}{separator}{:.6}{separator}{:.6}",
+                fix.designator,
+                fix.coordinate.y(),
+                fix.coordinate.x()
+            )
+        })
+        .collect::<Vec<_>>();
+    added.sort();
+    lines.extend(added);
+
+    let newline = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut rendered = lines.join(newline);
+    if original.ends_with('\n') || original.is_empty() {
+        rendered.push_str(newline);
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Point;
+
+    use super::*;
+
+    fn fix(lat: f64, lon: f64) -> Fix {
+        Fix {
+            designator: "ABCDE".to_string(),
+            coordinate: Point::new(lon, lat),
+        }
+    }
+
+    #[test]
+    fn lines_without_coordinates_do_not_count_as_fixes() {
+        let original = "\
+; fixes
+ABCDE\t48.000000\t11.000000
+ABCDE ; the second one is further east
+ABCDE\t49.000000\t12.000000
+";
+        let mut isecs = IsecMap::default();
+        isecs.insert("ABCDE".to_string(), fix(48.0, 11.0));
+        isecs.insert("ABCDE".to_string(), fix(49.5, 12.0));
+
+        assert_eq!(
+            render(original, &isecs),
+            "\
+; fixes
+ABCDE\t48.000000\t11.000000
+ABCDE ; the second one is further east
+ABCDE\t49.500000\t12.000000
+"
+        );
+    }
+}
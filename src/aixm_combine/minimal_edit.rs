@@ -0,0 +1,210 @@
+//! Writes the combined .sct as a patch of the file as read: only the lines of
+//! changed entities are replaced, comments, blank lines, `;` annotations and
+//! the order of sections and entities stay as they were.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Sections whose lines start with the designator of their entity, changed
+/// entities are replaced in place
+const KEYED_SECTIONS: [&str; 4] = ["[VOR]", "[NDB]", "[AIRPORT]", "[FIXES]"];
+
+/// Sections whose lines mean something by their position, taken as rendered
+/// whenever any of their lines changed
+const POSITIONAL_SECTIONS: [&str; 1] = ["[INFO]"];
+
+struct Section<'a> {
+    /// Upper case header, empty for the lines before the first section
+    name: String,
+    /// Header line as written
+    header: Option<&'a str>,
+    lines: Vec<&'a str>,
+}
+
+fn split_sections(sct: &str) -> Vec<Section<'_>> {
+    let mut sections = vec![Section {
+        name: String::new(),
+        header: None,
+        lines: vec![],
+    }];
+    for line in sct.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.trim_start().starts_with('[') {
+            sections.push(Section {
+                name: line.trim().to_ascii_uppercase(),
+                header: Some(line),
+                lines: vec![],
+            });
+        } else if let Some(section) = sections.last_mut() {
+            section.lines.push(line);
+        }
+    }
+    sections
+}
+
+/// Line split into the entity and its trailing `;` comment, including the
+/// whitespace before it
+fn split_comment(line: &str) -> (&str, &str) {
+    let data = line.find(';').map_or(line, |i| &line[..i]);
+    let data = data.trim_end();
+    (data, &line[data.len()..])
+}
+
+fn normalize(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Rendered lines of a section not matched against the original yet
+struct Pool<'a> {
+    keyed: bool,
+    lines: Vec<Option<&'a str>>,
+    /// Indices into `lines` by designator or, for unkeyed sections and
+    /// comments, by normalized line
+    index: HashMap<String, VecDeque<usize>>,
+}
+
+impl<'a> Pool<'a> {
+    fn new(name: &str) -> Self {
+        Self {
+            keyed: KEYED_SECTIONS.contains(&name),
+            lines: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, line: &'a str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        self.index
+            .entry(self.key(line))
+            .or_default()
+            .push_back(self.lines.len());
+        self.lines.push(Some(line));
+    }
+
+    fn key(&self, line: &str) -> String {
+        let is_comment = line.trim_start().starts_with(';');
+        match line.split_whitespace().next() {
+            Some(designator) if self.keyed && !is_comment => designator.to_ascii_uppercase(),
+            _ => normalize(line),
+        }
+    }
+
+    fn take(&mut self, line: &str) -> Option<&'a str> {
+        let i = self.index.get_mut(&self.key(line))?.pop_front()?;
+        self.lines[i].take()
+    }
+
+    fn rest(&mut self) -> impl Iterator<Item = &'a str> + '_ {
+        self.lines.iter_mut().filter_map(Option::take)
+    }
+}
+
+/// Patches the lines of `rendered` (the whole combined .sct) into `original`
+/// (the .sct as read). Entities missing in `rendered` are dropped, new ones
+/// are appended to the end of their section and sections only in `rendered`
+/// to the end of the file.
+pub(super) fn patch(original: &str, rendered: &str) -> String {
+    let original_sections = split_sections(original);
+    let rendered_sections = split_sections(rendered);
+
+    let mut pools = HashMap::<&str, Pool<'_>>::new();
+    for section in &rendered_sections {
+        let pool = pools
+            .entry(section.name.as_str())
+            .or_insert_with(|| Pool::new(&section.name));
+        for &line in &section.lines {
+            pool.push(line);
+        }
+    }
+    let last_occurrence = original_sections
+        .iter()
+        .enumerate()
+        .map(|(i, section)| (section.name.as_str(), i))
+        .collect::<HashMap<_, _>>();
+
+    let mut content = vec![];
+    for (i, section) in original_sections.iter().enumerate() {
+        content.extend(section.header.map(str::to_string));
+        let Some(pool) = pools.get_mut(section.name.as_str()) else {
+            // unknown to the writer, e.g. dropped by the parser
+            content.extend(section.lines.iter().map(ToString::to_string));
+            continue;
+        };
+
+        if POSITIONAL_SECTIONS.contains(&section.name.as_str()) {
+            let rendered = pool.rest().collect::<Vec<_>>();
+            let unchanged = section
+                .lines
+                .iter()
+                .map(|line| split_comment(line).0)
+                .filter(|data| !data.trim().is_empty())
+                .map(normalize)
+                .eq(rendered.iter().map(|line| normalize(line)));
+            if unchanged {
+                content.extend(section.lines.iter().map(ToString::to_string));
+            } else {
+                content.extend(rendered.into_iter().map(str::to_string));
+            }
+            continue;
+        }
+
+        let mut lines = vec![];
+        for line in &section.lines {
+            let (data, comment) = split_comment(line);
+            if data.trim().is_empty() {
+                // comment or blank line, consuming an identical rendered one
+                if !line.trim().is_empty() {
+                    pool.take(line);
+                }
+                lines.push((*line).to_string());
+                continue;
+            }
+            match pool.take(data) {
+                Some(rendered) if normalize(rendered) == normalize(data) => {
+                    lines.push((*line).to_string());
+                }
+                Some(rendered) => lines.push(format!("{}{comment}", rendered.trim_end())),
+                // removed, or changed in a section without designators and
+                // appended below
+                None => {}
+            }
+        }
+        if last_occurrence.get(section.name.as_str()) == Some(&i) {
+            // appended before the blank lines separating the next section
+            let blank_lines = lines
+                .iter()
+                .rev()
+                .take_while(|line| line.trim().is_empty())
+                .count();
+            let trailing = lines.split_off(lines.len() - blank_lines);
+            lines.extend(pool.rest().map(str::to_string));
+            lines.extend(trailing);
+        }
+        content.extend(lines);
+    }
+
+    for section in &rendered_sections {
+        let Some(pool) = pools.get_mut(section.name.as_str()) else {
+            continue;
+        };
+        let rest = pool.rest().map(str::to_string).collect::<Vec<_>>();
+        if rest.is_empty() {
+            continue;
+        }
+        content.push(String::new());
+        content.extend(section.header.map(str::to_string));
+        content.extend(rest);
+    }
+
+    let newline = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut patched = content.join(newline);
+    if original.ends_with('\n') {
+        patched.push_str(newline);
+    }
+    patched
+}
@@ -1,129 +1,829 @@
+mod airspace;
+mod airway;
+mod ese;
+mod fra;
 mod isec;
+mod minimal_edit;
+mod names;
+mod overrides;
+mod proximity;
+mod runway;
 mod sct;
+mod transform;
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
 
-use aixm::Member;
-use chrono::Utc;
+use geo::Point;
+use itertools::Itertools as _;
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
 use snafu::ResultExt as _;
-use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::mpsc};
+use tokio::sync::mpsc;
+use tracing::error;
 use vatsim_parser::{ese::Ese, isec::IsecMap, sct::Sct};
 
 use crate::{
     Message,
-    error::{AiracUpdaterResult, CreateNewSnafu, RenameSnafu, WriteNewSnafu},
+    airac::AiracCycle,
+    aixm_airspaces::AixmAirspace,
+    aixm_index::AixmIndex,
+    config::{Profile, SctWriter, StaleEntities},
+    error::{AiracUpdaterResult, InvalidFixPatternSnafu},
+    output,
+    report::{Change, ChangeAction},
+    sct_salvage, topsky,
+    verify::QualityCounts,
+};
+
+pub use ese::positions_lines;
+pub use names::NavaidNames;
+pub use runway::RunwayRename;
+use runway::{
+    detect_runway_renames, rename_runways_in_ese, rename_runways_in_rwy, rename_runways_in_sct,
 };
+pub use sct::passthrough_sections;
+use sct::{Candidate, RemovedEntity};
+pub use transform::{EntityKind, Transform};
 
 pub trait AixmUpdateExt {
-    fn update_from_aixm(self, aixm: &[Member], tx: mpsc::Sender<Message>) -> Self;
+    /// Updates the entities from AIXM, recording every addition and noticeable
+    /// move in `changes`.
+    fn update_from_aixm(
+        self,
+        aixm: &AixmIndex,
+        options: &CombineOptions,
+        changes: &mut Vec<Change>,
+        tx: mpsc::Sender<Message>,
+    ) -> Self;
+}
+
+/// Airports moved further than this many metres are warned about by default.
+pub const DEFAULT_AIRPORT_WARNING_DISTANCE: f64 = 1000.0;
+
+/// Distances in metres up to which an AIXM entity is taken to be an existing
+/// one of the same designator, instead of a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct MatchDistances {
+    /// Fixes of the .sct and isec.txt
+    pub fix: f64,
+    /// Free text labels of the .ese named like a navaid or fix
+    pub label: f64,
+    /// Runway thresholds, renamed instead of replaced
+    pub runway: f64,
+}
+
+impl Default for MatchDistances {
+    fn default() -> Self {
+        Self {
+            fix: 1000.0,
+            label: 1000.0,
+            runway: 100.0,
+        }
+    }
+}
+
+/// Thresholds and patterns of a combine, taken from the profile and passed to
+/// every [`AixmUpdateExt::update_from_aixm`].
+#[derive(Debug, Clone)]
+pub struct CombineOptions {
+    pub distances: MatchDistances,
+    /// Distance in metres beyond which a moved airport is warned about, as a
+    /// wrong reference point is more likely than a relocated aerodrome
+    pub airport_warning_distance: f64,
+    /// Difference in MHz up to which a position frequency counts as the AIXM
+    /// one and is left as written
+    pub frequency_tolerance: f64,
+    /// Designators of fixes never added, see [`EuroscopeFile::exclude_fixes`]
+    pub fix_exclude: RegexSet,
+    /// Deviations from the AIXM positions, see [`Profile::transforms`],
+    /// followed by the entities kept as they are or placed manually, see
+    /// [`Profile::designator_overrides`]
+    pub transforms: Vec<Transform>,
+}
+
+impl CombineOptions {
+    /// Options of `profile`, failing on invalid fix patterns.
+    pub fn from_profile(profile: &Profile) -> AiracUpdaterResult<Self> {
+        let mut transforms = profile.transforms.clone();
+        if let Some(path) = &profile.designator_overrides {
+            transforms.extend(overrides::load(path)?);
+        }
+        Ok(Self {
+            distances: profile.match_distances,
+            airport_warning_distance: profile.airport_warning_distance,
+            frequency_tolerance: profile.frequency_tolerance,
+            fix_exclude: RegexSet::new(&profile.fix_exclude).context(InvalidFixPatternSnafu)?,
+            transforms,
+        })
+    }
+
+    /// Position to update an entity to from its `aixm` one, adjusted by the
+    /// transforms, `None` if one keeps it as it is.
+    pub(crate) fn position(
+        &self,
+        kind: EntityKind,
+        designator: &str,
+        aixm: Point,
+        tx: &mpsc::Sender<Message>,
+    ) -> Option<Point> {
+        let position = transform::adjust(&self.transforms, kind, designator, aixm);
+        let content = match position {
+            None => format!("Keeping {kind} {designator} as it is"),
+            Some(position) if position != aixm => {
+                format!("Using the adjusted position of {kind} {designator}")
+            }
+            Some(_) => return position,
+        };
+        if let Err(e) = tx.blocking_send(Message::debug(content)) {
+            error!("{e}");
+        }
+        position
+    }
+}
+
+impl Default for CombineOptions {
+    fn default() -> Self {
+        Self {
+            distances: MatchDistances::default(),
+            airport_warning_distance: DEFAULT_AIRPORT_WARNING_DISTANCE,
+            frequency_tolerance: 0.0,
+            fix_exclude: RegexSet::empty(),
+            transforms: vec![],
+        }
+    }
+}
+
+/// Type of entity that can be left out of the update, see [`EntityTypes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntityType {
+    #[value(alias = "airport")]
+    Airports,
+    #[value(alias = "vor")]
+    Vors,
+    #[value(alias = "ndb")]
+    Ndbs,
+    #[value(alias = "fix")]
+    Fixes,
+    #[value(alias = "runway")]
+    Runways,
+    #[value(alias = "route")]
+    Routes,
+}
+
+impl EntityType {
+    pub const ALL: [Self; 6] = [
+        Self::Airports,
+        Self::Vors,
+        Self::Ndbs,
+        Self::Fixes,
+        Self::Runways,
+        Self::Routes,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EntityType::Airports => "Airports",
+            EntityType::Vors => "VORs",
+            EntityType::Ndbs => "NDBs",
+            EntityType::Fixes => "Fixes",
+            EntityType::Runways => "Runways",
+            EntityType::Routes => "Routes",
+        }
+    }
+}
+
+impl From<EntityKind> for EntityType {
+    fn from(kind: EntityKind) -> Self {
+        match kind {
+            EntityKind::Airport => EntityType::Airports,
+            EntityKind::Vor => EntityType::Vors,
+            EntityKind::Ndb => EntityType::Ndbs,
+            EntityKind::Fix => EntityType::Fixes,
+        }
+    }
+}
+
+/// Entity types updated from AIXM, all of them by default. Disabled ones are
+/// left as they are in the sector files, neither moved, added nor stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct EntityTypes {
+    pub airports: bool,
+    pub vors: bool,
+    pub ndbs: bool,
+    pub fixes: bool,
+    pub runways: bool,
+    pub routes: bool,
 }
 
-pub(crate) enum EuroscopeFile {
+impl Default for EntityTypes {
+    fn default() -> Self {
+        Self {
+            airports: true,
+            vors: true,
+            ndbs: true,
+            fixes: true,
+            runways: true,
+            routes: true,
+        }
+    }
+}
+
+impl EntityTypes {
+    pub fn get_mut(&mut self, entity_type: EntityType) -> &mut bool {
+        match entity_type {
+            EntityType::Airports => &mut self.airports,
+            EntityType::Vors => &mut self.vors,
+            EntityType::Ndbs => &mut self.ndbs,
+            EntityType::Fixes => &mut self.fixes,
+            EntityType::Runways => &mut self.runways,
+            EntityType::Routes => &mut self.routes,
+        }
+    }
+
+    pub fn contains(self, entity_type: impl Into<EntityType>) -> bool {
+        match entity_type.into() {
+            EntityType::Airports => self.airports,
+            EntityType::Vors => self.vors,
+            EntityType::Ndbs => self.ndbs,
+            EntityType::Fixes => self.fixes,
+            EntityType::Runways => self.runways,
+            EntityType::Routes => self.routes,
+        }
+    }
+}
+
+pub enum EuroscopeFile {
     Sct {
         path: PathBuf,
         content: Box<Sct>,
+        /// New entities held back for review, see [`Self::demote_additions`]
+        candidates: Vec<Candidate>,
+        /// Sections regenerated from AIXM, replacing the parsed ones when
+        /// written, e.g. the airways
+        sections: Vec<(&'static str, Vec<String>)>,
+        /// Sections unknown to the parser, written back verbatim, see
+        /// [`passthrough_sections`]
+        passthrough: Vec<(String, Vec<String>)>,
+        /// Stale entities removed, see [`Self::reject_changes`]
+        removed: Vec<RemovedEntity>,
+        /// The .sct as read, patched instead of rewritten with
+        /// [`SctWriter::MinimalEdit`]
+        original: String,
+        writer: SctWriter,
+        /// Malformed lines left out when parsing, 0-based, see
+        /// [`sct_salvage`]. Kept as they are, so the file is always patched
+        salvaged: Vec<usize>,
     },
-    #[expect(dead_code, reason = ".ese not handled yet")]
     Ese {
         path: PathBuf,
+        /// Parsed for validation only, the .ese is updated and written from
+        /// the raw bytes
         content: Box<Ese>,
+        raw: Vec<u8>,
+        changed: bool,
+        /// `[POSITIONS]` lines as read, which must survive every change
+        positions: Vec<String>,
     },
     Isec {
         path: PathBuf,
         content: Box<IsecMap>,
+        /// isec.txt as read, patched with the updated coordinates on write
+        original: String,
+    },
+    Rwy {
+        path: PathBuf,
+        content: Vec<u8>,
+        changed: bool,
+    },
+    /// Auxiliary file generated from AIXM, not read from the package
+    Generated { path: PathBuf, content: Vec<u8> },
+    /// TopSky maps of the AIXM airspaces, rendered by the `topsky` writer
+    TopSkyMaps {
+        path: PathBuf,
+        airspaces: Vec<AixmAirspace>,
     },
 }
 impl EuroscopeFile {
-    pub(crate) fn combine_with_aixm(self, aixm: &[Member], tx: mpsc::Sender<Message>) -> Self {
+    pub fn runway_renames(
+        files: &[Self],
+        aixm: &AixmIndex,
+        options: &CombineOptions,
+    ) -> Vec<RunwayRename> {
+        if !aixm.entity_types().runways {
+            return vec![];
+        }
+        files
+            .iter()
+            .flat_map(|file| match file {
+                EuroscopeFile::Sct { content, .. } => {
+                    detect_runway_renames(content, aixm, options.distances.runway)
+                }
+                _ => vec![],
+            })
+            .unique()
+            .collect()
+    }
+
+    pub fn apply_runway_renames(&mut self, renames: &[RunwayRename]) {
         match self {
-            EuroscopeFile::Sct { path, content } => {
-                let content = Sct::update_from_aixm(*content, aixm, tx);
+            EuroscopeFile::Sct { content, .. } => {
+                rename_runways_in_sct(content, renames);
+            }
+            EuroscopeFile::Ese { raw, changed, .. } => {
+                *changed |= rename_runways_in_ese(raw, renames);
+            }
+            EuroscopeFile::Rwy {
+                content, changed, ..
+            } => {
+                *changed |= rename_runways_in_rwy(content, renames);
+            }
+            EuroscopeFile::Isec { .. }
+            | EuroscopeFile::Generated { .. }
+            | EuroscopeFile::TopSkyMaps { .. } => (),
+        }
+    }
+
+    pub fn combine_with_aixm(
+        mut self,
+        aixm: &AixmIndex,
+        options: &CombineOptions,
+        changes: &mut Vec<Change>,
+        tx: mpsc::Sender<Message>,
+    ) -> Self {
+        if let EuroscopeFile::Ese {
+            path, raw, changed, ..
+        } = &mut self
+        {
+            let original = raw.clone();
+            ese::update_from_aixm(path, raw, aixm, options, changes, &tx);
+            *changed |= *raw != original;
+            // without dates in the release file names, the cycle in effect
+            if *changed {
+                ese::update_header(raw, aixm.cycle().unwrap_or_else(AiracCycle::current));
+            }
+        }
+
+        match self {
+            EuroscopeFile::Sct {
+                path,
+                content,
+                candidates,
+                mut sections,
+                passthrough,
+                removed,
+                original,
+                writer,
+                salvaged,
+            } => {
+                let content = Sct::update_from_aixm(*content, aixm, options, changes, tx.clone());
+                let airways = if aixm.entity_types().routes {
+                    airway::render_airways(aixm)
+                } else {
+                    None
+                };
+                if let Some(airways) = airways {
+                    if airways.unresolved > 0 {
+                        if let Err(e) = tx.blocking_send(Message::warn(format!(
+                            "Skipped {} airway segments between unknown designated points",
+                            airways.unresolved
+                        ))) {
+                            error!("{e}");
+                        }
+                    }
+                    sections.push(("[LOW AIRWAY]", airways.low));
+                    sections.push(("[HIGH AIRWAY]", airways.high));
+                }
+                if !aixm.airspaces().is_empty() {
+                    for section in airspace::update_artcc_sections(&content.to_string(), aixm) {
+                        if let Err(e) = tx.blocking_send(Message::info(format!(
+                            "{}: updated boundaries in {} from AIXM: {}",
+                            path.display(),
+                            section.header,
+                            section.updated.join(", ")
+                        ))) {
+                            error!("{e}");
+                        }
+                        sections.push((section.header, section.lines));
+                    }
+                }
                 EuroscopeFile::Sct {
                     path,
                     content: Box::new(content),
+                    candidates,
+                    sections,
+                    passthrough,
+                    removed,
+                    original,
+                    writer,
+                    salvaged,
                 }
             }
-            EuroscopeFile::Isec { path, content } => {
-                let content = IsecMap::update_from_aixm(*content, aixm, tx);
+            EuroscopeFile::Isec {
+                path,
+                content,
+                original,
+            } => {
+                let content = IsecMap::update_from_aixm(*content, aixm, options, changes, tx);
                 EuroscopeFile::Isec {
                     path,
                     content: Box::new(content),
+                    original,
                 }
             }
-            EuroscopeFile::Ese {
-                path: _,
-                content: _,
-            } => self,
+            EuroscopeFile::Ese { .. }
+            | EuroscopeFile::Rwy { .. }
+            | EuroscopeFile::Generated { .. }
+            | EuroscopeFile::TopSkyMaps { .. } => self,
         }
     }
 
-    pub(crate) async fn write_file(self, tx: mpsc::Sender<Message>) -> AiracUpdaterResult {
+    /// How the file is written if it is a .sct.
+    pub fn sct_writer(&self) -> Option<SctWriter> {
         match self {
-            Self::Sct {
-                content: ref sct, ..
+            EuroscopeFile::Sct { writer, .. } => Some(*writer),
+            _ => None,
+        }
+    }
+
+    pub fn set_sct_writer(&mut self, sct_writer: SctWriter) {
+        if let EuroscopeFile::Sct {
+            writer, salvaged, ..
+        } = self
+        {
+            *writer = if salvaged.is_empty() {
+                sct_writer
+            } else {
+                SctWriter::MinimalEdit
+            };
+        }
+    }
+
+    /// Takes the VORs, NDBs and fixes added by the combine out of the .sct
+    /// again, to be written as commented-out candidate lines for review.
+    pub fn demote_additions(&mut self, changes: &mut [Change]) {
+        if let EuroscopeFile::Sct {
+            content,
+            candidates,
+            ..
+        } = self
+        {
+            candidates.extend(sct::demote_additions(content, changes));
+        }
+    }
+
+    /// Takes the fixes added by the combine out again if their designator
+    /// matches one of `patterns`, returning the excluded designators and
+    /// positions.
+    pub fn exclude_fixes(
+        &mut self,
+        patterns: &RegexSet,
+        changes: &mut Vec<Change>,
+    ) -> Vec<(String, Point)> {
+        match self {
+            EuroscopeFile::Sct { content, .. } => {
+                let excluded = take_excluded_fixes(patterns, changes);
+                content.fixes.retain(|fix| {
+                    !excluded.iter().any(|(designator, position)| {
+                        *designator == fix.designator && *position == fix.coordinate
+                    })
+                });
+                excluded
+            }
+            EuroscopeFile::Isec { content, .. } => {
+                let excluded = take_excluded_fixes(patterns, changes);
+                for (designator, position) in &excluded {
+                    if let Some(fixes) = content.get_vec_mut(designator) {
+                        fixes.retain(|fix| fix.coordinate != *position);
+                        if fixes.is_empty() {
+                            content.remove(designator);
+                        }
+                    }
+                }
+                excluded
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Reports the VORs, NDBs and fixes of the .sct missing in AIXM, and
+    /// removes them with [`StaleEntities::Remove`]. Returns the ones kept.
+    pub fn handle_stale_entities(
+        &mut self,
+        aixm: &AixmIndex,
+        mode: StaleEntities,
+        transforms: &[Transform],
+        changes: &mut Vec<Change>,
+        tx: &mpsc::Sender<Message>,
+    ) -> Vec<(EntityKind, String, Point)> {
+        let EuroscopeFile::Sct {
+            path,
+            content,
+            removed,
+            ..
+        } = self
+        else {
+            return vec![];
+        };
+
+        let mut stale = sct::stale_entities(content, aixm);
+        stale.retain(|(kind, designator, _)| !transform::is_manual(transforms, *kind, designator));
+        for (kind, designator, _) in &stale {
+            if let Err(e) = tx.blocking_send(Message::warn(format!(
+                "{kind} {designator} of {} is missing in AIXM{}",
+                path.display(),
+                if mode == StaleEntities::Remove {
+                    ", removing it"
+                } else {
+                    ""
+                }
+            ))) {
+                error!("{e}");
+            }
+        }
+        if mode == StaleEntities::Remove {
+            removed.extend(sct::remove_entities(content, &stale));
+            changes.extend(
+                stale.into_iter().map(|(kind, designator, position)| {
+                    Change::removed(kind, designator, position)
+                }),
+            );
+            vec![]
+        } else {
+            stale
+        }
+    }
+
+    /// Undoes the `rejected` changes of this file, e.g. after a review.
+    pub fn reject_changes(&mut self, rejected: &[Change]) {
+        match self {
+            EuroscopeFile::Sct {
+                content,
+                candidates,
+                removed,
+                ..
             } => {
-                if let Some(file_name) = self.path().file_name() {
-                    let mut bkp_file_name = file_name.to_os_string();
-                    bkp_file_name.push(format!(".aau_bkp{}", Utc::now().format("%Y%m%d_%H%M%S")));
-                    let bkp_file_path = self.path().with_file_name(bkp_file_name);
-                    tx.send(Message::info(format!(
-                        "Moving {} to {}",
-                        self.path().display(),
-                        bkp_file_path.display()
-                    )))
-                    .await?;
-
-                    tokio::fs::rename(self.path(), &bkp_file_path)
-                        .await
-                        .context(RenameSnafu {
-                            from: self.path().to_path_buf(),
-                            to: bkp_file_path,
-                        })?;
-
-                    tx.send(Message::info(format!(
-                        "Writing new {}",
-                        self.path().display(),
-                    )))
-                    .await?;
-
-                    OpenOptions::new()
-                        .create_new(true)
-                        .write(true)
-                        .open(self.path())
-                        .await
-                        .context(CreateNewSnafu {
-                            path: self.path().to_path_buf(),
-                        })?
-                        .write_all(sct.to_string().as_bytes())
-                        .await
-                        .context(WriteNewSnafu {
-                            path: self.path().to_path_buf(),
-                        })?;
-
-                    tx.send(Message::info(format!(
-                        "Finished writing {}",
-                        self.path().display(),
-                    )))
-                    .await?;
+                for change in rejected {
+                    sct::revert_change(content, candidates, removed, change);
                 }
             }
-            Self::Ese {
-                path: _,
-                content: _,
-            } => (),
-            Self::Isec {
-                path: _,
-                content: _,
-            } => (),
+            EuroscopeFile::Isec { content, .. } => {
+                for change in rejected {
+                    isec::revert_change(content, change);
+                }
+            }
+            EuroscopeFile::Ese { raw, .. } => {
+                for change in rejected {
+                    ese::revert_label_move(raw, change);
+                }
+            }
+            EuroscopeFile::Rwy { .. }
+            | EuroscopeFile::Generated { .. }
+            | EuroscopeFile::TopSkyMaps { .. } => (),
         }
+    }
+
+    /// Waypoints of the SIDs and STARs of an .ese.
+    pub fn procedure_waypoints(&self) -> BTreeSet<String> {
+        match self {
+            EuroscopeFile::Ese { raw, .. } => ese::procedure_waypoints(raw),
+            _ => BTreeSet::new(),
+        }
+    }
+
+    /// SID/STAR waypoints of an .ese not found in AIXM, see
+    /// [`ese::unknown_waypoints`].
+    pub fn unknown_waypoints(&self, aixm: &AixmIndex) -> BTreeSet<String> {
+        match self {
+            EuroscopeFile::Ese { raw, .. } => ese::unknown_waypoints(raw, aixm),
+            _ => BTreeSet::new(),
+        }
+    }
+
+    /// Positions of an .ese whose frequency could not be updated, as several
+    /// AIXM frequencies exist for their call sign. Returns the position name,
+    /// call sign and the AIXM frequencies.
+    pub fn ambiguous_frequencies(&self, aixm: &AixmIndex) -> Vec<(String, String, String)> {
+        match self {
+            EuroscopeFile::Ese { raw, .. } => ese::ambiguous_frequencies(raw, aixm),
+            _ => vec![],
+        }
+    }
+
+    /// Auxiliary file generated from the combined file, i.e. the FRA and
+    /// holding point flags next to the isec.txt for the FRA plugin.
+    pub fn generate_auxiliary(&self, aixm: &AixmIndex) -> Option<EuroscopeFile> {
+        let EuroscopeFile::Isec { path, content, .. } = self else {
+            return None;
+        };
+
+        let points = fra::classify_points(aixm);
+        Some(EuroscopeFile::Generated {
+            path: path.with_file_name(fra::FRA_FILE_NAME),
+            content: fra::serialize_points(&points, content.keys().map(String::as_str)),
+        })
+    }
+
+    /// Adds the full AIXM names of the VORs and NDBs of the .sct as labels or
+    /// returns them as a sidecar file, see [`NavaidNames`]. Returns the number
+    /// of labels added or moved.
+    pub fn add_navaid_names(
+        &mut self,
+        aixm: &AixmIndex,
+        navaid_names: &NavaidNames,
+    ) -> (usize, Option<EuroscopeFile>) {
+        let EuroscopeFile::Sct {
+            path,
+            content,
+            sections,
+            ..
+        } = self
+        else {
+            return (0, None);
+        };
+
+        match navaid_names {
+            NavaidNames::Labels { color } => {
+                let mut labels = sct::section_lines(&content.to_string(), "[LABELS]");
+                let labelled = names::label_navaids(content, aixm, &mut labels, color);
+                if labelled > 0 {
+                    sections.push(("[LABELS]", labels));
+                }
+                (labelled, None)
+            }
+            NavaidNames::Sidecar => (
+                0,
+                Some(EuroscopeFile::Generated {
+                    path: names::sidecar_path(path),
+                    content: names::render_sidecar(content, aixm),
+                }),
+            ),
+        }
+    }
+
+    /// Makes sure the sections controllers cannot work without survived the
+    /// changes, i.e. the `[POSITIONS]` of the .ese. Must pass before writing.
+    pub fn verify_preserved(&self) -> AiracUpdaterResult {
+        if let EuroscopeFile::Ese {
+            path,
+            raw,
+            changed: true,
+            positions,
+            ..
+        } = self
+        {
+            ese::verify_positions(path, positions, raw)?;
+        }
+
         Ok(())
     }
 
-    fn path(&self) -> &Path {
+    /// Number of airports, navaids and fixes, or intersections, in the file.
+    pub fn entity_count(&self) -> Option<usize> {
         match self {
-            EuroscopeFile::Sct { path, content: _ } => path,
-            EuroscopeFile::Ese { path, content: _ } => path,
-            EuroscopeFile::Isec { path, content: _ } => path,
+            EuroscopeFile::Sct { content, .. } => Some(
+                content.airports.len()
+                    + content.vors.len()
+                    + content.ndbs.len()
+                    + content.fixes.len(),
+            ),
+            EuroscopeFile::Isec { content, .. } => Some(content.len()),
+            EuroscopeFile::Ese { .. }
+            | EuroscopeFile::Rwy { .. }
+            | EuroscopeFile::Generated { .. }
+            | EuroscopeFile::TopSkyMaps { .. } => None,
         }
     }
+
+    /// Compares the airports, navaids and fixes of a .sct against AIXM, from
+    /// the `changes` of [`Self::combine_with_aixm`] before anything else is
+    /// done with them. Moves up to `tolerance` metres are in tolerance.
+    pub fn quality_counts(
+        &self,
+        aixm: &AixmIndex,
+        changes: &[Change],
+        tolerance: f64,
+    ) -> Option<QualityCounts> {
+        let EuroscopeFile::Sct { content, .. } = self else {
+            return None;
+        };
+        let added = changes
+            .iter()
+            .filter(|change| change.action == ChangeAction::Added)
+            .count();
+        let out_of_tolerance = changes
+            .iter()
+            .filter(|change| {
+                matches!(change.action, ChangeAction::Moved { distance, .. } if distance > tolerance)
+            })
+            .count();
+        let stale = sct::stale_entities(content, aixm).len();
+        let checked = self.entity_count()?.saturating_sub(added + stale);
+        Some(QualityCounts {
+            checked,
+            within_tolerance: checked.saturating_sub(out_of_tolerance),
+            missing: added,
+            stale,
+        })
+    }
+
+    /// Content to write by the matching [`OutputWriter`](crate::output::OutputWriter),
+    /// `None` if the file is left untouched.
+    pub fn serialize(&self) -> Option<Vec<u8>> {
+        output::serialize(self)
+    }
+
+    /// Built-in rendering of the combined file, `None` if it is unchanged.
+    pub fn render(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Sct {
+                content,
+                candidates,
+                sections,
+                passthrough,
+                original,
+                writer,
+                salvaged,
+                ..
+            } => {
+                let rendered = sections
+                    .iter()
+                    .map(|(section, lines)| (*section, lines))
+                    .chain(
+                        passthrough
+                            .iter()
+                            .map(|(section, lines)| (section.as_str(), lines)),
+                    )
+                    .fold(content.to_string(), |rendered, (section, lines)| {
+                        sct::replace_section(&rendered, section, lines)
+                    });
+                let rendered = sct::insert_candidates(&rendered, candidates);
+                Some(match writer {
+                    SctWriter::Full => rendered.into_bytes(),
+                    SctWriter::MinimalEdit if salvaged.is_empty() => {
+                        minimal_edit::patch(original, &rendered).into_bytes()
+                    }
+                    SctWriter::MinimalEdit => sct_salvage::unmark(&minimal_edit::patch(
+                        &sct_salvage::mark(original, salvaged),
+                        &rendered,
+                    ))
+                    .into_bytes(),
+                })
+            }
+            Self::Ese { raw, changed, .. } => changed.then(|| raw.clone()),
+            Self::Isec {
+                content, original, ..
+            } => Some(isec::render(original, content).into_bytes()),
+            Self::Rwy {
+                content, changed, ..
+            } => changed.then(|| content.clone()),
+            Self::Generated { content, .. } => Some(content.clone()),
+            Self::TopSkyMaps { airspaces, .. } => Some(topsky::render_maps(airspaces).into_bytes()),
+        }
+    }
+
+    /// Content as read, for the files kept to be patched on write.
+    pub fn original(&self) -> Option<&str> {
+        match self {
+            EuroscopeFile::Sct { original, .. } | EuroscopeFile::Isec { original, .. } => {
+                Some(original)
+            }
+            EuroscopeFile::Ese { .. }
+            | EuroscopeFile::Rwy { .. }
+            | EuroscopeFile::Generated { .. }
+            | EuroscopeFile::TopSkyMaps { .. } => None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            EuroscopeFile::Sct { path, .. } => path,
+            EuroscopeFile::Ese { path, .. } => path,
+            EuroscopeFile::Isec { path, .. } => path,
+            EuroscopeFile::Rwy { path, .. } => path,
+            EuroscopeFile::Generated { path, .. } | EuroscopeFile::TopSkyMaps { path, .. } => path,
+        }
+    }
+}
+
+/// Removes the fix additions matching `patterns` from `changes`, returning
+/// their designators and positions.
+fn take_excluded_fixes(patterns: &RegexSet, changes: &mut Vec<Change>) -> Vec<(String, Point)> {
+    let (excluded, kept) = std::mem::take(changes)
+        .into_iter()
+        .partition::<Vec<_>, _>(|change| {
+            change.kind == EntityKind::Fix
+                && change.action == ChangeAction::Added
+                && patterns.is_match(&change.designator)
+        });
+    *changes = kept;
+    excluded
+        .into_iter()
+        .map(|change| (change.designator, change.position))
+        .collect()
 }
@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use geo::Point;
+use serde::{Deserialize, Serialize};
+use vatsim_parser::sct::Sct;
+
+use crate::{aixm_index::AixmIndex, geometry::to_sct_coordinates};
+
+use super::EntityKind;
+
+/// Where the full names of the VORs and NDBs from AIXM are written to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "output")]
+pub enum NavaidNames {
+    /// `[LABELS]` entries of the .sct at the navaid positions, in `color`
+    Labels { color: String },
+    /// `<name>.sct.aau_navaid_names.txt` next to the .sct
+    Sidecar,
+}
+
+/// Full AIXM names of the VORs and NDBs, by designator and frequency as in
+/// the .sct.
+fn aixm_names(aixm: &AixmIndex) -> HashMap<(String, String), String> {
+    let vors = aixm.vors().filter_map(|(_, vor)| {
+        let time_slice = &vor.aixm_time_slice.aixm_vortime_slice;
+        Some((
+            (
+                time_slice.aixm_designator.clone(),
+                format!("{:.3}", time_slice.aixm_frequency.value),
+            ),
+            time_slice.aixm_name.clone()?,
+        ))
+    });
+    let ndbs = aixm.ndbs().filter_map(|(_, ndb)| {
+        let time_slice = &ndb.aixm_time_slice.aixm_ndbtime_slice;
+        Some((
+            (
+                time_slice.aixm_designator.clone(),
+                format!("{:.3}", time_slice.aixm_frequency.value),
+            ),
+            time_slice.aixm_name.clone()?,
+        ))
+    });
+    vors.chain(ndbs)
+        .filter(|(_, name)| !name.trim().is_empty())
+        .collect()
+}
+
+/// Navaids of the .sct with a name in AIXM, VORs first.
+fn named_navaids<'a>(
+    sct: &'a Sct,
+    aixm: &AixmIndex,
+) -> Vec<(EntityKind, &'a str, &'a str, Point, String)> {
+    let names = aixm_names(aixm);
+    let name = |designator: &str, frequency: &str| {
+        names
+            .get(&(designator.to_string(), frequency.to_string()))
+            .cloned()
+    };
+    let vors = sct.vors.iter().filter_map(|vor| {
+        Some((
+            EntityKind::Vor,
+            vor.designator.as_str(),
+            vor.frequency.as_str(),
+            vor.coordinate,
+            name(&vor.designator, &vor.frequency)?,
+        ))
+    });
+    let ndbs = sct.ndbs.iter().filter_map(|ndb| {
+        Some((
+            EntityKind::Ndb,
+            ndb.designator.as_str(),
+            ndb.frequency.as_str(),
+            ndb.coordinate,
+            name(&ndb.designator, &ndb.frequency)?,
+        ))
+    });
+    vors.chain(ndbs).collect()
+}
+
+/// Text of a `[LABELS]` line, i.e. `"TEXT" <lat> <lon> <color>`.
+fn label_text(line: &str) -> Option<&str> {
+    let (text, _) = line.trim_start().strip_prefix('"')?.split_once('"')?;
+    Some(text)
+}
+
+/// Moves the labels named like a navaid of the .sct to its position and adds
+/// the missing ones in `color`, returning how many were added or moved. Of
+/// several navaids with the same name, the first (i.e. the VOR) is labelled.
+pub(super) fn label_navaids(
+    sct: &Sct,
+    aixm: &AixmIndex,
+    labels: &mut Vec<String>,
+    color: &str,
+) -> usize {
+    let mut labelled = 0;
+    let mut seen = vec![];
+    for (_, _, _, coordinate, name) in named_navaids(sct, aixm) {
+        if seen.contains(&name) {
+            continue;
+        }
+        let (lat, lon) = to_sct_coordinates(coordinate);
+        let existing = labels.iter_mut().find(|line| {
+            label_text(line).is_some_and(|text| text.trim().eq_ignore_ascii_case(&name))
+        });
+        match existing {
+            Some(line) => {
+                let (text, rest) = line
+                    .trim_start()
+                    .strip_prefix('"')
+                    .and_then(|line| line.split_once('"'))
+                    .unwrap_or_default();
+                // keep the color (and any comment) of the existing label
+                let mut line_color = rest
+                    .split_whitespace()
+                    .skip(2)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if line_color.is_empty() {
+                    line_color = color.to_string();
+                }
+                let updated = format!("\"{text}\" {lat} {lon} {line_color}");
+                if *line != updated {
+                    *line = updated;
+                    labelled += 1;
+                }
+            }
+            None => {
+                labels.push(format!("\"{name}\" {lat} {lon} {color}"));
+                labelled += 1;
+            }
+        }
+        seen.push(name);
+    }
+    labelled
+}
+
+pub(super) fn sidecar_path(sct_path: &Path) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_navaid_names.txt");
+    sct_path.with_file_name(file_name)
+}
+
+/// Lists the navaids of the .sct with their full AIXM name, one
+/// `<type>;<designator>;<frequency>;<name>` line per navaid.
+pub(super) fn render_sidecar(sct: &Sct, aixm: &AixmIndex) -> Vec<u8> {
+    let mut content = "; type;designator;frequency;name\n".to_string();
+    for (kind, designator, frequency, _, name) in named_navaids(sct, aixm) {
+        content.push_str(&format!("{kind};{designator};{frequency};{name}\n"));
+    }
+    content.into_bytes()
+}
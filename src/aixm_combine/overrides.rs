@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use snafu::ResultExt as _;
+
+use crate::error::{
+    AiracUpdaterResult, IncompleteOverrideSnafu, ParseOverridesCsvSnafu, ParseOverridesTomlSnafu,
+    ReadOverridesSnafu,
+};
+
+use super::{
+    EntityKind,
+    transform::{Adjustment, Transform},
+};
+
+/// Entry of the designator override list: the entity is placed at `lat`/`lon`
+/// instead of its AIXM position, or never modified if they are unset.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct DesignatorOverride {
+    designator: String,
+    /// Restrict to one kind of entity, all kinds if unset
+    #[serde(default)]
+    kind: Option<EntityKind>,
+    #[serde(default)]
+    lat: Option<f64>,
+    #[serde(default)]
+    lon: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct OverridesFile {
+    #[serde(default, rename = "override")]
+    overrides: Vec<DesignatorOverride>,
+}
+
+/// Reads the user-maintained list of designators deviating from AIXM on
+/// purpose, e.g. fixes moved for display, from a `.csv` (columns
+/// `designator,kind,lat,lon`) or otherwise a TOML file of `[[override]]`
+/// tables, as transforms keeping the entities or placing them manually.
+pub fn load(path: &Path) -> AiracUpdaterResult<Vec<Transform>> {
+    let content = std::fs::read_to_string(path).context(ReadOverridesSnafu { path })?;
+    let overrides = if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+    {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<DesignatorOverride>, _>>()
+            .context(ParseOverridesCsvSnafu { path })?
+    } else {
+        toml::from_str::<OverridesFile>(&content)
+            .context(ParseOverridesTomlSnafu { path })?
+            .overrides
+    };
+
+    overrides
+        .into_iter()
+        .map(|entry| {
+            let adjustment = match (entry.lat, entry.lon) {
+                (Some(lat), Some(lon)) => Adjustment::Coordinate { lat, lon },
+                (None, None) => Adjustment::Keep,
+                _ => {
+                    return IncompleteOverrideSnafu {
+                        path: PathBuf::from(path),
+                        designator: entry.designator,
+                    }
+                    .fail();
+                }
+            };
+            Ok(Transform {
+                designator: entry.designator,
+                kind: entry.kind,
+                adjustment,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,60 @@
+use geo::{Distance as _, Geodesic, Point};
+use rstar::{AABB, RTree, primitives::GeomWithData};
+
+const METRES_PER_DEGREE: f64 = 111_320.0;
+
+type IndexedPoint = GeomWithData<[f64; 2], usize>;
+
+/// Spatial index over entity positions, keyed by their index in the entity
+/// list of the file being updated.
+pub struct ProximityIndex {
+    tree: RTree<IndexedPoint>,
+}
+
+impl ProximityIndex {
+    pub fn new(points: impl IntoIterator<Item = (usize, Point)>) -> Self {
+        Self {
+            tree: RTree::bulk_load(
+                points
+                    .into_iter()
+                    .map(|(i, point)| IndexedPoint::new([point.x(), point.y()], i))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Indices of all entities within `max_distance` metres of `point`,
+    /// closest first.
+    pub fn within(&self, point: Point, max_distance: f64) -> Vec<usize> {
+        let lat_delta = max_distance / METRES_PER_DEGREE;
+        let lng_delta = max_distance / (METRES_PER_DEGREE * point.y().to_radians().cos().max(0.01));
+        let envelope = AABB::from_corners(
+            [point.x() - lng_delta, point.y() - lat_delta],
+            [point.x() + lng_delta, point.y() + lat_delta],
+        );
+
+        let mut candidates = self
+            .tree
+            .locate_in_envelope(&envelope)
+            .map(|candidate| {
+                let [x, y] = *candidate.geom();
+                (candidate.data, Geodesic.distance(point, Point::new(x, y)))
+            })
+            .filter(|(_, distance)| *distance < max_distance)
+            .collect::<Vec<_>>();
+        candidates.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        candidates.into_iter().map(|(i, _)| i).collect()
+    }
+
+    pub fn insert(&mut self, i: usize, point: Point) {
+        self.tree
+            .insert(IndexedPoint::new([point.x(), point.y()], i));
+    }
+
+    pub fn relocate(&mut self, i: usize, from: Point, to: Point) {
+        self.tree
+            .remove(&IndexedPoint::new([from.x(), from.y()], i));
+        self.insert(i, to);
+    }
+}
@@ -0,0 +1,317 @@
+use std::{collections::HashMap, fmt};
+
+use aixm::Member;
+use geo::{Bearing as _, Distance as _, Geodesic, Line, Point};
+use tokio::sync::mpsc;
+use tracing::error;
+use vatsim_parser::sct::{Runway, Sct};
+
+use crate::{Message, aixm_index::AixmIndex, geometry::GmlPos, report::MOVED_THRESHOLD};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RunwayRename {
+    pub airport: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl fmt::Display for RunwayRename {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.airport, self.from, self.to)
+    }
+}
+
+struct AixmThreshold<'a> {
+    airport: &'a str,
+    runway: &'a str,
+    designator: &'a str,
+    coordinate: Point,
+}
+
+fn href_uuid(href: &str) -> &str {
+    href.trim_start_matches("urn:uuid:")
+}
+
+/// Resolves the AIXM runway thresholds (`AirportHeliport` <- `Runway` <-
+/// `RunwayDirection` <- `RunwayCentrelinePoint`) into airport, designator and
+/// position.
+fn aixm_thresholds(aixm: &AixmIndex) -> Vec<AixmThreshold<'_>> {
+    let mut airports = HashMap::new();
+    let mut runways = HashMap::new();
+    let mut directions = HashMap::new();
+    let mut centreline_points = vec![];
+
+    for member in aixm.members() {
+        match member {
+            Member::AirportHeliport(ad) => {
+                if let Some(icao) = &ad
+                    .aixm_time_slice
+                    .aixm_airport_heliport_time_slice
+                    .aixm_location_indicator_icao
+                {
+                    airports.insert(ad.gml_identifier.as_str(), icao.as_str());
+                }
+            }
+            Member::Runway(rwy) => {
+                runways.insert(
+                    rwy.gml_identifier.as_str(),
+                    href_uuid(
+                        &rwy.aixm_time_slice
+                            .aixm_runway_time_slice
+                            .aixm_associated_airport_heliport
+                            .xlink_href,
+                    ),
+                );
+            }
+            Member::RunwayDirection(rwy_dir) => {
+                let time_slice = &rwy_dir.aixm_time_slice.aixm_runway_direction_time_slice;
+                directions.insert(
+                    rwy_dir.gml_identifier.as_str(),
+                    (
+                        href_uuid(&time_slice.aixm_used_runway.xlink_href),
+                        time_slice.aixm_designator.as_str(),
+                    ),
+                );
+            }
+            Member::RunwayCentrelinePoint(point) => {
+                let time_slice = &point
+                    .aixm_time_slice
+                    .aixm_runway_centreline_point_time_slice;
+                if time_slice.aixm_role.as_deref() == Some("THR") {
+                    let location = &time_slice.aixm_location.aixm_elevated_point;
+                    if let Ok(coordinate) =
+                        GmlPos::new(&location.gml_pos, location.srs_name.as_deref()).to_point()
+                    {
+                        centreline_points
+                            .push((href_uuid(&time_slice.aixm_on_runway.xlink_href), coordinate));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    centreline_points
+        .into_iter()
+        .filter_map(|(direction_id, coordinate)| {
+            let (runway_id, designator) = directions.get(direction_id)?;
+            let airport_id = runways.get(runway_id)?;
+            let airport = airports.get(airport_id)?;
+            Some(AixmThreshold {
+                airport: *airport,
+                runway: *runway_id,
+                designator: *designator,
+                coordinate,
+            })
+        })
+        .collect()
+}
+
+/// Finds runways of the .sct whose thresholds are still at the same position
+/// in AIXM, up to `max_distance` metres, but carry a different designator
+/// there.
+pub fn detect_runway_renames(sct: &Sct, aixm: &AixmIndex, max_distance: f64) -> Vec<RunwayRename> {
+    let thresholds = aixm_thresholds(aixm);
+    let mut renames = vec![];
+
+    for runway in &sct.runways {
+        for (designator, coordinate) in [
+            (&runway.designators.0, Point::from(runway.location.start)),
+            (&runway.designators.1, Point::from(runway.location.end)),
+        ] {
+            if let Some(threshold) = thresholds.iter().find(|threshold| {
+                threshold.airport == runway.aerodrome
+                    && Geodesic.distance(threshold.coordinate, coordinate) < max_distance
+            }) {
+                let rename = RunwayRename {
+                    airport: runway.aerodrome.clone(),
+                    from: designator.clone(),
+                    to: threshold.designator.to_string(),
+                };
+                if rename.from != rename.to && !renames.contains(&rename) {
+                    renames.push(rename);
+                }
+            }
+        }
+    }
+
+    renames
+}
+
+/// Pairs the thresholds of each AIXM runway, skipping runways without
+/// exactly two.
+fn aixm_runways<'a>(thresholds: &'a [AixmThreshold<'a>]) -> Vec<[&'a AixmThreshold<'a>; 2]> {
+    let mut by_runway = HashMap::<_, Vec<_>>::new();
+    for threshold in thresholds {
+        by_runway
+            .entry(threshold.runway)
+            .or_default()
+            .push(threshold);
+    }
+
+    by_runway
+        .into_values()
+        .filter_map(|mut pair| {
+            pair.sort_by_key(|threshold| threshold.designator);
+            <[_; 2]>::try_from(pair).ok()
+        })
+        .collect()
+}
+
+/// Magnetic heading of a runway moved from `from` to `to`, keeping the
+/// magnetic variation the sector file used.
+fn shift_heading(heading: u32, from: Line, to: Line) -> u32 {
+    let bearing = |line: Line| Geodesic.bearing(Point::from(line.start), Point::from(line.end));
+    let shifted = f64::from(heading) + bearing(to) - bearing(from);
+    match shifted.rem_euclid(360.0).round() as u32 {
+        0 => 360,
+        heading => heading,
+    }
+}
+
+/// Heading of a new runway from its designator, e.g. `08L` -> `80`.
+fn designator_heading(designator: &str) -> u32 {
+    designator
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .parse::<u32>()
+        .map_or(0, |number| number * 10)
+}
+
+/// Moves the thresholds of the .sct runways to their AIXM position, matched
+/// by airport and designators, and adds the AIXM runways missing for
+/// airports of the .sct. Renames are expected to be applied already.
+pub fn update_runways(sct: &mut Sct, aixm: &AixmIndex, tx: &mpsc::Sender<Message>) {
+    let thresholds = aixm_thresholds(aixm);
+    let send = |message: Message| {
+        if let Err(e) = tx.blocking_send(message) {
+            error!("{e}");
+        }
+    };
+
+    for [first, second] in aixm_runways(&thresholds) {
+        let existing = sct.runways.iter_mut().find(|runway| {
+            runway.aerodrome == first.airport
+                && ((runway.designators.0 == first.designator
+                    && runway.designators.1 == second.designator)
+                    || (runway.designators.0 == second.designator
+                        && runway.designators.1 == first.designator))
+        });
+        if let Some(runway) = existing {
+            let (start, end) = if runway.designators.0 == first.designator {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            let location = Line::new(start.coordinate, end.coordinate);
+            let moved = Geodesic
+                .distance(Point::from(runway.location.start), start.coordinate)
+                .max(Geodesic.distance(Point::from(runway.location.end), end.coordinate));
+            if moved < MOVED_THRESHOLD {
+                continue;
+            }
+            let reverse = |line: Line| Line::new(line.end, line.start);
+            runway.headings = (
+                shift_heading(runway.headings.0, runway.location, location),
+                shift_heading(
+                    runway.headings.1,
+                    reverse(runway.location),
+                    reverse(location),
+                ),
+            );
+            runway.location = location;
+            send(Message::info(format!(
+                "Runway {} {}/{} thresholds moved by up to {moved:.0} m",
+                runway.aerodrome, runway.designators.0, runway.designators.1
+            )));
+        } else if sct
+            .airports
+            .iter()
+            .any(|airport| airport.designator == first.airport)
+        {
+            send(Message::info(format!(
+                "Adding new runway: {} {}/{}",
+                first.airport, first.designator, second.designator
+            )));
+            sct.runways.push(Runway {
+                designators: (first.designator.to_string(), second.designator.to_string()),
+                headings: (
+                    designator_heading(first.designator),
+                    designator_heading(second.designator),
+                ),
+                location: Line::new(first.coordinate, second.coordinate),
+                aerodrome: first.airport.to_string(),
+            });
+        }
+    }
+}
+
+fn find_rename<'a>(renames: &'a [RunwayRename], airport: &str, runway: &str) -> Option<&'a str> {
+    renames
+        .iter()
+        .find(|rename| rename.airport == airport && rename.from == runway)
+        .map(|rename| rename.to.as_str())
+}
+
+pub fn rename_runways_in_sct(sct: &mut Sct, renames: &[RunwayRename]) -> bool {
+    let mut changed = false;
+    for runway in &mut sct.runways {
+        for designator in [&mut runway.designators.0, &mut runway.designators.1] {
+            if let Some(to) = find_rename(renames, &runway.aerodrome, designator) {
+                *designator = to.to_string();
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+/// Replaces the runway field of `<PREFIX>:<airport>:<runway>:…` lines, leaving
+/// all other bytes untouched as .ese and .rwy files are not necessarily UTF-8.
+fn rename_runways_in_lines(
+    content: &mut Vec<u8>,
+    prefixes: &[&[u8]],
+    renames: &[RunwayRename],
+) -> bool {
+    let mut changed = false;
+    let mut patched = Vec::with_capacity(content.len());
+
+    for line in content.split_inclusive(|b| *b == b'\n') {
+        let mut fields = line.splitn(4, |b| *b == b':');
+        if let (Some(prefix), Some(airport), Some(runway), Some(rest)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        {
+            if prefixes.contains(&prefix) {
+                if let Some(to) = find_rename(
+                    renames,
+                    &String::from_utf8_lossy(airport),
+                    &String::from_utf8_lossy(runway),
+                ) {
+                    patched.extend_from_slice(prefix);
+                    patched.push(b':');
+                    patched.extend_from_slice(airport);
+                    patched.push(b':');
+                    patched.extend_from_slice(to.as_bytes());
+                    patched.push(b':');
+                    patched.extend_from_slice(rest);
+                    changed = true;
+                    continue;
+                }
+            }
+        }
+        patched.extend_from_slice(line);
+    }
+
+    if changed {
+        *content = patched;
+    }
+    changed
+}
+
+pub fn rename_runways_in_ese(content: &mut Vec<u8>, renames: &[RunwayRename]) -> bool {
+    rename_runways_in_lines(content, &[b"SID".as_slice(), b"STAR".as_slice()], renames)
+}
+
+pub fn rename_runways_in_rwy(content: &mut Vec<u8>, renames: &[RunwayRename]) -> bool {
+    rename_runways_in_lines(content, &[b"ACTIVE_RUNWAY".as_slice()], renames)
+}
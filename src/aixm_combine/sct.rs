@@ -1,5 +1,9 @@
-use aixm::{AixmAirportHeliport, AixmDesignatedPoint, AixmNdb, AixmVor, LocationType, Member};
-use geo::{Distance as _, Geodesic, point};
+use std::collections::HashSet;
+
+use aixm::{AixmAirportHeliport, AixmDesignatedPoint, AixmNdb, AixmVor};
+use geo::{Contains as _, ConvexHull as _, Distance as _, Geodesic, MultiPoint, Point};
+use itertools::Itertools as _;
+use snafu::ResultExt as _;
 use tokio::sync::mpsc;
 use tracing::error;
 use vatsim_parser::{
@@ -7,65 +11,265 @@ use vatsim_parser::{
     sct::{Airport, Sct},
 };
 
-use crate::Message;
+use crate::{
+    Message,
+    aixm_index::AixmIndex,
+    error::{AiracUpdaterResult, InvalidEntitySnafu},
+    geometry::{GmlPos, to_sct_coordinates},
+    report::{Change, ChangeAction, MOVED_THRESHOLD},
+};
+
+use super::{
+    AixmUpdateExt, CombineOptions, EntityKind, proximity::ProximityIndex, runway::update_runways,
+};
 
-use super::AixmUpdateExt;
+/// Sections of the .sct read and written by the parser, all others are lost
+/// on a rewrite unless passed through.
+const KNOWN_SECTIONS: [&str; 16] = [
+    "[INFO]",
+    "[VOR]",
+    "[NDB]",
+    "[FIXES]",
+    "[AIRPORT]",
+    "[RUNWAY]",
+    "[SID]",
+    "[STAR]",
+    "[ARTCC]",
+    "[ARTCC HIGH]",
+    "[ARTCC LOW]",
+    "[GEO]",
+    "[REGIONS]",
+    "[LABELS]",
+    "[HIGH AIRWAY]",
+    "[LOW AIRWAY]",
+];
 
-fn update_airports(sct: &mut Sct, aixm_airport: &AixmAirportHeliport, tx: mpsc::Sender<Message>) {
-    let (lat, lng) = aixm_airport
-        .aixm_time_slice
-        .aixm_airport_heliport_time_slice
-        .aixm_arp
-        .aixm_elevated_point
-        .gml_pos
-        .split_once(' ')
-        .unwrap();
-    let coordinate = point! {
-        x: lng.parse().unwrap(),
-        y: lat.parse().unwrap(),
+/// `[NAME]` in upper case, with or without brackets in the configuration.
+fn section_header(name: &str) -> String {
+    let name = name.trim().trim_start_matches('[').trim_end_matches(']');
+    format!("[{}]", name.trim().to_ascii_uppercase())
+}
+
+/// Reads the `passthrough` sections of the raw .sct, to be written back
+/// verbatim, and returns them together with the other sections unknown to the
+/// parser, which are lost when the .sct is rewritten.
+pub fn passthrough_sections(
+    raw: &str,
+    passthrough: &[String],
+) -> (Vec<(String, Vec<String>)>, Vec<String>) {
+    let passthrough = passthrough
+        .iter()
+        .map(|name| section_header(name))
+        .collect::<Vec<_>>();
+    let mut kept = vec![];
+    let mut dropped = vec![];
+    for header in raw
+        .lines()
+        .filter(|line| line.trim_start().starts_with('['))
+        .map(section_header)
+        .unique()
+    {
+        if passthrough.contains(&header) {
+            let lines = section_lines(raw, &header);
+            kept.push((header, lines));
+        } else if !KNOWN_SECTIONS.contains(&header.as_str()) {
+            dropped.push(header);
+        }
+    }
+    (kept, dropped)
+}
+
+/// Moves `current` to `coordinate`, recording the change if it is noticeable.
+fn relocate(
+    current: &mut Point,
+    coordinate: Point,
+    kind: EntityKind,
+    designator: &str,
+    dataset: &str,
+    changes: &mut Vec<Change>,
+) -> f64 {
+    let distance = Geodesic.distance(*current, coordinate);
+    if distance >= MOVED_THRESHOLD {
+        changes.push(Change::moved(
+            kind, designator, *current, coordinate, distance, dataset,
+        ));
+    }
+    *current = coordinate;
+    distance
+}
+
+/// Whether the AIXM airport without ICAO location indicator is the .sct
+/// airport `designator`, by its AIXM designator or name, ignoring case, spaces
+/// and punctuation.
+fn matches_without_icao(aixm_airport: &AixmAirportHeliport, designator: &str) -> bool {
+    let normalize = |value: &str| {
+        value
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_uppercase())
+            .collect::<String>()
     };
-    if let Some(ad) = sct.airports.iter_mut().find(|ad| {
-        aixm_airport
-            .aixm_time_slice
-            .aixm_airport_heliport_time_slice
-            .aixm_location_indicator_icao
-            .as_ref()
-            .is_some_and(|designator| *designator == ad.designator)
+    let designator = normalize(designator);
+    let time_slice = &aixm_airport
+        .aixm_time_slice
+        .aixm_airport_heliport_time_slice;
+    !designator.is_empty()
+        && (normalize(&time_slice.aixm_designator) == designator
+            || time_slice
+                .aixm_name
+                .as_deref()
+                .is_some_and(|name| normalize(name) == designator))
+}
+
+/// Value read from an AIXM entity, e.g. its position, `None` if the entity is
+/// malformed, which is reported as a warning so that one bad record only
+/// skips its entity instead of the whole file.
+pub(super) fn skip_invalid<T>(
+    result: AiracUpdaterResult<T>,
+    kind: EntityKind,
+    designator: &str,
+    dataset: &str,
+    tx: &mpsc::Sender<Message>,
+) -> Option<T> {
+    let error = match result.context(InvalidEntitySnafu {
+        kind,
+        designator,
+        dataset,
     }) {
-        ad.coordinate = coordinate;
-    } else if let Some(designator) = &aixm_airport
+        Ok(value) => return Some(value),
+        Err(error) => error,
+    };
+    if let Err(e) = tx.blocking_send(Message::skipped(&error)) {
+        error!("{e}");
+    }
+    None
+}
+
+fn update_airports(
+    sct: &mut Sct,
+    aixm: &AixmIndex,
+    (dataset, aixm_airport): (&str, &AixmAirportHeliport),
+    warning_distance: f64,
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: mpsc::Sender<Message>,
+) {
+    let time_slice = &aixm_airport
         .aixm_time_slice
-        .aixm_airport_heliport_time_slice
+        .aixm_airport_heliport_time_slice;
+    let designator = time_slice
         .aixm_location_indicator_icao
-    {
-        if let Err(e) =
-            tx.blocking_send(Message::debug(format!("Adding new airport: {designator}")))
+        .as_deref()
+        .unwrap_or(&time_slice.aixm_designator);
+    let Some((point, aixm_coordinate)) = skip_invalid(
+        aixm.airport_position(aixm_airport),
+        EntityKind::Airport,
+        designator,
+        dataset,
+        &tx,
+    ) else {
+        return;
+    };
+    if let Some(icao) = &time_slice.aixm_location_indicator_icao {
+        if point != aixm.airport_reference().point(icao) {
+            if let Err(e) = tx.blocking_send(Message::debug(format!(
+                "No tower position for {icao} in AIXM, using the ARP"
+            ))) {
+                error!("{e}");
+            }
+        }
+        let Some(coordinate) = options.position(EntityKind::Airport, icao, aixm_coordinate, &tx)
+        else {
+            return;
+        };
+        if let Some(ad) = sct.airports.iter_mut().find(|ad| *icao == ad.designator) {
+            let distance = relocate(
+                &mut ad.coordinate,
+                coordinate,
+                EntityKind::Airport,
+                &ad.designator,
+                dataset,
+                changes,
+            );
+            if distance > warning_distance {
+                if let Err(e) = tx.blocking_send(Message::warn(format!(
+                    "Airport {} moved by {distance:.0} m, check its AIXM position",
+                    ad.designator
+                ))) {
+                    error!("{e}");
+                }
+            }
+        } else {
+            if let Err(e) = tx.blocking_send(Message::debug(format!("Adding new airport: {icao}")))
+            {
+                error!("{e}");
+            }
+            changes.push(Change::added(
+                EntityKind::Airport,
+                icao,
+                coordinate,
+                dataset,
+            ));
+            sct.airports.push(Airport {
+                designator: icao.clone(),
+                coordinate,
+                ctr_airspace: aixm.ctr_class(coordinate).to_string(),
+            });
+        }
+    } else if aixm.match_airports_without_icao() {
+        // only existing airports, new ones are never added by the fuzzier match
+        if let Some(ad) = sct
+            .airports
+            .iter_mut()
+            .find(|ad| matches_without_icao(aixm_airport, &ad.designator))
         {
-            error!("{e}");
+            if let Err(e) = tx.blocking_send(Message::debug(format!(
+                "Matched airport {} without ICAO location indicator by designator or name",
+                ad.designator
+            ))) {
+                error!("{e}");
+            }
+            // adjusted by the designator it has in the .sct
+            if let Some(coordinate) =
+                options.position(EntityKind::Airport, &ad.designator, aixm_coordinate, &tx)
+            {
+                relocate(
+                    &mut ad.coordinate,
+                    coordinate,
+                    EntityKind::Airport,
+                    &ad.designator,
+                    dataset,
+                    changes,
+                );
+            }
         }
-        sct.airports.push(Airport {
-            designator: designator.clone(),
-            coordinate,
-            ctr_airspace: "D".to_string(),
-        });
     }
 }
 
-fn update_vors(sct: &mut Sct, aixm_vor: &AixmVor, tx: mpsc::Sender<Message>) {
-    let (lat, lng) = (match &aixm_vor
-        .aixm_time_slice
-        .aixm_vortime_slice
-        .aixm_location
-        .location
-    {
-        LocationType::ElevatedPoint(ep) => &ep.gml_pos,
-        LocationType::Point(p) => &p.gml_pos,
-    })
-    .split_once(' ')
-    .unwrap();
-    let coordinate = point! {
-        x: lng.parse().unwrap(),
-        y: lat.parse().unwrap(),
+fn update_vors(
+    sct: &mut Sct,
+    (dataset, aixm_vor): (&str, &AixmVor),
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: mpsc::Sender<Message>,
+) {
+    let time_slice = &aixm_vor.aixm_time_slice.aixm_vortime_slice;
+    let Some(coordinate) = skip_invalid(
+        GmlPos::from(&time_slice.aixm_location.location).to_point(),
+        EntityKind::Vor,
+        &time_slice.aixm_designator,
+        dataset,
+        &tx,
+    ) else {
+        return;
+    };
+    let Some(coordinate) = options.position(
+        EntityKind::Vor,
+        &time_slice.aixm_designator,
+        coordinate,
+        &tx,
+    ) else {
+        return;
     };
     if let Some(vor) = sct.vors.iter_mut().find(|vor| {
         aixm_vor.aixm_time_slice.aixm_vortime_slice.aixm_designator == vor.designator
@@ -78,7 +282,14 @@ fn update_vors(sct: &mut Sct, aixm_vor: &AixmVor, tx: mpsc::Sender<Message>) {
                     .value
             ) == vor.frequency
     }) {
-        vor.coordinate = coordinate;
+        relocate(
+            &mut vor.coordinate,
+            coordinate,
+            EntityKind::Vor,
+            &vor.designator,
+            dataset,
+            changes,
+        );
     } else {
         if let Err(e) = tx.blocking_send(Message::debug(format!(
             "Adding new VOR: {} {:.3}",
@@ -92,6 +303,12 @@ fn update_vors(sct: &mut Sct, aixm_vor: &AixmVor, tx: mpsc::Sender<Message>) {
             error!("{e}");
         }
 
+        changes.push(Change::added(
+            EntityKind::Vor,
+            &aixm_vor.aixm_time_slice.aixm_vortime_slice.aixm_designator,
+            coordinate,
+            dataset,
+        ));
         sct.vors.push(VOR {
             designator: aixm_vor
                 .aixm_time_slice
@@ -111,21 +328,30 @@ fn update_vors(sct: &mut Sct, aixm_vor: &AixmVor, tx: mpsc::Sender<Message>) {
     }
 }
 
-fn update_ndbs(sct: &mut Sct, aixm_ndb: &AixmNdb, tx: mpsc::Sender<Message>) {
-    let (lat, lng) = (match &aixm_ndb
-        .aixm_time_slice
-        .aixm_ndbtime_slice
-        .aixm_location
-        .location
-    {
-        LocationType::ElevatedPoint(ep) => &ep.gml_pos,
-        LocationType::Point(p) => &p.gml_pos,
-    })
-    .split_once(' ')
-    .unwrap();
-    let coordinate = point! {
-        x: lng.parse().unwrap(),
-        y: lat.parse().unwrap(),
+fn update_ndbs(
+    sct: &mut Sct,
+    (dataset, aixm_ndb): (&str, &AixmNdb),
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: mpsc::Sender<Message>,
+) {
+    let time_slice = &aixm_ndb.aixm_time_slice.aixm_ndbtime_slice;
+    let Some(coordinate) = skip_invalid(
+        GmlPos::from(&time_slice.aixm_location.location).to_point(),
+        EntityKind::Ndb,
+        &time_slice.aixm_designator,
+        dataset,
+        &tx,
+    ) else {
+        return;
+    };
+    let Some(coordinate) = options.position(
+        EntityKind::Ndb,
+        &time_slice.aixm_designator,
+        coordinate,
+        &tx,
+    ) else {
+        return;
     };
     if let Some(ndb) = sct.ndbs.iter_mut().find(|ndb| {
         aixm_ndb.aixm_time_slice.aixm_ndbtime_slice.aixm_designator == ndb.designator
@@ -138,7 +364,14 @@ fn update_ndbs(sct: &mut Sct, aixm_ndb: &AixmNdb, tx: mpsc::Sender<Message>) {
                     .value
             ) == ndb.frequency
     }) {
-        ndb.coordinate = coordinate;
+        relocate(
+            &mut ndb.coordinate,
+            coordinate,
+            EntityKind::Ndb,
+            &ndb.designator,
+            dataset,
+            changes,
+        );
     } else {
         if let Err(e) = tx.blocking_send(Message::debug(format!(
             "Adding new NDB: {} {:.3}",
@@ -151,6 +384,12 @@ fn update_ndbs(sct: &mut Sct, aixm_ndb: &AixmNdb, tx: mpsc::Sender<Message>) {
         ))) {
             error!("{e}");
         }
+        changes.push(Change::added(
+            EntityKind::Ndb,
+            &aixm_ndb.aixm_time_slice.aixm_ndbtime_slice.aixm_designator,
+            coordinate,
+            dataset,
+        ));
         sct.ndbs.push(NDB {
             designator: aixm_ndb
                 .aixm_time_slice
@@ -170,31 +409,54 @@ fn update_ndbs(sct: &mut Sct, aixm_ndb: &AixmNdb, tx: mpsc::Sender<Message>) {
     }
 }
 
-fn update_fixes(sct: &mut Sct, aixm_fix: &AixmDesignatedPoint, tx: mpsc::Sender<Message>) {
-    let (lat, lng) = (match &aixm_fix
-        .aixm_time_slice
-        .aixm_designated_point_time_slice
-        .aixm_location
-        .location
-    {
-        LocationType::ElevatedPoint(ep) => &ep.gml_pos,
-        LocationType::Point(p) => &p.gml_pos,
-    })
-    .split_once(' ')
-    .unwrap();
-    let coordinate = point! {
-        x: lng.parse().unwrap(),
-        y: lat.parse().unwrap(),
+fn update_fixes(
+    sct: &mut Sct,
+    fix_index: &mut ProximityIndex,
+    (dataset, aixm_fix): (&str, &AixmDesignatedPoint),
+    max_distance: f64,
+    options: &CombineOptions,
+    changes: &mut Vec<Change>,
+    tx: mpsc::Sender<Message>,
+) {
+    let time_slice = &aixm_fix.aixm_time_slice.aixm_designated_point_time_slice;
+    let Some(coordinate) = skip_invalid(
+        GmlPos::from(&time_slice.aixm_location.location).to_point(),
+        EntityKind::Fix,
+        &time_slice.aixm_designator,
+        dataset,
+        &tx,
+    ) else {
+        return;
     };
-    if let Some(fix) = sct.fixes.iter_mut().find(|fix| {
-        aixm_fix
-            .aixm_time_slice
-            .aixm_designated_point_time_slice
-            .aixm_designator
-            == fix.designator
-            && Geodesic.distance(coordinate, fix.coordinate) < 1000.0
-    }) {
-        fix.coordinate = coordinate;
+    let Some(coordinate) = options.position(
+        EntityKind::Fix,
+        &time_slice.aixm_designator,
+        coordinate,
+        &tx,
+    ) else {
+        return;
+    };
+    if let Some(i) = fix_index
+        .within(coordinate, max_distance)
+        .into_iter()
+        .find(|i| {
+            aixm_fix
+                .aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_designator
+                == sct.fixes[*i].designator
+        })
+    {
+        fix_index.relocate(i, sct.fixes[i].coordinate, coordinate);
+        let fix = &mut sct.fixes[i];
+        relocate(
+            &mut fix.coordinate,
+            coordinate,
+            EntityKind::Fix,
+            &fix.designator,
+            dataset,
+            changes,
+        );
     } else if aixm_fix
         .aixm_time_slice
         .aixm_designated_point_time_slice
@@ -218,6 +480,16 @@ fn update_fixes(sct: &mut Sct, aixm_fix: &AixmDesignatedPoint, tx: mpsc::Sender<
         ))) {
             error!("{e}");
         }
+        changes.push(Change::added(
+            EntityKind::Fix,
+            &aixm_fix
+                .aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_designator,
+            coordinate,
+            dataset,
+        ));
+        fix_index.insert(sct.fixes.len(), coordinate);
         sct.fixes.push(Fix {
             designator: aixm_fix
                 .aixm_time_slice
@@ -230,25 +502,560 @@ fn update_fixes(sct: &mut Sct, aixm_fix: &AixmDesignatedPoint, tx: mpsc::Sender<
 }
 
 impl AixmUpdateExt for Sct {
-    fn update_from_aixm(mut self, aixm: &[Member], tx: mpsc::Sender<Message>) -> Self {
-        for data in aixm {
-            match data {
-                Member::AirportHeliport(aixm_airport_heliport) => {
-                    update_airports(&mut self, aixm_airport_heliport, tx.clone());
-                }
-                Member::Vor(aixm_vor) => {
-                    update_vors(&mut self, aixm_vor, tx.clone());
-                }
-                Member::Ndb(aixm_ndb) => {
-                    update_ndbs(&mut self, aixm_ndb, tx.clone());
+    fn update_from_aixm(
+        mut self,
+        aixm: &AixmIndex,
+        options: &CombineOptions,
+        changes: &mut Vec<Change>,
+        tx: mpsc::Sender<Message>,
+    ) -> Self {
+        let entity_types = aixm.entity_types();
+        if entity_types.airports {
+            for aixm_airport_heliport in aixm.airports() {
+                update_airports(
+                    &mut self,
+                    aixm,
+                    aixm_airport_heliport,
+                    options.airport_warning_distance,
+                    options,
+                    changes,
+                    tx.clone(),
+                );
+            }
+        }
+        if entity_types.vors {
+            for aixm_vor in aixm.vors() {
+                update_vors(&mut self, aixm_vor, options, changes, tx.clone());
+            }
+        }
+        if entity_types.ndbs {
+            for aixm_ndb in aixm.ndbs() {
+                update_ndbs(&mut self, aixm_ndb, options, changes, tx.clone());
+            }
+        }
+        if entity_types.fixes {
+            let mut fix_index = ProximityIndex::new(
+                self.fixes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, fix)| (i, fix.coordinate)),
+            );
+            for aixm_fix in aixm.designated_points() {
+                update_fixes(
+                    &mut self,
+                    &mut fix_index,
+                    aixm_fix,
+                    options.distances.fix,
+                    options,
+                    changes,
+                    tx.clone(),
+                );
+            }
+        }
+        if entity_types.runways {
+            update_runways(&mut self, aixm, &tx);
+        }
+
+        self
+    }
+}
+
+/// VOR, NDB or fix new in AIXM, held back as a commented-out line at the end
+/// of its section for a human to review.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    section: &'static str,
+    line: String,
+    designator: String,
+    position: Point,
+}
+
+/// Removes the VORs, NDBs and fixes added according to `changes` from `sct`
+/// again, marking the changes as candidates.
+pub(super) fn demote_additions(sct: &mut Sct, changes: &mut [Change]) -> Vec<Candidate> {
+    let mut candidates = vec![];
+    for change in changes
+        .iter_mut()
+        .filter(|change| change.action == ChangeAction::Added)
+    {
+        let (lat, lon) = to_sct_coordinates(change.position);
+        let is_added = |designator: &str, coordinate: Point| {
+            designator == change.designator && coordinate == change.position
+        };
+        let candidate = match change.kind {
+            EntityKind::Vor => sct
+                .vors
+                .iter()
+                .rposition(|vor| is_added(&vor.designator, vor.coordinate))
+                .map(|i| {
+                    let vor = sct.vors.remove(i);
+                    (
+                        "[VOR]",
+                        format!("{} {} {lat} {lon}", vor.designator, vor.frequency),
+                    )
+                }),
+            EntityKind::Ndb => sct
+                .ndbs
+                .iter()
+                .rposition(|ndb| is_added(&ndb.designator, ndb.coordinate))
+                .map(|i| {
+                    let ndb = sct.ndbs.remove(i);
+                    (
+                        "[NDB]",
+                        format!("{} {} {lat} {lon}", ndb.designator, ndb.frequency),
+                    )
+                }),
+            EntityKind::Fix => sct
+                .fixes
+                .iter()
+                .rposition(|fix| is_added(&fix.designator, fix.coordinate))
+                .map(|i| {
+                    let fix = sct.fixes.remove(i);
+                    ("[FIXES]", format!("{} {lat} {lon}", fix.designator))
+                }),
+            EntityKind::Airport => None,
+        };
+        if let Some((section, line)) = candidate {
+            change.action = ChangeAction::Candidate;
+            candidates.push(Candidate {
+                section,
+                line: format!("; CANDIDATE {line}"),
+                designator: change.designator.clone(),
+                position: change.position,
+            });
+        }
+    }
+
+    candidates
+}
+
+/// Appends the candidates of `section` to `lines`, before its trailing blank
+/// lines.
+fn push_candidates(lines: &mut Vec<String>, section: &str, candidates: &[Candidate]) {
+    let end = lines.len()
+        - lines
+            .iter()
+            .rev()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+    lines.splice(
+        end..end,
+        candidates
+            .iter()
+            .filter(|candidate| candidate.section.eq_ignore_ascii_case(section))
+            .map(|candidate| candidate.line.clone()),
+    );
+}
+
+/// Writes the candidates at the end of their sections of the rendered .sct,
+/// adding sections it does not have yet.
+pub(super) fn insert_candidates(sct: &str, candidates: &[Candidate]) -> String {
+    if candidates.is_empty() {
+        return sct.to_string();
+    }
+
+    let mut lines = vec![];
+    let mut seen = vec![];
+    let mut section = None::<String>;
+    for line in sct.lines() {
+        if line.trim_start().starts_with('[') {
+            if let Some(previous) = section.replace(line.trim().to_ascii_uppercase()) {
+                if !seen.contains(&previous) {
+                    push_candidates(&mut lines, &previous, candidates);
+                    seen.push(previous);
                 }
-                Member::DesignatedPoint(aixm_fix) => {
-                    update_fixes(&mut self, aixm_fix, tx.clone());
+            }
+        }
+        lines.push(line.to_string());
+    }
+    if let Some(last) = section {
+        if !seen.contains(&last) {
+            push_candidates(&mut lines, &last, candidates);
+            seen.push(last);
+        }
+    }
+    for missing in candidates
+        .iter()
+        .map(|candidate| candidate.section)
+        .unique()
+        .filter(|section| !seen.iter().any(|seen| seen == section))
+    {
+        lines.push(String::new());
+        lines.push(missing.to_string());
+        push_candidates(&mut lines, missing, candidates);
+    }
+
+    let newline = if sct.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut content = lines.join(newline);
+    if sct.ends_with('\n') {
+        content.push_str(newline);
+    }
+    content
+}
+
+/// Lines of `section` in the rendered .sct, without its trailing blank lines.
+pub(super) fn section_lines(sct: &str, section: &str) -> Vec<String> {
+    let mut lines = sct
+        .lines()
+        .skip_while(|line| !line.trim().eq_ignore_ascii_case(section))
+        .skip(1)
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+/// Replaces the content of `section` in the rendered .sct with `lines`,
+/// keeping the blank lines before the next section, or appends the section if
+/// it does not exist yet.
+pub(super) fn replace_section(sct: &str, section: &str, lines: &[String]) -> String {
+    let is_header = |line: &str| line.trim_start().starts_with('[');
+    let mut content = vec![];
+    let mut replaced = false;
+    let mut in_section = false;
+    let mut blank_lines = 0;
+    for line in sct.lines() {
+        if is_header(line) {
+            if in_section {
+                content.extend(std::iter::repeat_n(String::new(), blank_lines));
+            }
+            in_section = !replaced && line.trim().eq_ignore_ascii_case(section);
+            content.push(line.to_string());
+            if in_section {
+                content.extend(lines.iter().cloned());
+                replaced = true;
+                blank_lines = 0;
+            }
+        } else if in_section {
+            // only the trailing blank lines are kept
+            if line.trim().is_empty() {
+                blank_lines += 1;
+            } else {
+                blank_lines = 0;
+            }
+        } else {
+            content.push(line.to_string());
+        }
+    }
+    if in_section {
+        content.extend(std::iter::repeat_n(String::new(), blank_lines));
+    }
+    if !replaced {
+        content.push(String::new());
+        content.push(section.to_string());
+        content.extend(lines.iter().cloned());
+    }
+
+    let newline = if sct.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut replaced_content = content.join(newline);
+    if sct.ends_with('\n') {
+        replaced_content.push_str(newline);
+    }
+    replaced_content
+}
+
+/// VORs, NDBs and fixes of the .sct without an AIXM counterpart of the same
+/// kind and designator (fixes may also be navaids), inside the area covered by
+/// AIXM, i.e. the convex hull of its positions. Kinds AIXM has none of, e.g.
+/// because their dataset is not loaded, or that are not updated are never
+/// stale.
+pub(super) fn stale_entities(sct: &Sct, aixm: &AixmIndex) -> Vec<(EntityKind, String, Point)> {
+    let locations = aixm.locations().collect::<Vec<_>>();
+    let known = locations
+        .iter()
+        .map(|(kind, designator, ..)| (*kind, designator.as_str()))
+        .collect::<HashSet<_>>();
+    let kinds = known.iter().map(|(kind, _)| *kind).collect::<HashSet<_>>();
+    let area = MultiPoint::from(
+        locations
+            .iter()
+            .map(|(_, _, position, _)| *position)
+            .collect::<Vec<_>>(),
+    )
+    .convex_hull();
+
+    let is_known = |kind: EntityKind, designator: &str| match kind {
+        EntityKind::Vor => known.contains(&("VOR", designator)),
+        EntityKind::Ndb => known.contains(&("NDB", designator)),
+        EntityKind::Fix => ["Fix", "VOR", "NDB"]
+            .iter()
+            .any(|kind| known.contains(&(*kind, designator))),
+        EntityKind::Airport => true,
+    };
+    let is_loaded = |kind: EntityKind| {
+        aixm.entity_types().contains(kind)
+            && match kind {
+                EntityKind::Vor => kinds.contains("VOR"),
+                EntityKind::Ndb => kinds.contains("NDB"),
+                EntityKind::Fix => kinds.contains("Fix"),
+                EntityKind::Airport => false,
+            }
+    };
+
+    sct.vors
+        .iter()
+        .map(|vor| (EntityKind::Vor, &vor.designator, vor.coordinate))
+        .chain(
+            sct.ndbs
+                .iter()
+                .map(|ndb| (EntityKind::Ndb, &ndb.designator, ndb.coordinate)),
+        )
+        .chain(
+            sct.fixes
+                .iter()
+                .map(|fix| (EntityKind::Fix, &fix.designator, fix.coordinate)),
+        )
+        .filter(|(kind, designator, position)| {
+            is_loaded(*kind) && !is_known(*kind, designator) && area.contains(position)
+        })
+        .map(|(kind, designator, position)| (kind, designator.clone(), position))
+        .collect()
+}
+
+/// VOR, NDB or fix removed as stale, kept to put it back if the removal is
+/// rejected in review.
+#[derive(Debug)]
+pub enum RemovedEntity {
+    Vor(VOR),
+    Ndb(NDB),
+    Fix(Fix),
+}
+
+impl RemovedEntity {
+    fn is_removed_by(&self, change: &Change) -> bool {
+        let (kind, designator, coordinate) = match self {
+            RemovedEntity::Vor(vor) => (EntityKind::Vor, &vor.designator, vor.coordinate),
+            RemovedEntity::Ndb(ndb) => (EntityKind::Ndb, &ndb.designator, ndb.coordinate),
+            RemovedEntity::Fix(fix) => (EntityKind::Fix, &fix.designator, fix.coordinate),
+        };
+        kind == change.kind && *designator == change.designator && coordinate == change.position
+    }
+}
+
+pub(super) fn remove_entities(
+    sct: &mut Sct,
+    entities: &[(EntityKind, String, Point)],
+) -> Vec<RemovedEntity> {
+    let is_listed = |kind: EntityKind, designator: &str, position: Point| {
+        entities
+            .iter()
+            .any(|entity| entity.0 == kind && entity.1 == designator && entity.2 == position)
+    };
+    let (vors, kept) = std::mem::take(&mut sct.vors)
+        .into_iter()
+        .partition::<Vec<_>, _>(|vor| is_listed(EntityKind::Vor, &vor.designator, vor.coordinate));
+    sct.vors = kept;
+    let (ndbs, kept) = std::mem::take(&mut sct.ndbs)
+        .into_iter()
+        .partition::<Vec<_>, _>(|ndb| is_listed(EntityKind::Ndb, &ndb.designator, ndb.coordinate));
+    sct.ndbs = kept;
+    let (fixes, kept) = std::mem::take(&mut sct.fixes)
+        .into_iter()
+        .partition::<Vec<_>, _>(|fix| is_listed(EntityKind::Fix, &fix.designator, fix.coordinate));
+    sct.fixes = kept;
+
+    vors.into_iter()
+        .map(RemovedEntity::Vor)
+        .chain(ndbs.into_iter().map(RemovedEntity::Ndb))
+        .chain(fixes.into_iter().map(RemovedEntity::Fix))
+        .collect()
+}
+
+/// Designators and positions of the entities of `kind`.
+fn coordinates_mut(sct: &mut Sct, kind: EntityKind) -> Vec<(&String, &mut Point)> {
+    match kind {
+        EntityKind::Airport => sct
+            .airports
+            .iter_mut()
+            .map(|ad| (&ad.designator, &mut ad.coordinate))
+            .collect(),
+        EntityKind::Vor => sct
+            .vors
+            .iter_mut()
+            .map(|vor| (&vor.designator, &mut vor.coordinate))
+            .collect(),
+        EntityKind::Ndb => sct
+            .ndbs
+            .iter_mut()
+            .map(|ndb| (&ndb.designator, &mut ndb.coordinate))
+            .collect(),
+        EntityKind::Fix => sct
+            .fixes
+            .iter_mut()
+            .map(|fix| (&fix.designator, &mut fix.coordinate))
+            .collect(),
+    }
+}
+
+/// Undoes a change rejected in review: additions are taken out again, moved
+/// entities go back to their previous position and removed ones are put back.
+pub(super) fn revert_change(
+    sct: &mut Sct,
+    candidates: &mut Vec<Candidate>,
+    removed: &mut Vec<RemovedEntity>,
+    change: &Change,
+) {
+    let is_changed = |designator: &str, coordinate: Point| {
+        designator == change.designator && coordinate == change.position
+    };
+    match change.action {
+        ChangeAction::Added => match change.kind {
+            EntityKind::Airport => sct
+                .airports
+                .retain(|ad| !is_changed(&ad.designator, ad.coordinate)),
+            EntityKind::Vor => sct
+                .vors
+                .retain(|vor| !is_changed(&vor.designator, vor.coordinate)),
+            EntityKind::Ndb => sct
+                .ndbs
+                .retain(|ndb| !is_changed(&ndb.designator, ndb.coordinate)),
+            EntityKind::Fix => sct
+                .fixes
+                .retain(|fix| !is_changed(&fix.designator, fix.coordinate)),
+        },
+        ChangeAction::Candidate => {
+            candidates.retain(|candidate| !is_changed(&candidate.designator, candidate.position));
+        }
+        ChangeAction::Moved { from, .. } => {
+            if let Some((_, coordinate)) = coordinates_mut(sct, change.kind)
+                .into_iter()
+                .find(|(designator, coordinate)| is_changed(designator, **coordinate))
+            {
+                *coordinate = from;
+            }
+        }
+        ChangeAction::Removed => {
+            if let Some(i) = removed
+                .iter()
+                .position(|entity| entity.is_removed_by(change))
+            {
+                match removed.remove(i) {
+                    RemovedEntity::Vor(vor) => sct.vors.push(vor),
+                    RemovedEntity::Ndb(ndb) => sct.ndbs.push(ndb),
+                    RemovedEntity::Fix(fix) => sct.fixes.push(fix),
                 }
-                _ => (),
             }
         }
+    }
+}
 
-        self
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{aixm_combine::EuroscopeFile, config::SctWriter};
+
+    const ORIGINAL: &str = "\
+[INFO]
+Munich
+EDMM_CTR
+EDDM
+N048.21.14.000
+E011.47.10.000
+60
+40
+-3
+1
+
+[VOR]
+MBG 115.400 N048.19.00.000 E011.06.00.000
+
+[FREETEXT-DATA]
+; kept as written
+N048.21.14.000:E011.47.10.000:EDDM:Apron  East
+
+[MY-EXTENSION]
+something the parser does not know
+
+[NDB]
+MNW 338.000 N048.06.00.000 E011.36.00.000
+";
+
+    const FREETEXT_DATA: &str = "\
+[FREETEXT-DATA]
+; kept as written
+N048.21.14.000:E011.47.10.000:EDDM:Apron  East
+";
+
+    /// `ORIGINAL` as loaded with `passthrough`, written by `writer`
+    fn write(passthrough: &[&str], writer: SctWriter) -> String {
+        let passthrough = passthrough
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let file = EuroscopeFile::Sct {
+            path: PathBuf::from("EDMM.sct"),
+            content: Box::new(Sct::parse(ORIGINAL.as_bytes()).unwrap()),
+            candidates: vec![],
+            sections: vec![],
+            passthrough: passthrough_sections(ORIGINAL, &passthrough).0,
+            removed: vec![],
+            original: ORIGINAL.to_string(),
+            writer,
+            salvaged: vec![],
+        };
+        String::from_utf8(file.serialize().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn unknown_sections_are_passed_through_or_reported() {
+        let (kept, dropped) = passthrough_sections(ORIGINAL, &["freetext-data".to_string()]);
+        assert_eq!(
+            kept,
+            [(
+                "[FREETEXT-DATA]".to_string(),
+                vec![
+                    "; kept as written".to_string(),
+                    "N048.21.14.000:E011.47.10.000:EDDM:Apron  East".to_string(),
+                ]
+            )]
+        );
+        assert_eq!(dropped, ["[MY-EXTENSION]"]);
+    }
+
+    #[test]
+    fn full_writer_appends_passthrough_sections_verbatim() {
+        let written = write(&["FREETEXT-DATA"], SctWriter::Full);
+        assert!(
+            written.ends_with(&format!("\n{FREETEXT_DATA}")),
+            "{written}"
+        );
+        assert!(!written.contains("[MY-EXTENSION]"), "{written}");
+    }
+
+    #[test]
+    fn minimal_edit_keeps_passthrough_sections_in_place() {
+        let written = write(&["FREETEXT-DATA", "MY-EXTENSION"], SctWriter::MinimalEdit);
+        let section = written.find(FREETEXT_DATA).expect(&written);
+        assert!(written[..section].contains("[VOR]"), "{written}");
+        assert!(
+            written[section..].starts_with(&format!(
+                "{FREETEXT_DATA}\n[MY-EXTENSION]\nsomething the parser does not know\n\n[NDB]\n"
+            )),
+            "{written}"
+        );
+    }
+
+    #[test]
+    fn only_the_moved_entity_is_reverted() {
+        let mut sct = Sct::parse(ORIGINAL.as_bytes()).unwrap();
+        let moved_to = sct.vors[0].coordinate;
+        let from = Point::new(11.0, 48.3);
+        let revert = |sct: &mut Sct, position: Point| {
+            let change =
+                Change::moved(EntityKind::Vor, "MBG", from, position, 1000.0, "ED Navaids");
+            revert_change(sct, &mut vec![], &mut vec![], &change);
+        };
+
+        // e.g. moved again by a later change
+        revert(&mut sct, Point::new(11.2, 48.3));
+        assert_eq!(sct.vors[0].coordinate, moved_to);
+
+        revert(&mut sct, moved_to);
+        assert_eq!(sct.vors[0].coordinate, from);
     }
 }
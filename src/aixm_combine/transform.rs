@@ -0,0 +1,103 @@
+use std::fmt;
+
+use geo::{Destination as _, Geodesic, Point};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EntityKind {
+    Airport,
+    Vor,
+    Ndb,
+    Fix,
+}
+
+impl fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityKind::Airport => write!(f, "Airport"),
+            EntityKind::Vor => write!(f, "VOR"),
+            EntityKind::Ndb => write!(f, "NDB"),
+            EntityKind::Fix => write!(f, "Fix"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Adjustment {
+    /// Shift by the given metres north and east of the AIXM position
+    Offset {
+        #[serde(default)]
+        north: f64,
+        #[serde(default)]
+        east: f64,
+    },
+    /// Replace the AIXM position
+    Coordinate { lat: f64, lon: f64 },
+    /// Leave the entity as it is in the package, only from the designator
+    /// overrides
+    #[serde(skip)]
+    Keep,
+}
+
+impl Adjustment {
+    /// Adjusted `point`, `None` if the entity is kept as it is.
+    fn apply(self, point: Point) -> Option<Point> {
+        match self {
+            Adjustment::Offset { north, east } => {
+                let bearing = east.atan2(north).to_degrees();
+                Some(Geodesic.destination(point, bearing, north.hypot(east)))
+            }
+            Adjustment::Coordinate { lat, lon } => Some(Point::new(lon, lat)),
+            Adjustment::Keep => None,
+        }
+    }
+}
+
+/// Intentional deviation from the AIXM position of an entity, e.g. to
+/// declutter labels, applied to the AIXM position on every combine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Transform {
+    pub designator: String,
+    /// Restrict to one kind of entity, all kinds if unset
+    #[serde(default)]
+    pub kind: Option<EntityKind>,
+    #[serde(flatten)]
+    pub adjustment: Adjustment,
+}
+
+impl Transform {
+    fn applies_to(&self, kind: EntityKind, designator: &str) -> bool {
+        self.kind.is_none_or(|k| k == kind) && self.designator == designator
+    }
+}
+
+/// AIXM `position` of an entity with the matching `transforms` applied in
+/// order, `None` if one keeps it as it is.
+pub fn adjust(
+    transforms: &[Transform],
+    kind: EntityKind,
+    designator: &str,
+    position: Point,
+) -> Option<Point> {
+    transforms
+        .iter()
+        .filter(|transform| transform.applies_to(kind, designator))
+        .try_fold(position, |position, transform| {
+            transform.adjustment.apply(position)
+        })
+}
+
+/// Whether the `transforms` keep an entity or place it manually, so that its
+/// position does not depend on AIXM.
+pub fn is_manual(transforms: &[Transform], kind: EntityKind, designator: &str) -> bool {
+    transforms.iter().any(|transform| {
+        transform.applies_to(kind, designator)
+            && matches!(
+                transform.adjustment,
+                Adjustment::Coordinate { .. } | Adjustment::Keep
+            )
+    })
+}
@@ -1,13 +1,21 @@
-use serde::Deserialize;
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
 use snafu::ResultExt as _;
 use tracing::trace;
 
-use crate::error::{
-    AiracUpdaterResult, DecodeDfsDatasetsSnafu, DeserializeDfsDatasetsSnafu, FetchDfsDatasetsSnafu,
+use crate::{
+    airac::AiracCycle,
+    error::{
+        AiracUpdaterResult, DecodeDfsDatasetsSnafu, DeserializeDfsDatasetsSnafu,
+        FetchDfsDatasetsSnafu,
+    },
+    network,
 };
 
 #[derive(Debug, Deserialize, Clone)]
-pub(crate) struct DfsAmdts {
+pub struct DfsAmdts {
     #[serde(rename = "Amdts")]
     amdts: Vec<DfsAmdt>,
 }
@@ -30,7 +38,6 @@ struct DfsAmdtMetadata {
 enum DfsAmdtDataset {
     #[serde(rename = "group")]
     Group {
-        #[expect(dead_code, reason = "to be moved to public API of aixm lib")]
         name: String,
         items: Vec<DfsAmdtDataset>,
     },
@@ -69,30 +76,228 @@ struct DfsAmdtDatasetRelease {
     filename: String,
 }
 
-pub(crate) async fn fetch_dfs_datasets() -> AiracUpdaterResult<DfsAmdts> {
-    let raw_data = reqwest::get("https://aip.dfs.de/datasets/rest/")
+/// Single release of a dataset in an amendment, flattened for listing.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DatasetRelease {
+    pub amdt: u32,
+    /// Names of the enclosing groups, outermost first
+    pub groups: Vec<String>,
+    pub name: String,
+    pub release_type: String,
+    pub filename: String,
+    pub effective: Option<NaiveDate>,
+}
+
+impl DfsAmdts {
+    pub fn releases(&self) -> Vec<DatasetRelease> {
+        fn collect(
+            amdt: u32,
+            dataset: &DfsAmdtDataset,
+            groups: &mut Vec<String>,
+            releases: &mut Vec<DatasetRelease>,
+        ) {
+            match dataset {
+                DfsAmdtDataset::Group { name, items } => {
+                    groups.push(name.clone());
+                    for item in items {
+                        collect(amdt, item, groups, releases);
+                    }
+                    groups.pop();
+                }
+                DfsAmdtDataset::Leaf {
+                    name,
+                    releases: dataset_releases,
+                } => releases.extend(dataset_releases.iter().map(|r| DatasetRelease {
+                    amdt,
+                    groups: groups.clone(),
+                    name: name.clone(),
+                    release_type: r.release_type.clone(),
+                    filename: r.filename.clone(),
+                    effective: effective_from_filename(&r.filename),
+                })),
+            }
+        }
+
+        let mut releases = vec![];
+        for amdt in &self.amdts {
+            for dataset in &amdt.metadata.datasets {
+                collect(amdt.amdt, dataset, &mut vec![], &mut releases);
+            }
+        }
+        releases
+    }
+
+    /// All amendments, with the dates of their releases.
+    pub fn amendments(&self) -> Vec<Amendment> {
+        let releases = self.releases();
+        self.amdts
+            .iter()
+            .map(|amdt| {
+                let (effective, expires) = releases
+                    .iter()
+                    .find(|release| release.amdt == amdt.amdt)
+                    .map(|release| {
+                        let mut dates = dates_from_filename(&release.filename);
+                        (dates.next(), dates.next())
+                    })
+                    .unwrap_or_default();
+                Amendment {
+                    amdt: amdt.amdt,
+                    effective,
+                    expires,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Dates in a release filename, i.e. effective and expiry date 2025-02-20
+/// and 2025-03-20 in `ED_Navaids_2025-02-20_2025-03-20_revision.xml`.
+fn dates_from_filename(filename: &str) -> impl Iterator<Item = NaiveDate> {
+    filename
+        .split(['_', '.'])
+        .filter_map(|part| NaiveDate::parse_from_str(part, "%Y-%m-%d").ok())
+}
+
+pub fn effective_from_filename(filename: &str) -> Option<NaiveDate> {
+    dates_from_filename(filename).next()
+}
+
+/// Amendment provided by DFS with the validity of its releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amendment {
+    pub amdt: u32,
+    pub effective: Option<NaiveDate>,
+    pub expires: Option<NaiveDate>,
+}
+
+impl Amendment {
+    /// Cycle the releases of the amendment become effective with.
+    pub fn cycle(&self) -> Option<AiracCycle> {
+        self.effective.map(AiracCycle::containing)
+    }
+}
+
+impl fmt::Display for Amendment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let date = |date: Option<NaiveDate>| date.map_or("?".to_string(), |date| date.to_string());
+        write!(
+            f,
+            "AMDT {} ({} – {})",
+            self.amdt,
+            date(self.effective),
+            date(self.expires)
+        )
+    }
+}
+
+/// AIXM version of the DFS releases to download. DFS announced the
+/// migration from 5.1 to 5.1.1 and 5.2, publishing them side by side for a
+/// while.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AixmRelease {
+    #[default]
+    #[serde(rename = "5.1")]
+    V5_1,
+    #[serde(rename = "5.1.1")]
+    V5_1_1,
+    #[serde(rename = "5.2")]
+    V5_2,
+}
+
+impl AixmRelease {
+    pub const ALL: [Self; 3] = [Self::V5_1, Self::V5_1_1, Self::V5_2];
+
+    /// Release type as listed by DFS
+    pub fn release_type(self) -> &'static str {
+        match self {
+            AixmRelease::V5_1 => "AIXM 5.1",
+            AixmRelease::V5_1_1 => "AIXM 5.1.1",
+            AixmRelease::V5_2 => "AIXM 5.2",
+        }
+    }
+}
+
+impl fmt::Display for AixmRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.release_type())
+    }
+}
+
+const DFS_DATASETS_URL: &str = "https://aip.dfs.de/datasets/rest/";
+
+/// Base URL of the DFS datasets REST API, can be pointed to a mirror (see
+/// `serve-mirror`) via `AIRAC_UPDATER_DFS_URL`.
+pub fn dfs_base_url() -> String {
+    let mut url =
+        std::env::var("AIRAC_UPDATER_DFS_URL").unwrap_or_else(|_| DFS_DATASETS_URL.to_string());
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    url
+}
+
+pub async fn fetch_dfs_datasets_raw() -> AiracUpdaterResult<String> {
+    let url = dfs_base_url();
+    network::check_request(&url)?;
+    let raw_data = reqwest::get(url)
         .await
         .context(FetchDfsDatasetsSnafu)?
         .text()
         .await
         .context(DecodeDfsDatasetsSnafu)?;
     trace!("{raw_data}");
+    Ok(raw_data)
+}
+
+/// Difference of the local clock to the DFS server clock, from the `Date`
+/// header of the dataset list.
+pub async fn dfs_clock_skew() -> AiracUpdaterResult<Option<TimeDelta>> {
+    let url = dfs_base_url();
+    network::check_request(&url)?;
+    let response = reqwest::Client::new()
+        .head(url)
+        .send()
+        .await
+        .context(FetchDfsDatasetsSnafu)?;
+    Ok(response
+        .headers()
+        .get(reqwest::header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .map(|server_time| Utc::now() - server_time.with_timezone(&Utc)))
+}
+
+pub async fn fetch_dfs_datasets() -> AiracUpdaterResult<DfsAmdts> {
+    let raw_data = fetch_dfs_datasets_raw().await?;
     serde_json::from_str(&raw_data).context(DeserializeDfsDatasetsSnafu)
 }
 
-pub(crate) fn get_dataset_url(
+pub fn get_dataset_url(
+    amdts: &DfsAmdts,
+    amdt_id: u32,
+    dataset_name: &str,
+    release: AixmRelease,
+) -> Option<String> {
+    get_dataset_path(amdts, amdt_id, dataset_name, release)
+        .map(|path| format!("{}{path}", dfs_base_url()))
+}
+
+/// Path of the dataset relative to the REST base URL.
+pub fn get_dataset_path(
     amdts: &DfsAmdts,
     amdt_id: u32,
     dataset_name: &str,
-    release_type: &str,
+    release: AixmRelease,
 ) -> Option<String> {
     for amdt in &amdts.amdts {
         if amdt.amdt == amdt_id {
             for dataset in &amdt.metadata.datasets {
                 if let Some(DfsAmdtDataset::Leaf { name: _, releases }) = dataset.find(&|d| matches!(d, DfsAmdtDataset::Leaf{ name, releases: _} if name == dataset_name)) {
                     for r in releases {
-                        if r.release_type == release_type {
-                            return Some(format!("https://aip.dfs.de/datasets/rest/{}/{}", amdt_id, r.filename));
+                        if r.release_type == release.release_type() {
+                            return Some(format!("{}/{}", amdt_id, r.filename));
                         }
                     }
                 }
@@ -102,3 +307,31 @@ pub(crate) fn get_dataset_url(
 
     None
 }
+
+/// Amendment whose releases become effective with `cycle`, recognized by the
+/// effective date in the release filenames (e.g.
+/// `ED_Navaids_2025-02-20_2025-03-20_revision.xml`).
+pub fn find_amendment(amdts: &DfsAmdts, cycle: AiracCycle) -> Option<u32> {
+    fn has_release_from(dataset: &DfsAmdtDataset, needle: &str) -> bool {
+        match dataset {
+            DfsAmdtDataset::Group { items, .. } => {
+                items.iter().any(|item| has_release_from(item, needle))
+            }
+            DfsAmdtDataset::Leaf { releases, .. } => {
+                releases.iter().any(|r| r.filename.contains(needle))
+            }
+        }
+    }
+
+    let needle = format!("_{}_", cycle.effective().format("%Y-%m-%d"));
+    amdts
+        .amdts
+        .iter()
+        .find(|amdt| {
+            amdt.metadata
+                .datasets
+                .iter()
+                .any(|dataset| has_release_from(dataset, &needle))
+        })
+        .map(|amdt| amdt.amdt)
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use geo::Point;
+use quick_xml::DeError;
+use serde::Deserialize;
+
+use crate::geometry::GmlPos;
+
+const TEMPDELTA: &str = "TEMPDELTA";
+
+// Only the parts of the AIXM service model needed for the frequencies of the
+// .ese positions and the tower positions, the `aixm` crate does not cover
+// units and services.
+
+#[derive(Debug, Deserialize)]
+struct Feature<T> {
+    #[serde(rename = "gml:identifier")]
+    identifier: Option<String>,
+    #[serde(rename = "aixm:timeSlice", default)]
+    time_slices: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnitTimeSlice {
+    #[serde(rename = "aixm:UnitTimeSlice")]
+    unit: UnitTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnitTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:name")]
+    name: Option<String>,
+    #[serde(rename = "aixm:type")]
+    kind: Option<String>,
+    #[serde(rename = "aixm:position")]
+    position: Option<UnitPosition>,
+    #[serde(rename = "aixm:airportLocation")]
+    airport_location: Option<Href>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnitPosition {
+    #[serde(rename = "aixm:ElevatedPoint")]
+    point: ElevatedPoint,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElevatedPoint {
+    #[serde(rename = "@srsName")]
+    srs_name: Option<String>,
+    #[serde(rename = "gml:pos")]
+    pos: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelTimeSlice {
+    #[serde(rename = "aixm:RadioCommunicationChannelTimeSlice")]
+    channel: ChannelTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:frequencyTransmission")]
+    frequency_transmission: Option<Frequency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Frequency {
+    #[serde(rename = "@uom")]
+    uom: Option<String>,
+    #[serde(rename = "$text")]
+    value: f64,
+}
+
+impl Frequency {
+    /// MHz as in the .ese, e.g. `118.705`
+    fn mhz(&self) -> Option<String> {
+        let factor = match self.uom.as_deref().unwrap_or("MHZ") {
+            "MHZ" => 1.,
+            "KHZ" => 1e-3,
+            "HZ" => 1e-6,
+            _ => return None,
+        };
+        Some(format!("{:.3}", self.value * factor))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceTimeSlice {
+    #[serde(
+        rename = "aixm:AirTrafficControlServiceTimeSlice",
+        alias = "aixm:GroundTrafficControlServiceTimeSlice",
+        alias = "aixm:InformationServiceTimeSlice"
+    )]
+    service: ServiceTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServiceTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:type")]
+    kind: Option<String>,
+    #[serde(rename = "aixm:serviceProvider")]
+    service_provider: Option<Href>,
+    #[serde(rename = "aixm:call-sign", default)]
+    call_signs: Vec<CallSign>,
+    #[serde(rename = "aixm:radioCommunication", default)]
+    radio_communication: Vec<Href>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallSign {
+    #[serde(rename = "aixm:CallsignDetail")]
+    detail: Option<CallsignDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallsignDetail {
+    #[serde(rename = "aixm:callSign")]
+    call_sign: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Href {
+    #[serde(rename = "@xlink:href")]
+    href: String,
+}
+
+impl Href {
+    fn uuid(&self) -> &str {
+        self.href.trim_start_matches("urn:uuid:")
+    }
+}
+
+/// Frequency of an ATC or information service by its radio call sign.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceFrequency {
+    /// Normalised with [`normalize_call_sign`]
+    pub call_sign: String,
+    pub frequency: String,
+}
+
+/// Position of an aerodrome control tower unit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TowerPosition {
+    /// `gml:identifier` of the airport the tower is located at
+    pub airport: String,
+    pub position: Point,
+}
+
+/// Frequencies and tower positions of a dataset.
+#[derive(Debug, Default)]
+pub struct Services {
+    pub frequencies: Vec<ServiceFrequency>,
+    pub towers: Vec<TowerPosition>,
+}
+
+/// Upper case call sign with umlauts spelled out and single spaces, so
+/// `München  Tower` matches AIXM's `MUENCHEN TOWER`.
+pub fn normalize_call_sign(call_sign: &str) -> String {
+    call_sign
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+        .replace('Ä', "AE")
+        .replace('Ö', "OE")
+        .replace('Ü', "UE")
+        .replace('ß', "SS")
+}
+
+/// Frequencies and tower positions of a dataset, read member by member and
+/// linked once all members are read.
+#[derive(Debug, Default)]
+pub struct ServiceCollector {
+    /// Names of the units by their `gml:identifier`
+    units: HashMap<String, String>,
+    towers: Vec<TowerPosition>,
+    /// Frequencies of the channels by their `gml:identifier`
+    channels: HashMap<String, String>,
+    services: Vec<ServiceTimeSliceContent>,
+}
+
+impl ServiceCollector {
+    /// Reads a `Unit`, `RadioCommunicationChannel` or ATC, ground control or
+    /// information service feature. Temporary time slices are ignored.
+    pub fn add(&mut self, feature: &str, xml: &str) -> Result<(), DeError> {
+        match feature {
+            "Unit" => self.add_unit(quick_xml::de::from_str(xml)?),
+            "RadioCommunicationChannel" => {
+                let channel = quick_xml::de::from_str::<Feature<ChannelTimeSlice>>(xml)?;
+                if let (Some(identifier), Some(frequency)) = (
+                    channel.identifier,
+                    channel
+                        .time_slices
+                        .iter()
+                        .map(|time_slice| &time_slice.channel)
+                        .find(|channel| channel.interpretation != TEMPDELTA)
+                        .and_then(|channel| channel.frequency_transmission.as_ref()?.mhz()),
+                ) {
+                    self.channels.insert(identifier, frequency);
+                }
+            }
+            _ => {
+                let service = quick_xml::de::from_str::<Feature<ServiceTimeSlice>>(xml)?;
+                self.services.extend(
+                    service
+                        .time_slices
+                        .into_iter()
+                        .map(|time_slice| time_slice.service)
+                        .find(|service| service.interpretation != TEMPDELTA),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn add_unit(&mut self, unit: Feature<UnitTimeSlice>) {
+        let Some(time_slice) = unit
+            .time_slices
+            .into_iter()
+            .map(|time_slice| time_slice.unit)
+            .find(|unit| unit.interpretation != TEMPDELTA)
+        else {
+            return;
+        };
+        if let (Some("TWR"), Some(position), Some(airport)) = (
+            time_slice.kind.as_deref(),
+            &time_slice.position,
+            &time_slice.airport_location,
+        ) {
+            let point = &position.point;
+            if let Ok(position) = GmlPos::new(&point.pos, point.srs_name.as_deref()).to_point() {
+                self.towers.push(TowerPosition {
+                    airport: airport.uuid().to_string(),
+                    position,
+                });
+            }
+        }
+        if let (Some(identifier), Some(name)) = (unit.identifier, time_slice.name) {
+            self.units.insert(identifier, name);
+        }
+    }
+
+    /// Frequencies of the ATC and information services read by call sign,
+    /// and the positions of the `TWR` units located at an airport. ATIS
+    /// without a call sign of its own are named after their unit, e.g.
+    /// `MUENCHEN ATIS`.
+    pub fn finish(self) -> Services {
+        let mut frequencies = vec![];
+        for service in self.services {
+            let mut call_signs = service
+                .call_signs
+                .iter()
+                .filter_map(|call_sign| call_sign.detail.as_ref()?.call_sign.as_deref())
+                .map(normalize_call_sign)
+                .collect::<Vec<_>>();
+            if call_signs.is_empty() && service.kind.as_deref() == Some("ATIS") {
+                call_signs.extend(
+                    service
+                        .service_provider
+                        .as_ref()
+                        .and_then(|unit| self.units.get(unit.uuid()))
+                        .map(|name| normalize_call_sign(&format!("{name} ATIS"))),
+                );
+            }
+            for channel in &service.radio_communication {
+                let Some(frequency) = self.channels.get(channel.uuid()) else {
+                    continue;
+                };
+                frequencies.extend(call_signs.iter().map(|call_sign| ServiceFrequency {
+                    call_sign: call_sign.clone(),
+                    frequency: frequency.clone(),
+                }));
+            }
+        }
+        frequencies.sort_by(|a, b| (&a.call_sign, &a.frequency).cmp(&(&b.call_sign, &b.frequency)));
+        frequencies.dedup();
+
+        Services {
+            frequencies,
+            towers: self.towers,
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use geo::{Destination as _, Geodesic, Point};
+use quick_xml::DeError;
+use serde::Deserialize;
+
+use crate::geometry::{GmlPos, to_sct_coordinates};
+
+const TEMPDELTA: &str = "TEMPDELTA";
+/// Length of the drawn final approach course, from the localizer antenna
+const FINAL_APPROACH_METRES: f64 = 10. * 1852.;
+/// Colour of the final approach courses in the overlay, cyan
+const ILS_COLOR: u32 = 0xff_ff_00;
+
+// Only the parts of the ILS components needed for the localizer positions and
+// final approach courses, the `aixm` crate does not cover them.
+
+#[derive(Debug, Deserialize)]
+struct Feature<T> {
+    #[serde(rename = "aixm:timeSlice", default)]
+    time_slices: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocalizerTimeSlice {
+    #[serde(rename = "aixm:LocalizerTimeSlice")]
+    localizer: ComponentTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct GlidepathTimeSlice {
+    #[serde(rename = "aixm:GlidepathTimeSlice")]
+    glidepath: ComponentTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComponentTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:designator")]
+    designator: Option<String>,
+    #[serde(rename = "aixm:frequency")]
+    frequency: Option<Value>,
+    /// Front course of a localizer
+    #[serde(rename = "aixm:trueBearing")]
+    true_bearing: Option<Value>,
+    /// Angle of a glidepath
+    #[serde(rename = "aixm:slope")]
+    slope: Option<Value>,
+    #[serde(rename = "aixm:location")]
+    location: Option<Location>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Value {
+    #[serde(rename = "@uom")]
+    uom: Option<String>,
+    #[serde(rename = "$text")]
+    value: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Location {
+    #[serde(rename = "aixm:ElevatedPoint")]
+    point: ElevatedPoint,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElevatedPoint {
+    #[serde(rename = "@srsName")]
+    srs_name: Option<String>,
+    #[serde(rename = "gml:pos")]
+    pos: String,
+}
+
+impl ComponentTimeSliceContent {
+    fn position(&self) -> Option<Point> {
+        let point = &self.location.as_ref()?.point;
+        GmlPos::new(&point.pos, point.srs_name.as_deref())
+            .to_point()
+            .ok()
+    }
+
+    /// MHz, e.g. `108.700`
+    fn mhz(&self) -> Option<String> {
+        let frequency = self.frequency.as_ref()?;
+        let factor = match frequency.uom.as_deref().unwrap_or("MHZ") {
+            "MHZ" => 1.,
+            "KHZ" => 1e-3,
+            _ => return None,
+        };
+        Some(format!("{:.3}", frequency.value * factor))
+    }
+}
+
+fn base<T>(
+    time_slices: Vec<T>,
+    content: impl Fn(T) -> ComponentTimeSliceContent,
+) -> Option<ComponentTimeSliceContent> {
+    time_slices
+        .into_iter()
+        .map(content)
+        .find(|content| content.interpretation != TEMPDELTA)
+}
+
+/// Localizer of an ILS with the slope of the glidepath of the same designator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Localizer {
+    pub designator: String,
+    pub frequency: String,
+    pub position: Point,
+    /// True bearing of the front course in degrees
+    pub course: f64,
+    pub slope: Option<f64>,
+}
+
+impl Localizer {
+    /// End of the final approach course, opposite the front course from the
+    /// antenna.
+    pub fn final_approach_end(&self) -> Point {
+        Geodesic.destination(
+            self.position,
+            (self.course + 180.) % 360.,
+            FINAL_APPROACH_METRES,
+        )
+    }
+}
+
+/// Localizers of a dataset with the slopes of their glidepaths, read member
+/// by member and matched once all members are read.
+#[derive(Debug, Default)]
+pub struct LocalizerCollector {
+    /// Glidepath slopes by designator
+    slopes: HashMap<String, f64>,
+    localizers: Vec<ComponentTimeSliceContent>,
+}
+
+impl LocalizerCollector {
+    /// Reads a `Localizer` or `Glidepath` feature. Temporary time slices are
+    /// ignored.
+    pub fn add(&mut self, feature: &str, xml: &str) -> Result<(), DeError> {
+        if feature == "Glidepath" {
+            let glidepath = quick_xml::de::from_str::<Feature<GlidepathTimeSlice>>(xml)?;
+            if let Some(ComponentTimeSliceContent {
+                designator: Some(designator),
+                slope: Some(slope),
+                ..
+            }) = base(glidepath.time_slices, |time_slice| time_slice.glidepath)
+            {
+                self.slopes
+                    .insert(designator.trim().to_string(), slope.value);
+            }
+        } else {
+            let localizer = quick_xml::de::from_str::<Feature<LocalizerTimeSlice>>(xml)?;
+            self.localizers
+                .extend(base(localizer.time_slices, |time_slice| {
+                    time_slice.localizer
+                }));
+        }
+        Ok(())
+    }
+
+    /// Localizers read, with the number of skipped ones without designator,
+    /// frequency, position or true bearing.
+    pub fn finish(self) -> (Vec<Localizer>, usize) {
+        let mut skipped = 0;
+        let localizers = self
+            .localizers
+            .iter()
+            .filter_map(|localizer| {
+                let parsed = to_localizer(localizer, &self.slopes);
+                if parsed.is_none() {
+                    skipped += 1;
+                }
+                parsed
+            })
+            .collect();
+        (localizers, skipped)
+    }
+}
+
+fn to_localizer(
+    localizer: &ComponentTimeSliceContent,
+    slopes: &HashMap<String, f64>,
+) -> Option<Localizer> {
+    let designator = localizer.designator.as_deref()?.trim();
+    Some(Localizer {
+        designator: designator.to_string(),
+        frequency: localizer.mhz()?,
+        position: localizer.position()?,
+        course: localizer.true_bearing.as_ref()?.value,
+        slope: slopes.get(designator).copied(),
+    })
+}
+
+/// Overlay next to the .sct, e.g. `EDMM.sct.aau_ils.sct`.
+pub fn ils_path(sct_path: &Path) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_ils.sct");
+    sct_path.with_file_name(file_name)
+}
+
+/// Renders the localizers as `[VOR]` entries and their final approach courses
+/// as `[GEO]` lines, for loading alongside the base .sct.
+pub fn render_ils(localizers: &[Localizer]) -> Vec<u8> {
+    let mut content = format!(
+        "; ILS localizers and final approach courses, generated by {} {}\r\n\
+         \r\n\
+         #define COLOR_Ils {ILS_COLOR}\r\n\
+         \r\n\
+         [VOR]\r\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    for localizer in localizers {
+        let (lat, lon) = to_sct_coordinates(localizer.position);
+        content.push_str(&format!(
+            "{} {} {lat} {lon}\r\n",
+            localizer.designator, localizer.frequency
+        ));
+    }
+    content.push_str("\r\n[GEO]\r\n");
+    for localizer in localizers {
+        let slope = localizer
+            .slope
+            .map(|slope| format!(", glidepath {slope:.1}°"))
+            .unwrap_or_default();
+        content.push_str(&format!(
+            "; {} course {:.1}°{slope}\r\n",
+            localizer.designator, localizer.course
+        ));
+        let (from_lat, from_lon) = to_sct_coordinates(localizer.position);
+        let (to_lat, to_lon) = to_sct_coordinates(localizer.final_approach_end());
+        content.push_str(&format!(
+            "{} {from_lat} {from_lon} {to_lat} {to_lon} COLOR_Ils\r\n",
+            localizer.designator
+        ));
+    }
+
+    content.into_bytes()
+}
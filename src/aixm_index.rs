@@ -0,0 +1,656 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+
+use aixm::{AixmAirportHeliport, AixmDesignatedPoint, AixmNdb, AixmVor, Member};
+use geo::Point;
+use itertools::Itertools as _;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    airac::AiracCycle,
+    aixm::AixmDataset,
+    aixm_airspaces::AixmAirspace,
+    aixm_combine::{EntityKind, EntityTypes},
+    aixm_frequencies::ServiceFrequency,
+    aixm_ils::Localizer,
+    aixm_routes::RouteSegment,
+    config::MilitaryEntities,
+    error::AiracUpdaterResult,
+    geometry::GmlPos,
+};
+
+/// Class of the CTR of new airports unless AIXM or the profile say otherwise
+pub const DEFAULT_CTR_CLASS: &str = "D";
+
+/// AIXM point used as the .sct coordinate of an airport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AirportPoint {
+    /// Aerodrome reference point
+    #[default]
+    Arp,
+    /// Position of the `TWR` unit, the ARP for airports without one
+    Tower,
+}
+
+/// Which [`AirportPoint`] is used per airport.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AirportReference {
+    pub default: AirportPoint,
+    /// By ICAO location indicator, e.g. `EDDM = "tower"`
+    pub overrides: BTreeMap<String, AirportPoint>,
+}
+
+impl AirportReference {
+    pub fn point(&self, icao: &str) -> AirportPoint {
+        self.overrides.get(icao).copied().unwrap_or(self.default)
+    }
+}
+
+/// Several AIXM members describing the same entity, of which only the one
+/// with the latest validity start is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    pub kind: &'static str,
+    pub key: String,
+    pub count: usize,
+    pub valid_from: Option<String>,
+    pub dataset: String,
+}
+
+impl fmt::Display for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is defined {} times in AIXM, using the one from {} valid from {}",
+            self.kind,
+            self.key,
+            self.count,
+            self.dataset,
+            self.valid_from.as_deref().unwrap_or("<unknown>")
+        )
+    }
+}
+
+/// Immutable view of the loaded AIXM members with per-type lookups, shared by
+/// all updaters.
+pub struct AixmIndex {
+    members: Vec<Member>,
+    /// Index into `datasets` per member
+    member_datasets: Vec<usize>,
+    datasets: Vec<String>,
+    airports: Vec<usize>,
+    vors: Vec<usize>,
+    ndbs: Vec<usize>,
+    designated_points: Vec<usize>,
+    route_segments: Vec<RouteSegment>,
+    frequencies: Vec<ServiceFrequency>,
+    /// Tower position by airport `gml:identifier`
+    towers: HashMap<String, Point>,
+    airspaces: Vec<AixmAirspace>,
+    localizers: Vec<Localizer>,
+    airport_reference: AirportReference,
+    /// Class of new airports outside of any AIXM CTR with a class
+    default_ctr_class: String,
+    match_airports_without_icao: bool,
+    entity_types: EntityTypes,
+    /// Designators of the military-only entities by kind
+    military: HashSet<(EntityKind, String)>,
+    duplicates: Vec<Duplicate>,
+    /// Cycle of the latest release indexed, if their file names tell
+    cycle: Option<AiracCycle>,
+}
+
+/// Kind and identifying key of the entities the updaters match by designator.
+fn entity_key(member: &Member) -> Option<(&'static str, String)> {
+    match member {
+        Member::AirportHeliport(airport) => airport
+            .aixm_time_slice
+            .aixm_airport_heliport_time_slice
+            .aixm_location_indicator_icao
+            .clone()
+            .map(|icao| ("Airport", icao)),
+        Member::Vor(vor) => {
+            let time_slice = &vor.aixm_time_slice.aixm_vortime_slice;
+            Some((
+                "VOR",
+                format!(
+                    "{} {:.3}",
+                    time_slice.aixm_designator, time_slice.aixm_frequency.value
+                ),
+            ))
+        }
+        Member::Ndb(ndb) => {
+            let time_slice = &ndb.aixm_time_slice.aixm_ndbtime_slice;
+            Some((
+                "NDB",
+                format!(
+                    "{} {:.3}",
+                    time_slice.aixm_designator, time_slice.aixm_frequency.value
+                ),
+            ))
+        }
+        Member::DesignatedPoint(fix) => Some((
+            "Fix",
+            fix.aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_designator
+                .clone(),
+        )),
+        _ => None,
+    }
+}
+
+/// Kind and key of the entity a member describes, telling the members of the
+/// same entity apart from other ones. Designated points are told apart by
+/// their `gml:identifier` as well, as fixes legitimately share a designator,
+/// e.g. border fixes or VFR reporting points.
+fn identity(member: &Member) -> Option<(&'static str, String)> {
+    match member {
+        Member::DesignatedPoint(fix) => Some((
+            "Fix",
+            format!(
+                "{} {}",
+                fix.aixm_time_slice
+                    .aixm_designated_point_time_slice
+                    .aixm_designator,
+                fix.gml_identifier
+            ),
+        )),
+        _ => entity_key(member),
+    }
+}
+
+/// Member used per entity by [`identity`] and the number of members
+/// describing it, from the identity and validity start of every member. The
+/// latest valid member is picked (the first one read on ties) instead of
+/// whichever the updaters happen to see last.
+fn latest_per_entity<'a>(
+    members: impl IntoIterator<Item = (Option<(&'static str, String)>, Option<&'a str>)>,
+) -> HashMap<(&'static str, String), (usize, usize)> {
+    let mut chosen = HashMap::<_, (usize, usize, Option<&str>)>::new();
+    for (i, (identity, valid_from)) in members.into_iter().enumerate() {
+        let Some(identity) = identity else {
+            continue;
+        };
+        chosen
+            .entry(identity)
+            .and_modify(|(chosen_i, count, chosen_from)| {
+                *count += 1;
+                if valid_from > *chosen_from {
+                    *chosen_i = i;
+                    *chosen_from = valid_from;
+                }
+            })
+            .or_insert((i, 1, valid_from));
+    }
+
+    chosen
+        .into_iter()
+        .map(|(identity, (i, count, _))| (identity, (i, count)))
+        .collect()
+}
+
+/// Kind and designator of a member as listed in [`AixmDataset::military`].
+fn military_key(member: &Member) -> Option<(EntityKind, String)> {
+    match member {
+        Member::AirportHeliport(airport) => airport
+            .aixm_time_slice
+            .aixm_airport_heliport_time_slice
+            .aixm_location_indicator_icao
+            .clone()
+            .map(|icao| (EntityKind::Airport, icao)),
+        Member::Vor(vor) => Some((
+            EntityKind::Vor,
+            vor.aixm_time_slice
+                .aixm_vortime_slice
+                .aixm_designator
+                .clone(),
+        )),
+        Member::Ndb(ndb) => Some((
+            EntityKind::Ndb,
+            ndb.aixm_time_slice
+                .aixm_ndbtime_slice
+                .aixm_designator
+                .clone(),
+        )),
+        Member::DesignatedPoint(fix) => Some((
+            EntityKind::Fix,
+            fix.aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_designator
+                .clone(),
+        )),
+        _ => None,
+    }
+}
+
+fn position(member: &Member) -> Option<Point> {
+    let pos = match member {
+        Member::AirportHeliport(airport) => {
+            let arp = &airport
+                .aixm_time_slice
+                .aixm_airport_heliport_time_slice
+                .aixm_arp
+                .aixm_elevated_point;
+            GmlPos::new(&arp.gml_pos, arp.srs_name.as_deref())
+        }
+        Member::Vor(vor) => GmlPos::from(
+            &vor.aixm_time_slice
+                .aixm_vortime_slice
+                .aixm_location
+                .location,
+        ),
+        Member::Ndb(ndb) => GmlPos::from(
+            &ndb.aixm_time_slice
+                .aixm_ndbtime_slice
+                .aixm_location
+                .location,
+        ),
+        Member::DesignatedPoint(fix) => GmlPos::from(
+            &fix.aixm_time_slice
+                .aixm_designated_point_time_slice
+                .aixm_location
+                .location,
+        ),
+        _ => return None,
+    };
+
+    pos.to_point().ok()
+}
+
+/// Begin of the member's validity as ISO 8601 timestamp.
+fn valid_from(member: &Member) -> Option<&str> {
+    let valid_time = match member {
+        Member::AirportHeliport(airport) => {
+            &airport
+                .aixm_time_slice
+                .aixm_airport_heliport_time_slice
+                .gml_valid_time
+        }
+        Member::Vor(vor) => &vor.aixm_time_slice.aixm_vortime_slice.gml_valid_time,
+        Member::Ndb(ndb) => &ndb.aixm_time_slice.aixm_ndbtime_slice.gml_valid_time,
+        Member::DesignatedPoint(fix) => {
+            &fix.aixm_time_slice
+                .aixm_designated_point_time_slice
+                .gml_valid_time
+        }
+        _ => return None,
+    };
+
+    Some(valid_time.gml_time_period.gml_begin_position.as_str())
+}
+
+impl AixmIndex {
+    /// Indexes the members of all datasets.
+    pub fn new(datasets: impl IntoIterator<Item = AixmDataset>) -> Self {
+        let mut index = Self {
+            members: vec![],
+            member_datasets: vec![],
+            datasets: vec![],
+            airports: vec![],
+            vors: vec![],
+            ndbs: vec![],
+            designated_points: vec![],
+            route_segments: vec![],
+            frequencies: vec![],
+            towers: HashMap::new(),
+            airspaces: vec![],
+            localizers: vec![],
+            airport_reference: AirportReference::default(),
+            default_ctr_class: DEFAULT_CTR_CLASS.to_string(),
+            match_airports_without_icao: false,
+            entity_types: EntityTypes::default(),
+            military: HashSet::new(),
+            duplicates: vec![],
+            cycle: None,
+        };
+        for dataset in datasets {
+            index.member_datasets.extend(std::iter::repeat_n(
+                index.datasets.len(),
+                dataset.members.len(),
+            ));
+            index.members.extend(dataset.members);
+            index.route_segments.extend(dataset.route_segments);
+            index.frequencies.extend(dataset.frequencies);
+            index.towers.extend(
+                dataset
+                    .towers
+                    .into_iter()
+                    .map(|tower| (tower.airport, tower.position)),
+            );
+            index.airspaces.extend(dataset.airspaces);
+            index.localizers.extend(dataset.localizers);
+            index.military.extend(
+                dataset
+                    .military
+                    .into_iter()
+                    .map(|entity| (entity.kind, entity.designator)),
+            );
+            index.cycle = index
+                .cycle
+                .max(dataset.effective.map(AiracCycle::containing));
+            index.datasets.push(dataset.name);
+        }
+
+        let chosen = latest_per_entity(
+            index
+                .members
+                .iter()
+                .map(|member| (identity(member), valid_from(member))),
+        );
+        index.duplicates = chosen
+            .values()
+            .filter(|(_, count)| *count > 1)
+            .filter_map(|(i, count)| {
+                // reported by designator, not by identity
+                let (kind, key) = entity_key(&index.members[*i])?;
+                Some(Duplicate {
+                    kind,
+                    key,
+                    count: *count,
+                    valid_from: valid_from(&index.members[*i]).map(ToString::to_string),
+                    dataset: index.dataset(*i).to_string(),
+                })
+            })
+            .collect();
+        index
+            .duplicates
+            .sort_by(|a, b| (a.kind, &a.key).cmp(&(b.kind, &b.key)));
+
+        for (i, member) in index.members.iter().enumerate() {
+            if identity(member).is_some_and(|key| chosen[&key].0 != i) {
+                continue;
+            }
+
+            match member {
+                Member::AirportHeliport(_) => index.airports.push(i),
+                Member::Vor(_) => index.vors.push(i),
+                Member::Ndb(_) => index.ndbs.push(i),
+                Member::DesignatedPoint(_) => index.designated_points.push(i),
+                _ => (),
+            }
+        }
+
+        index
+    }
+
+    /// AIRAC cycle of the indexed releases, the latest one if a dataset is
+    /// taken from another amendment.
+    pub fn cycle(&self) -> Option<AiracCycle> {
+        self.cycle
+    }
+
+    /// Selects the point used by [`Self::airport_position`].
+    #[must_use]
+    pub fn with_airport_reference(mut self, airport_reference: AirportReference) -> Self {
+        self.airport_reference = airport_reference;
+        self
+    }
+
+    /// Position of the airport as selected by its [`AirportReference`],
+    /// along with the point actually used.
+    pub fn airport_position(
+        &self,
+        airport: &AixmAirportHeliport,
+    ) -> AiracUpdaterResult<(AirportPoint, Point)> {
+        let time_slice = &airport.aixm_time_slice.aixm_airport_heliport_time_slice;
+        let point = time_slice
+            .aixm_location_indicator_icao
+            .as_deref()
+            .map_or(self.airport_reference.default, |icao| {
+                self.airport_reference.point(icao)
+            });
+        if point == AirportPoint::Tower {
+            if let Some(tower) = self.towers.get(&airport.gml_identifier) {
+                return Ok((AirportPoint::Tower, *tower));
+            }
+        }
+
+        let arp = &time_slice.aixm_arp.aixm_elevated_point;
+        Ok((
+            AirportPoint::Arp,
+            GmlPos::new(&arp.gml_pos, arp.srs_name.as_deref()).to_point()?,
+        ))
+    }
+
+    #[must_use]
+    pub fn with_default_ctr_class(mut self, class: impl Into<String>) -> Self {
+        self.default_ctr_class = class.into();
+        self
+    }
+
+    /// Also matches airports without ICAO location indicator to the .sct
+    /// airports by their AIXM designator or name.
+    #[must_use]
+    pub fn with_airports_without_icao(mut self, enabled: bool) -> Self {
+        self.match_airports_without_icao = enabled;
+        self
+    }
+
+    pub fn match_airports_without_icao(&self) -> bool {
+        self.match_airports_without_icao
+    }
+
+    /// Limits the updaters to the given entity types.
+    #[must_use]
+    pub fn with_entity_types(mut self, entity_types: EntityTypes) -> Self {
+        self.entity_types = entity_types;
+        self
+    }
+
+    pub fn entity_types(&self) -> EntityTypes {
+        self.entity_types
+    }
+
+    /// Leaves the military-only entities out with
+    /// [`MilitaryEntities::Exclude`], as if they were missing in AIXM.
+    #[must_use]
+    pub fn with_military_entities(mut self, military_entities: MilitaryEntities) -> Self {
+        if military_entities == MilitaryEntities::Exclude {
+            let (members, military) = (&self.members, &self.military);
+            let is_civil =
+                |i: &usize| military_key(&members[*i]).is_none_or(|key| !military.contains(&key));
+            self.airports.retain(is_civil);
+            self.vors.retain(is_civil);
+            self.ndbs.retain(is_civil);
+            self.designated_points.retain(is_civil);
+        }
+        self
+    }
+
+    /// Military-only airports, navaids and fixes of the loaded datasets,
+    /// excluded or not, by kind and designator.
+    pub fn military(&self) -> Vec<(EntityKind, String)> {
+        self.members
+            .iter()
+            .filter_map(military_key)
+            .filter(|key| self.military.contains(key))
+            .unique()
+            .collect()
+    }
+
+    /// Class of the AIXM CTR containing `position`, or the default class if
+    /// there is none or it has no class.
+    pub fn ctr_class(&self, position: Point) -> &str {
+        self.airspaces
+            .iter()
+            .filter(|airspace| airspace.airspace_type.as_deref() == Some("CTR"))
+            .find(|airspace| airspace.contains(position))
+            .and_then(|airspace| airspace.class.as_deref())
+            .unwrap_or(&self.default_ctr_class)
+    }
+
+    pub fn airport_reference(&self) -> &AirportReference {
+        &self.airport_reference
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+
+    /// Position of every airport, navaid and fix by kind and key, e.g.
+    /// `("VOR", "MUN 112.300")`.
+    pub fn positions(&self) -> BTreeMap<(&'static str, String), Point> {
+        self.airports
+            .iter()
+            .chain(&self.vors)
+            .chain(&self.ndbs)
+            .chain(&self.designated_points)
+            .filter_map(|i| {
+                let member = &self.members[*i];
+                Some((entity_key(member)?, position(member)?))
+            })
+            .collect()
+    }
+
+    /// Designator, position and source dataset of every airport, navaid and
+    /// fix, by kind as in [`Self::positions`].
+    pub fn locations(&self) -> impl Iterator<Item = (&'static str, String, Point, &str)> {
+        self.airports
+            .iter()
+            .chain(&self.vors)
+            .chain(&self.ndbs)
+            .chain(&self.designated_points)
+            .filter_map(|i| {
+                let member = &self.members[*i];
+                let (kind, key) = entity_key(member)?;
+                // navaid keys are `<designator> <frequency>`
+                let designator = key.split_whitespace().next()?.to_string();
+                Some((kind, designator, position(member)?, self.dataset(*i)))
+            })
+    }
+
+    /// Name of the dataset the `i`th member was loaded from.
+    fn dataset(&self, i: usize) -> &str {
+        &self.datasets[self.member_datasets[i]]
+    }
+
+    // The per-type accessors yield the source dataset name with every member.
+
+    pub fn airports(&self) -> impl Iterator<Item = (&str, &AixmAirportHeliport)> {
+        self.airports
+            .iter()
+            .filter_map(|i| match &self.members[*i] {
+                Member::AirportHeliport(airport) => Some((self.dataset(*i), airport)),
+                _ => None,
+            })
+    }
+
+    pub fn vors(&self) -> impl Iterator<Item = (&str, &AixmVor)> {
+        self.vors.iter().filter_map(|i| match &self.members[*i] {
+            Member::Vor(vor) => Some((self.dataset(*i), vor)),
+            _ => None,
+        })
+    }
+
+    pub fn ndbs(&self) -> impl Iterator<Item = (&str, &AixmNdb)> {
+        self.ndbs.iter().filter_map(|i| match &self.members[*i] {
+            Member::Ndb(ndb) => Some((self.dataset(*i), ndb)),
+            _ => None,
+        })
+    }
+
+    pub fn designated_points(&self) -> impl Iterator<Item = (&str, &AixmDesignatedPoint)> {
+        self.designated_points
+            .iter()
+            .filter_map(|i| Some((self.dataset(*i), self.designated_point_at(*i)?)))
+    }
+
+    pub fn route_segments(&self) -> &[RouteSegment] {
+        &self.route_segments
+    }
+
+    pub fn frequencies(&self) -> &[ServiceFrequency] {
+        &self.frequencies
+    }
+
+    pub fn airspaces(&self) -> &[AixmAirspace] {
+        &self.airspaces
+    }
+
+    pub fn localizers(&self) -> &[Localizer] {
+        &self.localizers
+    }
+
+    /// Position of every designated point by its `gml:identifier`, for
+    /// resolving references to them.
+    pub fn designated_point_positions(&self) -> HashMap<&str, Point> {
+        self.designated_points
+            .iter()
+            .filter_map(|i| {
+                let fix = self.designated_point_at(*i)?;
+                Some((fix.gml_identifier.as_str(), position(&self.members[*i])?))
+            })
+            .collect()
+    }
+
+    fn designated_point_at(&self, i: usize) -> Option<&AixmDesignatedPoint> {
+        match &self.members[i] {
+            Member::DesignatedPoint(fix) => Some(fix),
+            _ => None,
+        }
+    }
+}
+
+impl FromIterator<AixmDataset> for AixmIndex {
+    fn from_iter<T: IntoIterator<Item = AixmDataset>>(iter: T) -> Self {
+        Self::new(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_FROM: Option<&str> = Some("2025-04-17T00:00:00Z");
+
+    fn fix(designator: &str, identifier: &str) -> Option<(&'static str, String)> {
+        Some(("Fix", format!("{designator} {identifier}")))
+    }
+
+    #[test]
+    fn fixes_sharing_a_designator_are_kept() {
+        // e.g. border fixes of the same name on both sides of the border
+        let chosen = latest_per_entity([
+            (fix("DM441", "urn:uuid:a"), VALID_FROM),
+            (fix("DM441", "urn:uuid:b"), VALID_FROM),
+        ]);
+        assert_eq!(chosen.len(), 2);
+        assert!(chosen.values().all(|(_, count)| *count == 1));
+    }
+
+    #[test]
+    fn latest_member_of_an_entity_is_chosen() {
+        let chosen = latest_per_entity([
+            (fix("DM441", "urn:uuid:a"), Some("2025-05-15T00:00:00Z")),
+            (None, None),
+            (fix("DM441", "urn:uuid:a"), Some("2025-06-12T00:00:00Z")),
+            (fix("DM441", "urn:uuid:a"), VALID_FROM),
+        ]);
+        assert_eq!(
+            chosen,
+            HashMap::from([(fix("DM441", "urn:uuid:a").unwrap(), (2, 3))])
+        );
+    }
+
+    #[test]
+    fn first_member_wins_ties() {
+        let chosen = latest_per_entity([
+            (Some(("VOR", "MUN 112.300".to_string())), VALID_FROM),
+            (Some(("VOR", "MUN 112.300".to_string())), VALID_FROM),
+        ]);
+        assert_eq!(chosen[&("VOR", "MUN 112.300".to_string())], (0, 2));
+    }
+}
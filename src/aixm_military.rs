@@ -0,0 +1,131 @@
+use crate::aixm_combine::EntityKind;
+
+const TEMPDELTA: &str = "TEMPDELTA";
+
+// Only the parts of the AIXM model telling military-only entities apart, the
+// `aixm` crate does not cover the control type, TACANs and navaid types.
+// They are picked up from the reader events while the members are scanned,
+// so the features are not read a second time.
+
+/// Entity AIXM marks as military only: an airport under military control, a
+/// TACAN without civil VOR (unlike a VORTAC) or a designated point of a
+/// military type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MilitaryEntity {
+    pub kind: EntityKind,
+    pub designator: String,
+}
+
+/// Time slice field read for the flags.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Interpretation,
+    Designator,
+    Icao,
+    ControlType,
+    Kind,
+}
+
+#[derive(Debug, Default)]
+struct TimeSliceFields {
+    interpretation: String,
+    designator: Option<String>,
+    icao: Option<String>,
+    /// `CIVIL`, `MIL` or `JOINT`
+    control_type: Option<String>,
+    /// e.g. `TACAN` or `VORTAC` for navaids, `OTHER:MIL_...` for points
+    kind: Option<String>,
+}
+
+/// Fields of the first time slice of an `AirportHeliport`, `Navaid` or
+/// `DesignatedPoint` feature that is not temporary, collected from the reader
+/// events of the feature with [`Self::start`], [`Self::text`] and
+/// [`Self::end`].
+#[derive(Debug, Default)]
+pub struct MilitaryFields {
+    /// Depth of the current element within the feature, the feature itself
+    /// at 0 and the fields of a time slice at 3
+    depth: usize,
+    /// Time slice field being read
+    field: Option<Field>,
+    time_slice: TimeSliceFields,
+    base: Option<TimeSliceFields>,
+}
+
+impl MilitaryFields {
+    /// Whether the military flags of `feature` are collected.
+    pub fn covers(feature: &[u8]) -> bool {
+        matches!(feature, b"AirportHeliport" | b"Navaid" | b"DesignatedPoint")
+    }
+
+    /// Start of an element below the feature, by its local name.
+    pub fn start(&mut self, local_name: &[u8]) {
+        self.depth += 1;
+        if self.depth == 3 && self.base.is_none() {
+            self.field = match local_name {
+                b"interpretation" => Some(Field::Interpretation),
+                b"designator" => Some(Field::Designator),
+                b"locationIndicatorICAO" => Some(Field::Icao),
+                b"controlType" => Some(Field::ControlType),
+                b"type" => Some(Field::Kind),
+                _ => None,
+            };
+        }
+    }
+
+    /// Text within the current element.
+    pub fn text(&mut self, text: &str) {
+        let value = match self.field {
+            Some(Field::Interpretation) => &mut self.time_slice.interpretation,
+            Some(Field::Designator) => self.time_slice.designator.get_or_insert_default(),
+            Some(Field::Icao) => self.time_slice.icao.get_or_insert_default(),
+            Some(Field::ControlType) => self.time_slice.control_type.get_or_insert_default(),
+            Some(Field::Kind) => self.time_slice.kind.get_or_insert_default(),
+            None => return,
+        };
+        value.push_str(text);
+    }
+
+    /// End of an element below the feature, by its local name.
+    pub fn end(&mut self, local_name: &[u8]) {
+        self.field = None;
+        if self.depth == 1 && local_name == b"timeSlice" && self.base.is_none() {
+            let time_slice = std::mem::take(&mut self.time_slice);
+            if time_slice.interpretation.trim() != TEMPDELTA {
+                self.base = Some(time_slice);
+            }
+        }
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Military-only entity of `feature`, if it is one.
+    pub fn entity(self, feature: &str) -> Option<MilitaryEntity> {
+        let base = self.base?;
+        let trimmed = |value: Option<String>| value.map(|value| value.trim().to_string());
+        let (kind, designator) = match feature {
+            "AirportHeliport" => (
+                EntityKind::Airport,
+                trimmed(base.icao).filter(|_| {
+                    base.control_type
+                        .is_some_and(|control_type| control_type.trim() == "MIL")
+                }),
+            ),
+            "Navaid" => (
+                EntityKind::Vor,
+                trimmed(base.designator)
+                    .filter(|_| base.kind.is_some_and(|kind| kind.trim() == "TACAN")),
+            ),
+            _ => (
+                EntityKind::Fix,
+                trimmed(base.designator).filter(|_| {
+                    base.kind
+                        .is_some_and(|kind| kind.to_ascii_uppercase().contains("MIL"))
+                }),
+            ),
+        };
+        Some(MilitaryEntity {
+            kind,
+            designator: designator?,
+        })
+    }
+}
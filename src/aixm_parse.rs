@@ -0,0 +1,825 @@
+use std::{borrow::Cow, collections::BTreeMap, sync::LazyLock};
+
+use aixm::{Member, MessageAixmBasicMessage};
+use quick_xml::{
+    DeError, NsReader, Reader, Writer,
+    errors::IllFormedError,
+    events::{BytesEnd, BytesStart, Event, attributes::Attribute},
+    name::{LocalName, Namespace, QName, ResolveResult},
+};
+use regex::Regex;
+use sha2::{Digest as _, Sha256};
+
+use crate::{
+    aixm_airspaces::{AirspaceCollector, AixmAirspace},
+    aixm_frequencies::{ServiceCollector, Services},
+    aixm_ils::{Localizer, LocalizerCollector},
+    aixm_military::{MilitaryEntity, MilitaryFields},
+    aixm_routes::{RouteCollector, RouteSegment},
+};
+
+/// Namespace declarations, with or without a prefix.
+static NAMESPACE_DECLARATION_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"xmlns(?::([\w.-]+))?\s*=\s*["']([^"']*)["']"#).expect("valid regex")
+});
+
+/// Prefix the parsers expect for the elements and attributes of `namespace`:
+/// the AIXM 5.1, 5.1.1 and 5.2 schemas, their message schemas, GML and
+/// XLink.
+fn expected_prefix(namespace: &[u8]) -> Option<&'static str> {
+    match namespace {
+        b"http://www.aixm.aero/schema/5.1"
+        | b"http://www.aixm.aero/schema/5.1.1"
+        | b"http://www.aixm.aero/schema/5.2" => Some("aixm"),
+        b"http://www.aixm.aero/schema/5.1/message"
+        | b"http://www.aixm.aero/schema/5.1.1/message"
+        | b"http://www.aixm.aero/schema/5.2/message" => Some("message"),
+        b"http://www.opengis.net/gml/3.2" => Some("gml"),
+        b"http://www.w3.org/1999/xlink" => Some("xlink"),
+        _ => None,
+    }
+}
+
+/// Rewrites the element and attribute names of the AIXM, message, GML and
+/// XLink namespaces to the prefixes `aixm:`, `message:`, `gml:` and `xlink:`,
+/// as the parsers match them by their qualified name. The parts read are the
+/// same in AIXM 5.1, 5.1.1 and 5.2, whose documents may bind other prefixes
+/// or declare one of these as the default namespace. Documents that are not
+/// well-formed are returned as they are.
+pub fn normalize_prefixes(xml: &str) -> Cow<'_, str> {
+    let normalized = NAMESPACE_DECLARATION_RE.captures_iter(xml).all(|captures| {
+        expected_prefix(captures[2].as_bytes()).is_none_or(|expected| {
+            captures
+                .get(1)
+                .is_some_and(|prefix| prefix.as_str() == expected)
+        })
+    });
+    if normalized {
+        return Cow::Borrowed(xml);
+    }
+    rename_prefixes(xml).map_or(Cow::Borrowed(xml), Cow::Owned)
+}
+
+fn rename_prefixes(xml: &str) -> Option<String> {
+    let mut reader = NsReader::from_str(xml);
+    let mut writer = Writer::new(Vec::with_capacity(xml.len()));
+    loop {
+        let event = match reader.read_event().ok()? {
+            Event::Start(start) => Event::Start(rename_start(&reader, &start)?),
+            Event::Empty(empty) => Event::Empty(rename_start(&reader, &empty)?),
+            Event::End(end) => Event::End(BytesEnd::new(qualified(
+                reader.resolve_element(end.name()),
+                end.name(),
+            )?)),
+            Event::Eof => break,
+            event => event,
+        };
+        writer.write_event(event).ok()?;
+    }
+    String::from_utf8(writer.into_inner()).ok()
+}
+
+/// `name` with the expected prefix of its namespace, if it has one.
+fn qualified(
+    (namespace, local_name): (ResolveResult<'_>, LocalName<'_>),
+    name: QName<'_>,
+) -> Option<String> {
+    let prefix = match namespace {
+        ResolveResult::Bound(Namespace(namespace)) => expected_prefix(namespace),
+        _ => None,
+    };
+    Some(match prefix {
+        Some(prefix) => format!(
+            "{prefix}:{}",
+            std::str::from_utf8(local_name.as_ref()).ok()?
+        ),
+        None => std::str::from_utf8(name.as_ref()).ok()?.to_string(),
+    })
+}
+
+fn rename_start(reader: &NsReader<&[u8]>, start: &BytesStart<'_>) -> Option<BytesStart<'static>> {
+    let mut renamed = BytesStart::new(qualified(
+        reader.resolve_element(start.name()),
+        start.name(),
+    )?);
+    let mut keys = vec![];
+    for attribute in start.attributes() {
+        let attribute = attribute.ok()?;
+        let key = match attribute.key.as_namespace_binding() {
+            // the known namespaces are declared with their expected prefix,
+            // also if they were the default namespace
+            Some(_) => match expected_prefix(&attribute.value) {
+                Some(prefix) => format!("xmlns:{prefix}"),
+                None => std::str::from_utf8(attribute.key.as_ref())
+                    .ok()?
+                    .to_string(),
+            },
+            None => qualified(reader.resolve_attribute(attribute.key), attribute.key)?,
+        };
+        // e.g. the same namespace declared with two prefixes
+        if keys.contains(&key) {
+            continue;
+        }
+        renamed.push_attribute(Attribute {
+            key: QName(key.as_bytes()),
+            value: attribute.value,
+        });
+        keys.push(key);
+    }
+    Some(renamed)
+}
+
+/// Member of an AIXM basic message as found by [`Members`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberXml<'a> {
+    /// Local name of the feature, e.g. `Navaid`
+    pub feature: &'a str,
+    /// The `message:hasMember` element
+    pub member: &'a str,
+    /// The feature element within it
+    pub xml: &'a str,
+    /// Set if the feature is an `AirportHeliport`, `Navaid` or
+    /// `DesignatedPoint` marked as military only
+    pub military: Option<MilitaryEntity>,
+}
+
+/// The members of an AIXM basic message in document order, found with a
+/// single streaming pass without deserializing them.
+pub struct Members<'a> {
+    xml: &'a str,
+    /// Reader of `xml` from `offset` on
+    reader: Reader<&'a [u8]>,
+    offset: usize,
+    depth: usize,
+    /// Start of the `message:hasMember` element being read
+    member_start: usize,
+    /// Feature element of the member being read
+    feature: Option<(usize, usize)>,
+    military: Option<MilitaryEntity>,
+    /// Document up to the first member, with the root element and the
+    /// elements before the members
+    prologue: Option<&'a str>,
+    /// End tag of the root element
+    epilogue: String,
+}
+
+impl<'a> Members<'a> {
+    /// Scans `xml`, which has to be well-formed as checked with
+    /// [`check_well_formed`]: the end tags are not matched against their
+    /// start tags again.
+    pub fn new(xml: &'a str) -> Self {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().check_end_names = false;
+        Self {
+            xml,
+            reader,
+            offset: 0,
+            depth: 0,
+            member_start: 0,
+            feature: None,
+            military: None,
+            prologue: None,
+            epilogue: String::new(),
+        }
+    }
+
+    /// Scans `xml` from the end of a member at `offset` on, with the
+    /// prologue and epilogue found when the members before were scanned.
+    fn resume(xml: &'a str, offset: usize, prologue_len: usize, epilogue: String) -> Self {
+        let mut reader = Reader::from_str(&xml[offset..]);
+        let config = reader.config_mut();
+        config.check_end_names = false;
+        // the end tag of the root element
+        config.allow_unmatched_ends = true;
+        Self {
+            xml,
+            reader,
+            offset,
+            depth: 1,
+            member_start: offset,
+            feature: None,
+            military: None,
+            prologue: Some(&xml[..prologue_len]),
+            epilogue,
+        }
+    }
+
+    /// Replaces `message` by the document with `member` as its only member,
+    /// as deserialized by the `aixm` crate.
+    pub fn single_member_message(&self, member: &str, message: &mut String) {
+        let prologue = self.prologue.unwrap_or_default();
+        message.clear();
+        // once sized for the largest member, the buffer is reused as it is
+        message.reserve(prologue.len() + member.len() + self.epilogue.len());
+        message.push_str(prologue);
+        message.push_str(member);
+        message.push_str(&self.epilogue);
+    }
+
+    /// Byte offset in the document up to which it has been scanned.
+    fn position(&self) -> usize {
+        self.offset + self.reader.buffer_position() as usize
+    }
+
+    /// Reads the rest of the `feature` element like [`Reader::read_to_end`],
+    /// collecting its military flags on the way.
+    fn read_military_flags(
+        &mut self,
+        feature: &str,
+    ) -> Result<Option<MilitaryEntity>, quick_xml::Error> {
+        let mut fields = MilitaryFields::default();
+        let mut depth = 0_usize;
+        loop {
+            match self.reader.read_event()? {
+                Event::Start(start) => {
+                    depth += 1;
+                    fields.start(start.local_name().as_ref());
+                }
+                Event::Text(text) => fields.text(&text.decode()?),
+                Event::End(end) => {
+                    let Some(inner) = depth.checked_sub(1) else {
+                        return Ok(fields.entity(feature));
+                    };
+                    depth = inner;
+                    fields.end(end.local_name().as_ref());
+                }
+                Event::Eof => {
+                    return Err(IllFormedError::MissingEndTag(feature.to_string()).into());
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Members<'a> {
+    type Item = Result<MemberXml<'a>, quick_xml::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let xml = self.xml;
+        loop {
+            let before = self.position();
+            let event = match self.reader.read_event() {
+                Ok(event) => event,
+                Err(e) => return Some(Err(e)),
+            };
+            match event {
+                Event::Start(start) => {
+                    match self.depth {
+                        0 => {
+                            self.epilogue =
+                                format!("</{}>", String::from_utf8_lossy(start.name().as_ref()));
+                        }
+                        1 if start.local_name().as_ref() == b"hasMember" => {
+                            self.prologue.get_or_insert(&xml[..before]);
+                            self.member_start = before;
+                            self.feature = None;
+                            self.military = None;
+                        }
+                        // the feature is skipped as a whole, it is
+                        // deserialized on its own, only the military flags
+                        // are read from the events
+                        2 if self.feature.is_none() => {
+                            let read = if MilitaryFields::covers(start.local_name().as_ref()) {
+                                self.read_military_flags(local_name(&xml[before..]))
+                                    .map(|military| self.military = military)
+                            } else {
+                                self.reader.read_to_end(start.name()).map(|_| ())
+                            };
+                            if let Err(e) = read {
+                                return Some(Err(e));
+                            }
+                            self.feature = Some((before, self.position()));
+                            continue;
+                        }
+                        _ => (),
+                    }
+                    self.depth += 1;
+                }
+                Event::Empty(_) if self.depth == 2 && self.feature.is_none() => {
+                    self.feature = Some((before, self.position()));
+                }
+                Event::End(end) => {
+                    self.depth = self.depth.saturating_sub(1);
+                    if self.depth == 1 && end.local_name().as_ref() == b"hasMember" {
+                        let Some((feature_start, feature_end)) = self.feature.take() else {
+                            continue;
+                        };
+                        let feature = &xml[feature_start..feature_end];
+                        return Some(Ok(MemberXml {
+                            feature: local_name(feature),
+                            member: &xml[self.member_start..self.position()],
+                            xml: feature,
+                            military: self.military.take(),
+                        }));
+                    }
+                }
+                Event::Eof => return None,
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Local name of the element `xml` starts with, e.g. `Navaid` of
+/// `<aixm:Navaid gml:id="...">`.
+fn local_name(xml: &str) -> &str {
+    let name = xml
+        .trim_start()
+        .trim_start_matches('<')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or_default();
+    name.rsplit_once(':')
+        .map_or(name, |(_, local_name)| local_name)
+}
+
+/// Everything read from a dataset by [`parse_dataset`].
+#[derive(Debug, Default)]
+pub struct ParsedDataset {
+    /// Members of the feature types the `aixm` crate covers
+    pub members: Vec<Member>,
+    pub route_segments: Vec<RouteSegment>,
+    /// Route segments without a known route or course
+    pub skipped_segments: usize,
+    pub services: Services,
+    pub airspaces: Vec<AixmAirspace>,
+    /// Airspaces with unsupported geometry
+    pub skipped_airspaces: usize,
+    pub military: Vec<MilitaryEntity>,
+    pub localizers: Vec<Localizer>,
+    /// Localizers without designator, frequency, position or true bearing
+    pub skipped_localizers: usize,
+    /// Number of members per feature type, e.g. `Airspace` or `GeoBorder`,
+    /// including types none of the parsers reads
+    pub member_counts: BTreeMap<String, usize>,
+}
+
+/// Reads the members of an AIXM basic message in a single pass over the
+/// document, each member deserialized once by the parser of its feature
+/// type: routes, services, airspaces and ILS components by the parsers of
+/// this crate, the other features as [`Member`]s of the `aixm` crate. `xml`
+/// has to be well-formed, see [`Members::new`].
+pub fn parse_dataset(xml: &str) -> Result<ParsedDataset, DeError> {
+    match resume_parse_dataset(xml, None, || false)? {
+        ParseProgress::Done(parsed) => Ok(parsed),
+        ParseProgress::Interrupted(_) => unreachable!("never interrupted"),
+    }
+}
+
+/// Outcome of [`resume_parse_dataset`].
+pub enum ParseProgress {
+    Done(ParsedDataset),
+    Interrupted(ParseCheckpoint),
+}
+
+/// Progress of an interrupted [`resume_parse_dataset`], with everything read
+/// up to the member it stopped after.
+pub struct ParseCheckpoint {
+    /// SHA-256 of the document, a checkpoint of another one is not resumed
+    checksum: Vec<u8>,
+    /// Byte offset after the last member read
+    offset: usize,
+    /// Number of members read
+    members: usize,
+    prologue_len: usize,
+    epilogue: String,
+    parser: Box<DatasetParser>,
+}
+
+impl ParseCheckpoint {
+    /// Number of members read before the parse was interrupted.
+    pub fn members(&self) -> usize {
+        self.members
+    }
+
+    /// Byte offset in the document the parse resumes at.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Whether the checkpoint was taken parsing `xml`.
+    pub fn matches(&self, xml: &str) -> bool {
+        self.offset <= xml.len() && Sha256::digest(xml.as_bytes()).as_slice() == self.checksum
+    }
+}
+
+/// Like [`parse_dataset`], but checks `interrupted` after each member and
+/// stops with a [`ParseCheckpoint`] once it returns `true`. A `checkpoint`
+/// of an earlier parse of the same document is resumed from its offset
+/// instead of reading the members before again, one of another document is
+/// ignored.
+pub fn resume_parse_dataset(
+    xml: &str,
+    checkpoint: Option<ParseCheckpoint>,
+    interrupted: impl Fn() -> bool,
+) -> Result<ParseProgress, DeError> {
+    let (mut members, mut parser, mut count) = match checkpoint {
+        Some(checkpoint) if checkpoint.matches(xml) => (
+            Members::resume(
+                xml,
+                checkpoint.offset,
+                checkpoint.prologue_len,
+                checkpoint.epilogue,
+            ),
+            checkpoint.parser,
+            checkpoint.members,
+        ),
+        _ => (Members::new(xml), Box::default(), 0),
+    };
+    let mut message = String::new();
+    while let Some(member) = members.next() {
+        parser.add(&member?, &members, &mut message)?;
+        count += 1;
+        if interrupted() {
+            return Ok(ParseProgress::Interrupted(ParseCheckpoint {
+                checksum: Sha256::digest(xml.as_bytes()).to_vec(),
+                offset: members.position(),
+                members: count,
+                prologue_len: members.prologue.map_or(0, str::len),
+                epilogue: members.epilogue,
+                parser,
+            }));
+        }
+    }
+    Ok(ParseProgress::Done(parser.finish()))
+}
+
+/// State of [`resume_parse_dataset`] between the members.
+#[derive(Debug, Default)]
+struct DatasetParser {
+    parsed: ParsedDataset,
+    routes: RouteCollector,
+    services: ServiceCollector,
+    airspaces: AirspaceCollector,
+    localizers: LocalizerCollector,
+}
+
+impl DatasetParser {
+    /// Reads `member` of `members`, `message` is reused for the members
+    /// deserialized by the `aixm` crate.
+    fn add(
+        &mut self,
+        member: &MemberXml<'_>,
+        members: &Members<'_>,
+        message: &mut String,
+    ) -> Result<(), DeError> {
+        let parsed = &mut self.parsed;
+        // only the first member of a type allocates its name
+        match parsed.member_counts.get_mut(member.feature) {
+            Some(count) => *count += 1,
+            None => {
+                parsed.member_counts.insert(member.feature.to_string(), 1);
+            }
+        }
+        match member.feature {
+            "Route" | "RouteSegment" => self.routes.add(member.feature, member.xml)?,
+            "Unit"
+            | "RadioCommunicationChannel"
+            | "AirTrafficControlService"
+            | "GroundTrafficControlService"
+            | "InformationService" => self.services.add(member.feature, member.xml)?,
+            "Airspace" => self.airspaces.add(member.xml)?,
+            "Localizer" | "Glidepath" => self.localizers.add(member.feature, member.xml)?,
+            _ => {
+                parsed.military.extend(member.military.clone());
+                members.single_member_message(member.member, message);
+                parsed.members.extend(
+                    quick_xml::de::from_str::<MessageAixmBasicMessage>(message)?
+                        .message_has_member
+                        .into_iter()
+                        .map(|m| m.member),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ParsedDataset {
+        let mut parsed = self.parsed;
+        (parsed.route_segments, parsed.skipped_segments) = self.routes.finish();
+        parsed.services = self.services.finish();
+        (parsed.airspaces, parsed.skipped_airspaces) = self.airspaces.finish();
+        (parsed.localizers, parsed.skipped_localizers) = self.localizers.finish();
+        parsed
+    }
+}
+
+/// Cheap streaming check that `data` is a complete XML document, so
+/// truncated downloads and HTML error pages are told apart from broken AIXM
+/// before caching or deserializing them. Returns what is wrong otherwise.
+pub fn check_well_formed(data: &[u8]) -> Result<(), String> {
+    let mut reader = Reader::from_reader(data);
+    let mut depth = 0_usize;
+    let mut root = None;
+    loop {
+        match reader.read_event() {
+            Ok(Event::DocType(doctype)) if doctype.trim_ascii().eq_ignore_ascii_case(b"html") => {
+                return Err("received an HTML page instead".to_string());
+            }
+            Ok(Event::Start(start)) => {
+                root.get_or_insert_with(|| start.local_name().as_ref().to_ascii_lowercase());
+                depth += 1;
+            }
+            Ok(Event::Empty(empty)) => {
+                root.get_or_insert_with(|| empty.local_name().as_ref().to_ascii_lowercase());
+            }
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) => break,
+            Ok(_) => (),
+            Err(e) => {
+                return Err(format!("{e} at byte {}", reader.buffer_position()));
+            }
+        }
+    }
+
+    match root {
+        None => Err("no XML element found".to_string()),
+        Some(root) if root == b"html" => Err("received an HTML page instead".to_string()),
+        Some(_) if depth > 0 => Err(format!(
+            "ends with {depth} unclosed elements, the file is truncated"
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aixm_combine::EntityKind,
+        aixm_routes::{AirwayLevel, SegmentPath},
+    };
+
+    #[test]
+    fn expected_prefixes_are_kept() {
+        let xml = r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.1/message" xmlns:aixm="http://www.aixm.aero/schema/5.1" xmlns:gml="http://www.opengis.net/gml/3.2"><message:hasMember><aixm:Navaid gml:id="n1"/></message:hasMember></message:AIXMBasicMessage>"#;
+        assert!(matches!(normalize_prefixes(xml), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn other_prefixes_are_renamed() {
+        let xml = r#"<msg:AIXMBasicMessage xmlns:msg="http://www.aixm.aero/schema/5.2/message" xmlns:a="http://www.aixm.aero/schema/5.2" xmlns:gml="http://www.opengis.net/gml/3.2"><msg:hasMember><a:Navaid gml:id="n1"><a:name>MUNICH</a:name></a:Navaid></msg:hasMember></msg:AIXMBasicMessage>"#;
+        assert_eq!(
+            normalize_prefixes(xml),
+            r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.2/message" xmlns:aixm="http://www.aixm.aero/schema/5.2" xmlns:gml="http://www.opengis.net/gml/3.2"><message:hasMember><aixm:Navaid gml:id="n1"><aixm:name>MUNICH</aixm:name></aixm:Navaid></message:hasMember></message:AIXMBasicMessage>"#
+        );
+    }
+
+    #[test]
+    fn default_namespace_is_prefixed() {
+        let xml = r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.1.1/message" xmlns:gml="http://www.opengis.net/gml/3.2"><message:hasMember><Navaid xmlns="http://www.aixm.aero/schema/5.1.1" gml:id="n1"><name>MUNICH</name></Navaid></message:hasMember></message:AIXMBasicMessage>"#;
+        assert_eq!(
+            normalize_prefixes(xml),
+            r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.1.1/message" xmlns:gml="http://www.opengis.net/gml/3.2"><message:hasMember><aixm:Navaid xmlns:aixm="http://www.aixm.aero/schema/5.1.1" gml:id="n1"><aixm:name>MUNICH</aixm:name></aixm:Navaid></message:hasMember></message:AIXMBasicMessage>"#
+        );
+    }
+
+    const MESSAGE: &str = r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.1/message" xmlns:aixm="http://www.aixm.aero/schema/5.1" xmlns:gml="http://www.opengis.net/gml/3.2" xmlns:xlink="http://www.w3.org/1999/xlink" gml:id="m1">
+  <gml:boundedBy/>
+  <message:hasMember>
+    <aixm:Route gml:id="r1">
+      <gml:identifier codeSpace="urn:uuid:">route-1</gml:identifier>
+      <aixm:timeSlice>
+        <aixm:RouteTimeSlice gml:id="r1-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:designatorPrefix>U</aixm:designatorPrefix>
+          <aixm:designatorSecondLetter>N</aixm:designatorSecondLetter>
+          <aixm:designatorNumber>850</aixm:designatorNumber>
+        </aixm:RouteTimeSlice>
+      </aixm:timeSlice>
+    </aixm:Route>
+  </message:hasMember>
+  <message:hasMember>
+    <aixm:RouteSegment gml:id="s1">
+      <aixm:timeSlice>
+        <aixm:RouteSegmentTimeSlice gml:id="s1-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:level>UPPER</aixm:level>
+          <aixm:start><aixm:EnRouteSegmentPoint><aixm:pointChoice_fixDesignatedPoint xlink:href="urn:uuid:fix-1"/></aixm:EnRouteSegmentPoint></aixm:start>
+          <aixm:end><aixm:EnRouteSegmentPoint><aixm:pointChoice_fixDesignatedPoint xlink:href="urn:uuid:fix-2"/></aixm:EnRouteSegmentPoint></aixm:end>
+          <aixm:routeFormed xlink:href="urn:uuid:route-1"/>
+        </aixm:RouteSegmentTimeSlice>
+      </aixm:timeSlice>
+    </aixm:RouteSegment>
+  </message:hasMember>
+  <message:hasMember>
+    <aixm:RouteSegment gml:id="s2">
+      <aixm:timeSlice>
+        <aixm:RouteSegmentTimeSlice gml:id="s2-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:routeFormed xlink:href="urn:uuid:unknown-route"/>
+        </aixm:RouteSegmentTimeSlice>
+      </aixm:timeSlice>
+    </aixm:RouteSegment>
+  </message:hasMember>
+  <message:hasMember>
+    <aixm:Airspace gml:id="a1">
+      <aixm:timeSlice>
+        <aixm:AirspaceTimeSlice gml:id="a1-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:type>CTR</aixm:type>
+          <aixm:designator>EDDM</aixm:designator>
+          <aixm:geometryComponent>
+            <aixm:AirspaceGeometryComponent>
+              <aixm:theAirspaceVolume>
+                <aixm:AirspaceVolume>
+                  <aixm:horizontalProjection>
+                    <aixm:Surface srsName="urn:ogc:def:crs:EPSG::4326">
+                      <gml:patches>
+                        <gml:PolygonPatch>
+                          <gml:exterior>
+                            <gml:LinearRing>
+                              <gml:posList>48.0 11.0 48.5 11.0 48.5 12.0 48.0 11.0</gml:posList>
+                            </gml:LinearRing>
+                          </gml:exterior>
+                        </gml:PolygonPatch>
+                      </gml:patches>
+                    </aixm:Surface>
+                  </aixm:horizontalProjection>
+                </aixm:AirspaceVolume>
+              </aixm:theAirspaceVolume>
+            </aixm:AirspaceGeometryComponent>
+          </aixm:geometryComponent>
+        </aixm:AirspaceTimeSlice>
+      </aixm:timeSlice>
+    </aixm:Airspace>
+  </message:hasMember>
+</message:AIXMBasicMessage>"#;
+
+    #[test]
+    fn members_are_found_without_other_elements() {
+        let members = Members::new(MESSAGE)
+            .map(|member| member.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            members
+                .iter()
+                .map(|member| member.feature)
+                .collect::<Vec<_>>(),
+            ["Route", "RouteSegment", "RouteSegment", "Airspace"]
+        );
+        for member in &members {
+            assert!(member.member.starts_with("<message:hasMember>"));
+            assert!(member.member.ends_with("</message:hasMember>"));
+            assert!(member.xml.starts_with(&format!("<aixm:{}", member.feature)));
+            assert!(member.xml.ends_with(&format!("</aixm:{}>", member.feature)));
+        }
+    }
+
+    #[test]
+    fn single_member_message_keeps_the_root_element() {
+        let mut members = Members::new(MESSAGE);
+        let route = members.next().unwrap().unwrap();
+        let mut message = String::new();
+        members.single_member_message(route.member, &mut message);
+        assert!(message.starts_with("<message:AIXMBasicMessage "));
+        assert!(message.contains("<gml:boundedBy/>"));
+        assert!(message.ends_with("</message:hasMember></message:AIXMBasicMessage>"));
+        assert_eq!(message.matches("<message:hasMember>").count(), 1);
+    }
+
+    #[test]
+    fn every_feature_is_read_in_one_pass() {
+        let parsed = parse_dataset(MESSAGE).unwrap();
+        assert!(parsed.members.is_empty());
+        assert_eq!(
+            parsed.route_segments,
+            [RouteSegment {
+                designator: "UN850".to_string(),
+                level: AirwayLevel::Upper,
+                path: SegmentPath::Fixes("fix-1".to_string(), "fix-2".to_string()),
+            }]
+        );
+        assert_eq!(parsed.skipped_segments, 1);
+        assert_eq!(parsed.airspaces.len(), 1);
+        assert_eq!(parsed.airspaces[0].designator, "EDDM");
+        assert_eq!(parsed.airspaces[0].outlines[0].len(), 4);
+        assert_eq!(
+            parsed.member_counts,
+            BTreeMap::from([
+                ("Airspace".to_string(), 1),
+                ("Route".to_string(), 1),
+                ("RouteSegment".to_string(), 2),
+            ])
+        );
+    }
+
+    fn interrupted_after(xml: &str, members: usize) -> ParseCheckpoint {
+        let read = std::cell::Cell::new(0);
+        let interrupted = || {
+            read.set(read.get() + 1);
+            read.get() == members
+        };
+        match resume_parse_dataset(xml, None, interrupted).unwrap() {
+            ParseProgress::Interrupted(checkpoint) => checkpoint,
+            ParseProgress::Done(_) => panic!("not interrupted"),
+        }
+    }
+
+    #[test]
+    fn interrupted_parse_resumes_after_the_last_member_read() {
+        let checkpoint = interrupted_after(MESSAGE, 2);
+        assert_eq!(checkpoint.members(), 2);
+        assert!(MESSAGE[checkpoint.offset()..].starts_with("\n  <message:hasMember>"));
+
+        let ParseProgress::Done(resumed) =
+            resume_parse_dataset(MESSAGE, Some(checkpoint), || false).unwrap()
+        else {
+            panic!("interrupted again");
+        };
+        let parsed = parse_dataset(MESSAGE).unwrap();
+        assert_eq!(resumed.route_segments, parsed.route_segments);
+        assert_eq!(resumed.skipped_segments, 1);
+        assert_eq!(resumed.airspaces.len(), 1);
+        assert_eq!(resumed.member_counts, parsed.member_counts);
+    }
+
+    #[test]
+    fn checkpoint_of_another_document_is_not_resumed() {
+        let checkpoint = interrupted_after(MESSAGE, 3);
+        let changed = MESSAGE.replace("UN850", "UN851");
+        assert!(!checkpoint.matches(&changed));
+
+        let ParseProgress::Done(parsed) =
+            resume_parse_dataset(&changed, Some(checkpoint), || false).unwrap()
+        else {
+            panic!("interrupted again");
+        };
+        assert_eq!(parsed.route_segments[0].designator, "UN851");
+        assert_eq!(parsed.member_counts.values().sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn military_flags_are_read_while_scanning() {
+        let xml = r#"<message:AIXMBasicMessage xmlns:message="http://www.aixm.aero/schema/5.1/message" xmlns:aixm="http://www.aixm.aero/schema/5.1" xmlns:gml="http://www.opengis.net/gml/3.2">
+  <message:hasMember>
+    <aixm:AirportHeliport gml:id="a1">
+      <gml:identifier codeSpace="urn:uuid:">airport-1</gml:identifier>
+      <aixm:timeSlice>
+        <aixm:AirportHeliportTimeSlice gml:id="a1-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:locationIndicatorICAO>ETSI</aixm:locationIndicatorICAO>
+          <aixm:controlType>MIL</aixm:controlType>
+        </aixm:AirportHeliportTimeSlice>
+      </aixm:timeSlice>
+    </aixm:AirportHeliport>
+  </message:hasMember>
+  <message:hasMember>
+    <aixm:Navaid gml:id="n1">
+      <aixm:timeSlice>
+        <aixm:NavaidTimeSlice gml:id="n1-1">
+          <aixm:interpretation>TEMPDELTA</aixm:interpretation>
+          <aixm:type>TACAN</aixm:type>
+          <aixm:designator>MIQ</aixm:designator>
+        </aixm:NavaidTimeSlice>
+      </aixm:timeSlice>
+      <aixm:timeSlice>
+        <aixm:NavaidTimeSlice gml:id="n1-2">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:type>VORTAC</aixm:type>
+          <aixm:designator>MIQ</aixm:designator>
+        </aixm:NavaidTimeSlice>
+      </aixm:timeSlice>
+    </aixm:Navaid>
+  </message:hasMember>
+  <message:hasMember>
+    <aixm:DesignatedPoint gml:id="p1">
+      <aixm:timeSlice>
+        <aixm:DesignatedPointTimeSlice gml:id="p1-1">
+          <aixm:interpretation>BASELINE</aixm:interpretation>
+          <aixm:designator>MIL01</aixm:designator>
+          <aixm:type>OTHER:MIL_REP</aixm:type>
+        </aixm:DesignatedPointTimeSlice>
+      </aixm:timeSlice>
+    </aixm:DesignatedPoint>
+  </message:hasMember>
+</message:AIXMBasicMessage>"#;
+        let military = Members::new(xml)
+            .map(|member| member.unwrap().military)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            military,
+            [
+                Some(MilitaryEntity {
+                    kind: EntityKind::Airport,
+                    designator: "ETSI".to_string(),
+                }),
+                None,
+                Some(MilitaryEntity {
+                    kind: EntityKind::Fix,
+                    designator: "MIL01".to_string(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn attribute_prefixes_are_renamed() {
+        let xml = r#"<aixm:Service xmlns:aixm="http://www.aixm.aero/schema/5.1" xmlns:g="http://www.opengis.net/gml/3.2" xmlns:xl="http://www.w3.org/1999/xlink" g:id="s1"><aixm:clientAirport xl:href="urn:uuid:1"/></aixm:Service>"#;
+        assert_eq!(
+            normalize_prefixes(xml),
+            r#"<aixm:Service xmlns:aixm="http://www.aixm.aero/schema/5.1" xmlns:gml="http://www.opengis.net/gml/3.2" xmlns:xlink="http://www.w3.org/1999/xlink" gml:id="s1"><aixm:clientAirport xlink:href="urn:uuid:1"/></aixm:Service>"#
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use geo::Point;
+use quick_xml::DeError;
+use serde::Deserialize;
+
+use crate::geometry::gml_points;
+
+const TEMPDELTA: &str = "TEMPDELTA";
+
+// Only the parts of the AIXM route model needed for the airway sections, the
+// `aixm` crate does not cover routes.
+
+#[derive(Debug, Deserialize)]
+struct Route {
+    #[serde(rename = "gml:identifier")]
+    identifier: String,
+    #[serde(rename = "aixm:timeSlice", default)]
+    time_slices: Vec<RouteTimeSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteTimeSlice {
+    #[serde(rename = "aixm:RouteTimeSlice")]
+    route: RouteTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:designatorPrefix")]
+    prefix: Option<String>,
+    #[serde(rename = "aixm:designatorSecondLetter")]
+    second_letter: Option<String>,
+    #[serde(rename = "aixm:designatorNumber")]
+    number: Option<String>,
+}
+
+impl RouteTimeSliceContent {
+    /// e.g. `UN850` or `T161`, nil elements are read as empty.
+    fn designator(&self) -> Option<String> {
+        let designator = [&self.prefix, &self.second_letter, &self.number]
+            .into_iter()
+            .flatten()
+            .map(|part| part.trim())
+            .collect::<String>();
+        (!designator.is_empty()).then_some(designator)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RouteSegmentMember {
+    #[serde(rename = "aixm:timeSlice", default)]
+    time_slices: Vec<SegmentTimeSlice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SegmentTimeSlice {
+    #[serde(rename = "aixm:RouteSegmentTimeSlice")]
+    segment: SegmentTimeSliceContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct SegmentTimeSliceContent {
+    #[serde(rename = "aixm:interpretation")]
+    interpretation: String,
+    #[serde(rename = "aixm:level")]
+    level: Option<String>,
+    #[serde(rename = "aixm:start")]
+    start: Option<SegmentEnd>,
+    #[serde(rename = "aixm:end")]
+    end: Option<SegmentEnd>,
+    #[serde(rename = "aixm:routeFormed")]
+    route_formed: Option<Href>,
+    #[serde(rename = "aixm:curveExtent")]
+    curve_extent: Option<CurveExtent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SegmentEnd {
+    #[serde(rename = "aixm:EnRouteSegmentPoint")]
+    point: Option<SegmentPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SegmentPoint {
+    #[serde(rename = "aixm:pointChoice_fixDesignatedPoint")]
+    fix: Option<Href>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Href {
+    #[serde(rename = "@xlink:href")]
+    href: String,
+}
+
+impl Href {
+    fn uuid(&self) -> &str {
+        self.href.trim_start_matches("urn:uuid:")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CurveExtent {
+    #[serde(rename = "aixm:Curve")]
+    curve: Curve,
+}
+
+#[derive(Debug, Deserialize)]
+struct Curve {
+    #[serde(rename = "@srsName")]
+    srs_name: Option<String>,
+    #[serde(rename = "gml:segments")]
+    segments: Segments,
+}
+
+#[derive(Debug, Deserialize)]
+struct Segments {
+    #[serde(rename = "gml:GeodesicString", default)]
+    geodesic_strings: Vec<PosList>,
+    #[serde(rename = "gml:LineStringSegment", default)]
+    line_strings: Vec<PosList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PosList {
+    #[serde(rename = "gml:posList")]
+    pos_list: Option<String>,
+    #[serde(rename = "gml:pos", default)]
+    pos: Vec<String>,
+}
+
+impl Curve {
+    fn points(&self) -> Option<Vec<Point>> {
+        let srs_name = self.srs_name.as_deref();
+        let mut points = vec![];
+        for pos_list in self
+            .segments
+            .geodesic_strings
+            .iter()
+            .chain(&self.segments.line_strings)
+        {
+            let segment = gml_points(pos_list.pos_list.as_deref(), &pos_list.pos, srs_name).ok()?;
+            // consecutive segments share their end point
+            let skip = usize::from(points.last() == segment.first());
+            points.extend(segment.into_iter().skip(skip));
+        }
+        (points.len() > 1).then_some(points)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AirwayLevel {
+    Lower,
+    Upper,
+    Both,
+}
+
+/// Course of a route segment, either its own geometry or the designated
+/// points (by UUID) it connects, to be resolved against the waypoints.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SegmentPath {
+    Curve(Vec<Point>),
+    Fixes(String, String),
+}
+
+/// Segment of an ATS route, with the designator of the route it forms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteSegment {
+    pub designator: String,
+    pub level: AirwayLevel,
+    pub path: SegmentPath,
+}
+
+/// Route segments of a dataset, read member by member and resolved against
+/// the routes once all members are read.
+#[derive(Debug, Default)]
+pub struct RouteCollector {
+    /// Designators of the routes by their `gml:identifier`
+    routes: HashMap<String, String>,
+    segments: Vec<SegmentTimeSliceContent>,
+}
+
+impl RouteCollector {
+    /// Reads a `Route` or `RouteSegment` feature. Temporary time slices are
+    /// ignored.
+    pub fn add(&mut self, feature: &str, xml: &str) -> Result<(), DeError> {
+        if feature == "Route" {
+            let route = quick_xml::de::from_str::<Route>(xml)?;
+            if let Some(designator) = route
+                .time_slices
+                .iter()
+                .map(|time_slice| &time_slice.route)
+                .find(|route| route.interpretation != TEMPDELTA)
+                .and_then(RouteTimeSliceContent::designator)
+            {
+                self.routes.insert(route.identifier, designator);
+            }
+        } else {
+            let segment = quick_xml::de::from_str::<RouteSegmentMember>(xml)?;
+            self.segments.extend(
+                segment
+                    .time_slices
+                    .into_iter()
+                    .map(|time_slice| time_slice.segment)
+                    .find(|segment| segment.interpretation != TEMPDELTA),
+            );
+        }
+        Ok(())
+    }
+
+    /// Route segments of the routes read, with the number of skipped ones
+    /// without a known route or course.
+    pub fn finish(self) -> (Vec<RouteSegment>, usize) {
+        let mut skipped = 0;
+        let route_segments = self
+            .segments
+            .iter()
+            .filter_map(|segment| {
+                let route_segment = resolve_segment(&self.routes, segment);
+                if route_segment.is_none() {
+                    skipped += 1;
+                }
+                route_segment
+            })
+            .collect();
+
+        (route_segments, skipped)
+    }
+}
+
+fn resolve_segment(
+    routes: &HashMap<String, String>,
+    segment: &SegmentTimeSliceContent,
+) -> Option<RouteSegment> {
+    let designator = routes.get(segment.route_formed.as_ref()?.uuid())?.clone();
+    let level = match segment.level.as_deref() {
+        Some("UPPER") => AirwayLevel::Upper,
+        Some("LOWER") => AirwayLevel::Lower,
+        _ => AirwayLevel::Both,
+    };
+    let fix =
+        |end: Option<&SegmentEnd>| Some(end?.point.as_ref()?.fix.as_ref()?.uuid().to_string());
+    let path = match segment
+        .curve_extent
+        .as_ref()
+        .and_then(|extent| extent.curve.points())
+    {
+        Some(points) => SegmentPath::Curve(points),
+        None => SegmentPath::Fixes(fix(segment.start.as_ref())?, fix(segment.end.as_ref())?),
+    };
+
+    Some(RouteSegment {
+        designator,
+        level,
+        path,
+    })
+}
@@ -0,0 +1,404 @@
+//! Content-addressed store of the written files: every version is kept once as
+//! a blob named by its SHA-256, and every run records the blobs of its files.
+//! Runs can so be compared and restored independently of the backups next to
+//! the files, and files unchanged since the last run are not rewritten.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::{fs, sync::mpsc, task::spawn_blocking};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{
+    EuroscopeFile, Message,
+    cancel::CancelToken,
+    config::WriteMode,
+    error::{AiracUpdaterResult, ReadArtifactSnafu, RunNotFoundSnafu, WriteArtifactSnafu},
+    file_diff::FileDiff,
+    staging::{WritePlan, write_files},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ArtifactStoreConfig {
+    pub enabled: bool,
+    /// Store directory, `airac-aixm-updater/artifacts` in the platform data
+    /// directory if unset
+    pub dir: Option<PathBuf>,
+    /// Runs kept per package, older ones and the blobs only they refer to
+    /// are removed after every run. `0` keeps all runs
+    pub keep_runs: usize,
+}
+
+impl Default for ArtifactStoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+            keep_runs: 10,
+        }
+    }
+}
+
+static ARTIFACT_STORE: OnceLock<ArtifactStoreConfig> = OnceLock::new();
+
+/// Sets the store location for the rest of the process, before any run.
+pub fn init(config: ArtifactStoreConfig) {
+    if ARTIFACT_STORE.set(config).is_err() {
+        debug!("artifact store config already set");
+    }
+}
+
+/// SHA-256 of `data` in lowercase hex.
+pub fn content_hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut hash, byte| {
+            hash.push_str(&format!("{byte:02x}"));
+            hash
+        })
+}
+
+/// File of a run and the blob of its content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StoredFile {
+    pub path: PathBuf,
+    pub hash: String,
+    pub size: u64,
+}
+
+/// Files written by a run, including the ones skipped as unchanged.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunRecord {
+    pub run: Uuid,
+    pub time: DateTime<Utc>,
+    pub prf: PathBuf,
+    pub amdt: u32,
+    pub files: Vec<StoredFile>,
+}
+
+impl RunRecord {
+    pub fn file(&self, path: &Path) -> Option<&StoredFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+}
+
+pub struct ArtifactStore {
+    dir: PathBuf,
+    keep_runs: usize,
+}
+
+impl ArtifactStore {
+    /// The configured store, `None` if disabled or there is no data
+    /// directory.
+    pub fn open() -> Option<Self> {
+        let config = ARTIFACT_STORE.get_or_init(ArtifactStoreConfig::default);
+        if !config.enabled {
+            return None;
+        }
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::data_dir()?
+                .join("airac-aixm-updater")
+                .join("artifacts"),
+        };
+        Some(Self {
+            dir,
+            keep_runs: config.keep_runs,
+        })
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir
+            .join("blobs")
+            .join(hash.get(..2).unwrap_or_default())
+            .join(hash)
+    }
+
+    /// Adds `data` unless stored already, returning its hash.
+    pub async fn put(&self, data: &[u8]) -> AiracUpdaterResult<String> {
+        let hash = content_hash(data);
+        let path = self.blob_path(&hash);
+        if fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(hash);
+        }
+        write_atomic(&path, data).await?;
+        Ok(hash)
+    }
+
+    pub async fn get(&self, hash: &str) -> AiracUpdaterResult<Vec<u8>> {
+        let path = self.blob_path(hash);
+        fs::read(&path).await.context(ReadArtifactSnafu { path })
+    }
+
+    /// Stores the content of `files` and records them as written by a run of
+    /// the package `prf`, then prunes the runs beyond the ones kept.
+    pub async fn record(
+        &self,
+        run: Uuid,
+        prf: &Path,
+        amdt: u32,
+        files: impl IntoIterator<Item = &EuroscopeFile>,
+    ) -> AiracUpdaterResult<RunRecord> {
+        let mut stored = vec![];
+        for file in files {
+            let Some(content) = file.serialize() else {
+                continue;
+            };
+            stored.push(StoredFile {
+                path: file.path().to_path_buf(),
+                hash: self.put(&content).await?,
+                size: content.len() as u64,
+            });
+        }
+        let record = RunRecord {
+            run,
+            time: Utc::now(),
+            prf: prf.to_path_buf(),
+            amdt,
+            files: stored,
+        };
+        // a batch shares the run id, so the package is part of the key
+        let package = content_hash(prf.as_os_str().as_encoded_bytes());
+        let path = self.dir.join("runs").join(format!(
+            "{}_{run}_{}.json",
+            record.time.format("%Y%m%d_%H%M%S"),
+            &package[..12]
+        ));
+        // serializing paths, strings and numbers cannot fail
+        let json = serde_json::to_vec_pretty(&record).unwrap_or_default();
+        write_atomic(&path, &json).await?;
+        if self.keep_runs > 0 {
+            self.prune(prf).await;
+        }
+        Ok(record)
+    }
+
+    /// Removes the runs of `prf` beyond the newest [`Self::keep_runs`], and
+    /// the blobs no remaining run refers to. Failures are only logged, the
+    /// store is consistent either way.
+    async fn prune(&self, prf: &Path) {
+        let mut records = self.records().await;
+        let outdated = records
+            .iter()
+            .filter(|(_, record)| record.prf == prf)
+            .skip(self.keep_runs)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        if outdated.is_empty() {
+            return;
+        }
+        for path in &outdated {
+            if let Err(e) = fs::remove_file(path).await {
+                warn!("Could not remove run record {}: {e}", path.display());
+            }
+        }
+        records.retain(|(path, _)| !outdated.contains(path));
+
+        let referenced = records
+            .iter()
+            .flat_map(|(_, record)| record.files.iter().map(|file| file.hash.as_str()))
+            .collect::<HashSet<_>>();
+        let Ok(mut prefixes) = fs::read_dir(self.dir.join("blobs")).await else {
+            return;
+        };
+        while let Ok(Some(prefix)) = prefixes.next_entry().await {
+            let Ok(mut blobs) = fs::read_dir(prefix.path()).await else {
+                continue;
+            };
+            while let Ok(Some(blob)) = blobs.next_entry().await {
+                let is_referenced = blob
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|hash| referenced.contains(hash));
+                if is_referenced {
+                    continue;
+                }
+                if let Err(e) = fs::remove_file(blob.path()).await {
+                    warn!("Could not remove blob {}: {e}", blob.path().display());
+                }
+            }
+        }
+        debug!(
+            "Pruned {} runs of {} from the artifact store",
+            outdated.len(),
+            prf.display()
+        );
+    }
+
+    /// Recorded runs, of the package `prf` only if given, newest first.
+    pub async fn runs(&self, prf: Option<&Path>) -> Vec<RunRecord> {
+        self.records()
+            .await
+            .into_iter()
+            .map(|(_, record)| record)
+            .filter(|record| prf.is_none_or(|prf| record.prf == prf))
+            .collect()
+    }
+
+    /// Every run record with its file, newest first.
+    async fn records(&self) -> Vec<(PathBuf, RunRecord)> {
+        let mut runs = vec![];
+        let Ok(mut entries) = fs::read_dir(self.dir.join("runs")).await else {
+            return runs;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let record = fs::read(&path)
+                .await
+                .ok()
+                .and_then(|json| serde_json::from_slice::<RunRecord>(&json).ok());
+            match record {
+                Some(record) => runs.push((path, record)),
+                None => warn!("Skipping unreadable run record {}", path.display()),
+            }
+        }
+        runs.sort_by(|(_, a), (_, b)| b.time.cmp(&a.time));
+        runs
+    }
+
+    /// Records of the run whose id starts with `run`, e.g. the first eight
+    /// characters, one per package of a batch, of `prf` only if given.
+    pub async fn find_run(
+        &self,
+        run: &str,
+        prf: Option<&Path>,
+    ) -> AiracUpdaterResult<Vec<RunRecord>> {
+        let runs = self.runs(prf).await;
+        let found = runs
+            .iter()
+            .find(|record| record.run.to_string().starts_with(run))
+            .map(|record| record.run)
+            .context(RunNotFoundSnafu { run })?;
+        Ok(runs
+            .into_iter()
+            .filter(|record| record.run == found)
+            .collect())
+    }
+
+    /// Whether `content` is what the last run of `prf` wrote to `path` and
+    /// the file still holds it, i.e. writing it again changes nothing.
+    pub async fn is_unchanged(&self, prf: &Path, path: &Path, content: &[u8]) -> bool {
+        let Some(last) = self
+            .runs(Some(prf))
+            .await
+            .into_iter()
+            .find_map(|record| record.file(path).cloned())
+        else {
+            return false;
+        };
+        if last.hash != content_hash(content) {
+            return false;
+        }
+        fs::read(path)
+            .await
+            .is_ok_and(|current| content_hash(&current) == last.hash)
+    }
+
+    /// Line diffs of the files that differ between two runs, given by the
+    /// records of their packages, files only in one of them against an empty
+    /// file. Blobs of equal hash are not read.
+    pub async fn diff_runs(
+        &self,
+        from: &[RunRecord],
+        to: &[RunRecord],
+    ) -> AiracUpdaterResult<Vec<FileDiff>> {
+        let mut paths = from
+            .iter()
+            .chain(to)
+            .flat_map(|record| &record.files)
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+
+        let stored = |records: &[RunRecord], path: &Path| {
+            records.iter().find_map(|record| record.file(path)).cloned()
+        };
+        let mut diffs = vec![];
+        for path in paths {
+            let (old, new) = (stored(from, &path), stored(to, &path));
+            if old.as_ref().map(|file| &file.hash) == new.as_ref().map(|file| &file.hash) {
+                continue;
+            }
+            let old = match old {
+                Some(file) => self.get(&file.hash).await?,
+                None => vec![],
+            };
+            let new = match new {
+                Some(file) => self.get(&file.hash).await?,
+                None => vec![],
+            };
+            let diff = spawn_blocking(move || {
+                FileDiff::new(
+                    &path,
+                    &String::from_utf8_lossy(&old),
+                    &String::from_utf8_lossy(&new),
+                )
+            })
+            .await?;
+            diffs.push(diff);
+        }
+        Ok(diffs)
+    }
+
+    /// Writes the files of `record` back, keeping the current ones as backups
+    /// like a run does.
+    pub async fn restore_run(
+        &self,
+        record: &RunRecord,
+        tx: mpsc::Sender<Message>,
+    ) -> AiracUpdaterResult {
+        let mut files = vec![];
+        for file in &record.files {
+            files.push(EuroscopeFile::Generated {
+                path: file.path.clone(),
+                content: self.get(&file.hash).await?,
+            });
+        }
+        let package_dir = record
+            .prf
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        tx.send(Message::info(format!(
+            "Restoring {} files of run {} ({})",
+            files.len(),
+            record.run,
+            record.time.format("%Y-%m-%d %H:%M:%S UTC")
+        )))
+        .await?;
+        let plan = WritePlan::new(&files, WriteMode::Backup, Uuid::new_v4()).await?;
+        write_files(package_dir, &plan, false, &CancelToken::default(), tx).await
+    }
+}
+
+/// Writes next to `path` first, so an interrupted write never leaves a
+/// truncated blob or record behind.
+async fn write_atomic(path: &Path, content: &[u8]) -> AiracUpdaterResult {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .context(WriteArtifactSnafu { path: dir })?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, content)
+        .await
+        .context(WriteArtifactSnafu { path: &partial })?;
+    fs::rename(&partial, path)
+        .await
+        .context(WriteArtifactSnafu { path })
+}
@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use vatsim_parser::sct::Sct;
+
+use crate::{
+    aixm_airspaces::AixmAirspace, aixm_combine::EuroscopeFile, geometry::to_sct_coordinates,
+};
+
+/// Colour of airspaces without a type
+const DEFAULT_GEO_COLOR: &str = "AIRSPACE";
+
+/// Path of an Aurora file next to the .sct, e.g. `EDMM.sct.aau_aurora.vor`.
+fn aurora_path(sct_path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".aau_aurora.{extension}"));
+    sct_path.with_file_name(file_name)
+}
+
+/// Semicolon separated Aurora line, terminated by a semicolon.
+fn aurora_line<'a>(fields: impl IntoIterator<Item = &'a str>) -> String {
+    fields
+        .into_iter()
+        .map(|field| format!("{field};"))
+        .collect::<String>()
+}
+
+/// Geography lines of the outlines of `airspaces`, one per boundary segment,
+/// coloured by the airspace type (e.g. `CTR`) so it can be mapped to a colour
+/// of the Aurora profile.
+fn geo_lines(airspaces: &[AixmAirspace]) -> Vec<String> {
+    airspaces
+        .iter()
+        .flat_map(|airspace| {
+            let color = airspace
+                .airspace_type
+                .as_deref()
+                .unwrap_or(DEFAULT_GEO_COLOR);
+            airspace.outlines.iter().flat_map(move |outline| {
+                outline.windows(2).map(move |segment| {
+                    let (start_lat, start_lon) = to_sct_coordinates(segment[0]);
+                    let (end_lat, end_lon) = to_sct_coordinates(segment[1]);
+                    aurora_line([start_lat.as_str(), &start_lon, &end_lat, &end_lon, color])
+                })
+            })
+        })
+        .collect()
+}
+
+/// Renders the airports, navaids, fixes and runways of the combined .sct as
+/// an Aurora `.isc` with one include file per section, so controllers on
+/// Aurora get the same AIXM data as those on EuroScope. The outlines of the
+/// AIXM `airspaces` are written as geography (`.gts`).
+pub fn render_aurora(sct_path: &Path, sct: &Sct, airspaces: &[AixmAirspace]) -> Vec<EuroscopeFile> {
+    let airports = sct.airports.iter().map(|airport| {
+        let (lat, lon) = to_sct_coordinates(airport.coordinate);
+        aurora_line([airport.designator.as_str(), &lat, &lon])
+    });
+    let vors = sct.vors.iter().map(|vor| {
+        let (lat, lon) = to_sct_coordinates(vor.coordinate);
+        aurora_line([vor.designator.as_str(), &vor.frequency, &lat, &lon])
+    });
+    let ndbs = sct.ndbs.iter().map(|ndb| {
+        let (lat, lon) = to_sct_coordinates(ndb.coordinate);
+        aurora_line([ndb.designator.as_str(), &ndb.frequency, &lat, &lon])
+    });
+    let fixes = sct.fixes.iter().map(|fix| {
+        let (lat, lon) = to_sct_coordinates(fix.coordinate);
+        aurora_line([fix.designator.as_str(), &lat, &lon])
+    });
+    let runways = sct.runways.iter().map(|runway| {
+        let (start_lat, start_lon) = to_sct_coordinates(runway.location.start.into());
+        let (end_lat, end_lon) = to_sct_coordinates(runway.location.end.into());
+        aurora_line([
+            runway.aerodrome.as_str(),
+            &runway.designators.0,
+            &runway.designators.1,
+            &runway.headings.0.to_string(),
+            &runway.headings.1.to_string(),
+            &start_lat,
+            &start_lon,
+            &end_lat,
+            &end_lon,
+        ])
+    });
+
+    let sections: [(&str, &str, Vec<String>); 6] = [
+        ("[AIRPORT]", "apt", airports.collect()),
+        ("[VOR]", "vor", vors.collect()),
+        ("[NDB]", "ndb", ndbs.collect()),
+        ("[FIXES]", "fix", fixes.collect()),
+        ("[RUNWAY]", "rwy", runways.collect()),
+        ("[GEO]", "gts", geo_lines(airspaces)),
+    ];
+
+    let mut isc = format!("[INFO]\n{}\n", sct.info.name);
+    let mut files = vec![];
+    for (section, extension, lines) in sections {
+        let path = aurora_path(sct_path, extension);
+        let include = path.file_name().unwrap_or_default().to_string_lossy();
+        isc.push_str(&format!("\n{section}\nF;{include}\n"));
+        files.push(EuroscopeFile::Generated {
+            content: lines
+                .iter()
+                .map(|line| format!("{line}\n"))
+                .collect::<String>()
+                .into_bytes(),
+            path,
+        });
+    }
+
+    std::iter::once(EuroscopeFile::Generated {
+        path: aurora_path(sct_path, "isc"),
+        content: isc.into_bytes(),
+    })
+    .chain(files)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Point;
+
+    use super::*;
+
+    #[test]
+    fn airspace_outlines_are_written_as_segments() {
+        let airspace = AixmAirspace {
+            designator: "EDDM".to_string(),
+            airspace_type: Some("CTR".to_string()),
+            class: Some("D".to_string()),
+            outlines: vec![vec![
+                Point::new(11.0, 48.0),
+                Point::new(12.0, 48.5),
+                Point::new(11.0, 48.0),
+            ]],
+        };
+        assert_eq!(
+            geo_lines(&[airspace]),
+            [
+                "N048.00.00.000;E011.00.00.000;N048.30.00.000;E012.00.00.000;CTR;",
+                "N048.30.00.000;E012.00.00.000;N048.00.00.000;E011.00.00.000;CTR;",
+            ]
+        );
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+
+use snafu::ensure;
+use tokio::sync::watch;
+use tracing::error;
+
+use crate::error::{AiracUpdaterResult, CancelledSnafu};
+
+/// Cancellation of a running job, shared between the UI and the job.
+#[derive(Debug, Clone)]
+pub struct CancelToken(Arc<watch::Sender<bool>>);
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self(Arc::new(watch::Sender::new(false)))
+    }
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Fails with [`Error::Cancelled`](crate::error::Error::Cancelled) once
+    /// cancelled, to be checked between steps that cannot be interrupted.
+    pub fn check(&self) -> AiracUpdaterResult {
+        ensure!(!self.is_cancelled(), CancelledSnafu);
+        Ok(())
+    }
+
+    /// Completes once cancelled.
+    pub async fn cancelled(&self) {
+        if let Err(e) = self.0.subscribe().wait_for(|cancelled| *cancelled).await {
+            error!("{e}");
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Backpressure of the progress channel: how full it gets and for how long
+//! senders had to wait for the GUI or command line to take messages.
+
+use std::{fmt, time::Duration};
+
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time::{Instant, MissedTickBehavior, interval},
+};
+use tracing::error;
+
+/// How often the queue depth is sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChannelMetrics {
+    pub capacity: usize,
+    pub max_depth: usize,
+    pub mean_depth: f64,
+    /// Time the channel was full, i.e. every send blocked
+    pub full: Duration,
+    /// Longest stretch the channel stayed full
+    pub longest_full: Duration,
+    pub sampled: Duration,
+}
+
+impl fmt::Display for ChannelMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let share = if self.sampled.is_zero() {
+            0.0
+        } else {
+            self.full.as_secs_f64() / self.sampled.as_secs_f64() * 100.0
+        };
+        write!(
+            f,
+            "Message channel: max depth {}/{}, mean depth {:.1}, sends blocked for {:.1?} ({share:.1}% of the run, longest {:.1?})",
+            self.max_depth, self.capacity, self.mean_depth, self.full, self.longest_full
+        )
+    }
+}
+
+/// Samples the queue depth of a channel until [`Self::finish`]ed.
+pub struct ChannelMonitor {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<ChannelMetrics>,
+}
+
+impl ChannelMonitor {
+    /// Starts sampling `tx`. Only a weak sender is kept, so the channel still
+    /// closes once all other senders are dropped.
+    pub fn start<T: Send + 'static>(tx: &mpsc::Sender<T>) -> Self {
+        let (stop, mut stopped) = oneshot::channel();
+        let weak = tx.downgrade();
+        let capacity = tx.max_capacity();
+        let handle = tokio::spawn(async move {
+            let mut metrics = ChannelMetrics {
+                capacity,
+                ..ChannelMetrics::default()
+            };
+            let (mut samples, mut depth_sum) = (0_u64, 0_u64);
+            let mut full_since = None::<Instant>;
+            let started = Instant::now();
+            let mut ticks = interval(SAMPLE_INTERVAL);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = &mut stopped => break,
+                    _ = ticks.tick() => {}
+                }
+                let Some(tx) = weak.upgrade() else {
+                    break;
+                };
+                let depth = capacity - tx.capacity();
+                drop(tx);
+
+                samples += 1;
+                depth_sum += depth as u64;
+                metrics.max_depth = metrics.max_depth.max(depth);
+                let now = Instant::now();
+                match (depth == capacity, full_since) {
+                    (true, None) => full_since = Some(now),
+                    (false, Some(since)) => {
+                        metrics.full += now - since;
+                        metrics.longest_full = metrics.longest_full.max(now - since);
+                        full_since = None;
+                    }
+                    _ => (),
+                }
+            }
+            if let Some(since) = full_since {
+                metrics.full += since.elapsed();
+                metrics.longest_full = metrics.longest_full.max(since.elapsed());
+            }
+            if samples > 0 {
+                metrics.mean_depth = depth_sum as f64 / samples as f64;
+            }
+            metrics.sampled = started.elapsed();
+            metrics
+        });
+        Self { stop, handle }
+    }
+
+    pub async fn finish(self) -> Option<ChannelMetrics> {
+        let Self { stop, handle } = self;
+        // dropping the sender stops the monitor as well
+        drop(stop);
+        match handle.await {
+            Ok(metrics) => Some(metrics),
+            Err(e) => {
+                error!("{e}");
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,602 @@
+use std::{
+    io::{self, IsTerminal as _, Write as _},
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use airac_aixm_updater_core::{
+    CLOCK_SKEW_TOLERANCE_SECS, MESSAGE_CHANNEL_CAPACITY, Message, RunMode,
+    airac::AiracCycle,
+    aixm_combine::EntityType,
+    aixm_dfs::{DatasetRelease, dfs_clock_skew, fetch_dfs_datasets},
+    artifact_store::{ArtifactStore, RunRecord},
+    cancel::CancelToken,
+    config::Config,
+    cycle_diff::print_cycle_diff,
+    error::{
+        AiracUpdaterResult, ApplyFailedSnafu, ArtifactStoreDisabledSnafu,
+        ConfirmationUnavailableSnafu, ProfileNotFoundSnafu, SerializeDatasetListSnafu,
+    },
+    error_summary::ErrorSummary,
+    file_diff::{DiffLineKind, FileDiff},
+    load_es::newer_sibling_sector,
+    message_log::MessageLog,
+    mirror::{serve_mirror, sync_mirror},
+    pipeline::Pipeline,
+    report::{ChangeReport, ReportFormat},
+    spawn_batch,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use snafu::{OptionExt as _, ResultExt as _, ensure};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::{JoinHandle, spawn_blocking},
+};
+use tracing::{Level, debug, error, info, trace, warn};
+use uuid::Uuid;
+
+use crate::restore::{find_backups, find_orphaned, restore_backup};
+
+/// Update VATSIM sector files from DFS AIXM data. Starts the GUI when no
+/// command is given.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    /// Config profile to use instead of the active one
+    #[arg(long, global = true)]
+    pub(crate) profile: Option<String>,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+impl Cli {
+    pub(crate) fn load_config(&self) -> AiracUpdaterResult<Config> {
+        let mut config = Config::load()?;
+        if let Some(profile) = &self.profile {
+            ensure!(
+                config.profiles.contains_key(profile),
+                ProfileNotFoundSnafu { profile }
+            );
+            config.active_profile.clone_from(profile);
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Update the sector files referenced by a EuroScope .prf, like the GUI
+    Apply {
+        /// EuroScope .prf of the sector package. Several packages are updated
+        /// one after the other, downloading the AIXM data only once
+        #[arg(required = true)]
+        prfs: Vec<PathBuf>,
+        /// DFS amendment to use, 0 being the current one (see list-datasets)
+        #[arg(long, default_value_t = 0)]
+        amdt: u32,
+        /// Write the changes as JSON (see schema/change-report.v1.schema.json),
+        /// or as CSV if the file name ends in `.csv`
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Only report the changes, without writing any files
+        #[arg(long)]
+        dry_run: bool,
+        /// Only score the .sct files against AIXM, without writing any files,
+        /// and append the score to `<name>.prf.aau_quality.jsonl`
+        #[arg(long, conflicts_with = "dry_run")]
+        verify: bool,
+        /// Entity types left as they are, in addition to the ones disabled in
+        /// the profile, e.g. `--skip fixes,routes`
+        #[arg(long, value_enum, value_delimiter = ',')]
+        skip: Vec<EntityType>,
+        /// Only update these entity types, the ones disabled in the profile
+        /// included, e.g. `--only vor,ndb` for a navaid frequency fix
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "skip")]
+        only: Vec<EntityType>,
+        /// Write the files without asking, despite suspicious results as well
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Restore sector files left missing or empty by a crashed run from their
+    /// newest backup, asking for every file
+    Recover {
+        /// EuroScope .prf of the sector package
+        prf: PathBuf,
+        /// Restore without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Serve mirrored DFS AIXM datasets over HTTP in the same REST shape as aip.dfs.de
+    ServeMirror {
+        /// Directory containing the mirrored dataset list and datasets
+        dir: PathBuf,
+        /// Address to listen on, e.g. `0.0.0.0:8080` for other machines
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: SocketAddr,
+        /// Download the current datasets into the directory before serving
+        #[arg(long)]
+        sync: bool,
+    },
+    /// Print the AIXM differences between two AIRAC cycles, without touching
+    /// any sector files
+    DiffCycles {
+        /// Earlier cycle (YYNN)
+        #[arg(long)]
+        from: String,
+        /// Later cycle (YYNN)
+        #[arg(long)]
+        to: String,
+    },
+    /// List the DFS amendments with their datasets and releases, e.g. to find
+    /// the dataset names for the config
+    ListDatasets {
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// List the runs recorded in the artifact store, print the differences
+    /// between two of them or write the files of one back
+    Runs {
+        /// Only the runs of this EuroScope .prf
+        prf: Option<PathBuf>,
+        /// Print the line differences between two runs, given by the start of
+        /// their id
+        #[arg(long, num_args = 2, value_names = ["FROM", "TO"], conflicts_with = "restore")]
+        diff: Option<Vec<String>>,
+        /// Write the files of a run back, keeping the current ones as backups
+        #[arg(long, value_name = "RUN")]
+        restore: Option<String>,
+        /// Restore without asking
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Print identifier and validity of the AIRAC cycle of a date
+    Airac {
+        /// Cycle (YYNN) or date within it (YYYY-MM-DD), today if omitted
+        #[arg(value_parser = parse_airac_cycle)]
+        cycle: Option<AiracCycle>,
+        /// Also print the following cycles
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum OutputFormat {
+    Table,
+    Json,
+}
+
+/// Asks `question` on the terminal, anything but yes declines.
+#[expect(clippy::print_stdout, reason = "interactive prompt")]
+fn confirm(question: &str) -> bool {
+    print!("{question} [y/N] ");
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+#[expect(clippy::print_stdout, reason = "listing is the command's output")]
+fn print_releases(releases: &[DatasetRelease], format: OutputFormat) -> AiracUpdaterResult {
+    if format == OutputFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(releases).context(SerializeDatasetListSnafu)?
+        );
+        return Ok(());
+    }
+
+    let rows = releases
+        .iter()
+        .map(|release| {
+            [
+                release.amdt.to_string(),
+                release
+                    .effective
+                    .map(|date| date.to_string())
+                    .unwrap_or_default(),
+                release.groups.join(" / "),
+                release.name.clone(),
+                release.release_type.clone(),
+                release.filename.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["AMDT", "EFFECTIVE", "GROUP", "NAME", "TYPE", "FILENAME"].map(String::from);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(column, width)| format!("{column:width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    }
+
+    Ok(())
+}
+
+fn parse_airac_cycle(cycle: &str) -> Result<AiracCycle, String> {
+    AiracCycle::parse(cycle).ok_or_else(|| format!("expected YYNN or YYYY-MM-DD, got {cycle}"))
+}
+
+/// One line per cycle: identifier, effective and expiry date.
+#[expect(clippy::print_stdout, reason = "cycles are the command's output")]
+fn print_airac_cycles(first: AiracCycle, count: u32) {
+    for cycle in (0..count).map(|n| first.offset(i64::from(n))) {
+        println!("{cycle}\t{}\t{}", cycle.effective(), cycle.expires());
+    }
+}
+
+/// One line per run: id, time, amendment, .prf and number of files.
+#[expect(clippy::print_stdout, reason = "runs are the command's output")]
+fn print_runs(runs: &[RunRecord]) {
+    for record in runs {
+        println!(
+            "{}\t{}\tAMDT {}\t{}\t{} files",
+            record.run,
+            record.time.format("%Y-%m-%d %H:%M:%S UTC"),
+            record.amdt,
+            record.prf.display(),
+            record.files.len()
+        );
+    }
+}
+
+/// Unified diff of every file, hunks separated by `@@`.
+#[expect(
+    clippy::print_stdout,
+    reason = "the differences are the command's output"
+)]
+fn print_file_diffs(diffs: &[FileDiff]) {
+    if diffs.is_empty() {
+        println!("No differences");
+    }
+    for diff in diffs {
+        println!(
+            "--- {} (+{} -{})",
+            diff.path.display(),
+            diff.added,
+            diff.removed
+        );
+        for hunk in &diff.hunks {
+            println!("@@");
+            for line in hunk {
+                let sign = match line.kind {
+                    DiffLineKind::Context => ' ',
+                    DiffLineKind::Removed => '-',
+                    DiffLineKind::Added => '+',
+                };
+                println!("{sign}{}", line.text);
+            }
+        }
+    }
+}
+
+/// Summary of a run, available once all senders are dropped.
+struct RunSummary {
+    pipeline: Pipeline,
+    report: ChangeReport,
+    errors: ErrorSummary,
+    /// A confirmation was declined as there was no terminal to ask on
+    unconfirmed: bool,
+}
+
+/// Asks `question` on the terminal for an update waiting on `reply`, `None`
+/// if there is no terminal, e.g. in scripts.
+async fn ask(question: &'static str, reply: &mut oneshot::Sender<bool>) -> Option<bool> {
+    if !io::stdin().is_terminal() {
+        return None;
+    }
+    Some(tokio::select! {
+        answer = spawn_blocking(move || confirm(question)) => answer.unwrap_or(false),
+        () = reply.closed() => false,
+    })
+}
+
+/// Forwards progress messages of the shared pipeline code to the log and the
+/// message log, and returns the final stage of every item, the changes and
+/// the errors once all senders are dropped. Suspicious results and the files
+/// to write are confirmed on the terminal unless `yes` is set.
+fn spawn_message_logger(
+    mut message_log: Option<MessageLog>,
+    yes: bool,
+) -> (mpsc::Sender<Message>, JoinHandle<RunSummary>) {
+    let (tx, mut rx) = mpsc::channel::<Message>(MESSAGE_CHANNEL_CAPACITY);
+    let handle = tokio::spawn(async move {
+        let mut pipeline = Pipeline::default();
+        let mut report = ChangeReport::default();
+        let mut errors = ErrorSummary::default();
+        let mut unconfirmed = false;
+        while let Some(msg) = rx.recv().await {
+            if let Some(log) = &mut message_log {
+                if let Err(e) = log.write(&msg) {
+                    error!("{e}");
+                    message_log = None;
+                }
+            }
+            match msg.level {
+                Level::ERROR => error!("{}", msg.content),
+                Level::WARN => warn!("{}", msg.content),
+                Level::INFO => info!("{}", msg.content),
+                Level::DEBUG => debug!("{}", msg.content),
+                Level::TRACE => trace!("{}", msg.content),
+            }
+            if let Some((item, stage)) = &msg.stage {
+                pipeline.update(item, *stage);
+            }
+            if msg.level == Level::ERROR {
+                errors.add(msg.error.unwrap_or_default(), msg.content);
+            }
+            if let Some(review) = msg.review {
+                warn!("Changes cannot be reviewed on the command line, accepting all");
+                if review.reply.send(vec![]).is_err() {
+                    error!("Update ended before the review was answered");
+                }
+            }
+            if let Some(request) = msg.anomalies {
+                let mut reply = request.reply;
+                let question = "Write the files despite the suspicious results?";
+                let confirmed = if yes {
+                    true
+                } else if let Some(answer) = ask(question, &mut reply).await {
+                    answer
+                } else {
+                    unconfirmed = true;
+                    false
+                };
+                if reply.send(confirmed).is_err() {
+                    debug!("Update ended before the suspicious results were confirmed");
+                }
+            }
+            if let Some(request) = msg.writes {
+                for file in &request.plan.files {
+                    info!("{file}");
+                }
+                let mut reply = request.reply;
+                let confirmed = if yes {
+                    true
+                } else if let Some(answer) = ask("Write these files?", &mut reply).await {
+                    answer
+                } else {
+                    unconfirmed = true;
+                    false
+                };
+                if reply.send(confirmed).is_err() {
+                    debug!("Update ended before writing the files was confirmed");
+                }
+            }
+            if let Some(summaries) = &msg.summary {
+                for summary in summaries {
+                    info!("{summary}");
+                }
+            }
+            if let Some((file, changes)) = msg.changes {
+                for change in &changes {
+                    info!("{}: {change}", file.display());
+                }
+                report.add(&file, changes);
+            }
+        }
+        RunSummary {
+            pipeline,
+            report,
+            errors,
+            unconfirmed,
+        }
+    });
+    (tx, handle)
+}
+
+pub(crate) fn run(command: Command, config: Config) -> AiracUpdaterResult {
+    let rt = config.runtime.build()?;
+
+    rt.block_on(async move {
+        match command {
+            Command::Apply {
+                prfs,
+                amdt,
+                report,
+                dry_run,
+                verify,
+                skip,
+                only,
+                yes,
+            } => {
+                if let Ok(Some(clock_skew)) = dfs_clock_skew().await {
+                    if clock_skew.num_seconds().abs() > CLOCK_SKEW_TOLERANCE_SECS {
+                        warn!(
+                            "System clock differs from DFS server time by {}s, backup timestamps may be misleading",
+                            clock_skew.num_seconds()
+                        );
+                    }
+                }
+                for prf in &prfs {
+                    if let Some((sibling, sct)) = newer_sibling_sector(prf) {
+                        warn!(
+                            "{} references {}, which looks newer than the sector file of {}",
+                            sibling.display(),
+                            sct.display(),
+                            prf.display()
+                        );
+                    }
+                }
+                let run = Uuid::new_v4();
+                let mut message_log = config.open_message_log();
+                if let Some(log) = &mut message_log {
+                    log.set_run(run);
+                }
+                let (tx, logger) = spawn_message_logger(message_log, yes);
+                // Ctrl+C rolls back instead of leaving half-written files
+                let cancel = CancelToken::default();
+                let interrupt = tokio::spawn({
+                    let cancel = cancel.clone();
+                    async move {
+                        if tokio::signal::ctrl_c().await.is_ok() {
+                            warn!("Interrupted, cancelling");
+                            cancel.cancel();
+                        }
+                    }
+                });
+                let mode = if verify {
+                    RunMode::Verify
+                } else if dry_run {
+                    RunMode::DryRun
+                } else {
+                    RunMode::Write
+                };
+                let mut profile = config.profile();
+                for entity_type in skip {
+                    *profile.entity_types.get_mut(entity_type) = false;
+                }
+                if !only.is_empty() {
+                    for entity_type in EntityType::ALL {
+                        *profile.entity_types.get_mut(entity_type) = only.contains(&entity_type);
+                    }
+                }
+                spawn_batch(prfs, amdt, profile, mode, run, cancel, tx).await;
+                interrupt.abort();
+                let mut summary = logger.await?;
+                summary.report.set_run(run);
+                summary.errors.log();
+                for stats in summary.report.displacement_stats() {
+                    if stats.is_systematic() {
+                        warn!("{stats}, check the datum and parsing of the sources");
+                    } else {
+                        info!("{stats}");
+                    }
+                }
+                if let Some(report) = report {
+                    summary
+                        .report
+                        .write(&report, ReportFormat::from_path(&report))
+                        .await?;
+                    info!("Wrote change report {}", report.display());
+                }
+                ensure!(!summary.unconfirmed, ConfirmationUnavailableSnafu);
+                let failed = summary.pipeline.failed().collect::<Vec<_>>();
+                ensure!(
+                    failed.is_empty(),
+                    ApplyFailedSnafu {
+                        items: failed.join(", ")
+                    }
+                );
+                Ok(())
+            }
+            Command::Recover { prf, yes } => {
+                let orphaned = find_orphaned(&find_backups(&prf));
+                if orphaned.is_empty() {
+                    info!("No missing or empty files with backups found");
+                    return Ok(());
+                }
+                let (tx, logger) = spawn_message_logger(config.open_message_log(), false);
+                let mut result = Ok(());
+                for orphan in orphaned {
+                    warn!(
+                        "{} is {}, newest backup is {}",
+                        orphan.file.display(),
+                        orphan.state(),
+                        orphan.backup.display()
+                    );
+                    let question = format!(
+                        "Restore {} from {}?",
+                        orphan.file.display(),
+                        orphan.backup.display()
+                    );
+                    if !yes && !confirm(&question) {
+                        continue;
+                    }
+                    result = restore_backup(orphan.file, orphan.backup, tx.clone()).await;
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                drop(tx);
+                logger.await?;
+                result
+            }
+            Command::ServeMirror { dir, bind, sync } => {
+                if sync {
+                    let (tx, logger) = spawn_message_logger(config.open_message_log(), false);
+                    let result = {
+                        let profile = config.profile();
+                        sync_mirror(&dir, &profile.datasets, profile.aixm_release, tx).await
+                    };
+                    logger.await?;
+                    result?;
+                }
+                serve_mirror(dir, bind).await
+            }
+            Command::DiffCycles { from, to } => {
+                let (tx, logger) = spawn_message_logger(config.open_message_log(), false);
+                let result = {
+                    let profile = config.profile();
+                    print_cycle_diff(&from, &to, &profile.datasets, profile.aixm_release, tx).await
+                };
+                logger.await?;
+                result
+            }
+            Command::ListDatasets { format } => {
+                print_releases(&fetch_dfs_datasets().await?.releases(), format)
+            }
+            Command::Airac { cycle, count } => {
+                print_airac_cycles(cycle.unwrap_or_else(AiracCycle::current), count);
+                Ok(())
+            }
+            Command::Runs {
+                prf,
+                diff,
+                restore,
+                yes,
+            } => {
+                let store = ArtifactStore::open().context(ArtifactStoreDisabledSnafu)?;
+                let prf = prf.as_deref();
+                if let Some([from, to]) = diff.as_deref() {
+                    let (from, to) = (
+                        store.find_run(from, prf).await?,
+                        store.find_run(to, prf).await?,
+                    );
+                    print_file_diffs(&store.diff_runs(&from, &to).await?);
+                    return Ok(());
+                }
+                if let Some(run) = restore {
+                    let records = store.find_run(&run, prf).await?;
+                    let question = format!(
+                        "Restore {} files of {} packages of run {}?",
+                        records
+                            .iter()
+                            .map(|record| record.files.len())
+                            .sum::<usize>(),
+                        records.len(),
+                        records[0].run,
+                    );
+                    if !yes && !confirm(&question) {
+                        return Ok(());
+                    }
+                    let (tx, logger) = spawn_message_logger(config.open_message_log(), false);
+                    let mut result = Ok(());
+                    for record in &records {
+                        result = store.restore_run(record, tx.clone()).await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    drop(tx);
+                    logger.await?;
+                    return result;
+                }
+                print_runs(&store.runs(prf).await);
+                Ok(())
+            }
+        }
+    })
+}
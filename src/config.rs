@@ -0,0 +1,405 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::runtime::{self, Runtime};
+use tracing::error;
+
+use crate::{
+    aixm::DFS_DATASETS,
+    aixm_combine::{
+        DEFAULT_AIRPORT_WARNING_DISTANCE, EntityTypes, MatchDistances, NavaidNames, Transform,
+    },
+    aixm_dfs::AixmRelease,
+    aixm_index::{AirportReference, DEFAULT_CTR_CLASS},
+    artifact_store::ArtifactStoreConfig,
+    cross_check::CrossCheck,
+    dataset_cache::DatasetCacheConfig,
+    error::{
+        AiracUpdaterResult, ConfigDirNotFoundSnafu, CreateConfigDirSnafu, CreateRuntimeSnafu,
+        ParseConfigSnafu, ReadConfigSnafu, SerializeConfigSnafu, WriteConfigSnafu,
+    },
+    geo_export::GeoExport,
+    load_es::PathRemap,
+    message_log::MessageLog,
+    network::NetworkConfig,
+    provider::DataProvider,
+    report::ReportFormat,
+    traffic_circuits::TrafficCircuits,
+    validation::Validation,
+    verify::DEFAULT_TOLERANCE,
+};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WriteMode {
+    /// Keep the original files as `.aau_bkp<timestamp>`
+    #[default]
+    Backup,
+    /// Replace the original files without keeping a backup
+    Overwrite,
+}
+
+/// How the combined .sct is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SctWriter {
+    /// Rewrite the whole file from the parsed sections
+    #[default]
+    Full,
+    /// Only replace the lines of changed entities, keeping comments, blank
+    /// lines and the order of sections and entities
+    MinimalEdit,
+}
+
+/// What happens to the airports, navaids and fixes AIXM marks as military
+/// only, e.g. pure TACANs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MilitaryEntities {
+    #[default]
+    Include,
+    /// Left out of the update as if missing in AIXM
+    Exclude,
+    /// Included, but listed in the follow-ups
+    Tag,
+}
+
+/// How VORs, NDBs and fixes new in AIXM end up in the .sct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NewEntities {
+    #[default]
+    Insert,
+    /// Commented out as `; CANDIDATE` at the end of their section, for review
+    Candidate,
+}
+
+/// What happens to VORs, NDBs and fixes of the .sct that are missing in AIXM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StaleEntities {
+    #[default]
+    Keep,
+    /// Keep, but report them
+    Flag,
+    Remove,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Profile {
+    pub datasets: Vec<String>,
+    /// Directory of downloaded DFS AIXM files (`ED_*.xml`) to use instead of
+    /// fetching from aip.dfs.de
+    pub aixm_dir: Option<PathBuf>,
+    /// Where the datasets are downloaded from, unless `aixm_dir` is set
+    pub provider: DataProvider,
+    /// AIXM version of the DFS releases, e.g. `"5.2"`
+    pub aixm_release: AixmRelease,
+    /// Amendment per dataset instead of the chosen one, e.g. to take a dataset
+    /// from the previous amendment while its current publication is broken
+    pub dataset_amdts: BTreeMap<String, u32>,
+    pub write_mode: WriteMode,
+    pub sct_writer: SctWriter,
+    pub new_entities: NewEntities,
+    pub stale_entities: StaleEntities,
+    pub military_entities: MilitaryEntities,
+    /// Regexes of fix designators never added from AIXM, e.g. `^VP` for VFR
+    /// reporting points
+    pub fix_exclude: Vec<String>,
+    /// TOML or CSV list of designators never modified or placed at a manual
+    /// position instead of the AIXM one
+    pub designator_overrides: Option<PathBuf>,
+    /// How far an AIXM entity may be from an existing one of the same
+    /// designator to be matched instead of added
+    pub match_distances: MatchDistances,
+    /// Distance in metres beyond which a moved airport is warned about
+    pub airport_warning_distance: f64,
+    /// Difference in MHz up to which an .ese position frequency is left as
+    /// written
+    pub frequency_tolerance: f64,
+    /// ARP or tower position as .sct airport coordinate, globally and per
+    /// airport
+    pub airport_reference: AirportReference,
+    /// Airspace class of new airports outside of any AIXM CTR with a class
+    pub default_ctr_class: String,
+    /// Match AIXM airports without ICAO location indicator by designator or
+    /// name, e.g. for small VFR fields
+    pub match_airports_without_icao: bool,
+    /// Entity types updated from AIXM, e.g. only the navaids to keep fixes
+    /// named by local convention
+    pub entity_types: EntityTypes,
+    /// Distance in metres up to which an entity counts as in its AIXM position
+    /// when verifying
+    pub verify_tolerance: f64,
+    /// Also write Aurora sector files generated from the combined .sct
+    pub aurora_output: bool,
+    /// Also write TopSky maps of the AIXM airspaces next to the .sct
+    pub topsky_output: bool,
+    /// ILS localizers and final approach courses drawn into a separate
+    /// overlay next to the .sct
+    pub ils_overlay: bool,
+    /// Full AIXM names of the VORs and NDBs as labels or a sidecar file
+    pub navaid_names: Option<NavaidNames>,
+    /// Debug export of the combined .sct entities next to the .sct, for
+    /// comparing against other sources, e.g. in QGIS
+    pub geo_export: Option<GeoExport>,
+    /// Accept or reject every change in the GUI before anything is written
+    pub review_changes: bool,
+    /// Suspicious results to confirm before anything is written
+    pub validation: Validation,
+    /// List the files to write with their backups and wait for a
+    /// confirmation before writing
+    pub confirm_writes: bool,
+    /// Change report written next to the .prf after every run, previews
+    /// included
+    pub change_report: Option<ReportFormat>,
+    /// Position adjustments applied to the AIXM positions on every combine
+    pub transforms: Vec<Transform>,
+    /// Write symlinked files even if they point outside of the package
+    pub allow_external_symlinks: bool,
+    /// Dataset to read temporary airspaces (AIXM TEMPDELTA) from into a
+    /// separate overlay next to the .sct
+    pub temporary_airspace: Option<String>,
+    /// Aerodrome traffic circuits drawn into a separate overlay next to the
+    /// .sct
+    pub traffic_circuits: Option<TrafficCircuits>,
+    /// Flag AIXM airports and navaids far from their ourairports position
+    pub cross_check: Option<CrossCheck>,
+    /// Prefix replacements for the paths in the .prf, the first match wins
+    pub path_remaps: Vec<PathRemap>,
+    /// Nonstandard .sct sections copied verbatim when the .sct is written,
+    /// e.g. `[FREETEXT-DATA]`
+    pub passthrough_sections: Vec<String>,
+    /// Leave out the lines of a .sct the parser rejects instead of failing,
+    /// and write them back untouched
+    pub salvage_sct: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            datasets: DFS_DATASETS.iter().map(ToString::to_string).collect(),
+            aixm_dir: None,
+            provider: DataProvider::default(),
+            aixm_release: AixmRelease::default(),
+            dataset_amdts: BTreeMap::new(),
+            write_mode: WriteMode::default(),
+            sct_writer: SctWriter::default(),
+            new_entities: NewEntities::default(),
+            stale_entities: StaleEntities::default(),
+            military_entities: MilitaryEntities::default(),
+            fix_exclude: vec![],
+            designator_overrides: None,
+            match_distances: MatchDistances::default(),
+            airport_warning_distance: DEFAULT_AIRPORT_WARNING_DISTANCE,
+            frequency_tolerance: 0.0,
+            airport_reference: AirportReference::default(),
+            default_ctr_class: DEFAULT_CTR_CLASS.to_string(),
+            match_airports_without_icao: false,
+            entity_types: EntityTypes::default(),
+            verify_tolerance: DEFAULT_TOLERANCE,
+            aurora_output: false,
+            topsky_output: false,
+            ils_overlay: false,
+            navaid_names: None,
+            geo_export: None,
+            change_report: None,
+            review_changes: false,
+            validation: Validation::default(),
+            confirm_writes: true,
+            transforms: vec![],
+            allow_external_symlinks: false,
+            temporary_airspace: None,
+            traffic_circuits: None,
+            cross_check: None,
+            path_remaps: vec![],
+            passthrough_sections: vec![],
+            salvage_sct: false,
+        }
+    }
+}
+
+/// Async runtime sizing, tokio's defaults (one worker per core, up to 512
+/// blocking threads) if unset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RuntimeConfig {
+    pub worker_threads: Option<usize>,
+    pub max_blocking_threads: Option<usize>,
+}
+
+impl RuntimeConfig {
+    pub fn build(self) -> AiracUpdaterResult<Runtime> {
+        let mut builder = runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads.max(1));
+        }
+        if let Some(max_blocking_threads) = self.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads.max(1));
+        }
+        builder.build().context(CreateRuntimeSnafu)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub active_profile: String,
+    pub profiles: BTreeMap<String, Profile>,
+    pub runtime: RuntimeConfig,
+    pub network: NetworkConfig,
+    pub dataset_cache: DatasetCacheConfig,
+    pub artifact_store: ArtifactStoreConfig,
+    /// JSON lines file every message of a run is appended to
+    pub message_log: Option<PathBuf>,
+}
+
+/// Built-in profiles for the common kinds of sector packages, part of a new
+/// config and changeable like any other profile afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// Center package: airways, navaids and en-route fixes
+    EnRoute,
+    /// TMA and tower package: runways and every fix and airfield
+    Tma,
+}
+
+impl Preset {
+    pub const ALL: [Self; 2] = [Self::EnRoute, Self::Tma];
+
+    /// Name of the profile of the preset
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::EnRoute => "en-route",
+            Preset::Tma => "tma",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Preset::EnRoute => {
+                "Center packages: airways, navaids and fixes, VFR reporting points excluded, \
+                 generous match distances"
+            }
+            Preset::Tma => {
+                "TMA and tower packages: runways too, new entities as candidates for review, \
+                 small airfields matched by name, tight match distances"
+            }
+        }
+    }
+
+    pub fn profile(self) -> Profile {
+        match self {
+            Preset::EnRoute => Profile {
+                datasets: [
+                    "ED AirportHeliport",
+                    "ED Navaids",
+                    "ED Routes",
+                    "ED Waypoints",
+                ]
+                .map(String::from)
+                .to_vec(),
+                fix_exclude: vec!["^VP".to_string()],
+                match_distances: MatchDistances {
+                    fix: 2000.0,
+                    label: 2000.0,
+                    ..MatchDistances::default()
+                },
+                entity_types: EntityTypes {
+                    runways: false,
+                    ..EntityTypes::default()
+                },
+                verify_tolerance: 500.0,
+                ..Profile::default()
+            },
+            Preset::Tma => Profile {
+                new_entities: NewEntities::Candidate,
+                match_distances: MatchDistances {
+                    fix: 500.0,
+                    label: 250.0,
+                    ..MatchDistances::default()
+                },
+                match_airports_without_icao: true,
+                verify_tolerance: 50.0,
+                ..Profile::default()
+            },
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            active_profile: DEFAULT_PROFILE.to_string(),
+            profiles: std::iter::once((DEFAULT_PROFILE.to_string(), Profile::default()))
+                .chain(Preset::ALL.map(|preset| (preset.name().to_string(), preset.profile())))
+                .collect(),
+            runtime: RuntimeConfig::default(),
+            network: NetworkConfig::default(),
+            dataset_cache: DatasetCacheConfig::default(),
+            artifact_store: ArtifactStoreConfig::default(),
+            message_log: None,
+        }
+    }
+}
+
+impl Config {
+    /// Opens the message log if configured, logging why it could not be.
+    pub fn open_message_log(&self) -> Option<MessageLog> {
+        let path = self.message_log.as_ref()?;
+        MessageLog::open(path).inspect_err(|e| error!("{e}")).ok()
+    }
+
+    pub fn path() -> AiracUpdaterResult<PathBuf> {
+        Ok(dirs::config_dir()
+            .context(ConfigDirNotFoundSnafu)?
+            .join("airac-aixm-updater")
+            .join("config.toml"))
+    }
+
+    /// Loads the config file, falling back to the default config if it does
+    /// not exist yet. Presets missing from older config files are added.
+    pub fn load() -> AiracUpdaterResult<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path).context(ReadConfigSnafu { path: &path })?;
+        let mut config: Self = toml::from_str(&content).context(ParseConfigSnafu { path })?;
+        for preset in Preset::ALL {
+            config
+                .profiles
+                .entry(preset.name().to_string())
+                .or_insert_with(|| preset.profile());
+        }
+        Ok(config)
+    }
+
+    pub fn save(&self) -> AiracUpdaterResult {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(CreateConfigDirSnafu { path: parent })?;
+        }
+        let content = toml::to_string_pretty(self).context(SerializeConfigSnafu)?;
+        fs::write(&path, content).context(WriteConfigSnafu { path })
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profiles
+            .get(&self.active_profile)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        self.profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+    }
+}
@@ -0,0 +1,182 @@
+use std::{collections::HashMap, fmt};
+
+use geo::{Distance, Geodesic, Point, point};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt as _;
+use tokio::{sync::mpsc, task::spawn_blocking};
+
+use crate::{
+    Message,
+    aixm_index::AixmIndex,
+    error::{AiracUpdaterResult, FetchReferenceSnafu, ParseReferenceSnafu},
+    network,
+};
+
+const OURAIRPORTS_AIRPORTS_URL: &str =
+    "https://davidmegginson.github.io/ourairports-data/airports.csv";
+const OURAIRPORTS_NAVAIDS_URL: &str =
+    "https://davidmegginson.github.io/ourairports-data/navaids.csv";
+
+/// Comparison of the AIXM positions against the ourairports data, to catch
+/// glitches in either before they end up in the sector files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CrossCheck {
+    pub airports_url: String,
+    pub navaids_url: String,
+    /// Distance in metres above which a position is flagged
+    pub max_distance: f64,
+}
+
+impl Default for CrossCheck {
+    fn default() -> Self {
+        Self {
+            airports_url: OURAIRPORTS_AIRPORTS_URL.to_string(),
+            navaids_url: OURAIRPORTS_NAVAIDS_URL.to_string(),
+            max_distance: 500.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct OurAirportsAirport {
+    ident: String,
+    #[serde(default)]
+    gps_code: String,
+    latitude_deg: Option<f64>,
+    longitude_deg: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OurAirportsNavaid {
+    ident: String,
+    #[serde(rename = "type")]
+    navaid_type: String,
+    latitude_deg: Option<f64>,
+    longitude_deg: Option<f64>,
+}
+
+/// Reference positions by kind (as in [`AixmIndex::positions`]) and ident.
+/// Navaid idents are not unique world-wide, so there may be several.
+#[derive(Debug, Default)]
+pub struct ReferencePositions {
+    positions: HashMap<(&'static str, String), Vec<Point>>,
+}
+
+impl ReferencePositions {
+    fn parse(airports: &[u8], navaids: &[u8]) -> Result<Self, csv::Error> {
+        let mut reference = Self::default();
+        for airport in csv::Reader::from_reader(airports).deserialize() {
+            let airport: OurAirportsAirport = airport?;
+            let (Some(lat), Some(lon)) = (airport.latitude_deg, airport.longitude_deg) else {
+                continue;
+            };
+            let ident = if airport.gps_code.is_empty() {
+                airport.ident
+            } else {
+                airport.gps_code
+            };
+            reference.insert("Airport", ident, point!(x: lon, y: lat));
+        }
+        for navaid in csv::Reader::from_reader(navaids).deserialize() {
+            let navaid: OurAirportsNavaid = navaid?;
+            let kind = match navaid.navaid_type.as_str() {
+                "VOR" | "VOR-DME" | "VORTAC" => "VOR",
+                "NDB" | "NDB-DME" => "NDB",
+                _ => continue,
+            };
+            let (Some(lat), Some(lon)) = (navaid.latitude_deg, navaid.longitude_deg) else {
+                continue;
+            };
+            reference.insert(kind, navaid.ident, point!(x: lon, y: lat));
+        }
+
+        Ok(reference)
+    }
+
+    fn insert(&mut self, kind: &'static str, ident: String, position: Point) {
+        self.positions
+            .entry((kind, ident))
+            .or_default()
+            .push(position);
+    }
+
+    /// Distance to the closest reference position of the same kind and ident.
+    fn distance(&self, kind: &'static str, ident: &str, position: Point) -> Option<f64> {
+        self.positions
+            .get(&(kind, ident.to_string()))?
+            .iter()
+            .map(|reference| Geodesic.distance(*reference, position))
+            .min_by(f64::total_cmp)
+    }
+}
+
+/// AIXM position further from the reference than the configured maximum.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disagreement {
+    pub kind: &'static str,
+    pub key: String,
+    pub distance: f64,
+}
+
+impl fmt::Display for Disagreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is {:.0} m from its ourairports position",
+            self.kind, self.key, self.distance
+        )
+    }
+}
+
+async fn fetch_reference(url: &str) -> AiracUpdaterResult<Vec<u8>> {
+    network::check_request(url)?;
+    Ok(reqwest::get(url)
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context(FetchReferenceSnafu { url })?
+        .bytes()
+        .await
+        .context(FetchReferenceSnafu { url })?
+        .to_vec())
+}
+
+impl CrossCheck {
+    pub async fn load_reference(
+        &self,
+        tx: mpsc::Sender<Message>,
+    ) -> AiracUpdaterResult<ReferencePositions> {
+        tx.send(Message::info(format!(
+            "Fetching reference positions: {}, {}",
+            self.airports_url, self.navaids_url
+        )))
+        .await?;
+        let (airports, navaids) = tokio::try_join!(
+            fetch_reference(&self.airports_url),
+            fetch_reference(&self.navaids_url)
+        )?;
+        spawn_blocking(move || ReferencePositions::parse(&airports, &navaids))
+            .await?
+            .context(ParseReferenceSnafu)
+    }
+
+    /// Airports and navaids whose AIXM position is further than
+    /// `max_distance` from the closest reference position. Entities missing
+    /// in the reference are not flagged.
+    pub fn check(&self, aixm: &AixmIndex, reference: &ReferencePositions) -> Vec<Disagreement> {
+        aixm.positions()
+            .into_iter()
+            .filter(|((kind, _), _)| *kind != "Fix")
+            .filter_map(|((kind, key), position)| {
+                // navaid keys are `<designator> <frequency>`
+                let ident = key.split_whitespace().next()?;
+                let distance = reference.distance(kind, ident, position)?;
+                (distance > self.max_distance).then_some(Disagreement {
+                    kind,
+                    key,
+                    distance,
+                })
+            })
+            .collect()
+    }
+}
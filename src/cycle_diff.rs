@@ -0,0 +1,376 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+#[cfg(feature = "gui")]
+use egui::{CollapsingHeader, Grid, Ui};
+use geo::{Distance as _, Geodesic, Point};
+use snafu::OptionExt as _;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    Message,
+    airac::AiracCycle,
+    aixm::load_aixm_files,
+    aixm_dfs::{AixmRelease, fetch_dfs_datasets, find_amendment},
+    aixm_index::AixmIndex,
+    aixm_routes::{AirwayLevel, SegmentPath},
+    error::{AiracUpdaterResult, AmendmentNotFoundSnafu, InvalidAiracCycleSnafu},
+    provider::Dfs,
+    report::MOVED_THRESHOLD,
+};
+
+pub enum CycleChange {
+    Added {
+        kind: &'static str,
+        key: String,
+        position: Point,
+    },
+    Removed {
+        kind: &'static str,
+        key: String,
+        position: Point,
+    },
+    Moved {
+        kind: &'static str,
+        key: String,
+        from: Point,
+        to: Point,
+        distance: f64,
+    },
+    /// Kept with another attribute, e.g. the level of a route segment
+    Changed {
+        kind: &'static str,
+        key: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for CycleChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CycleChange::Added {
+                kind,
+                key,
+                position,
+            } => write!(
+                f,
+                "+ {kind} {key} ({:.6} {:.6})",
+                position.y(),
+                position.x()
+            ),
+            CycleChange::Removed {
+                kind,
+                key,
+                position,
+            } => write!(
+                f,
+                "- {kind} {key} ({:.6} {:.6})",
+                position.y(),
+                position.x()
+            ),
+            CycleChange::Moved {
+                kind,
+                key,
+                from,
+                to,
+                distance,
+            } => write!(
+                f,
+                "~ {kind} {key} moved {distance:.0} m ({:.6} {:.6} -> {:.6} {:.6})",
+                from.y(),
+                from.x(),
+                to.y(),
+                to.x()
+            ),
+            CycleChange::Changed {
+                kind,
+                key,
+                from,
+                to,
+            } => write!(f, "~ {kind} {key} changed {from} -> {to}"),
+        }
+    }
+}
+
+impl CycleChange {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CycleChange::Added { kind, .. }
+            | CycleChange::Removed { kind, .. }
+            | CycleChange::Moved { kind, .. }
+            | CycleChange::Changed { kind, .. } => kind,
+        }
+    }
+
+    pub fn action(&self) -> &'static str {
+        match self {
+            CycleChange::Added { .. } => "added",
+            CycleChange::Removed { .. } => "removed",
+            CycleChange::Moved { .. } => "moved",
+            CycleChange::Changed { .. } => "changed",
+        }
+    }
+}
+
+const ROUTE_SEGMENT: &str = "Route segment";
+
+/// Route segments by route designator and the designators of their end
+/// points (or the coordinates for segments with their own course), e.g.
+/// `UN850 DM441-MAG`, with their level and start position. Segments between
+/// unknown points are left out.
+fn route_segments(index: &AixmIndex) -> BTreeMap<String, (AirwayLevel, Point)> {
+    let positions = index.designated_point_positions();
+    let designators = index
+        .designated_points()
+        .map(|(_, fix)| {
+            (
+                fix.gml_identifier.as_str(),
+                fix.aixm_time_slice
+                    .aixm_designated_point_time_slice
+                    .aixm_designator
+                    .as_str(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let coordinates = |point: &Point| format!("{:.4}/{:.4}", point.y(), point.x());
+    index
+        .route_segments()
+        .iter()
+        .filter_map(|segment| {
+            let (start, end, position) = match &segment.path {
+                SegmentPath::Fixes(start, end) => (
+                    (*designators.get(start.as_str())?).to_string(),
+                    (*designators.get(end.as_str())?).to_string(),
+                    *positions.get(start.as_str())?,
+                ),
+                SegmentPath::Curve(points) => {
+                    let (first, last) = (points.first()?, points.last()?);
+                    (coordinates(first), coordinates(last), *first)
+                }
+            };
+            Some((
+                format!("{} {start}-{end}", segment.designator),
+                (segment.level, position),
+            ))
+        })
+        .collect()
+}
+
+/// Added, removed and changed route segments, see [`route_segments`].
+fn diff_route_segments(from: &AixmIndex, to: &AixmIndex) -> Vec<CycleChange> {
+    let from = route_segments(from);
+    let to = route_segments(to);
+    let level = |level: AirwayLevel| format!("{level:?}").to_uppercase();
+
+    let mut changes = vec![];
+    for (key, (from_level, position)) in &from {
+        match to.get(key) {
+            None => changes.push(CycleChange::Removed {
+                kind: ROUTE_SEGMENT,
+                key: key.clone(),
+                position: *position,
+            }),
+            Some((to_level, _)) if to_level != from_level => {
+                changes.push(CycleChange::Changed {
+                    kind: ROUTE_SEGMENT,
+                    key: key.clone(),
+                    from: level(*from_level),
+                    to: level(*to_level),
+                });
+            }
+            Some(_) => (),
+        }
+    }
+    for (key, (_, position)) in &to {
+        if !from.contains_key(key) {
+            changes.push(CycleChange::Added {
+                kind: ROUTE_SEGMENT,
+                key: key.clone(),
+                position: *position,
+            });
+        }
+    }
+    changes
+}
+
+/// AIXM-level differences of airports, navaids, fixes and route segments
+/// between two cycles.
+pub fn diff_cycles(from: &AixmIndex, to: &AixmIndex) -> Vec<CycleChange> {
+    let route_segment_changes = diff_route_segments(from, to);
+    let from = from.positions();
+    let to = to.positions();
+
+    let mut changes = vec![];
+    for (&(kind, ref key), from_position) in &from {
+        match to.get(&(kind, key.clone())) {
+            None => changes.push(CycleChange::Removed {
+                kind,
+                key: key.clone(),
+                position: *from_position,
+            }),
+            Some(to_position) => {
+                let distance = Geodesic.distance(*from_position, *to_position);
+                if distance >= MOVED_THRESHOLD {
+                    changes.push(CycleChange::Moved {
+                        kind,
+                        key: key.clone(),
+                        from: *from_position,
+                        to: *to_position,
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+    for (&(kind, ref key), to_position) in &to {
+        if !from.contains_key(&(kind, key.clone())) {
+            changes.push(CycleChange::Added {
+                kind,
+                key: key.clone(),
+                position: *to_position,
+            });
+        }
+    }
+    changes.extend(route_segment_changes);
+
+    changes
+}
+
+/// Downloads the datasets of both amendments and compares them.
+pub async fn compare_amendments(
+    from_amdt: u32,
+    to_amdt: u32,
+    datasets: &[String],
+    release: AixmRelease,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<CycleChange>> {
+    let dfs = Dfs { release };
+    let from_index = AixmIndex::new(load_aixm_files(&dfs, from_amdt, datasets, tx.clone()).await?);
+    let to_index = AixmIndex::new(load_aixm_files(&dfs, to_amdt, datasets, tx).await?);
+
+    Ok(diff_cycles(&from_index, &to_index))
+}
+
+/// Downloads the datasets of both cycles and prints their differences, one
+/// change per line.
+#[expect(clippy::print_stdout, reason = "diff is the command's output")]
+pub async fn print_cycle_diff(
+    from: &str,
+    to: &str,
+    datasets: &[String],
+    release: AixmRelease,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let amdts = fetch_dfs_datasets().await?;
+    let amendment = |cycle: &str| {
+        let cycle = AiracCycle::from_ident(cycle).context(InvalidAiracCycleSnafu { cycle })?;
+        find_amendment(&amdts, cycle).context(AmendmentNotFoundSnafu {
+            cycle: cycle.ident(),
+        })
+    };
+    let changes =
+        compare_amendments(amendment(from)?, amendment(to)?, datasets, release, tx).await?;
+    for change in &changes {
+        println!("{change}");
+    }
+    info!("{} changes between {from} and {to}", changes.len());
+
+    Ok(())
+}
+
+#[cfg(feature = "gui")]
+const KINDS: &[&str] = &["Airport", "VOR", "NDB", "Fix", ROUTE_SEGMENT];
+#[cfg(feature = "gui")]
+const ACTIONS: &[&str] = &["added", "removed", "moved", "changed"];
+
+/// Counts per entity kind and action, with the changes listed per group.
+#[cfg(feature = "gui")]
+pub fn show_cycle_diff(ui: &mut Ui, changes: &[CycleChange]) {
+    let group = |kind: &str, action: &str| {
+        changes
+            .iter()
+            .filter(move |change| change.kind() == kind && change.action() == action)
+    };
+
+    Grid::new("cycle_diff_counts").striped(true).show(ui, |ui| {
+        ui.strong("Type");
+        for action in ACTIONS {
+            ui.strong(*action);
+        }
+        ui.end_row();
+        for kind in KINDS {
+            ui.label(*kind);
+            for action in ACTIONS {
+                ui.label(group(kind, action).count().to_string());
+            }
+            ui.end_row();
+        }
+    });
+
+    for kind in KINDS {
+        for action in ACTIONS {
+            let count = group(kind, action).count();
+            if count == 0 {
+                continue;
+            }
+            CollapsingHeader::new(format!("{kind} {action} ({count})"))
+                .id_salt(("cycle_diff", kind, action))
+                .show(ui, |ui| {
+                    for change in group(kind, action) {
+                        ui.monospace(change.to_string());
+                    }
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aixm::AixmDataset, aixm_routes::RouteSegment};
+
+    fn index(segments: &[(&str, AirwayLevel, [(f64, f64); 2])]) -> AixmIndex {
+        AixmIndex::new([AixmDataset {
+            name: "ED Routes".to_string(),
+            route_segments: segments
+                .iter()
+                .map(|(designator, level, points)| RouteSegment {
+                    designator: (*designator).to_string(),
+                    level: *level,
+                    path: SegmentPath::Curve(points.iter().map(|&p| Point::from(p)).collect()),
+                })
+                .collect(),
+            ..AixmDataset::default()
+        }])
+    }
+
+    #[test]
+    fn route_segments_are_added_removed_and_changed() {
+        let kept = [(11., 48.), (11.5, 48.5)];
+        let from = index(&[
+            ("Y101", AirwayLevel::Lower, kept),
+            ("UN850", AirwayLevel::Upper, [(12., 49.), (12.5, 49.5)]),
+        ]);
+        let to = index(&[
+            ("Y101", AirwayLevel::Both, kept),
+            ("T161", AirwayLevel::Lower, [(10., 47.), (10.5, 47.5)]),
+        ]);
+
+        let changes = diff_cycles(&from, &to)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                "- Route segment UN850 49.0000/12.0000-49.5000/12.5000 (49.000000 12.000000)",
+                "~ Route segment Y101 48.0000/11.0000-48.5000/11.5000 changed LOWER -> BOTH",
+                "+ Route segment T161 47.0000/10.0000-47.5000/10.5000 (47.000000 10.000000)",
+            ]
+        );
+    }
+}
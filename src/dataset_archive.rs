@@ -0,0 +1,103 @@
+//! Datasets published as .zip bundles instead of raw XML, unpacked before
+//! they are checked and deserialized.
+
+use std::io::{Cursor, Read as _};
+
+use quick_xml::{Reader, events::Event};
+use reqwest::header::{CONTENT_TYPE, HeaderMap};
+use snafu::{OptionExt as _, ResultExt as _};
+use zip::ZipArchive;
+
+use crate::{
+    aixm_parse::normalize_prefixes,
+    error::{
+        AiracUpdaterResult, ArchiveWithoutXmlSnafu, MalformedDatasetSnafu, OpenArchiveSnafu,
+        ReadArchiveEntrySnafu,
+    },
+};
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Whether `data` is a zip archive, by the content type of the response if
+/// any or its leading bytes.
+pub fn is_zip(data: &[u8], headers: Option<&HeaderMap>) -> bool {
+    let zip_content_type = headers
+        .and_then(|headers| headers.get(CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().contains("zip"));
+    zip_content_type || data.starts_with(ZIP_MAGIC)
+}
+
+/// The XML of `data`, extracted if it is a zip archive. The members of
+/// several XML files are merged into the message of the first one, in the
+/// order of their names.
+pub fn unpack_dataset(
+    data: Vec<u8>,
+    headers: Option<&HeaderMap>,
+    dataset: &str,
+) -> AiracUpdaterResult<Vec<u8>> {
+    if !is_zip(&data, headers) {
+        return Ok(data);
+    }
+
+    let mut archive = ZipArchive::new(Cursor::new(data)).context(OpenArchiveSnafu { dataset })?;
+    let mut names = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".xml"))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut documents = vec![];
+    for name in &names {
+        let mut entry = archive
+            .by_name(name)
+            .context(OpenArchiveSnafu { dataset })?;
+        if !entry.is_file() {
+            continue;
+        }
+        let mut xml = vec![];
+        entry.read_to_end(&mut xml).context(ReadArchiveEntrySnafu {
+            dataset,
+            entry: name,
+        })?;
+        documents.push(xml);
+    }
+
+    let mut documents = documents.into_iter();
+    let first = documents
+        .next()
+        .context(ArchiveWithoutXmlSnafu { dataset })?;
+    if documents.as_slice().is_empty() {
+        return Ok(first);
+    }
+    let mut merged = normalize_prefixes(&String::from_utf8_lossy(&first)).into_owned();
+    for document in documents {
+        let document = String::from_utf8_lossy(&document);
+        let document = normalize_prefixes(&document);
+        let (Some(members), Some(end)) = (root_content(&document), merged.rfind("</")) else {
+            return MalformedDatasetSnafu {
+                dataset,
+                reason: "archive contains an XML file without root element",
+            }
+            .fail();
+        };
+        merged.insert_str(end, members);
+    }
+    Ok(merged.into_bytes())
+}
+
+/// Everything between the start and end tag of the root element.
+fn root_content(xml: &str) -> Option<&str> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(_) => break,
+            Event::Eof => return None,
+            _ => (),
+        }
+    }
+    let start = usize::try_from(reader.buffer_position()).ok()?;
+    let end = xml.rfind("</")?;
+    xml.get(start..end)
+}
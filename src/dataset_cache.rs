@@ -0,0 +1,158 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use reqwest::{
+    RequestBuilder,
+    header::{ETAG, HeaderMap, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt as _;
+use tokio::fs;
+use tracing::debug;
+
+use crate::error::{AiracUpdaterResult, WriteDatasetCacheSnafu};
+
+/// On-disk cache of downloaded datasets, revalidated with conditional
+/// requests so repeated runs within a cycle only download changed datasets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DatasetCacheConfig {
+    pub enabled: bool,
+    /// Cache directory, `airac-aixm-updater/datasets` in the platform cache
+    /// directory if unset
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for DatasetCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: None,
+        }
+    }
+}
+
+static DATASET_CACHE: OnceLock<DatasetCacheConfig> = OnceLock::new();
+
+/// Sets the cache location for the rest of the process, before any download.
+pub fn init(config: DatasetCacheConfig) {
+    if DATASET_CACHE.set(config).is_err() {
+        debug!("dataset cache config already set");
+    }
+}
+
+/// Validators of a cached response, sent back on the next request.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Cache entry of a single dataset release.
+pub struct CacheEntry {
+    data: PathBuf,
+    validators: PathBuf,
+}
+
+impl CacheEntry {
+    /// Entry for `key`, i.e. amendment and release file name, `None` if
+    /// caching is disabled or there is no cache directory.
+    pub fn new(key: &str) -> Option<Self> {
+        let config = DATASET_CACHE.get_or_init(DatasetCacheConfig::default);
+        if !config.enabled {
+            return None;
+        }
+        let dir = match &config.dir {
+            Some(dir) => dir.clone(),
+            None => dirs::cache_dir()?
+                .join("airac-aixm-updater")
+                .join("datasets"),
+        };
+        let file_name = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        Some(Self {
+            validators: dir.join(format!("{file_name}.validators.json")),
+            data: dir.join(file_name),
+        })
+    }
+
+    /// Adds the validators of the cached response, if any, so the server can
+    /// answer with `304 Not Modified`.
+    pub async fn conditional(&self, mut request: RequestBuilder) -> RequestBuilder {
+        let Ok(validators) = fs::read(&self.validators).await else {
+            return request;
+        };
+        let Ok(validators) = serde_json::from_slice::<Validators>(&validators) else {
+            return request;
+        };
+        if !fs::try_exists(&self.data).await.unwrap_or(false) {
+            return request;
+        }
+        if let Some(etag) = validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+
+    pub async fn read(&self) -> Option<Vec<u8>> {
+        fs::read(&self.data).await.ok()
+    }
+
+    /// Stores a fresh response, replacing the data atomically so an
+    /// interrupted write never leaves a truncated dataset behind.
+    pub async fn store(&self, data: &[u8], headers: &HeaderMap) -> AiracUpdaterResult {
+        let validators = Validators::from_headers(headers);
+        if validators.etag.is_none() && validators.last_modified.is_none() {
+            // could never be revalidated
+            return Ok(());
+        }
+        if let Some(dir) = self.data.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .context(WriteDatasetCacheSnafu { path: dir })?;
+        }
+
+        let partial = self.data.with_extension("partial");
+        write(&partial, data).await?;
+        fs::rename(&partial, &self.data)
+            .await
+            .context(WriteDatasetCacheSnafu { path: &self.data })?;
+        // serializing two optional strings cannot fail
+        let validators = serde_json::to_vec(&validators).unwrap_or_default();
+        write(&self.validators, &validators).await
+    }
+}
+
+async fn write(path: &Path, content: &[u8]) -> AiracUpdaterResult {
+    fs::write(path, content)
+        .await
+        .context(WriteDatasetCacheSnafu { path })
+}
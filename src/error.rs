@@ -1,16 +1,17 @@
 use std::path::PathBuf;
 
+use serde::Serialize;
 use snafu::Snafu;
 use tokio::{sync::mpsc::error::SendError, task::JoinError};
 use vatsim_parser::{ese::EseError, isec::IsecError, prf::PrfError, sct::SctError};
 
-use crate::Message;
+use crate::{Message, aixm_combine::EntityKind, pipeline::Stage};
 
-pub(crate) type AiracUpdaterResult<T = ()> = Result<T, Error>;
+pub type AiracUpdaterResult<T = ()> = Result<T, Error>;
 
 #[derive(Debug, Snafu)]
-#[snafu(visibility(pub(crate)))]
-pub(crate) enum Error {
+#[snafu(visibility(pub))]
+pub enum Error {
     #[snafu(display("Could not rename file ({} -> {}): {source}", from.display(), to.display()))]
     Rename {
         source: std::io::Error,
@@ -27,18 +28,93 @@ pub(crate) enum Error {
         source: std::io::Error,
         path: PathBuf,
     },
+    #[snafu(display(
+        "Refusing to write {}, its [POSITIONS] section would change at: {line}",
+        path.display()
+    ))]
+    EsePositionsChanged { path: PathBuf, line: String },
+    #[snafu(display("Could not resolve path ({}): {source}", path.display()))]
+    ResolveSymlink {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+    #[snafu(display(
+        "{} links to {} outside of the package, set allow-external-symlinks to write it",
+        path.display(),
+        target.display()
+    ))]
+    SymlinkOutsidePackage { path: PathBuf, target: PathBuf },
+    #[snafu(display(
+        "{} changed since the files to write were listed, nothing was written",
+        path.display()
+    ))]
+    ChangedSincePlanned { path: PathBuf },
+    #[snafu(display("Could not copy backup ({} -> {}): {source}", from.display(), to.display()))]
+    CopyBackup {
+        source: std::io::Error,
+        from: PathBuf,
+        to: PathBuf,
+    },
+    #[snafu(display("Could not create staging directory ({}): {source}", path.display()))]
+    CreateStagingDir {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Could not write GEO export ({}): {source}", path.display()))]
+    WriteGeoExport {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Could not write follow-up list ({}): {source}", path.display()))]
+    WriteFollowUp { source: csv::Error, path: PathBuf },
+
+    #[snafu(display("Could not write quality score history ({}): {source}", path.display()))]
+    WriteQualityHistory {
+        source: std::io::Error,
+        path: PathBuf,
+    },
 
     #[snafu(display("Could not deserialize DFS AIXM dataset list: {source}"))]
     DeserializeDfsDatasets { source: serde_json::Error },
 
+    #[snafu(display("Could not serialize DFS AIXM dataset list: {source}"))]
+    SerializeDatasetList { source: serde_json::Error },
+    #[snafu(display("Could not serialize change report: {source}"))]
+    SerializeChangeReport { source: serde_json::Error },
+    #[snafu(display("Could not serialize change report as CSV: {source}"))]
+    SerializeChangeReportCsv { source: csv::Error },
+    #[snafu(display("Could not write change report ({}): {source}", path.display()))]
+    WriteChangeReport {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
     #[snafu(display("Could not decode DFS AIXM dataset list: {source}"))]
     DecodeDfsDatasets { source: reqwest::Error },
 
     #[snafu(display("Could not fetch DFS AIXM dataset list: {source}"))]
     FetchDfsDatasets { source: reqwest::Error },
 
+    #[snafu(display("Invalid AIRAC cycle, expected YYNN: {cycle}"))]
+    InvalidAiracCycle { cycle: String },
+
+    #[snafu(display("DFS does not provide an amendment for AIRAC cycle {cycle}"))]
+    AmendmentNotFound { cycle: String },
+
     #[snafu(display("Could not find AIXM dataset ({dataset})"))]
     DatasetNotFound { dataset: String },
+    #[snafu(display("Could not fetch AIXM dataset index ({url}): {source}"))]
+    FetchDatasetIndex { url: String, source: reqwest::Error },
+    #[snafu(display("Could not find AIXM dataset ({dataset}) in {}", dir.display()))]
+    LocalDatasetNotFound { dataset: String, dir: PathBuf },
+
+    #[snafu(display("AIXM dataset is not valid UTF-8 ({dataset}): {source}"))]
+    DatasetNotUtf8 {
+        dataset: String,
+        source: std::str::Utf8Error,
+    },
 
     #[snafu(display("Could not deserialize AIXM dataset ({dataset}): {source}"))]
     DeserializeDataset {
@@ -46,6 +122,27 @@ pub(crate) enum Error {
         source: quick_xml::DeError,
     },
 
+    #[snafu(display(
+        "AIXM dataset {dataset} is not well-formed XML: {reason}. Try again later or check the download"
+    ))]
+    MalformedDataset { dataset: String, reason: String },
+
+    #[snafu(display("Could not open AIXM dataset archive ({dataset}): {source}"))]
+    OpenArchive {
+        dataset: String,
+        source: zip::result::ZipError,
+    },
+
+    #[snafu(display("Could not extract {entry} from AIXM dataset archive ({dataset}): {source}"))]
+    ReadArchiveEntry {
+        dataset: String,
+        entry: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("AIXM dataset archive contains no XML file ({dataset})"))]
+    ArchiveWithoutXml { dataset: String },
+
     #[snafu(display("Could not decode AIXM dataset ({dataset}): {source}"))]
     DecodeDataset {
         dataset: String,
@@ -58,15 +155,96 @@ pub(crate) enum Error {
         source: reqwest::Error,
     },
 
+    #[snafu(display("Server error fetching AIXM dataset ({dataset}): HTTP {status}"))]
+    DatasetServerError { dataset: String, status: u16 },
+
+    #[snafu(display("Could not write dataset cache ({}): {source}", path.display()))]
+    WriteDatasetCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Could not read stored artifact ({}): {source}", path.display()))]
+    ReadArtifact {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write artifact store ({}): {source}", path.display()))]
+    WriteArtifact {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("The artifact store is disabled in the config"))]
+    ArtifactStoreDisabled,
+    #[snafu(display("No recorded run {run} in the artifact store"))]
+    RunNotFound { run: String },
+
+    #[snafu(display("Could not fetch reference data ({url}): {source}"))]
+    FetchReference { url: String, source: reqwest::Error },
+    #[snafu(display("Could not parse reference data: {source}"))]
+    ParseReference { source: csv::Error },
+
+    #[snafu(display("Could not listen for mirror requests on {addr}: {source}"))]
+    BindMirror {
+        addr: std::net::SocketAddr,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not accept mirror connection: {source}"))]
+    AcceptMirror { source: std::io::Error },
+    #[snafu(display("Could not create mirror directory ({}): {source}", path.display()))]
+    CreateMirrorDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write mirror file ({}): {source}", path.display()))]
+    WriteMirror {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Could not determine config directory"))]
+    ConfigDirNotFound,
+    #[snafu(display("Could not create config directory ({}): {source}", path.display()))]
+    CreateConfigDir {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not read config ({}): {source}", path.display()))]
+    ReadConfig {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse config ({}): {source}", path.display()))]
+    ParseConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[snafu(display("Could not serialize config: {source}"))]
+    SerializeConfig { source: toml::ser::Error },
+    #[snafu(display("Could not write config ({}): {source}", path.display()))]
+    WriteConfig {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Profile not found in config: {profile}"))]
+    ProfileNotFound { profile: String },
+    #[snafu(display("Update failed for {items}"))]
+    ApplyFailed { items: String },
+    #[snafu(display(
+        "Nothing was written, as there is no terminal to confirm on, pass --yes to write without asking"
+    ))]
+    ConfirmationUnavailable,
+
+    #[snafu(display("Could not create async runtime: {source}"))]
+    CreateRuntime { source: std::io::Error },
+
     #[snafu(display("Could not read AIXM ({}): {source}", filename.display()))]
-    #[expect(dead_code, reason = "to be used for local AIXM data")]
     ReadAixm {
         filename: PathBuf,
         source: std::io::Error,
     },
 
     #[snafu(display("Could not open AIXM ({}): {source}", filename.display()))]
-    #[expect(dead_code, reason = "to be used for local AIXM data")]
     OpenAixm {
         filename: PathBuf,
         source: std::io::Error,
@@ -123,6 +301,17 @@ pub(crate) enum Error {
         source: Box<EseError>,
     },
 
+    #[snafu(display("Could not open .rwy ({}): {source}", filename.display()))]
+    OpenRwy {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not read .rwy ({}): {source}", filename.display()))]
+    ReadRwy {
+        filename: PathBuf,
+        source: std::io::Error,
+    },
+
     #[snafu(display("Could not open .sct ({}): {source}", filename.display()))]
     OpenSct {
         filename: PathBuf,
@@ -140,9 +329,162 @@ pub(crate) enum Error {
         source: Box<SctError>,
     },
 
+    #[snafu(display("Request to {url} blocked, its host is not in network.allowed-hosts"))]
+    HostNotAllowed { url: String },
+    #[snafu(display("Could not write audit log ({}): {source}", path.display()))]
+    WriteAuditLog {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Could not open message log ({}): {source}", path.display()))]
+    OpenMessageLog {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not write message log ({}): {source}", path.display()))]
+    WriteMessageLog {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not save log ({}): {source}", path.display()))]
+    SaveLog {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Invalid fix-exclude pattern: {source}"))]
+    InvalidFixPattern { source: regex::Error },
+    #[snafu(display("Could not read designator overrides ({}): {source}", path.display()))]
+    ReadOverrides {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[snafu(display("Could not parse designator overrides ({}): {source}", path.display()))]
+    ParseOverridesToml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[snafu(display("Could not parse designator overrides ({}): {source}", path.display()))]
+    ParseOverridesCsv { path: PathBuf, source: csv::Error },
+    #[snafu(display(
+        "Designator override {designator} ({}) needs both lat and lon, or neither",
+        path.display()
+    ))]
+    IncompleteOverride { path: PathBuf, designator: String },
+
+    #[snafu(display("Cancelled"))]
+    Cancelled,
+
+    #[snafu(display(
+        "Output writer {name} needs interface {version}, this version provides {current}"
+    ))]
+    IncompatibleOutputWriter {
+        name: String,
+        version: String,
+        current: String,
+    },
+
+    #[snafu(display("Invalid gml:pos: {pos}"))]
+    InvalidGmlPos { pos: String },
+    #[snafu(display("Skipped {kind} {designator} from {dataset}: {source}"))]
+    InvalidEntity {
+        kind: EntityKind,
+        designator: String,
+        dataset: String,
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<Error>,
+    },
+    #[snafu(display("Unsupported srsName: {srs_name}"))]
+    UnsupportedSrsName { srs_name: String },
+
     #[snafu(context(false))]
     Send { source: SendError<Message> },
 
     #[snafu(context(false))]
     Join { source: JoinError },
 }
+
+/// Where an error occurred, to group it in the summary and the JSON log.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ErrorContext {
+    pub stage: Option<Stage>,
+    /// Dataset name or file path
+    pub file: Option<String>,
+    pub entity: Option<String>,
+}
+
+impl Error {
+    pub fn context(&self) -> ErrorContext {
+        let at = |stage, file: String, entity: Option<&str>| ErrorContext {
+            stage: Some(stage),
+            file: Some(file),
+            entity: entity.map(str::to_string),
+        };
+        match self {
+            Error::Rename { to: path, .. }
+            | Error::CopyBackup { to: path, .. }
+            | Error::CreateNew { path, .. }
+            | Error::WriteNew { path, .. }
+            | Error::ResolveSymlink { path, .. }
+            | Error::SymlinkOutsidePackage { path, .. }
+            | Error::ChangedSincePlanned { path }
+            | Error::CreateStagingDir { path, .. }
+            | Error::WriteGeoExport { path, .. }
+            | Error::WriteFollowUp { path, .. }
+            | Error::WriteQualityHistory { path, .. } => {
+                at(Stage::Writing, path.display().to_string(), None)
+            }
+            Error::EsePositionsChanged { path, line } => at(
+                Stage::Writing,
+                path.display().to_string(),
+                Some(line.as_str()),
+            ),
+            Error::DatasetNotFound { dataset }
+            | Error::FetchDataset { dataset, .. }
+            | Error::DecodeDataset { dataset, .. }
+            | Error::DatasetServerError { dataset, .. }
+            | Error::MalformedDataset { dataset, .. } => {
+                at(Stage::Downloading, dataset.clone(), None)
+            }
+            Error::FetchReference { url, .. }
+            | Error::FetchDatasetIndex { url, .. }
+            | Error::HostNotAllowed { url } => at(Stage::Downloading, url.clone(), None),
+            Error::LocalDatasetNotFound { dataset, .. }
+            | Error::DatasetNotUtf8 { dataset, .. }
+            | Error::DeserializeDataset { dataset, .. }
+            | Error::OpenArchive { dataset, .. }
+            | Error::ReadArchiveEntry { dataset, .. }
+            | Error::ArchiveWithoutXml { dataset } => at(Stage::Parsing, dataset.clone(), None),
+            Error::ReadAixm { filename, .. }
+            | Error::OpenAixm { filename, .. }
+            | Error::OpenPrf { filename, .. }
+            | Error::ReadPrf { filename, .. }
+            | Error::ParsePrf { filename, .. }
+            | Error::OpenIsec { filename, .. }
+            | Error::ReadIsec { filename, .. }
+            | Error::ParseIsec { filename, .. }
+            | Error::OpenEse { filename, .. }
+            | Error::ReadEse { filename, .. }
+            | Error::ParseEse { filename, .. }
+            | Error::OpenRwy { filename, .. }
+            | Error::ReadRwy { filename, .. }
+            | Error::OpenSct { filename, .. }
+            | Error::ReadSct { filename, .. }
+            | Error::ParseSct { filename, .. } => {
+                at(Stage::Parsing, filename.display().to_string(), None)
+            }
+            Error::InvalidEntity {
+                designator,
+                dataset,
+                ..
+            } => at(Stage::Combining, dataset.clone(), Some(designator.as_str())),
+            Error::InvalidGmlPos { pos } => ErrorContext {
+                stage: Some(Stage::Parsing),
+                entity: Some(pos.clone()),
+                ..ErrorContext::default()
+            },
+            _ => ErrorContext::default(),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(feature = "gui")]
+use egui::{CollapsingHeader, Grid, RichText, Ui};
+use tracing::error;
+
+use crate::error::ErrorContext;
+
+/// Group of errors without a file or dataset.
+const GENERAL: &str = "general";
+
+/// Errors of a run grouped by file or dataset, in the order they occurred.
+#[derive(Debug, Default)]
+pub struct ErrorSummary {
+    groups: Vec<(String, Vec<(ErrorContext, String)>)>,
+}
+
+impl ErrorSummary {
+    pub fn add(&mut self, context: ErrorContext, message: String) {
+        let file = context.file.as_deref().unwrap_or(GENERAL);
+        if let Some((_, errors)) = self.groups.iter_mut().find(|(group, _)| group == file) {
+            errors.push((context, message));
+        } else {
+            self.groups
+                .push((file.to_string(), vec![(context, message)]));
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    /// Logs the groups again at the end of a command line run, where the
+    /// individual errors are easily lost in the progress output.
+    pub fn log(&self) {
+        for (file, errors) in &self.groups {
+            error!("{file}: {} errors", errors.len());
+            for (context, message) in errors {
+                error!("  {}", describe(context, message));
+            }
+        }
+    }
+}
+
+fn describe(context: &ErrorContext, message: &str) -> String {
+    let stage = context
+        .stage
+        .map(|stage| format!("[{stage}] "))
+        .unwrap_or_default();
+    match &context.entity {
+        Some(entity) => format!("{stage}{entity}: {message}"),
+        None => format!("{stage}{message}"),
+    }
+}
+
+#[cfg(feature = "gui")]
+pub fn show_error_summary(ui: &mut Ui, summary: &ErrorSummary) {
+    let error_color = ui.style().visuals.error_fg_color;
+    ui.heading("Errors");
+    for (file, errors) in &summary.groups {
+        CollapsingHeader::new(
+            RichText::new(format!("{file} ({} errors)", errors.len())).color(error_color),
+        )
+        .id_salt(("errors", file))
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new(("error-summary", file))
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Stage");
+                    ui.strong("Entity");
+                    ui.strong("Error");
+                    ui.end_row();
+                    for (context, message) in errors {
+                        ui.label(
+                            context
+                                .stage
+                                .as_ref()
+                                .map(ToString::to_string)
+                                .unwrap_or_default(),
+                        );
+                        ui.monospace(context.entity.clone().unwrap_or_default());
+                        ui.label(message);
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
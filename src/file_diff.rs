@@ -0,0 +1,154 @@
+//! Line diffs between the sector files as read and as written, to check the
+//! result of a run without external diff tools.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+#[cfg(feature = "gui")]
+use egui::{CollapsingHeader, RichText, ScrollArea, TextStyle, TextWrapMode, Ui};
+use similar::{ChangeTag, TextDiff};
+
+/// Unchanged lines shown around every change
+const CONTEXT_LINES: usize = 3;
+/// Diffing gives up on an optimal result after this long, e.g. for a fully
+/// reordered file
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLineKind {
+    Context,
+    Removed,
+    Added,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    /// 1-based line numbers in the old and new file
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    pub text: String,
+}
+
+/// Changed lines of a file with some context, grouped into hunks.
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub hunks: Vec<Vec<DiffLine>>,
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl FileDiff {
+    pub fn new(path: &Path, old: &str, new: &str) -> Self {
+        let diff = TextDiff::configure()
+            .timeout(DIFF_TIMEOUT)
+            .diff_lines(old, new);
+        let (mut added, mut removed) = (0, 0);
+        let hunks = diff
+            .grouped_ops(CONTEXT_LINES)
+            .iter()
+            .map(|ops| {
+                ops.iter()
+                    .flat_map(|op| diff.iter_changes(op))
+                    .map(|change| {
+                        let kind = match change.tag() {
+                            ChangeTag::Equal => DiffLineKind::Context,
+                            ChangeTag::Delete => {
+                                removed += 1;
+                                DiffLineKind::Removed
+                            }
+                            ChangeTag::Insert => {
+                                added += 1;
+                                DiffLineKind::Added
+                            }
+                        };
+                        DiffLine {
+                            kind,
+                            old: change.old_index().map(|i| i + 1),
+                            new: change.new_index().map(|i| i + 1),
+                            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            path: path.to_path_buf(),
+            hunks,
+            added,
+            removed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hunks.is_empty()
+    }
+}
+
+/// Unified diff per file, each in a scroll area of its own.
+#[cfg(feature = "gui")]
+pub fn show_file_diffs(ui: &mut Ui, diffs: &[FileDiff]) {
+    let visuals = ui.style().visuals.clone();
+    let row_height = ui.text_style_height(&TextStyle::Monospace);
+    for diff in diffs {
+        let title = format!(
+            "{} (+{} −{})",
+            diff.path.display(),
+            diff.added,
+            diff.removed
+        );
+        CollapsingHeader::new(title)
+            .id_salt(("diff", &diff.path))
+            .show(ui, |ui| {
+                if diff.is_empty() {
+                    ui.label("No differences");
+                    return;
+                }
+                // hunks separated by an empty row
+                let rows = diff
+                    .hunks
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, hunk)| {
+                        (i > 0)
+                            .then_some(None)
+                            .into_iter()
+                            .chain(hunk.iter().map(Some))
+                    })
+                    .collect::<Vec<_>>();
+                ScrollArea::both()
+                    .id_salt(("diff-scroll", &diff.path))
+                    .max_height(400.)
+                    .auto_shrink([false, true])
+                    .show_rows(ui, row_height, rows.len(), |ui, range| {
+                        ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
+                        for row in &rows[range] {
+                            let Some(line) = row else {
+                                ui.monospace(RichText::new("⋯").weak());
+                                continue;
+                            };
+                            let number = |number: Option<usize>| {
+                                number.map_or_else(|| " ".repeat(6), |n| format!("{n:>6}"))
+                            };
+                            let (sign, color) = match line.kind {
+                                DiffLineKind::Context => (' ', visuals.text_color()),
+                                DiffLineKind::Removed => ('-', visuals.error_fg_color),
+                                DiffLineKind::Added => ('+', visuals.hyperlink_color),
+                            };
+                            ui.monospace(
+                                RichText::new(format!(
+                                    "{} {} {sign} {}",
+                                    number(line.old),
+                                    number(line.new),
+                                    line.text
+                                ))
+                                .color(color),
+                            );
+                        }
+                    });
+            });
+    }
+}
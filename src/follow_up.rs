@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use snafu::ResultExt as _;
+use tokio::{fs, sync::mpsc};
+
+use crate::{
+    Message,
+    error::{AiracUpdaterResult, WriteFollowUpSnafu},
+};
+
+/// Why an entity was left for the maintainer instead of being updated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FollowUpReason {
+    /// Defined several times in AIXM, one of the definitions was picked
+    AixmConflict,
+    /// Several AIXM frequencies for the call sign of a position
+    AmbiguousFrequency,
+    /// New fix skipped by `fix-exclude`, but used by a SID or STAR
+    ExcludedButReferenced,
+    /// SID/STAR waypoint not found in AIXM
+    UnknownWaypoint,
+    /// Missing in AIXM, but kept in the .sct
+    Stale,
+    /// New entity only written as a commented-out candidate line
+    Candidate,
+    /// Change rejected in review
+    Rejected,
+    /// Military only in AIXM, see [`MilitaryEntities::Tag`]
+    ///
+    /// [`MilitaryEntities::Tag`]: crate::config::MilitaryEntities::Tag
+    Military,
+}
+
+impl fmt::Display for FollowUpReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FollowUpReason::AixmConflict => "aixm-conflict",
+            FollowUpReason::AmbiguousFrequency => "ambiguous-frequency",
+            FollowUpReason::ExcludedButReferenced => "excluded-but-referenced",
+            FollowUpReason::UnknownWaypoint => "unknown-waypoint",
+            FollowUpReason::Stale => "stale",
+            FollowUpReason::Candidate => "candidate",
+            FollowUpReason::Rejected => "rejected",
+            FollowUpReason::Military => "military",
+        })
+    }
+}
+
+/// Entity the tool chose not to touch, one row of the follow-up list.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct FollowUp {
+    pub reason: FollowUpReason,
+    /// File of the entity, empty for AIXM conflicts
+    pub file: String,
+    pub kind: String,
+    pub designator: String,
+    pub detail: String,
+}
+
+impl FollowUp {
+    pub fn new(
+        reason: FollowUpReason,
+        file: Option<&Path>,
+        kind: impl ToString,
+        designator: impl Into<String>,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            reason,
+            file: file
+                .map(|file| file.display().to_string())
+                .unwrap_or_default(),
+            kind: kind.to_string(),
+            designator: designator.into(),
+            detail: detail.into(),
+        }
+    }
+}
+
+/// `<name>.prf.aau_follow_up.csv` next to the .prf
+pub fn follow_up_path(prf: &Path) -> PathBuf {
+    let mut file_name = prf.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_follow_up.csv");
+    prf.with_file_name(file_name)
+}
+
+/// Keeps the fixes excluded by `fix-exclude` only if a SID or STAR uses them,
+/// the others were skipped on purpose.
+pub fn retain_referenced(follow_ups: &mut Vec<FollowUp>, procedure_waypoints: &BTreeSet<String>) {
+    follow_ups.retain(|follow_up| {
+        follow_up.reason != FollowUpReason::ExcludedButReferenced
+            || procedure_waypoints.contains(&follow_up.designator)
+    });
+}
+
+fn render(follow_ups: &[FollowUp]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for follow_up in follow_ups {
+        writer.serialize(follow_up)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Writes the follow-up list next to the .prf, overwriting the one of the
+/// previous run, sorted by reason and file.
+pub async fn write_follow_ups(
+    prf: &Path,
+    follow_ups: &mut [FollowUp],
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let path = follow_up_path(prf);
+    follow_ups.sort();
+    let content = render(follow_ups).context(WriteFollowUpSnafu { path: &path })?;
+    fs::write(&path, content)
+        .await
+        .map_err(csv::Error::from)
+        .context(WriteFollowUpSnafu { path: &path })?;
+    tx.send(Message::info(format!(
+        "{} entities need manual attention, see {}",
+        follow_ups.len(),
+        path.display()
+    )))
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::path::{Path, PathBuf};
+
+use geo::Point;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::ResultExt as _;
+use tokio::{fs, sync::mpsc};
+use vatsim_parser::sct::Sct;
+
+use crate::{
+    Message,
+    aixm_combine::EuroscopeFile,
+    error::{AiracUpdaterResult, WriteGeoExportSnafu},
+};
+
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+const UTM_K0: f64 = 0.9996;
+const UTM_FALSE_EASTING: f64 = 500_000.0;
+const UTM_FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExportFormat {
+    #[default]
+    GeoJson,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum ExportCrs {
+    #[default]
+    Wgs84,
+    /// UTM on WGS84, northern hemisphere
+    Utm { zone: u8 },
+}
+
+impl ExportCrs {
+    fn urn(self) -> String {
+        match self {
+            ExportCrs::Wgs84 => "urn:ogc:def:crs:OGC:1.3:CRS84".to_string(),
+            ExportCrs::Utm { zone } => format!("urn:ogc:def:crs:EPSG::326{zone:02}"),
+        }
+    }
+
+    /// Projects a WGS84 point into `(x, y)` of this CRS, i.e. longitude and
+    /// latitude or easting and northing in metres.
+    pub fn project(self, point: Point) -> (f64, f64) {
+        match self {
+            ExportCrs::Wgs84 => (point.x(), point.y()),
+            ExportCrs::Utm { zone } => utm(point, zone),
+        }
+    }
+}
+
+/// Transverse Mercator forward projection (Snyder, USGS PP 1395, p. 61).
+fn utm(point: Point, zone: u8) -> (f64, f64) {
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let e4 = e2 * e2;
+    let e6 = e4 * e2;
+    let ep2 = e2 / (1.0 - e2);
+
+    let lat = point.y().to_radians();
+    let central_meridian = (f64::from(zone) * 6.0 - 183.0).to_radians();
+
+    let n = WGS84_A / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    let t = lat.tan().powi(2);
+    let c = ep2 * lat.cos().powi(2);
+    let a = lat.cos() * (point.x().to_radians() - central_meridian);
+    let m = WGS84_A
+        * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * lat
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * lat).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * lat).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * lat).sin());
+
+    let easting = UTM_K0
+        * n
+        * (a + (1.0 - t + c) * a.powi(3) / 6.0
+            + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0)
+        + UTM_FALSE_EASTING;
+    let mut northing = UTM_K0
+        * (m + n
+            * lat.tan()
+            * (a * a / 2.0
+                + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+                + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+    if lat < 0.0 {
+        northing += UTM_FALSE_NORTHING_SOUTH;
+    }
+
+    (easting, northing)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GeoExport {
+    pub format: ExportFormat,
+    pub crs: ExportCrs,
+}
+
+struct ExportEntity<'a> {
+    kind: &'static str,
+    designator: &'a str,
+    point: Point,
+}
+
+fn sct_entities(sct: &Sct) -> Vec<ExportEntity<'_>> {
+    let airports = sct.airports.iter().map(|ad| ExportEntity {
+        kind: "airport",
+        designator: &ad.designator,
+        point: ad.coordinate,
+    });
+    let vors = sct.vors.iter().map(|vor| ExportEntity {
+        kind: "vor",
+        designator: &vor.designator,
+        point: vor.coordinate,
+    });
+    let ndbs = sct.ndbs.iter().map(|ndb| ExportEntity {
+        kind: "ndb",
+        designator: &ndb.designator,
+        point: ndb.coordinate,
+    });
+    let fixes = sct.fixes.iter().map(|fix| ExportEntity {
+        kind: "fix",
+        designator: &fix.designator,
+        point: fix.coordinate,
+    });
+
+    airports.chain(vors).chain(ndbs).chain(fixes).collect()
+}
+
+impl GeoExport {
+    pub fn path_for(self, sct_path: &Path) -> PathBuf {
+        let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(match self.format {
+            ExportFormat::GeoJson => ".aau_export.geojson",
+            ExportFormat::Csv => ".aau_export.csv",
+        });
+        sct_path.with_file_name(file_name)
+    }
+
+    pub fn render(self, sct: &Sct) -> String {
+        let entities = sct_entities(sct);
+        match self.format {
+            ExportFormat::GeoJson => {
+                let features = entities
+                    .iter()
+                    .map(|entity| {
+                        let (x, y) = self.crs.project(entity.point);
+                        json!({
+                            "type": "Feature",
+                            "geometry": { "type": "Point", "coordinates": [x, y] },
+                            "properties": { "type": entity.kind, "designator": entity.designator },
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({
+                    "type": "FeatureCollection",
+                    "crs": { "type": "name", "properties": { "name": self.crs.urn() } },
+                    "features": features,
+                })
+                .to_string()
+            }
+            ExportFormat::Csv => {
+                let mut csv = match self.crs {
+                    ExportCrs::Wgs84 => "type,designator,lon,lat\n",
+                    ExportCrs::Utm { .. } => "type,designator,easting,northing\n",
+                }
+                .to_string();
+                for entity in &entities {
+                    let (x, y) = self.crs.project(entity.point);
+                    csv.push_str(&format!(
+                        "{},{},{x:.8},{y:.8}\n",
+                        entity.kind, entity.designator
+                    ));
+                }
+                csv
+            }
+        }
+    }
+}
+
+/// Writes the debug export for every combined .sct, overwriting earlier
+/// exports.
+pub async fn write_geo_exports(
+    files: &[EuroscopeFile],
+    geo_export: GeoExport,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    for file in files {
+        if let EuroscopeFile::Sct { path, content, .. } = file {
+            let export_path = geo_export.path_for(path);
+            fs::write(&export_path, geo_export.render(content))
+                .await
+                .context(WriteGeoExportSnafu { path: &export_path })?;
+            tx.send(Message::info(format!(
+                "Wrote GEO export {}",
+                export_path.display()
+            )))
+            .await?;
+        }
+    }
+
+    Ok(())
+}
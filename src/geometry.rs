@@ -0,0 +1,201 @@
+use aixm::LocationType;
+use geo::{Point, point};
+use snafu::{OptionExt as _, ensure};
+
+use crate::error::{AiracUpdaterResult, InvalidGmlPosSnafu, UnsupportedSrsNameSnafu};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisOrder {
+    LatLon,
+    LonLat,
+}
+
+impl AxisOrder {
+    /// Axis order of the CRS given as `srsName`. AIXM defaults to EPSG:4326,
+    /// which is latitude first in all its spellings but the legacy
+    /// `epsg.xml#4326` URL, while CRS84 is longitude first.
+    pub fn from_srs_name(srs_name: Option<&str>) -> Option<Self> {
+        match srs_name {
+            None
+            | Some(
+                "EPSG:4326"
+                | "urn:ogc:def:crs:EPSG::4326"
+                | "urn:ogc:def:crs:EPSG:6.6:4326"
+                | "http://www.opengis.net/def/crs/EPSG/0/4326",
+            ) => Some(Self::LatLon),
+            Some(
+                "urn:ogc:def:crs:OGC:1.3:CRS84"
+                | "urn:ogc:def:crs:OGC::CRS84"
+                | "http://www.opengis.net/def/crs/OGC/1.3/CRS84"
+                | "CRS:84"
+                | "OGC:CRS84"
+                | "http://www.opengis.net/gml/srs/epsg.xml#4326",
+            ) => Some(Self::LonLat),
+            Some(_) => None,
+        }
+    }
+}
+
+/// A `gml:pos` together with the `srsName` of its enclosing point.
+#[derive(Debug, Clone, Copy)]
+pub struct GmlPos<'a> {
+    pos: &'a str,
+    srs_name: Option<&'a str>,
+}
+
+impl<'a> GmlPos<'a> {
+    pub fn new(pos: &'a str, srs_name: Option<&'a str>) -> Self {
+        Self { pos, srs_name }
+    }
+
+    pub fn to_point(self) -> AiracUpdaterResult<Point> {
+        let axis_order =
+            AxisOrder::from_srs_name(self.srs_name).context(UnsupportedSrsNameSnafu {
+                srs_name: self.srs_name.unwrap_or_default(),
+            })?;
+
+        let mut coordinates = self.pos.split_whitespace().map(str::parse::<f64>);
+        let (Some(Ok(first)), Some(Ok(second))) = (coordinates.next(), coordinates.next()) else {
+            return InvalidGmlPosSnafu { pos: self.pos }.fail();
+        };
+
+        let (lat, lng) = match axis_order {
+            AxisOrder::LatLon => (first, second),
+            AxisOrder::LonLat => (second, first),
+        };
+        ensure!(
+            (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lng),
+            InvalidGmlPosSnafu { pos: self.pos }
+        );
+
+        Ok(point! { x: lng, y: lat })
+    }
+}
+
+impl<'a> From<&'a LocationType> for GmlPos<'a> {
+    fn from(location: &'a LocationType) -> Self {
+        match location {
+            LocationType::ElevatedPoint(ep) => Self::new(&ep.gml_pos, ep.srs_name.as_deref()),
+            LocationType::Point(p) => Self::new(&p.gml_pos, p.srs_name.as_deref()),
+        }
+    }
+}
+
+/// Points of a `gml:posList`, or of the `gml:pos` elements if there is none.
+pub fn gml_points(
+    pos_list: Option<&str>,
+    pos: &[String],
+    srs_name: Option<&str>,
+) -> AiracUpdaterResult<Vec<Point>> {
+    if let Some(pos_list) = pos_list {
+        let values = pos_list.split_whitespace().collect::<Vec<_>>();
+        values
+            .chunks(2)
+            .map(|pos| GmlPos::new(&pos.join(" "), srs_name).to_point())
+            .collect()
+    } else {
+        pos.iter()
+            .map(|pos| GmlPos::new(pos, srs_name).to_point())
+            .collect()
+    }
+}
+
+/// Coordinate in .sct/.ese notation, e.g. `N048.21.13.620`.
+fn format_sct_coordinate(value: f64, positive: char, negative: char) -> String {
+    let hemisphere = if value < 0.0 { negative } else { positive };
+    let millis = (value.abs() * 3_600_000.0).round() as u64;
+    format!(
+        "{hemisphere}{:03}.{:02}.{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Latitude and longitude of `point` in .sct/.ese notation.
+pub fn to_sct_coordinates(point: Point) -> (String, String) {
+    (
+        format_sct_coordinate(point.y(), 'N', 'S'),
+        format_sct_coordinate(point.x(), 'E', 'W'),
+    )
+}
+
+fn parse_sct_coordinate(value: &str, positive: char, negative: char) -> Option<f64> {
+    let value = value.trim();
+    let sign = match value.chars().next()?.to_ascii_uppercase() {
+        c if c == positive => 1.0,
+        c if c == negative => -1.0,
+        _ => return None,
+    };
+    let mut parts = value[1..].splitn(3, '.');
+    let degrees = parts.next()?.parse::<f64>().ok()?;
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let seconds = parts.next()?.parse::<f64>().ok()?;
+    Some(sign * (degrees + minutes / 60.0 + seconds / 3600.0))
+}
+
+/// Parses latitude and longitude in .sct/.ese notation, e.g.
+/// `N048.21.13.620` and `E011.47.09.900`.
+pub fn from_sct_coordinates(lat: &str, lon: &str) -> Option<Point> {
+    Some(point! {
+        x: parse_sct_coordinate(lon, 'E', 'W')?,
+        y: parse_sct_coordinate(lat, 'N', 'S')?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epsg_4326_is_latitude_first() {
+        for srs_name in [
+            None,
+            Some("EPSG:4326"),
+            Some("urn:ogc:def:crs:EPSG::4326"),
+            Some("urn:ogc:def:crs:EPSG:6.6:4326"),
+            Some("http://www.opengis.net/def/crs/EPSG/0/4326"),
+        ] {
+            assert_eq!(
+                AxisOrder::from_srs_name(srs_name),
+                Some(AxisOrder::LatLon),
+                "{srs_name:?}"
+            );
+            let point = GmlPos::new("48.35 11.78", srs_name).to_point().unwrap();
+            assert_eq!((point.y(), point.x()), (48.35, 11.78), "{srs_name:?}");
+        }
+    }
+
+    #[test]
+    fn crs84_and_legacy_epsg_url_are_longitude_first() {
+        for srs_name in [
+            "CRS:84",
+            "OGC:CRS84",
+            "urn:ogc:def:crs:OGC:1.3:CRS84",
+            "urn:ogc:def:crs:OGC::CRS84",
+            "http://www.opengis.net/def/crs/OGC/1.3/CRS84",
+            "http://www.opengis.net/gml/srs/epsg.xml#4326",
+        ] {
+            assert_eq!(
+                AxisOrder::from_srs_name(Some(srs_name)),
+                Some(AxisOrder::LonLat),
+                "{srs_name}"
+            );
+            let point = GmlPos::new("11.78 48.35", Some(srs_name))
+                .to_point()
+                .unwrap();
+            assert_eq!((point.y(), point.x()), (48.35, 11.78), "{srs_name}");
+        }
+    }
+
+    #[test]
+    fn unknown_crs_is_rejected() {
+        assert_eq!(AxisOrder::from_srs_name(Some("EPSG:25832")), None);
+        assert!(
+            GmlPos::new("48.35 11.78", Some("EPSG:25832"))
+                .to_point()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use airac_aixm_updater_core::{
+    config::{DEFAULT_PROFILE, Preset},
+    staging::WriteConfirmation,
+    validation::AnomalyConfirmation,
+};
+use egui::{Context, Id, Modal, RichText};
+use tokio::sync::oneshot;
+
+/// Suspicious results listed in the confirmation, the rest only in the log
+const LISTED_ANOMALIES: usize = 15;
+
+/// Answer to a [`Confirmation`], `None` while it is still open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Answer {
+    Confirmed,
+    Cancelled,
+}
+
+/// Modal question before a destructive action, holding the action until it
+/// is answered.
+pub(super) struct Confirmation<A> {
+    pub(super) title: String,
+    pub(super) message: String,
+    pub(super) confirm_label: &'static str,
+    pub(super) action: A,
+}
+
+impl<A> Confirmation<A> {
+    /// Shows the dialog on top of everything else. Cancel has the initial
+    /// focus, so Enter or Space only confirm after tabbing to the confirm
+    /// button, and Escape or a click outside cancel.
+    pub(super) fn show(&self, ctx: &Context) -> Option<Answer> {
+        let mut answer = None;
+        let modal = Modal::new(Id::new("confirmation")).show(ctx, |ui| {
+            ui.set_max_width(420.);
+            ui.heading(&self.title);
+            ui.add_space(8.);
+            ui.label(&self.message);
+            ui.add_space(12.);
+            ui.horizontal(|ui| {
+                let cancel = ui.button("Cancel");
+                if ui.memory(|memory| memory.focused().is_none()) {
+                    cancel.request_focus();
+                }
+                if cancel.clicked() {
+                    answer = Some(Answer::Cancelled);
+                }
+                let confirm_label =
+                    RichText::new(self.confirm_label).color(ui.visuals().warn_fg_color);
+                if ui.button(confirm_label).clicked() {
+                    answer = Some(Answer::Confirmed);
+                }
+            });
+        });
+        if answer.is_none() && modal.should_close() {
+            answer = Some(Answer::Cancelled);
+        }
+        answer
+    }
+}
+
+/// Asks whether to write the files of a run despite its suspicious results,
+/// answering the run with the reply.
+pub(super) fn anomaly_confirmation(
+    request: AnomalyConfirmation,
+) -> Confirmation<oneshot::Sender<bool>> {
+    let mut message = request
+        .anomalies
+        .iter()
+        .take(LISTED_ANOMALIES)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let more = request.anomalies.len().saturating_sub(LISTED_ANOMALIES);
+    if more > 0 {
+        message.push_str(&format!("\n… and {more} more, see the log"));
+    }
+    Confirmation {
+        title: "Write despite suspicious results?".to_string(),
+        message,
+        confirm_label: "Write anyway",
+        action: request.reply,
+    }
+}
+
+/// Asks whether to write the planned files of a run, each with its backup,
+/// answering the run with the reply.
+pub(super) fn write_confirmation(
+    request: WriteConfirmation,
+) -> Confirmation<oneshot::Sender<bool>> {
+    let message = request
+        .plan
+        .files
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Confirmation {
+        title: format!("Write {} files?", request.plan.files.len()),
+        message,
+        confirm_label: "Write",
+        action: request.reply,
+    }
+}
+
+/// Asks on the first start which profile to begin with, returning its name
+/// once chosen. Closing the dialog keeps the default profile.
+pub(super) fn choose_preset(ctx: &Context) -> Option<&'static str> {
+    let mut choice = None;
+    let modal = Modal::new(Id::new("preset")).show(ctx, |ui| {
+        ui.set_max_width(420.);
+        ui.heading("What kind of package do you maintain?");
+        ui.add_space(8.);
+        ui.label("The settings of every profile can be changed later on.");
+        ui.add_space(12.);
+        for preset in Preset::ALL {
+            if ui
+                .button(preset.name())
+                .on_hover_text(preset.description())
+                .clicked()
+            {
+                choice = Some(preset.name());
+            }
+            ui.label(preset.description());
+            ui.add_space(8.);
+        }
+        if ui.button(DEFAULT_PROFILE).clicked() {
+            choice = Some(DEFAULT_PROFILE);
+        }
+        ui.label("Everything enabled with the default settings");
+    });
+    if choice.is_none() && modal.should_close() {
+        choice = Some(DEFAULT_PROFILE);
+    }
+    choice
+}
@@ -0,0 +1,102 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// How deep below a EuroScope folder .prf files are searched for, e.g.
+/// `EuroScope\EDMM\EDMM.prf`
+const SEARCH_DEPTH: usize = 3;
+
+/// EuroScope folders found on this machine and the .prf files in them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Discovery {
+    pub(crate) folders: Vec<PathBuf>,
+    pub(crate) packages: Vec<PathBuf>,
+}
+
+impl Discovery {
+    /// Probes the standard EuroScope install and documents folders as well as
+    /// the folders EuroScope remembers in the registry.
+    pub(crate) fn probe() -> Self {
+        let mut folders = dirs::document_dir()
+            .map(|documents| documents.join("EuroScope"))
+            .into_iter()
+            .chain(
+                ["ProgramFiles(x86)", "ProgramFiles"]
+                    .into_iter()
+                    .filter_map(|var| Some(PathBuf::from(env::var_os(var)?).join("EuroScope"))),
+            )
+            .chain(registry_folders())
+            .filter(|folder| folder.is_dir())
+            .collect::<Vec<_>>();
+        folders.sort();
+        folders.dedup();
+
+        let mut packages = vec![];
+        for folder in &folders {
+            find_prfs(folder, SEARCH_DEPTH, &mut packages);
+        }
+        packages.sort();
+        packages.dedup();
+
+        Self { folders, packages }
+    }
+}
+
+fn find_prfs(dir: &Path, depth: usize, prfs: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                find_prfs(&path, depth - 1, prfs);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("prf"))
+        {
+            prfs.push(path);
+        }
+    }
+}
+
+/// Folders of the paths EuroScope stores under `HKCU\Software\EuroScope`. The
+/// value names differ between versions, so every absolute path is taken, the
+/// folder of it for files.
+#[cfg(windows)]
+fn registry_folders() -> Vec<PathBuf> {
+    use std::{os::windows::process::CommandExt as _, process::Command};
+
+    // no console window flashing up from the GUI
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let Ok(output) = Command::new("reg")
+        .args(["query", r"HKCU\Software\EuroScope", "/s"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+    else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (_, value) = line.split_once("REG_SZ")?;
+            let path = PathBuf::from(value.trim());
+            if !path.is_absolute() {
+                return None;
+            }
+            if path.is_dir() {
+                Some(path)
+            } else {
+                path.parent().map(Path::to_path_buf)
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn registry_folders() -> Vec<PathBuf> {
+    vec![]
+}
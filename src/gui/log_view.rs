@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use airac_aixm_updater_core::{Message, locale::DisplayLocale};
+use chrono::SecondsFormat;
+use egui::{
+    CollapsingHeader, Label, RichText, ScrollArea, Stroke, TextEdit, TextWrapMode, Ui, Widget as _,
+};
+use tracing::Level;
+
+/// Levels, search text and grouping of the log panel. Debug messages are
+/// hidden initially, as they make up most of a full run.
+pub(super) struct LogFilter {
+    debug: bool,
+    info: bool,
+    warn: bool,
+    error: bool,
+    search: String,
+    grouped: bool,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            info: true,
+            warn: true,
+            error: true,
+            search: String::new(),
+            grouped: false,
+        }
+    }
+}
+
+impl LogFilter {
+    fn shows_level(&self, level: Level) -> bool {
+        match level {
+            Level::ERROR => self.error,
+            Level::WARN => self.warn,
+            Level::INFO => self.info,
+            Level::DEBUG | Level::TRACE => self.debug,
+        }
+    }
+
+    pub(super) fn show_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.error, "Errors");
+            ui.checkbox(&mut self.warn, "Warnings");
+            ui.checkbox(&mut self.info, "Info");
+            ui.checkbox(&mut self.debug, "Debug");
+            ui.separator();
+            TextEdit::singleline(&mut self.search)
+                .hint_text("Search")
+                .desired_width(200.)
+                .ui(ui);
+            if !self.search.is_empty() && ui.small_button("✕").clicked() {
+                self.search.clear();
+            }
+            ui.separator();
+            ui.checkbox(&mut self.grouped, "Group by dataset or file");
+        });
+    }
+
+    /// Messages of `log` passing the filter, in their order.
+    fn filter<'a>(&self, log: &'a [Message]) -> Vec<&'a Message> {
+        let search = self.search.to_lowercase();
+        log.iter()
+            .filter(|msg| self.shows_level(msg.level))
+            .filter(|msg| search.is_empty() || msg.content.to_lowercase().contains(&search))
+            .collect()
+    }
+}
+
+/// Dataset or file a message is about, if it says so.
+fn group(msg: &Message) -> Option<String> {
+    if let Some((item, _)) = &msg.stage {
+        return Some(item.clone());
+    }
+    if let Some(file) = msg.error.as_ref().and_then(|context| context.file.clone()) {
+        return Some(file);
+    }
+    if let Some((dataset, _)) = &msg.member_counts {
+        return Some(dataset.clone());
+    }
+    msg.changes
+        .as_ref()
+        .map(|(file, _)| file.display().to_string())
+}
+
+/// The messages of `log` passing `filter`, optionally as one collapsible
+/// group per dataset or file in the order they first appear. Groups with
+/// warnings or errors are open initially.
+pub(super) fn show_log(ui: &mut Ui, log: &[Message], filter: &LogFilter, locale: &DisplayLocale) {
+    let messages = filter.filter(log);
+    ui.label(format!("{} of {} messages", messages.len(), log.len()));
+
+    egui::Frame::new()
+        .stroke(Stroke::new(1., ui.style().visuals.text_color()))
+        .show(ui, |ui| {
+            ScrollArea::both()
+                .stick_to_bottom(true)
+                .auto_shrink(false)
+                .show(ui, |ui| {
+                    if !filter.grouped {
+                        for msg in messages {
+                            show_message(ui, msg, locale);
+                        }
+                        return;
+                    }
+
+                    let mut groups: Vec<(String, Vec<&Message>)> = vec![];
+                    let mut index = HashMap::new();
+                    for msg in messages {
+                        let name = group(msg).unwrap_or_else(|| "General".to_string());
+                        let i = *index.entry(name.clone()).or_insert_with(|| {
+                            groups.push((name, vec![]));
+                            groups.len() - 1
+                        });
+                        groups[i].1.push(msg);
+                    }
+                    for (name, messages) in groups {
+                        let problems = messages
+                            .iter()
+                            .any(|msg| matches!(msg.level, Level::ERROR | Level::WARN));
+                        CollapsingHeader::new(format!("{name} ({})", messages.len()))
+                            .id_salt(("log-group", &name))
+                            .default_open(problems)
+                            .show(ui, |ui| {
+                                for msg in messages {
+                                    show_message(ui, msg, locale);
+                                }
+                            });
+                    }
+                })
+        });
+}
+
+/// All of `log` as text, one line per message with UTC timestamp and
+/// level, regardless of the filter.
+pub(super) fn log_text(log: &[Message]) -> String {
+    log.iter()
+        .map(|msg| {
+            format!(
+                "{} {:5} {}\n",
+                msg.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+                msg.level.as_str(),
+                msg.content
+            )
+        })
+        .collect()
+}
+
+fn show_message(ui: &mut Ui, msg: &Message, locale: &DisplayLocale) {
+    Label::new(
+        RichText::new(format!("[{}] {}", locale.date_time(msg.time), msg.content))
+            .size(12.)
+            .line_height(Some(18.))
+            .color(match msg.level {
+                Level::ERROR => ui.style().visuals.error_fg_color,
+                Level::WARN => ui.style().visuals.warn_fg_color,
+                Level::INFO => ui.style().visuals.text_color(),
+                Level::TRACE | Level::DEBUG => {
+                    ui.style().visuals.gray_out(ui.style().visuals.text_color())
+                }
+            }),
+    )
+    .wrap_mode(TextWrapMode::Extend)
+    .ui(ui);
+}
@@ -0,0 +1,267 @@
+use airac_aixm_updater_core::{
+    aixm_combine::EntityKind,
+    report::{Change, ChangeAction},
+};
+use egui::{
+    Align2, Color32, FontId, InputState, Painter, Pos2, Rect, Sense, Stroke, Ui, Vec2, Visuals,
+    vec2,
+};
+use geo::Point;
+
+use super::outline;
+
+const MAP_HEIGHT: f32 = 360.;
+/// Distance in points within which a change is shown on hover
+const HOVER_DISTANCE: f32 = 8.;
+const ADDED_COLOR: Color32 = Color32::from_rgb(0x4c, 0xaf, 0x50);
+/// Graticule spacings in degrees, the first at least this many points apart
+/// is drawn
+const GRID_STEPS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1., 2., 5., 10.];
+const MIN_GRID_SPACING: f64 = 80.;
+
+/// Centre and zoom of the map, in degrees and points per degree latitude.
+#[derive(Debug, Clone, Copy)]
+struct View {
+    center: Point,
+    scale: f64,
+    /// Longitudes shrink with the cosine of the latitude of the first fit
+    lon_factor: f64,
+}
+
+impl View {
+    fn fit(points: &[Point], size: Vec2) -> Option<Self> {
+        let first = points.first()?;
+        let (mut min, mut max) = (*first, *first);
+        for point in points {
+            min = Point::new(min.x().min(point.x()), min.y().min(point.y()));
+            max = Point::new(max.x().max(point.x()), max.y().max(point.y()));
+        }
+        let center = Point::new((min.x() + max.x()) / 2., (min.y() + max.y()) / 2.);
+        let lon_factor = center.y().to_radians().cos().max(0.1);
+        let width = ((max.x() - min.x()) * lon_factor).max(0.01);
+        let height = (max.y() - min.y()).max(0.01);
+        let scale = (f64::from(size.x) / width).min(f64::from(size.y) / height) * 0.85;
+        Some(Self {
+            center,
+            scale,
+            lon_factor,
+        })
+    }
+
+    fn to_screen(&self, rect: Rect, point: Point) -> Pos2 {
+        rect.center()
+            + vec2(
+                ((point.x() - self.center.x()) * self.lon_factor * self.scale) as f32,
+                (-(point.y() - self.center.y()) * self.scale) as f32,
+            )
+    }
+
+    fn from_screen(&self, rect: Rect, pos: Pos2) -> Point {
+        let offset = pos - rect.center();
+        Point::new(
+            self.center.x() + f64::from(offset.x) / (self.lon_factor * self.scale),
+            self.center.y() - f64::from(offset.y) / self.scale,
+        )
+    }
+
+    /// Zooms by `factor`, keeping `anchor` in place.
+    fn zoom(&mut self, factor: f64, anchor: Point) {
+        self.scale *= factor;
+        self.center = Point::new(
+            anchor.x() - (anchor.x() - self.center.x()) / factor,
+            anchor.y() - (anchor.y() - self.center.y()) / factor,
+        );
+    }
+}
+
+/// Map of changes with the old and new positions of moved entities, to spot
+/// obviously wrong coordinates. Drawn on a graticule with the bundled outline
+/// of Germany for orientation.
+#[derive(Debug, Default)]
+pub(super) struct ChangeMap {
+    view: Option<View>,
+    /// Number of changes the view was fitted to, refitted when it changes
+    fitted: usize,
+}
+
+impl ChangeMap {
+    /// Shows the changes, the ones not `included` (e.g. rejected in review)
+    /// dimmed. Drag to pan, scroll to zoom.
+    pub(super) fn show<'a>(
+        &mut self,
+        ui: &mut Ui,
+        changes: impl IntoIterator<Item = (&'a Change, bool)>,
+    ) {
+        let changes = changes.into_iter().collect::<Vec<_>>();
+        let size = vec2(ui.available_width(), MAP_HEIGHT);
+
+        if self.view.is_none() || self.fitted != changes.len() {
+            let points = changes
+                .iter()
+                .flat_map(|(change, _)| {
+                    let from = match change.action {
+                        ChangeAction::Moved { from, .. } => Some(from),
+                        _ => None,
+                    };
+                    [Some(change.position), from]
+                })
+                .flatten()
+                .collect::<Vec<_>>();
+            self.view = View::fit(&points, size);
+            self.fitted = changes.len();
+        }
+        let Some(view) = &mut self.view else {
+            ui.label("No changes to show.");
+            return;
+        };
+
+        let visuals = ui.visuals().clone();
+        ui.horizontal(|ui| {
+            legend(ui, ADDED_COLOR, "added");
+            legend(ui, visuals.warn_fg_color, "moved (from ○)");
+            legend(ui, visuals.error_fg_color, "removed");
+            if ui.small_button("Fit").clicked() {
+                self.fitted = usize::MAX;
+            }
+        });
+
+        let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0., visuals.extreme_bg_color);
+
+        if response.dragged() {
+            let delta = response.drag_delta();
+            let origin = view.from_screen(rect, rect.center());
+            let moved = view.from_screen(rect, rect.center() - delta);
+            view.center = Point::new(
+                view.center.x() + moved.x() - origin.x(),
+                view.center.y() + moved.y() - origin.y(),
+            );
+        }
+        if let Some(hover) = response.hover_pos() {
+            let scroll = ui.input_mut(|input| std::mem::take(&mut input.smooth_scroll_delta.y));
+            let zoom =
+                f64::from(ui.input(InputState::zoom_delta)) * f64::from(scroll * 0.005).exp();
+            if (zoom - 1.).abs() > f64::EPSILON {
+                view.zoom(zoom, view.from_screen(rect, hover));
+            }
+        }
+
+        draw_graticule(&painter, rect, view, &visuals);
+        draw_outline(&painter, rect, view, &visuals);
+
+        let mut hovered = None::<(f32, &Change)>;
+        for (change, included) in &changes {
+            let dim = |color: Color32| {
+                if *included {
+                    color
+                } else {
+                    color.gamma_multiply(0.3)
+                }
+            };
+            let position = view.to_screen(rect, change.position);
+            let color = match change.action {
+                ChangeAction::Added | ChangeAction::Candidate => dim(ADDED_COLOR),
+                ChangeAction::Moved { from, .. } => {
+                    let from = view.to_screen(rect, from);
+                    let color = dim(visuals.warn_fg_color);
+                    painter.line_segment([from, position], Stroke::new(1., color));
+                    painter.circle_stroke(from, 3., Stroke::new(1., color));
+                    color
+                }
+                ChangeAction::Removed => dim(visuals.error_fg_color),
+            };
+            if change.kind == EntityKind::Airport {
+                painter.rect_filled(Rect::from_center_size(position, Vec2::splat(8.)), 0., color);
+            } else if matches!(change.action, ChangeAction::Removed) {
+                let stroke = Stroke::new(1.5, color);
+                painter.line_segment(
+                    [position - Vec2::splat(3.), position + Vec2::splat(3.)],
+                    stroke,
+                );
+                painter.line_segment([position + vec2(-3., 3.), position + vec2(3., -3.)], stroke);
+            } else {
+                painter.circle_filled(position, 3.5, color);
+            }
+            if rect.contains(position) {
+                painter.text(
+                    position + vec2(5., -3.),
+                    Align2::LEFT_BOTTOM,
+                    &change.designator,
+                    FontId::proportional(10.),
+                    dim(visuals.text_color()),
+                );
+            }
+
+            if let Some(hover) = response.hover_pos() {
+                let distance = hover.distance(position);
+                if distance < HOVER_DISTANCE
+                    && hovered.is_none_or(|(closest, _)| distance < closest)
+                {
+                    hovered = Some((distance, *change));
+                }
+            }
+        }
+
+        if let Some((_, change)) = hovered {
+            response.on_hover_text_at_pointer(change.to_string());
+        }
+    }
+}
+
+fn legend(ui: &mut Ui, color: Color32, text: &str) {
+    ui.colored_label(color, "●");
+    ui.label(text);
+}
+
+fn draw_outline(painter: &Painter, rect: Rect, view: &View, visuals: &Visuals) {
+    let points = outline::GERMANY
+        .iter()
+        .map(|&(lat, lon)| view.to_screen(rect, Point::new(lon, lat)))
+        .collect();
+    painter.line(points, Stroke::new(1., visuals.weak_text_color()));
+}
+
+fn draw_graticule(painter: &Painter, rect: Rect, view: &View, visuals: &Visuals) {
+    let step = GRID_STEPS
+        .into_iter()
+        .find(|step| step * view.scale * view.lon_factor >= MIN_GRID_SPACING)
+        .unwrap_or(GRID_STEPS[GRID_STEPS.len() - 1]);
+    let stroke = Stroke::new(0.5, visuals.weak_text_color().gamma_multiply(0.5));
+    let top_left = view.from_screen(rect, rect.left_top());
+    let bottom_right = view.from_screen(rect, rect.right_bottom());
+    let font = FontId::monospace(9.);
+
+    let mut lon = (top_left.x() / step).floor() * step;
+    while lon <= bottom_right.x() {
+        let x = view.to_screen(rect, Point::new(lon, view.center.y())).x;
+        painter.line_segment(
+            [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+            stroke,
+        );
+        painter.text(
+            Pos2::new(x + 2., rect.bottom() - 2.),
+            Align2::LEFT_BOTTOM,
+            format!("{lon:.2}°"),
+            font.clone(),
+            visuals.weak_text_color(),
+        );
+        lon += step;
+    }
+    let mut lat = (bottom_right.y() / step).floor() * step;
+    while lat <= top_left.y() {
+        let y = view.to_screen(rect, Point::new(view.center.x(), lat)).y;
+        painter.line_segment(
+            [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+            stroke,
+        );
+        painter.text(
+            Pos2::new(rect.left() + 2., y - 2.),
+            Align2::LEFT_BOTTOM,
+            format!("{lat:.2}°"),
+            font.clone(),
+            visuals.weak_text_color(),
+        );
+        lat += step;
+    }
+}
@@ -0,0 +1,1679 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write as _,
+    iter, mem,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use airac_aixm_updater_core::{
+    CLOCK_SKEW_TOLERANCE_SECS, MESSAGE_CHANNEL_CAPACITY, Message, RunMode,
+    airac::AiracCycle,
+    aixm::AIRSPACE_DATASET,
+    aixm_combine::EntityType,
+    aixm_dfs::{AixmRelease, Amendment, dfs_clock_skew, fetch_dfs_datasets},
+    aixm_index::AirportPoint,
+    cancel::CancelToken,
+    config::{Config, MilitaryEntities, SctWriter, WriteMode},
+    cycle_diff::{CycleChange, compare_amendments, show_cycle_diff},
+    error::{AiracUpdaterResult, SaveLogSnafu},
+    error_summary::{ErrorSummary, show_error_summary},
+    file_diff::{FileDiff, show_file_diffs},
+    load_es::newer_sibling_sector,
+    locale::DisplayLocale,
+    message_log::MessageLog,
+    pipeline::{Pipeline, show_pipeline},
+    provider::{AisIndex, DataProvider, UrlTemplate},
+    report::{ChangeReport, show_report},
+    run_summary::{FileSummary, show_run_summary},
+    spawn_batch,
+};
+use chrono::{Local, TimeDelta};
+use eframe::{CreationContext, Frame, NativeOptions};
+use egui::{Button, CollapsingHeader, ComboBox, Context, DragValue, Grid, ScrollArea, Ui};
+use rfd::{AsyncFileDialog, FileHandle};
+use snafu::ResultExt as _;
+use tokio::{
+    runtime::Runtime,
+    sync::{
+        mpsc::{self},
+        oneshot,
+    },
+    task::JoinHandle,
+    time::sleep,
+};
+use tracing::{Level, debug, error, info, trace, warn};
+use uuid::Uuid;
+
+use crate::{
+    restore::{
+        FileBackups, OrphanedBackup, find_backups, find_orphaned, restore_backup, show_backups,
+    },
+    warnings::{Warning, WarningFix, has_backups_from_cycle, show_warnings},
+};
+
+mod dialog;
+mod discover;
+mod log_view;
+mod map;
+mod outline;
+mod review;
+mod settings;
+
+use dialog::{Answer, Confirmation, anomaly_confirmation, choose_preset, write_confirmation};
+use discover::Discovery;
+use log_view::{LogFilter, log_text, show_log};
+use map::ChangeMap;
+use review::Review;
+use settings::Settings;
+
+pub(crate) fn run(config: Config) -> eframe::Result {
+    let settings = Settings::load();
+    let native_options = NativeOptions {
+        viewport: settings.viewport(),
+        ..NativeOptions::default()
+    };
+    eframe::run_native(
+        "VATGER AIRAC Updater",
+        native_options,
+        Box::new(|cc| Ok(Box::new(App::new(cc, config, settings)))),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Update,
+    CompareCycles,
+    Restore,
+}
+
+/// What the path chosen in the open file dialog is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilePick {
+    AixmDir,
+    Prf,
+    BatchPrf,
+    SaveLog,
+}
+
+/// Destructive action waiting for confirmation.
+enum PendingAction {
+    Restore { file: PathBuf, backup: PathBuf },
+    Overwrite,
+}
+
+struct App {
+    config: Config,
+    settings: Settings,
+    /// Locale and time zone times and numbers are shown in
+    locale: DisplayLocale,
+    picked_path: Option<PathBuf>,
+    /// Further packages updated along with the chosen one, sharing the
+    /// downloaded AIXM data
+    batch: Vec<PathBuf>,
+    rt: Runtime,
+    tx: mpsc::Sender<Message>,
+    /// Messages of the jobs, batched per frame by [`forward_messages`]
+    rx: mpsc::UnboundedReceiver<Vec<Message>>,
+    log_buffer: Vec<Message>,
+    log_filter: LogFilter,
+    /// Run finished or running whose log is not saved yet, see
+    /// [`Settings::save_log`]
+    log_pending: bool,
+    pipeline: Pipeline,
+    report: ChangeReport,
+    change_map: ChangeMap,
+    /// Differences of the files written or previewed by the last run
+    diffs: Vec<FileDiff>,
+    /// What the last run did per .sct
+    summaries: Vec<FileSummary>,
+    /// Number of members per feature type of the datasets of the last run
+    member_counts: BTreeMap<String, BTreeMap<String, usize>>,
+    errors: ErrorSummary,
+    message_log: Option<MessageLog>,
+    warnings: Vec<Warning>,
+    /// Amendments provided by DFS, `None` if unreachable
+    network_check: Option<oneshot::Receiver<(Option<Vec<Amendment>>, Option<TimeDelta>)>>,
+    amendments: Vec<Amendment>,
+    discovery: Option<oneshot::Receiver<Discovery>>,
+    /// EuroScope folders and packages found on startup
+    discovered: Discovery,
+    amdt: u32,
+    view: View,
+    compare: (u32, u32),
+    cycle_diff: Option<oneshot::Receiver<AiracUpdaterResult<Vec<CycleChange>>>>,
+    cycle_changes: Vec<CycleChange>,
+    backups: Vec<FileBackups>,
+    /// Files of `backups` missing or empty after a crashed run
+    orphaned: Vec<OrphanedBackup>,
+    restore: Option<oneshot::Receiver<AiracUpdaterResult>>,
+    /// Open file dialog, run on the runtime as it blocks the event loop on
+    /// some platforms
+    file_dialog: Option<(FilePick, oneshot::Receiver<Option<PathBuf>>)>,
+    confirmation: Option<Confirmation<PendingAction>>,
+    /// No config file existed yet, asking for a preset profile
+    first_run: bool,
+    /// Changes of the running update waiting for review
+    review: Option<Review>,
+    /// Suspicious results of the running update waiting for confirmation
+    anomalies: Option<Confirmation<oneshot::Sender<bool>>>,
+    /// Files of the running update waiting for confirmation
+    writes: Option<Confirmation<oneshot::Sender<bool>>>,
+    /// Running or last update
+    job: Option<(JoinHandle<()>, CancelToken)>,
+}
+
+impl App {
+    fn new(cc: &CreationContext<'_>, config: Config, settings: Settings) -> Self {
+        cc.egui_ctx.set_zoom_factor(settings.zoom_factor);
+        let picked_path = settings.last_prf.clone().filter(|path| path.exists());
+
+        let (tx, messages) = mpsc::channel(MESSAGE_CHANNEL_CAPACITY);
+        let (batches, rx) = mpsc::unbounded_channel();
+        let rt = config.runtime.build().unwrap();
+        rt.spawn(forward_messages(messages, batches, cc.egui_ctx.clone()));
+        let message_log = config.open_message_log();
+        let mut app = Self {
+            config,
+            settings,
+            locale: DisplayLocale::detect(),
+            picked_path: None,
+            batch: vec![],
+            rt,
+            tx,
+            rx,
+            log_buffer: vec![],
+            log_filter: LogFilter::default(),
+            log_pending: false,
+            pipeline: Pipeline::default(),
+            report: ChangeReport::default(),
+            change_map: ChangeMap::default(),
+            diffs: vec![],
+            summaries: vec![],
+            member_counts: BTreeMap::new(),
+            errors: ErrorSummary::default(),
+            message_log,
+            warnings: vec![],
+            network_check: None,
+            amendments: vec![],
+            discovery: None,
+            discovered: Discovery::default(),
+            amdt: 0,
+            view: View::Update,
+            compare: (0, 0),
+            cycle_diff: None,
+            cycle_changes: vec![],
+            backups: vec![],
+            orphaned: vec![],
+            restore: None,
+            file_dialog: None,
+            confirmation: None,
+            first_run: Config::path().is_ok_and(|path| !path.exists()),
+            review: None,
+            anomalies: None,
+            writes: None,
+            job: None,
+        };
+        app.start_network_check(&cc.egui_ctx);
+        app.start_discovery(&cc.egui_ctx);
+        app.check_profile();
+        if let Some(path) = picked_path {
+            app.pick_prf(path);
+        }
+        app
+    }
+
+    fn pick_prf(&mut self, path: PathBuf) {
+        info!(".prf chosen: {}", path.display());
+        if let Some((sibling, sct)) = newer_sibling_sector(&path) {
+            warn!(
+                "{} references the newer sector file {}",
+                sibling.display(),
+                sct.display()
+            );
+            self.set_warning(Warning::NewerSiblingSector, true);
+        } else {
+            self.set_warning(Warning::NewerSiblingSector, false);
+        }
+        if self.settings.last_prf.as_ref() != Some(&path) {
+            self.settings.last_prf = Some(path.clone());
+            self.save_settings();
+        }
+        self.picked_path = Some(path);
+        self.check_backups();
+    }
+
+    /// AIRAC cycle an update would load: of the chosen DFS amendment, as many
+    /// cycles ahead as chosen for the other providers, or the current one for
+    /// local files of whatever cycle.
+    fn selected_cycle(&self) -> AiracCycle {
+        let profile = self.config.profile();
+        if profile.aixm_dir.is_some() {
+            return AiracCycle::current();
+        }
+        if profile.provider == DataProvider::Dfs {
+            self.amendments
+                .iter()
+                .find(|amendment| amendment.amdt == self.amdt)
+                .and_then(Amendment::cycle)
+                .unwrap_or_else(AiracCycle::current)
+        } else {
+            AiracCycle::current().offset(i64::from(self.amdt))
+        }
+    }
+
+    /// Amendment to select for `cycle`, if it can be loaded.
+    fn amdt_of_cycle(&self, cycle: AiracCycle) -> Option<u32> {
+        let profile = self.config.profile();
+        if profile.aixm_dir.is_some() {
+            None
+        } else if profile.provider == DataProvider::Dfs {
+            self.amendments
+                .iter()
+                .find(|amendment| amendment.cycle() == Some(cycle))
+                .map(|amendment| amendment.amdt)
+        } else {
+            (0..=MAX_CYCLES_AHEAD)
+                .find(|ahead| AiracCycle::current().offset(i64::from(*ahead)) == cycle)
+        }
+    }
+
+    /// Warns if the chosen package has backups from the selected cycle.
+    fn check_backups(&mut self) {
+        let applied = self
+            .picked_path
+            .as_deref()
+            .is_some_and(|path| has_backups_from_cycle(path, self.selected_cycle()));
+        self.set_warning(Warning::AmendmentAlreadyApplied, applied);
+    }
+
+    fn check_profile(&mut self) {
+        let profile = self.config.profile();
+        self.set_warning(
+            Warning::WaypointsDatasetMissing,
+            !profile.datasets.iter().any(|d| d == "ED Waypoints"),
+        );
+        self.set_warning(
+            Warning::BackupsDisabled,
+            profile.write_mode == WriteMode::Overwrite,
+        );
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            error!("{e}");
+        }
+    }
+
+    fn save_config(&mut self) {
+        if let Err(e) = self.config.save() {
+            error!("{e}");
+            self.log_buffer.push(Message::failure(&e));
+        }
+        self.check_profile();
+    }
+
+    fn start_network_check(&mut self, ctx: &Context) {
+        let (check_tx, check_rx) = oneshot::channel();
+        let ctx = ctx.clone();
+        self.rt.spawn(async move {
+            let amendments = fetch_dfs_datasets()
+                .await
+                .ok()
+                .map(|amdts| amdts.amendments());
+            let clock_skew = if amendments.is_some() {
+                dfs_clock_skew().await.ok().flatten()
+            } else {
+                None
+            };
+            if check_tx.send((amendments, clock_skew)).is_err() {
+                debug!("network check result dropped");
+            }
+            ctx.request_repaint();
+        });
+        self.network_check = Some(check_rx);
+    }
+
+    fn handle_network_check(&mut self) {
+        if let Some(check_rx) = &mut self.network_check {
+            match check_rx.try_recv() {
+                Ok((amendments, clock_skew)) => {
+                    self.network_check = None;
+                    self.set_warning(Warning::NoNetwork, amendments.is_none());
+                    self.amendments = amendments.unwrap_or_default();
+                    self.default_compared_cycles();
+                    self.check_backups();
+                    if let Some(clock_skew) = clock_skew {
+                        let skewed = clock_skew.num_seconds().abs() > CLOCK_SKEW_TOLERANCE_SECS;
+                        if skewed {
+                            warn!(
+                                "System clock differs from DFS server time by {}s",
+                                clock_skew.num_seconds()
+                            );
+                        }
+                        self.set_warning(Warning::ClockSkew, skewed);
+                    }
+                }
+                Err(oneshot::error::TryRecvError::Empty) => (),
+                Err(oneshot::error::TryRecvError::Closed) => self.network_check = None,
+            }
+        }
+    }
+
+    fn start_discovery(&mut self, ctx: &Context) {
+        let (discovery_tx, discovery_rx) = oneshot::channel();
+        let ctx = ctx.clone();
+        self.rt.spawn_blocking(move || {
+            if discovery_tx.send(Discovery::probe()).is_err() {
+                debug!("EuroScope discovery result dropped");
+            }
+            ctx.request_repaint();
+        });
+        self.discovery = Some(discovery_rx);
+    }
+
+    /// Takes over the found EuroScope packages, choosing the only one if no
+    /// .prf was chosen before.
+    fn handle_discovery(&mut self) {
+        if let Some(discovery_rx) = &mut self.discovery {
+            match discovery_rx.try_recv() {
+                Ok(discovered) => {
+                    self.discovery = None;
+                    info!(
+                        "Found {} EuroScope packages in {} folders",
+                        discovered.packages.len(),
+                        discovered.folders.len()
+                    );
+                    if let [package] = discovered.packages.as_slice() {
+                        if self.picked_path.is_none() {
+                            self.pick_prf(package.clone());
+                        }
+                    }
+                    self.discovered = discovered;
+                }
+                Err(oneshot::error::TryRecvError::Empty) => (),
+                Err(oneshot::error::TryRecvError::Closed) => self.discovery = None,
+            }
+        }
+    }
+
+    fn start_cycle_diff(&mut self, ctx: &Context) {
+        let (diff_tx, diff_rx) = oneshot::channel();
+        let profile = self.config.profile();
+        let ((from, to), datasets, release, tx, ctx) = (
+            self.compare,
+            profile.datasets,
+            profile.aixm_release,
+            self.tx.clone(),
+            ctx.clone(),
+        );
+        self.cycle_changes = vec![];
+        self.rt.spawn(async move {
+            let result = compare_amendments(from, to, &datasets, release, tx).await;
+            if diff_tx.send(result).is_err() {
+                debug!("cycle comparison result dropped");
+            }
+            ctx.request_repaint();
+        });
+        self.cycle_diff = Some(diff_rx);
+    }
+
+    fn handle_cycle_diff(&mut self) {
+        if let Some(diff_rx) = &mut self.cycle_diff {
+            match diff_rx.try_recv() {
+                Ok(result) => {
+                    self.cycle_diff = None;
+                    match result {
+                        Ok(changes) => self.cycle_changes = changes,
+                        Err(e) => {
+                            error!("{e}");
+                            self.log_buffer.push(Message::failure(&e));
+                        }
+                    }
+                }
+                Err(oneshot::error::TryRecvError::Empty) => (),
+                Err(oneshot::error::TryRecvError::Closed) => self.cycle_diff = None,
+            }
+        }
+    }
+
+    /// Compares the previous with the current cycle unless other amendments
+    /// were picked already.
+    fn default_compared_cycles(&mut self) {
+        if self.compare.0 != self.compare.1 {
+            return;
+        }
+        let current = AiracCycle::current();
+        let amdt_of = |cycle| {
+            self.amendments
+                .iter()
+                .find(|amendment| amendment.cycle() == Some(cycle))
+                .map(|amendment| amendment.amdt)
+        };
+        if let (Some(from), Some(to)) = (amdt_of(current.previous()), amdt_of(current)) {
+            self.compare = (from, to);
+        }
+    }
+
+    /// AIXM changes between two amendments, without any sector files.
+    fn show_cycle_comparison(&mut self, ui: &mut Ui, ctx: &Context) {
+        if self.amendments.is_empty() {
+            ui.label("The amendments provided by DFS could not be loaded.");
+            return;
+        }
+
+        let amendments = &self.amendments;
+        let (from, to) = &mut self.compare;
+        for (label, amdt) in [("From", from), ("To", to)] {
+            let selected = amendments
+                .iter()
+                .find(|amendment| amendment.amdt == *amdt)
+                .map_or_else(|| format!("AMDT {amdt}"), ToString::to_string);
+            ComboBox::from_label(label)
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for amendment in amendments {
+                        ui.selectable_value(amdt, amendment.amdt, amendment.to_string());
+                    }
+                });
+        }
+
+        ui.horizontal(|ui| {
+            let idle = self.cycle_diff.is_none();
+            if ui
+                .add_enabled(
+                    idle && self.compare.0 != self.compare.1,
+                    Button::new("Compare"),
+                )
+                .clicked()
+            {
+                self.start_cycle_diff(ctx);
+            }
+            if !idle {
+                ui.spinner();
+            }
+        });
+
+        ui.add_space(10.);
+
+        if !self.cycle_changes.is_empty() {
+            ScrollArea::vertical()
+                .max_height(ui.available_height() / 2.)
+                .show(ui, |ui| show_cycle_diff(ui, &self.cycle_changes));
+            ui.add_space(10.);
+        }
+    }
+
+    fn refresh_backups(&mut self) {
+        self.backups = self
+            .picked_path
+            .as_deref()
+            .map(find_backups)
+            .unwrap_or_default();
+        self.orphaned = find_orphaned(&self.backups);
+    }
+
+    fn start_restore(&mut self, file: PathBuf, backup: PathBuf, ctx: &Context) {
+        let (restore_tx, restore_rx) = oneshot::channel();
+        let (tx, ctx) = (self.tx.clone(), ctx.clone());
+        self.rt.spawn(async move {
+            let result = restore_backup(file, backup, tx).await;
+            if restore_tx.send(result).is_err() {
+                debug!("restore result dropped");
+            }
+            ctx.request_repaint();
+        });
+        self.restore = Some(restore_rx);
+    }
+
+    fn handle_restore(&mut self) {
+        if let Some(restore_rx) = &mut self.restore {
+            match restore_rx.try_recv() {
+                Ok(result) => {
+                    self.restore = None;
+                    if let Err(e) = result {
+                        error!("{e}");
+                        self.log_buffer.push(Message::failure(&e));
+                    }
+                    self.refresh_backups();
+                }
+                Err(oneshot::error::TryRecvError::Empty) => (),
+                Err(oneshot::error::TryRecvError::Closed) => self.restore = None,
+            }
+        }
+    }
+
+    /// Awaits `dialog`, which has to be created on the UI thread, on the
+    /// runtime.
+    fn start_file_dialog(
+        &mut self,
+        pick: FilePick,
+        dialog: impl Future<Output = Option<FileHandle>> + Send + 'static,
+        ctx: &Context,
+    ) {
+        let (path_tx, path_rx) = oneshot::channel();
+        let ctx = ctx.clone();
+        self.rt.spawn(async move {
+            let path = dialog.await.map(|handle| handle.path().to_path_buf());
+            if path_tx.send(path).is_err() {
+                debug!("file dialog result dropped");
+            }
+            ctx.request_repaint();
+        });
+        self.file_dialog = Some((pick, path_rx));
+    }
+
+    /// Writes the log of the last run as text, appended to `path` if
+    /// `append`.
+    fn save_log(&self, path: &Path, append: bool) -> AiracUpdaterResult {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .context(SaveLogSnafu { path })?;
+        file.write_all(log_text(&self.log_buffer).as_bytes())
+            .context(SaveLogSnafu { path })
+    }
+
+    /// Appends the log of the finished run to the log file of the day, if
+    /// enabled in the settings.
+    fn handle_auto_save_log(&mut self) {
+        if !self.log_pending || self.is_processing() {
+            return;
+        }
+        self.log_pending = false;
+        if !self.settings.save_log {
+            return;
+        }
+        let save = || -> AiracUpdaterResult<PathBuf> {
+            let dir = Settings::log_dir()?;
+            fs::create_dir_all(&dir).context(SaveLogSnafu { path: &dir })?;
+            let path = dir.join(format!(
+                "airac-updater-{}.log",
+                Local::now().format("%Y%m%d")
+            ));
+            self.save_log(&path, true)?;
+            Ok(path)
+        };
+        match save() {
+            Ok(path) => debug!("Appended log to {}", path.display()),
+            Err(e) => error!("{e}"),
+        }
+    }
+
+    fn handle_file_dialog(&mut self) {
+        let Some((pick, path_rx)) = &mut self.file_dialog else {
+            return;
+        };
+        let pick = *pick;
+        match path_rx.try_recv() {
+            Ok(path) => {
+                self.file_dialog = None;
+                let Some(path) = path else {
+                    return;
+                };
+                match pick {
+                    FilePick::AixmDir => {
+                        info!("AIXM folder chosen: {}", path.display());
+                        self.config.profile_mut().aixm_dir = Some(path);
+                        self.save_config();
+                    }
+                    FilePick::Prf => {
+                        self.log_buffer = vec![];
+                        self.pick_prf(path);
+                    }
+                    FilePick::BatchPrf => {
+                        if self.picked_path.as_ref() != Some(&path) && !self.batch.contains(&path) {
+                            self.batch.push(path);
+                        }
+                    }
+                    FilePick::SaveLog => match self.save_log(&path, false) {
+                        Ok(()) => info!("Saved log to {}", path.display()),
+                        Err(e) => error!("{e}"),
+                    },
+                }
+            }
+            Err(oneshot::error::TryRecvError::Empty) => (),
+            Err(oneshot::error::TryRecvError::Closed) => self.file_dialog = None,
+        }
+    }
+
+    /// Backups of the files of the chosen .prf, to undo a run.
+    fn show_restore(&mut self, ui: &mut Ui) {
+        let Some(picked_path) = &self.picked_path else {
+            ui.label("Choose a EuroScope .prf under \"Update sector files\" first.");
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("EuroScope .prf:");
+            ui.monospace(picked_path.display().to_string());
+        });
+
+        ui.horizontal(|ui| {
+            let idle = self.restore.is_none();
+            if ui.add_enabled(idle, Button::new("Refresh")).clicked() {
+                self.refresh_backups();
+            }
+            if !idle {
+                ui.spinner();
+            }
+        });
+
+        ui.add_space(10.);
+
+        let mut recover = None;
+        for orphan in &self.orphaned {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "{} is {}, probably from a crashed run",
+                        orphan.file.display(),
+                        orphan.state()
+                    ),
+                );
+                if ui
+                    .add_enabled(self.restore.is_none(), Button::new("Recover"))
+                    .on_hover_text(format!("Restore from {}", orphan.backup.display()))
+                    .clicked()
+                {
+                    recover = Some((orphan.file.clone(), orphan.backup.clone()));
+                }
+            });
+        }
+        if !self.orphaned.is_empty() {
+            ui.add_space(10.);
+        }
+
+        if self.backups.is_empty() {
+            ui.label("No backups found next to the .prf or in its subfolders.");
+        } else {
+            let clicked = ScrollArea::vertical()
+                .max_height(ui.available_height() / 2.)
+                .show(ui, |ui| {
+                    show_backups(ui, &self.backups, self.restore.is_none(), &self.locale)
+                })
+                .inner;
+            if let Some((file, backup)) = clicked.or(recover) {
+                self.confirmation = Some(Confirmation {
+                    title: "Restore backup?".to_string(),
+                    message: format!(
+                        "{} will be replaced by {}. The current file is kept as a new backup.",
+                        file.display(),
+                        backup.display()
+                    ),
+                    confirm_label: "Restore",
+                    action: PendingAction::Restore { file, backup },
+                });
+            }
+        }
+        ui.add_space(10.);
+    }
+
+    fn handle_first_run(&mut self, ctx: &Context) {
+        if !self.first_run {
+            return;
+        }
+        if let Some(profile) = choose_preset(ctx) {
+            self.first_run = false;
+            self.config.active_profile = profile.to_string();
+            self.save_config();
+        }
+    }
+
+    fn handle_confirmation(&mut self, ctx: &Context) {
+        let Some(confirmation) = &self.confirmation else {
+            return;
+        };
+        match confirmation.show(ctx) {
+            Some(Answer::Confirmed) => {
+                if let Some(confirmation) = self.confirmation.take() {
+                    match confirmation.action {
+                        PendingAction::Restore { file, backup } => {
+                            self.start_restore(file, backup, ctx);
+                        }
+                        PendingAction::Overwrite => self.start_processing(RunMode::Write),
+                    }
+                }
+            }
+            Some(Answer::Cancelled) => self.confirmation = None,
+            None => (),
+        }
+    }
+
+    /// Sends the rejected changes to the waiting update, or cancels it by
+    /// dropping the reply channel.
+    fn handle_review(&mut self, ctx: &Context) {
+        // e.g. cancelled meanwhile
+        if !self.is_processing() {
+            self.review = None;
+        }
+        let Some(review) = &mut self.review else {
+            return;
+        };
+        match review.show(ctx) {
+            Some(Answer::Confirmed) => {
+                if let Some(review) = self.review.take() {
+                    let (rejected, reply) = review.finish();
+                    for (file, changes) in &rejected {
+                        self.report.reject(file, changes);
+                    }
+                    if reply.send(rejected).is_err() {
+                        error!("Update ended before the review was finished");
+                    }
+                }
+            }
+            Some(Answer::Cancelled) => self.review = None,
+            None => (),
+        }
+    }
+
+    /// Answers the confirmations a running update waits for before writing.
+    fn handle_run_confirmations(&mut self, ctx: &Context) {
+        let is_processing = self.is_processing();
+        for (confirmation, what) in [
+            (&mut self.anomalies, "the suspicious results were confirmed"),
+            (&mut self.writes, "writing the files was confirmed"),
+        ] {
+            let Some(pending) = confirmation else {
+                continue;
+            };
+            // e.g. cancelled meanwhile, dropping the reply is a no
+            if !is_processing {
+                *confirmation = None;
+                continue;
+            }
+            let Some(answer) = pending.show(ctx) else {
+                continue;
+            };
+            if let Some(pending) = confirmation.take() {
+                if pending.action.send(answer == Answer::Confirmed).is_err() {
+                    error!("Update ended before {what}");
+                }
+            }
+        }
+    }
+
+    /// Members per feature type of every loaded dataset, including the types
+    /// that are not read.
+    fn show_member_counts(&self, ui: &mut Ui) {
+        Grid::new("member-counts").striped(true).show(ui, |ui| {
+            ui.strong("Dataset");
+            ui.strong("Feature");
+            ui.strong("Members");
+            ui.end_row();
+            for (dataset, counts) in &self.member_counts {
+                for (feature, count) in counts {
+                    ui.label(dataset);
+                    ui.monospace(feature);
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+            }
+        });
+    }
+
+    fn set_warning(&mut self, warning: Warning, active: bool) {
+        self.warnings.retain(|w| *w != warning);
+        if active {
+            self.warnings.push(warning);
+        }
+    }
+
+    fn apply_warning_fix(&mut self, fix: WarningFix, ctx: &Context) {
+        match fix {
+            WarningFix::RetryNetworkCheck => {
+                self.set_warning(Warning::NoNetwork, false);
+                self.set_warning(Warning::ClockSkew, false);
+                self.start_network_check(ctx);
+            }
+            WarningFix::NextAmendment => {
+                let next = self.selected_cycle().next();
+                if let Some(amdt) = self.amdt_of_cycle(next) {
+                    self.amdt = amdt;
+                    self.check_backups();
+                } else {
+                    warn!("AIRAC {next} cannot be loaded from the selected source yet");
+                }
+            }
+            WarningFix::AddWaypointsDataset => {
+                self.config
+                    .profile_mut()
+                    .datasets
+                    .push("ED Waypoints".to_string());
+                self.save_config();
+            }
+            WarningFix::EnableBackups => {
+                self.config.profile_mut().write_mode = WriteMode::Backup;
+                self.save_config();
+            }
+            WarningFix::PickNewerSibling => {
+                if let Some((sibling, _)) =
+                    self.picked_path.as_deref().and_then(newer_sibling_sector)
+                {
+                    self.pick_prf(sibling);
+                }
+            }
+            WarningFix::Dismiss(warning) => self.set_warning(warning, false),
+        }
+    }
+
+    /// Distances up to which AIXM entities are matched to existing ones, e.g.
+    /// to keep border fixes that legitimately exist twice close together.
+    fn show_match_distances(&mut self, ui: &mut Ui) {
+        let mut distances = self.config.profile().match_distances;
+        let mut save = false;
+        CollapsingHeader::new("Match distances").show(ui, |ui| {
+            Grid::new("match-distances").show(ui, |ui| {
+                for (label, distance) in [
+                    ("Fixes (.sct, isec.txt)", &mut distances.fix),
+                    (".ese free text labels", &mut distances.label),
+                    ("Runway thresholds", &mut distances.runway),
+                ] {
+                    ui.label(label);
+                    let response = ui.add(
+                        DragValue::new(distance)
+                            .range(1.0..=20_000.0)
+                            .speed(10.)
+                            .suffix(" m"),
+                    );
+                    save |= response.drag_stopped() || response.lost_focus();
+                    ui.end_row();
+                }
+            });
+        });
+        if distances != self.config.profile().match_distances {
+            self.config.profile_mut().match_distances = distances;
+        }
+        if save {
+            self.save_config();
+        }
+    }
+
+    /// DFS, Austro Control or another AIS listing its AIXM exports on an
+    /// index page (see [`AisIndex`]) or publishing them under a URL scheme
+    /// (see [`UrlTemplate`]).
+    fn show_provider(&mut self, ui: &mut Ui) {
+        let mut provider = self.config.profile().provider.clone();
+        let mut save = false;
+        ComboBox::from_label("Data provider")
+            .selected_text(match &provider {
+                DataProvider::Dfs => "DFS (aip.dfs.de)",
+                DataProvider::Index(index) if *index == AisIndex::austro_control() => {
+                    "Austro Control (LOVV)"
+                }
+                DataProvider::Index(_) => "AIS index page",
+                DataProvider::Url(_) => "URL template",
+            })
+            .show_ui(ui, |ui| {
+                save |= ui
+                    .selectable_value(&mut provider, DataProvider::Dfs, "DFS (aip.dfs.de)")
+                    .changed();
+                save |= ui
+                    .selectable_value(
+                        &mut provider,
+                        DataProvider::Index(AisIndex::austro_control()),
+                        "Austro Control (LOVV)",
+                    )
+                    .changed();
+                let other_index = matches!(
+                    &provider,
+                    DataProvider::Index(index) if *index != AisIndex::austro_control()
+                );
+                if !other_index && ui.selectable_label(false, "AIS index page").clicked() {
+                    provider = DataProvider::Index(AisIndex {
+                        name: String::new(),
+                        index_url: String::new(),
+                    });
+                    save = true;
+                }
+                if !matches!(provider, DataProvider::Url(_))
+                    && ui.selectable_label(false, "URL template").clicked()
+                {
+                    provider = DataProvider::Url(UrlTemplate::default());
+                    save = true;
+                }
+            });
+        let url = match &mut provider {
+            DataProvider::Dfs => None,
+            DataProvider::Index(index) => Some((
+                &mut index.name,
+                &mut index.index_url,
+                "Page listing the exports, named after the dataset and effective date (e.g. LO_Navaids_2025-03-20.xml)",
+            )),
+            DataProvider::Url(template) => Some((
+                &mut template.name,
+                &mut template.url,
+                "{dataset} and {airac} (e.g. 2503) are replaced",
+            )),
+        };
+        if let Some((name, url, hint)) = url {
+            Grid::new("url-provider").show(ui, |ui| {
+                ui.label("Name");
+                save |= ui.text_edit_singleline(name).lost_focus();
+                ui.end_row();
+                ui.label("URL").on_hover_text(hint);
+                save |= ui.text_edit_singleline(url).lost_focus();
+                ui.end_row();
+                ui.label("AIRAC cycles ahead");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut self.amdt).range(0..=MAX_CYCLES_AHEAD));
+                    ui.label(format!(
+                        "AIRAC {}",
+                        AiracCycle::current().offset(i64::from(self.amdt))
+                    ));
+                });
+                ui.end_row();
+            });
+        } else {
+            let mut release = self.config.profile().aixm_release;
+            ComboBox::from_label("AIXM version")
+                .selected_text(release.to_string())
+                .show_ui(ui, |ui| {
+                    for option in AixmRelease::ALL {
+                        ui.selectable_value(&mut release, option, option.to_string());
+                    }
+                });
+            if release != self.config.profile().aixm_release {
+                self.config.profile_mut().aixm_release = release;
+                save = true;
+            }
+        }
+        if provider != self.config.profile().provider {
+            self.config.profile_mut().provider = provider;
+        }
+        if save {
+            self.save_config();
+        }
+    }
+
+    /// Amendment overrides of the datasets of the profile, "Same" following
+    /// the chosen amendment.
+    fn show_dataset_amdts(&mut self, ui: &mut Ui) {
+        let profile = self.config.profile();
+        let mut dataset_amdts = profile.dataset_amdts.clone();
+        CollapsingHeader::new(format!(
+            "Amendment per dataset ({} overridden)",
+            dataset_amdts.len()
+        ))
+        .id_salt("dataset-amdts")
+        .show(ui, |ui| {
+            Grid::new("dataset-amdts-grid").show(ui, |ui| {
+                for dataset in &profile.datasets {
+                    let mut amdt = dataset_amdts.get(dataset).copied();
+                    ui.label(dataset);
+                    ComboBox::from_id_salt(("dataset-amdt", dataset))
+                        .selected_text(
+                            amdt.map_or_else(|| "Same".to_string(), |amdt| format!("AMDT {amdt}")),
+                        )
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut amdt, None, "Same");
+                            for amendment in &self.amendments {
+                                ui.selectable_value(
+                                    &mut amdt,
+                                    Some(amendment.amdt),
+                                    amendment.to_string(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                    match amdt {
+                        Some(amdt) => dataset_amdts.insert(dataset.clone(), amdt),
+                        None => dataset_amdts.remove(dataset),
+                    };
+                }
+            });
+        });
+        if dataset_amdts != profile.dataset_amdts {
+            self.config.profile_mut().dataset_amdts = dataset_amdts;
+            self.save_config();
+        }
+    }
+
+    fn start_processing(&mut self, mode: RunMode) {
+        if let Some(p) = &self.picked_path {
+            let prfs = iter::once(p.clone())
+                .chain(self.batch.iter().cloned())
+                .collect::<Vec<_>>();
+            self.log_buffer = vec![];
+            self.pipeline.clear();
+            self.report.clear();
+            self.diffs = vec![];
+            self.summaries = vec![];
+            self.member_counts.clear();
+            self.errors.clear();
+            self.log_pending = true;
+            let run = Uuid::new_v4();
+            self.report.set_run(run);
+            if let Some(log) = &mut self.message_log {
+                log.set_run(run);
+            }
+            let (amdt, profile, tx) = (self.amdt, self.config.profile(), self.tx.clone());
+            let cancel = CancelToken::default();
+            let job_cancel = cancel.clone();
+            let handle = self.rt.spawn(async move {
+                spawn_batch(prfs, amdt, profile, mode, run, job_cancel, tx).await;
+                if mode == RunMode::Write {
+                    info!("You can close the window.");
+                }
+            });
+            self.job = Some((handle, cancel));
+        } else {
+            error!("Path not found");
+        }
+    }
+
+    fn is_processing(&self) -> bool {
+        self.job
+            .as_ref()
+            .is_some_and(|(handle, _)| !handle.is_finished())
+    }
+
+    fn handle_log_rx(&mut self) {
+        while let Ok(batch) = self.rx.try_recv() {
+            self.handle_messages(batch);
+        }
+    }
+
+    fn handle_messages(&mut self, batch: Vec<Message>) {
+        for mut msg in batch {
+            match msg.level {
+                Level::TRACE => trace!("{}", msg.content),
+                Level::DEBUG => debug!("{}", msg.content),
+                Level::INFO => info!("{}", msg.content),
+                Level::WARN => warn!("{}", msg.content),
+                Level::ERROR => error!("{}", msg.content),
+            }
+            if let Some(log) = &mut self.message_log {
+                if let Err(e) = log.write(&msg) {
+                    error!("{e}");
+                    self.message_log = None;
+                }
+            }
+            if let Some((item, stage)) = &msg.stage {
+                self.pipeline.update(item, *stage);
+            }
+            if let Some((file, changes)) = &msg.changes {
+                self.report.add(file, changes.clone());
+            }
+            if let Some(summaries) = msg.summary.take() {
+                self.summaries = summaries;
+            }
+            if let Some((dataset, counts)) = msg.member_counts.take() {
+                self.member_counts.insert(dataset, counts);
+            }
+            if let Some(diff) = msg.diff.take() {
+                self.diffs.push(diff);
+            }
+            if let Some(request) = msg.review.take() {
+                self.review = Some(Review::new(request));
+            }
+            if let Some(request) = msg.anomalies.take() {
+                self.anomalies = Some(anomaly_confirmation(request));
+            }
+            if let Some(request) = msg.writes.take() {
+                self.writes = Some(write_confirmation(request));
+            }
+            if msg.level == Level::ERROR {
+                self.errors
+                    .add(msg.error.clone().unwrap_or_default(), msg.content.clone());
+            }
+            self.log_buffer.push(msg);
+        }
+    }
+}
+
+/// AIRAC cycles ahead of the current one selectable for the providers other
+/// than DFS
+const MAX_CYCLES_AHEAD: u32 = 2;
+
+/// Minimum time between repaints caused by progress messages
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Takes everything the jobs sent since the last frame as one batch and
+/// repaints once per batch, so the jobs neither wait for user input to
+/// repaint nor for the GUI to take every message.
+async fn forward_messages(
+    mut messages: mpsc::Receiver<Message>,
+    batches: mpsc::UnboundedSender<Vec<Message>>,
+    ctx: Context,
+) {
+    let mut batch = Vec::new();
+    while messages
+        .recv_many(&mut batch, MESSAGE_CHANNEL_CAPACITY)
+        .await
+        > 0
+    {
+        trace!("Forwarding {} messages", batch.len());
+        if batches.send(mem::take(&mut batch)).is_err() {
+            break;
+        }
+        ctx.request_repaint();
+        sleep(FRAME_INTERVAL).await;
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
+        if self.settings.track_window(ctx) {
+            self.save_settings();
+        }
+        self.handle_log_rx();
+        self.handle_network_check();
+        self.handle_discovery();
+        self.handle_cycle_diff();
+        self.handle_restore();
+        self.handle_file_dialog();
+        self.handle_auto_save_log();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("AIRAC Updater");
+
+            let previous_view = self.view;
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.view, View::Update, "Update sector files");
+                ui.selectable_value(&mut self.view, View::CompareCycles, "Compare cycles");
+                ui.selectable_value(&mut self.view, View::Restore, "Restore previous version");
+            });
+            if self.view == View::Restore && previous_view != View::Restore {
+                self.refresh_backups();
+            }
+
+            ui.add_space(10.);
+
+            match self.view {
+                View::Update => (),
+                View::CompareCycles => {
+                    self.show_cycle_comparison(ui, ctx);
+                    ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        !self.log_buffer.is_empty() && self.file_dialog.is_none(),
+                        Button::new("Save log…"),
+                    )
+                    .on_hover_text("Write the whole log with timestamps and levels, e.g. to attach it to an issue")
+                    .clicked()
+                {
+                    let dialog = AsyncFileDialog::new()
+                        .add_filter("Log", &["log", "txt"])
+                        .set_file_name(format!(
+                            "airac-updater-{}.log",
+                            Local::now().format("%Y%m%d")
+                        ));
+                    self.start_file_dialog(FilePick::SaveLog, dialog.save_file(), ui.ctx());
+                }
+                let mut save_log = self.settings.save_log;
+                if ui
+                    .checkbox(&mut save_log, "Save the log of every run")
+                    .on_hover_text(
+                        Settings::log_dir()
+                            .map(|dir| format!("Appended to airac-updater-YYYYMMDD.log in {}", dir.display()))
+                            .unwrap_or_default(),
+                    )
+                    .changed()
+                {
+                    self.settings.save_log = save_log;
+                    self.save_settings();
+                }
+            });
+            self.log_filter.show_controls(ui);
+            show_log(ui, &self.log_buffer, &self.log_filter, &self.locale);
+                    return;
+                }
+                View::Restore => {
+                    self.show_restore(ui);
+                    self.show_log(ui);
+                    return;
+                }
+            }
+
+            if let Some(fix) = show_warnings(ui, &self.warnings) {
+                self.apply_warning_fix(fix, ctx);
+            }
+            let selected_cycle = self.selected_cycle();
+
+            let previous_profile = self.config.active_profile.clone();
+            ComboBox::from_label("Profile")
+                .selected_text(self.config.active_profile.clone())
+                .show_ui(ui, |ui| {
+                    for name in self.config.profiles.keys() {
+                        ui.selectable_value(
+                            &mut self.config.active_profile,
+                            name.clone(),
+                            name.as_str(),
+                        );
+                    }
+                });
+            if self.config.active_profile != previous_profile {
+                self.save_config();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("AIXM source:");
+                if let Some(aixm_dir) = &self.config.profile().aixm_dir {
+                    ui.monospace(aixm_dir.display().to_string());
+                    if ui.button("Use aip.dfs.de").clicked() {
+                        self.config.profile_mut().aixm_dir = None;
+                        self.save_config();
+                    }
+                } else {
+                    ui.monospace(self.config.profile().provider.name());
+                }
+                if ui
+                    .add_enabled(
+                        self.file_dialog.is_none(),
+                        Button::new("Choose local AIXM folder…"),
+                    )
+                    .clicked()
+                {
+                    let dialog = AsyncFileDialog::new().pick_folder();
+                    self.start_file_dialog(FilePick::AixmDir, dialog, ui.ctx());
+                }
+            });
+
+            if self.config.profile().aixm_dir.is_none() {
+                self.show_provider(ui);
+            }
+
+            // local files are whatever cycle was downloaded
+            if self.config.profile().aixm_dir.is_none()
+                && self.config.profile().provider == DataProvider::Dfs
+                && !self.amendments.is_empty()
+            {
+                let selected = self
+                    .amendments
+                    .iter()
+                    .find(|amendment| amendment.amdt == self.amdt)
+                    .map_or_else(|| format!("AMDT {}", self.amdt), ToString::to_string);
+                ComboBox::from_label("Amendment")
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        for amendment in &self.amendments {
+                            ui.selectable_value(
+                                &mut self.amdt,
+                                amendment.amdt,
+                                amendment.to_string(),
+                            );
+                        }
+                    });
+                self.show_dataset_amdts(ui);
+            }
+            if self.selected_cycle() != selected_cycle {
+                self.check_backups();
+            }
+
+            ui.add_space(10.);
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        self.file_dialog.is_none(),
+                        Button::new("Choose EuroScope .prf file…"),
+                    )
+                    .clicked()
+                {
+                    // start next to the current .prf or in the EuroScope folder
+                    let directory = self
+                        .picked_path
+                        .as_ref()
+                        .and_then(|path| path.parent())
+                        .or_else(|| self.discovered.folders.first().map(PathBuf::as_path));
+                    let mut dialog =
+                        AsyncFileDialog::new().add_filter("EuroScope profile", &["prf"]);
+                    if let Some(directory) = directory {
+                        dialog = dialog.set_directory(directory);
+                    }
+                    self.start_file_dialog(FilePick::Prf, dialog.pick_file(), ui.ctx());
+                }
+
+                if !self.discovered.packages.is_empty() {
+                    let mut picked = None;
+                    ComboBox::from_id_salt("discovered")
+                        .selected_text("Found packages")
+                        .show_ui(ui, |ui| {
+                            for package in &self.discovered.packages {
+                                if ui
+                                    .selectable_label(
+                                        self.picked_path.as_ref() == Some(package),
+                                        package.display().to_string(),
+                                    )
+                                    .clicked()
+                                {
+                                    picked = Some(package.clone());
+                                }
+                            }
+                        });
+                    if let Some(path) = picked {
+                        self.log_buffer = vec![];
+                        self.pick_prf(path);
+                    }
+                }
+            });
+
+            if let Some(picked_path) = &self.picked_path {
+                ui.horizontal(|ui| {
+                    ui.label("EuroScope .prf:");
+                    ui.monospace(picked_path.display().to_string());
+                });
+                let mut removed = None;
+                for (i, path) in self.batch.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("Also:");
+                        ui.monospace(path.display().to_string());
+                        if ui.small_button("Remove").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.batch.remove(i);
+                }
+                if ui
+                    .add_enabled(self.file_dialog.is_none(), Button::new("Add .prf to batch…"))
+                    .on_hover_text("Update further sector packages in the same run, downloading the AIXM data only once")
+                    .clicked()
+                {
+                    let mut dialog =
+                        AsyncFileDialog::new().add_filter("EuroScope profile", &["prf"]);
+                    if let Some(directory) = picked_path.parent() {
+                        dialog = dialog.set_directory(directory);
+                    }
+                    self.start_file_dialog(FilePick::BatchPrf, dialog.pick_file(), ui.ctx());
+                }
+            }
+
+            ui.add_space(10.);
+
+            ui.label("This tool will augment the .sct, airways.txt and isec.txt, referenced in the .prf chosen above, with AIRAC data from DFS AIXM files.");
+            ui.hyperlink("https://aip.dfs.de/datasets/");
+            ui.label("The original files will remain as backup, suffixed with the time stamp of execution.");
+
+            ui.add_space(10.);
+
+            let mut load_airspaces = self
+                .config
+                .profile()
+                .datasets
+                .iter()
+                .any(|dataset| dataset == AIRSPACE_DATASET);
+            if ui
+                .checkbox(&mut load_airspaces, format!("Load airspaces ({AIRSPACE_DATASET})"))
+                .on_hover_text("Needed for the airspace classes, boundaries and temporary airspaces")
+                .changed()
+            {
+                let datasets = &mut self.config.profile_mut().datasets;
+                if load_airspaces {
+                    datasets.push(AIRSPACE_DATASET.to_string());
+                } else {
+                    datasets.retain(|dataset| dataset != AIRSPACE_DATASET);
+                }
+                self.save_config();
+            }
+
+            let mut aurora_output = self.config.profile().aurora_output;
+            if ui
+                .checkbox(&mut aurora_output, "Also write Aurora sector files")
+                .on_hover_text("Generated from the combined .sct, next to it")
+                .changed()
+            {
+                self.config.profile_mut().aurora_output = aurora_output;
+                self.save_config();
+            }
+
+            let mut topsky_output = self.config.profile().topsky_output;
+            if ui
+                .checkbox(&mut topsky_output, "Also write TopSky maps")
+                .on_hover_text("AIXM airspace outlines as TopSky maps, next to the .sct")
+                .changed()
+            {
+                self.config.profile_mut().topsky_output = topsky_output;
+                self.save_config();
+            }
+
+            let mut ils_overlay = self.config.profile().ils_overlay;
+            if ui
+                .checkbox(&mut ils_overlay, "Draw ILS final approach courses")
+                .on_hover_text("Localizers and final approach courses from AIXM, in an overlay next to the .sct")
+                .changed()
+            {
+                self.config.profile_mut().ils_overlay = ils_overlay;
+                self.save_config();
+            }
+
+            let mut review_changes = self.config.profile().review_changes;
+            if ui
+                .checkbox(&mut review_changes, "Review changes before writing")
+                .on_hover_text("Accept or reject every change before any file is written")
+                .changed()
+            {
+                self.config.profile_mut().review_changes = review_changes;
+                self.save_config();
+            }
+
+            let mut validation = self.config.profile().validation.enabled;
+            if ui
+                .checkbox(&mut validation, "Confirm suspicious results before writing")
+                .on_hover_text("Large moves, duplicate designators and positions outside the expected area")
+                .changed()
+            {
+                self.config.profile_mut().validation.enabled = validation;
+                self.save_config();
+            }
+
+            let mut confirm_writes = self.config.profile().confirm_writes;
+            if ui
+                .checkbox(&mut confirm_writes, "Confirm the files before writing")
+                .on_hover_text("Lists every file with its backup before anything is written")
+                .changed()
+            {
+                self.config.profile_mut().confirm_writes = confirm_writes;
+                self.save_config();
+            }
+
+            let mut minimal_edit = self.config.profile().sct_writer == SctWriter::MinimalEdit;
+            if ui
+                .checkbox(&mut minimal_edit, "Keep .sct comments and formatting")
+                .on_hover_text("Only replace the lines of changed entities instead of rewriting the whole .sct")
+                .changed()
+            {
+                self.config.profile_mut().sct_writer = if minimal_edit {
+                    SctWriter::MinimalEdit
+                } else {
+                    SctWriter::Full
+                };
+                self.save_config();
+            }
+
+            let mut match_airports_without_icao =
+                self.config.profile().match_airports_without_icao;
+            if ui
+                .checkbox(
+                    &mut match_airports_without_icao,
+                    "Match airports without ICAO code by name",
+                )
+                .on_hover_text(
+                    "Move .sct airports named like the AIXM designator or name of an airport without ICAO location indicator",
+                )
+                .changed()
+            {
+                self.config.profile_mut().match_airports_without_icao =
+                    match_airports_without_icao;
+                self.save_config();
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Update:");
+                let mut entity_types = self.config.profile().entity_types;
+                let mut changed = false;
+                for entity_type in EntityType::ALL {
+                    changed |= ui
+                        .checkbox(entity_types.get_mut(entity_type), entity_type.label())
+                        .changed();
+                }
+                if changed {
+                    self.config.profile_mut().entity_types = entity_types;
+                    self.save_config();
+                }
+            })
+            .response
+            .on_hover_text("Disabled types are left as they are in the sector files");
+
+            let mut military_entities = self.config.profile().military_entities;
+            ComboBox::from_label("Military-only entities")
+                .selected_text(match military_entities {
+                    MilitaryEntities::Include => "Include",
+                    MilitaryEntities::Exclude => "Exclude",
+                    MilitaryEntities::Tag => "Include and tag",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut military_entities, MilitaryEntities::Include, "Include");
+                    ui.selectable_value(&mut military_entities, MilitaryEntities::Exclude, "Exclude");
+                    ui.selectable_value(
+                        &mut military_entities,
+                        MilitaryEntities::Tag,
+                        "Include and tag",
+                    );
+                })
+                .response
+                .on_hover_text(
+                    "Airports under military control, pure TACANs and military points, tagged in the follow-up list",
+                );
+            if military_entities != self.config.profile().military_entities {
+                self.config.profile_mut().military_entities = military_entities;
+                self.save_config();
+            }
+
+            let mut airport_point = self.config.profile().airport_reference.default;
+            ComboBox::from_label("Airport coordinate")
+                .selected_text(match airport_point {
+                    AirportPoint::Arp => "ARP",
+                    AirportPoint::Tower => "Tower",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut airport_point, AirportPoint::Arp, "ARP");
+                    ui.selectable_value(&mut airport_point, AirportPoint::Tower, "Tower");
+                })
+                .response
+                .on_hover_text("Per airport overrides are set in the config file");
+            if airport_point != self.config.profile().airport_reference.default {
+                self.config.profile_mut().airport_reference.default = airport_point;
+                self.save_config();
+            }
+
+            self.show_match_distances(ui);
+
+            ui.horizontal(|ui| {
+                let can_start = self.picked_path.is_some() && !self.is_processing();
+                if ui.add_enabled(can_start, Button::new("Start Processing…")).clicked() {
+                    if self.config.profile().write_mode == WriteMode::Overwrite {
+                        self.confirmation = Some(Confirmation {
+                            title: "Overwrite without backups?".to_string(),
+                            message: "Backups are disabled for this profile, the original files will be replaced and cannot be restored.".to_string(),
+                            confirm_label: "Overwrite",
+                            action: PendingAction::Overwrite,
+                        });
+                    } else {
+                        self.start_processing(RunMode::Write);
+                    }
+                }
+                if ui
+                    .add_enabled(can_start, Button::new("Preview changes…"))
+                    .on_hover_text("Show what would change, without writing any files")
+                    .clicked()
+                {
+                    self.start_processing(RunMode::DryRun);
+                }
+                if ui
+                    .add_enabled(can_start, Button::new("Verify…"))
+                    .on_hover_text(
+                        "Score the sector file against AIXM and record the score, without writing any files",
+                    )
+                    .clicked()
+                {
+                    self.start_processing(RunMode::Verify);
+                }
+                if let Some((_, cancel)) = &self.job {
+                    let can_cancel = self.is_processing() && !cancel.is_cancelled();
+                    if ui
+                        .add_enabled(can_cancel, Button::new("Cancel"))
+                        .on_hover_text("Stop and roll back files already written")
+                        .clicked()
+                    {
+                        cancel.cancel();
+                    }
+                }
+            });
+
+            ui.add_space(10.);
+
+            if !self.pipeline.is_empty() {
+                show_pipeline(ui, &self.pipeline);
+                ui.add_space(10.);
+            }
+
+            if !self.summaries.is_empty() {
+                CollapsingHeader::new("Summary")
+                    .default_open(true)
+                    .show(ui, |ui| show_run_summary(ui, &self.summaries));
+                ui.add_space(10.);
+            }
+
+            if !self.report.is_empty() {
+                show_report(ui, &self.report, &self.locale);
+                CollapsingHeader::new("Map of changes").show(ui, |ui| {
+                    self.change_map.show(
+                        ui,
+                        self.report
+                            .files()
+                            .iter()
+                            .flat_map(|(_, changes)| changes)
+                            .map(|change| (change, true)),
+                    );
+                });
+                ui.add_space(10.);
+            }
+
+            if !self.member_counts.is_empty() {
+                CollapsingHeader::new("Datasets").show(ui, |ui| self.show_member_counts(ui));
+                ui.add_space(10.);
+            }
+
+            if !self.diffs.is_empty() {
+                CollapsingHeader::new("File differences").show(ui, |ui| {
+                    show_file_diffs(ui, &self.diffs);
+                });
+                ui.add_space(10.);
+            }
+
+            if !self.errors.is_empty() {
+                show_error_summary(ui, &self.errors);
+                ui.add_space(10.);
+            }
+
+            self.show_log(ui);
+        });
+
+        self.handle_first_run(ctx);
+        self.handle_confirmation(ctx);
+        self.handle_review(ctx);
+        self.handle_run_confirmations(ctx);
+    }
+}
+
+impl Drop for App {
+    /// Closing the window mid-run cancels the job and waits for its rollback,
+    /// so no renamed but unwritten files are left behind.
+    fn drop(&mut self) {
+        if let Some((handle, cancel)) = self.job.take() {
+            if !handle.is_finished() {
+                cancel.cancel();
+                if let Err(e) = self.rt.block_on(handle) {
+                    error!("{e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Low-resolution outline of Germany (borders and mainland coast), roughly
+//! the outer boundary of the DFS FIRs, for orientation in the change map.
+//! Accurate to some kilometres only, positions are never checked against it.
+
+/// Latitude and longitude in degrees, counter-clockwise from the Dollart.
+pub(super) const GERMANY: &[(f64, f64)] = &[
+    (53.33, 7.03),
+    (53.24, 7.20),
+    (52.65, 7.05),
+    (52.21, 7.02),
+    (52.00, 6.70),
+    (51.84, 5.96),
+    (51.45, 6.20),
+    (51.05, 5.87),
+    (50.77, 6.02),
+    (50.33, 6.40),
+    (50.13, 6.14),
+    (49.75, 6.50),
+    (49.47, 6.37),
+    (49.20, 6.70),
+    (49.12, 7.05),
+    (49.05, 7.60),
+    (48.97, 8.23),
+    (48.58, 7.80),
+    (48.00, 7.57),
+    (47.59, 7.59),
+    (47.58, 8.20),
+    (47.68, 8.70),
+    (47.66, 9.18),
+    (47.52, 9.70),
+    (47.27, 10.18),
+    (47.55, 10.45),
+    (47.42, 10.98),
+    (47.60, 11.60),
+    (47.58, 12.17),
+    (47.78, 12.95),
+    (47.53, 13.00),
+    (47.95, 12.90),
+    (48.27, 13.02),
+    (48.57, 13.44),
+    (48.77, 13.84),
+    (49.12, 13.20),
+    (49.50, 12.55),
+    (50.10, 12.20),
+    (50.32, 12.10),
+    (50.42, 12.97),
+    (50.75, 13.55),
+    (50.85, 14.40),
+    (50.87, 14.82),
+    (51.25, 15.00),
+    (51.73, 14.64),
+    (51.95, 14.72),
+    (52.57, 14.63),
+    (52.90, 14.13),
+    (53.27, 14.42),
+    (53.92, 14.21),
+    (54.15, 13.78),
+    (54.68, 13.43),
+    (54.55, 13.08),
+    (54.47, 12.50),
+    (54.18, 12.08),
+    (53.95, 11.30),
+    (53.97, 10.88),
+    (54.43, 11.10),
+    (54.43, 10.20),
+    (54.80, 9.90),
+    (54.80, 9.45),
+    (54.91, 8.65),
+    (54.48, 8.95),
+    (54.35, 8.60),
+    (53.87, 8.70),
+    (53.55, 8.55),
+    (53.70, 8.30),
+    (53.52, 8.13),
+    (53.70, 7.80),
+    (53.60, 7.20),
+    (53.33, 7.03),
+];
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use airac_aixm_updater_core::{
+    report::Change,
+    review::{Rejected, ReviewRequest},
+};
+use egui::{Align, CollapsingHeader, Context, Id, Layout, Modal, ScrollArea};
+use tokio::sync::oneshot;
+
+use super::{dialog::Answer, map::ChangeMap};
+
+/// Changes of a running update to accept or reject one by one before they are
+/// written, all accepted initially.
+pub(super) struct Review {
+    files: Vec<(PathBuf, Vec<(Change, bool)>)>,
+    reply: oneshot::Sender<Rejected>,
+    map: ChangeMap,
+}
+
+impl Review {
+    pub(super) fn new(request: ReviewRequest) -> Self {
+        Self {
+            files: request
+                .files
+                .into_iter()
+                .map(|(file, changes)| {
+                    (
+                        file,
+                        changes.into_iter().map(|change| (change, true)).collect(),
+                    )
+                })
+                .collect(),
+            reply: request.reply,
+            map: ChangeMap::default(),
+        }
+    }
+
+    fn set_all(&mut self, accepted: bool) {
+        for (_, changes) in &mut self.files {
+            for (_, change_accepted) in changes {
+                *change_accepted = accepted;
+            }
+        }
+    }
+
+    /// Shows the changes on top of everything else. Escape or a click outside
+    /// cancel the update, like the cancel button.
+    pub(super) fn show(&mut self, ctx: &Context) -> Option<Answer> {
+        let mut answer = None;
+        let total = self
+            .files
+            .iter()
+            .map(|(_, changes)| changes.len())
+            .sum::<usize>();
+        let modal = Modal::new(Id::new("review")).show(ctx, |ui| {
+            ui.set_max_width(720.);
+            ui.heading("Review changes");
+            ui.add_space(8.);
+            ui.label("Only the accepted changes are written, rejected ones are listed in the follow-up list.");
+            ui.add_space(8.);
+            ui.horizontal(|ui| {
+                if ui.button("Accept all").clicked() {
+                    self.set_all(true);
+                }
+                if ui.button("Reject all").clicked() {
+                    self.set_all(false);
+                }
+            });
+            CollapsingHeader::new("Map")
+                .id_salt("review-map")
+                .show(ui, |ui| {
+                    self.map.show(
+                        ui,
+                        self.files
+                            .iter()
+                            .flat_map(|(_, changes)| changes)
+                            .map(|(change, accepted)| (change, *accepted)),
+                    );
+                });
+            ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                for (file, changes) in &mut self.files {
+                    CollapsingHeader::new(format!("{} ({} changes)", file.display(), changes.len()))
+                        .id_salt(("review", &*file))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for (change, accepted) in changes {
+                                ui.checkbox(accepted, change.to_string());
+                            }
+                        });
+                }
+            });
+            ui.add_space(12.);
+            let accepted = self
+                .files
+                .iter()
+                .flat_map(|(_, changes)| changes)
+                .filter(|(_, accepted)| *accepted)
+                .count();
+            ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                if ui.button("Cancel update").clicked() {
+                    answer = Some(Answer::Cancelled);
+                }
+                if ui
+                    .button(format!("Write {accepted} of {total} changes"))
+                    .clicked()
+                {
+                    answer = Some(Answer::Confirmed);
+                }
+            });
+        });
+        if answer.is_none() && modal.should_close() {
+            answer = Some(Answer::Cancelled);
+        }
+        answer
+    }
+
+    /// Rejected changes per file and the channel to send them on.
+    pub(super) fn finish(self) -> (Rejected, oneshot::Sender<Rejected>) {
+        let rejected = self
+            .files
+            .into_iter()
+            .map(|(file, changes)| {
+                (
+                    file,
+                    changes
+                        .into_iter()
+                        .filter(|(_, accepted)| !accepted)
+                        .map(|(change, _)| change)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(|(_, changes)| !changes.is_empty())
+            .collect();
+        (rejected, self.reply)
+    }
+}
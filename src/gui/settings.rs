@@ -0,0 +1,110 @@
+use std::{fs, path::PathBuf};
+
+use airac_aixm_updater_core::{
+    config::Config,
+    error::{
+        AiracUpdaterResult, CreateConfigDirSnafu, ParseConfigSnafu, ReadConfigSnafu,
+        SerializeConfigSnafu, WriteConfigSnafu,
+    },
+};
+use egui::{Context, ViewportBuilder};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt as _;
+use tracing::error;
+
+/// Window and choices of the GUI restored on the next start, stored in
+/// `settings.toml` next to the config file. Everything of an update itself
+/// belongs to the profiles of the config instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub(crate) struct Settings {
+    pub(crate) last_prf: Option<PathBuf>,
+    pub(crate) zoom_factor: f32,
+    /// Inner size of the window in points
+    pub(crate) window_size: Option<[f32; 2]>,
+    /// Outer position of the window in points
+    pub(crate) window_position: Option<[f32; 2]>,
+    /// Append the log of every run to `airac-updater-YYYYMMDD.log`, see
+    /// [`Settings::log_dir`]
+    pub(crate) save_log: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            last_prf: None,
+            zoom_factor: 1.5,
+            window_size: None,
+            window_position: None,
+            save_log: false,
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> AiracUpdaterResult<PathBuf> {
+        Ok(Config::path()?.with_file_name("settings.toml"))
+    }
+
+    /// Loads the settings of the last run, the defaults if there are none or
+    /// they cannot be read.
+    pub(crate) fn load() -> Self {
+        let load = || -> AiracUpdaterResult<Self> {
+            let path = Self::path()?;
+            if !path.exists() {
+                return Ok(Self::default());
+            }
+            let content = fs::read_to_string(&path).context(ReadConfigSnafu { path: &path })?;
+            toml::from_str(&content).context(ParseConfigSnafu { path })
+        };
+        load().inspect_err(|e| error!("{e}")).unwrap_or_default()
+    }
+
+    pub(crate) fn save(&self) -> AiracUpdaterResult {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(CreateConfigDirSnafu { path: parent })?;
+        }
+        let content = toml::to_string_pretty(self).context(SerializeConfigSnafu)?;
+        fs::write(&path, content).context(WriteConfigSnafu { path })
+    }
+
+    /// Directory of the automatically saved logs, `logs` next to the
+    /// settings.
+    pub(crate) fn log_dir() -> AiracUpdaterResult<PathBuf> {
+        Ok(Config::path()?.with_file_name("logs"))
+    }
+
+    pub(crate) fn viewport(&self) -> ViewportBuilder {
+        let mut viewport = ViewportBuilder::default();
+        if let Some(size) = self.window_size {
+            viewport = viewport.with_inner_size(size);
+        }
+        if let Some(position) = self.window_position {
+            viewport = viewport.with_position(position);
+        }
+        viewport
+    }
+
+    /// Takes over the current window geometry and zoom, returning whether any
+    /// of it changed. Ignored while a mouse button is held, i.e. mid-resize.
+    pub(crate) fn track_window(&mut self, ctx: &Context) -> bool {
+        let (size, position, dragging) = ctx.input(|input| {
+            let viewport = input.viewport();
+            (
+                viewport.inner_rect.map(|rect| rect.size().into()),
+                viewport.outer_rect.map(|rect| rect.min.into()),
+                input.pointer.any_down(),
+            )
+        });
+        if dragging {
+            return false;
+        }
+
+        let previous = self.clone();
+        self.window_size = size.or(self.window_size);
+        self.window_position = position.or(self.window_position);
+        self.zoom_factor = ctx.zoom_factor();
+        *self != previous
+    }
+}
@@ -0,0 +1,1323 @@
+//! Core of the AIRAC updater: loading the DFS AIXM datasets and EuroScope
+//! sector files, combining them and writing the results, shared by the GUI
+//! and command line of `airac-aixm-updater` and usable by other tooling.
+//!
+//! [`spawn_jobs`] runs the whole pipeline, reporting progress as [`Message`]s,
+//! [`spawn_batch`] does so for several sector packages at once.
+//! The steps are available on their own, e.g. [`load_aixm_files`],
+//! [`load_euroscope_files`] and [`EuroscopeFile::combine_with_aixm`].
+
+pub mod airac;
+pub mod aixm;
+pub mod aixm_airspaces;
+pub mod aixm_combine;
+pub mod aixm_dfs;
+pub mod aixm_frequencies;
+pub mod aixm_ils;
+pub mod aixm_index;
+pub mod aixm_military;
+pub mod aixm_parse;
+pub mod aixm_routes;
+pub mod artifact_store;
+pub mod aurora;
+pub mod cancel;
+pub mod channel_metrics;
+pub mod config;
+pub mod cross_check;
+pub mod cycle_diff;
+pub mod dataset_archive;
+pub mod dataset_cache;
+pub mod error;
+pub mod error_summary;
+pub mod file_diff;
+pub mod follow_up;
+pub mod geo_export;
+pub mod geometry;
+pub mod load_es;
+#[cfg(feature = "gui")]
+pub mod locale;
+pub mod message_log;
+pub mod mirror;
+pub mod network;
+pub mod output;
+pub mod pipeline;
+pub mod provider;
+pub mod report;
+pub mod review;
+pub mod run_summary;
+pub mod sct_salvage;
+pub mod staging;
+pub mod tempdelta;
+pub mod topsky;
+pub mod traffic_circuits;
+pub mod validation;
+pub mod verify;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+pub use aixm::{load_aixm_files, load_local_aixm_files};
+pub use aixm_combine::{AixmUpdateExt, EuroscopeFile};
+use aixm_combine::{CombineOptions, EntityKind, NavaidNames};
+use aixm_ils::{ils_path, render_ils};
+use aixm_index::AixmIndex;
+use artifact_store::ArtifactStore;
+use aurora::render_aurora;
+use cancel::CancelToken;
+use channel_metrics::ChannelMonitor;
+use chrono::{DateTime, Utc};
+use config::{MilitaryEntities, NewEntities, Profile, StaleEntities};
+pub use error::{AiracUpdaterResult, Error};
+use error::{CancelledSnafu, ErrorContext};
+use file_diff::FileDiff;
+use follow_up::{FollowUp, FollowUpReason, retain_referenced, write_follow_ups};
+use futures::future::try_join_all;
+use geo_export::write_geo_exports;
+pub use load_es::load_euroscope_files;
+use pipeline::Stage;
+use provider::Amendments;
+use report::{Change, ChangeAction, ChangeReport, ReportFormat};
+use review::{Rejected, ReviewRequest};
+use run_summary::{FileSummary, summarize};
+use staging::{WriteConfirmation, WritePlan, write_files};
+use tempdelta::{load_temporary_airspaces, overlay_path, render_overlay};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::spawn_blocking,
+    try_join,
+};
+use topsky::topsky_maps;
+use tracing::{Level, error};
+use traffic_circuits::{circuits_path, load_traffic_circuits, render_circuits};
+use uuid::Uuid;
+use validation::{Anomaly, AnomalyConfirmation};
+use verify::{QualityCounts, QualityScore, record_quality_score};
+
+/// Clock skew to the DFS server above which backup timestamps are flagged
+pub const CLOCK_SKEW_TOLERANCE_SECS: i64 = 120;
+
+/// Capacity of the progress channel passed to [`spawn_jobs`]. Large enough
+/// that the jobs rarely wait for the GUI to take messages between frames.
+pub const MESSAGE_CHANNEL_CAPACITY: usize = 4096;
+
+/// Progress of a job, logged by the GUI and the command line.
+pub struct Message {
+    pub content: String,
+    pub level: Level,
+    pub time: DateTime<Utc>,
+    pub stage: Option<(String, Stage)>,
+    pub changes: Option<(PathBuf, Vec<Change>)>,
+    /// Where the error occurred, for error messages
+    pub error: Option<ErrorContext>,
+    pub review: Option<ReviewRequest>,
+    pub anomalies: Option<AnomalyConfirmation>,
+    pub writes: Option<WriteConfirmation>,
+    /// Number of members per feature type of a loaded dataset
+    pub member_counts: Option<(String, BTreeMap<String, usize>)>,
+    /// What the run did per .sct, sent at its end
+    pub summary: Option<Vec<FileSummary>>,
+    pub diff: Option<FileDiff>,
+}
+impl Message {
+    pub fn new(content: String, level: Level) -> Self {
+        Self {
+            content,
+            level,
+            time: Utc::now(),
+            stage: None,
+            changes: None,
+            error: None,
+            review: None,
+            anomalies: None,
+            writes: None,
+            member_counts: None,
+            summary: None,
+            diff: None,
+        }
+    }
+
+    pub fn stage(item: impl fmt::Display, stage: Stage) -> Self {
+        Self {
+            stage: Some((item.to_string(), stage)),
+            ..Self::debug(format!("{item}: {stage}"))
+        }
+    }
+
+    pub fn changes(file: &Path, changes: Vec<Change>) -> Self {
+        let content = format!("{}: {} changes", file.display(), changes.len());
+        Self {
+            changes: Some((file.to_path_buf(), changes)),
+            ..Self::info(content)
+        }
+    }
+
+    /// Asks for the changes to be reviewed, the run waits for the reply.
+    pub fn review(request: ReviewRequest) -> Self {
+        let content = format!(
+            "{} changes waiting for review",
+            request
+                .files
+                .iter()
+                .map(|(_, changes)| changes.len())
+                .sum::<usize>()
+        );
+        Self {
+            review: Some(request),
+            ..Self::info(content)
+        }
+    }
+
+    /// Dataset loaded, with the number of members per feature type.
+    pub fn loaded(dataset: &str, member_counts: BTreeMap<String, usize>) -> Self {
+        let counts = member_counts
+            .iter()
+            .map(|(feature, count)| format!("{count} {feature}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            member_counts: Some((dataset.to_string(), member_counts)),
+            ..Self::info(format!("Loaded AIXM: {dataset} ({counts})"))
+        }
+    }
+
+    /// Added, moved, removed, skipped and unchanged entities of every .sct of
+    /// a run.
+    pub fn summary(summaries: Vec<FileSummary>) -> Self {
+        let content = format!("Summary of {} sector files", summaries.len());
+        Self {
+            summary: Some(summaries),
+            ..Self::info(content)
+        }
+    }
+
+    /// Asks whether to write despite suspicious results, the run waits for
+    /// the reply.
+    pub fn anomalies(request: AnomalyConfirmation) -> Self {
+        let content = format!(
+            "{} suspicious results waiting for confirmation",
+            request.anomalies.len()
+        );
+        Self {
+            anomalies: Some(request),
+            ..Self::warn(content)
+        }
+    }
+
+    /// Asks whether to write the planned files, the run waits for the reply.
+    pub fn writes(request: WriteConfirmation) -> Self {
+        let content = format!(
+            "{} files of run {} waiting for confirmation",
+            request.plan.files.len(),
+            request.plan.run_id
+        );
+        Self {
+            writes: Some(request),
+            ..Self::info(content)
+        }
+    }
+
+    /// Differences of a file as read and as written (or previewed).
+    pub fn diff(diff: FileDiff) -> Self {
+        let content = format!(
+            "{}: {} lines added, {} removed",
+            diff.path.display(),
+            diff.added,
+            diff.removed
+        );
+        Self {
+            diff: Some(diff),
+            ..Self::debug(content)
+        }
+    }
+
+    pub fn debug(content: String) -> Self {
+        Self::new(content, Level::DEBUG)
+    }
+
+    pub fn info(content: String) -> Self {
+        Self::new(content, Level::INFO)
+    }
+
+    pub fn warn(content: String) -> Self {
+        Self::new(content, Level::WARN)
+    }
+
+    pub fn error(content: String) -> Self {
+        Self::new(content, Level::ERROR)
+    }
+
+    /// Warning keeping the stage, file and entity of `error`, for errors that
+    /// only skip a single entity instead of failing the run.
+    pub fn skipped(error: &Error) -> Self {
+        Self {
+            error: Some(error.context()),
+            ..Self::warn(error.to_string())
+        }
+    }
+
+    /// Error message keeping the stage, file and entity of `error`.
+    pub fn failure(error: &Error) -> Self {
+        Self {
+            error: Some(error.context()),
+            ..Self::error(error.to_string())
+        }
+    }
+}
+
+/// What [`spawn_jobs`] does with the combined files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunMode {
+    Write,
+    /// Only report the changes
+    DryRun,
+    /// Only score the .sct files against AIXM and record the score, see
+    /// [`verify`]
+    Verify,
+}
+
+/// Updates the files referenced by `prf` from the given amendment, `0` being
+/// the current one. Unless `mode` is [`RunMode::Write`], nothing is
+/// written.
+///
+/// Once `cancel` is cancelled, downloads are aborted, the files are not
+/// combined any further and files already moved into place are rolled back.
+///
+/// `run` uniquely identifies the run in the backup file names, so they can be
+/// correlated with the message log and change report of the same run.
+pub async fn spawn_jobs(
+    prf: impl AsRef<Path>,
+    amdt: u32,
+    profile: Profile,
+    mode: RunMode,
+    run: Uuid,
+    cancel: CancelToken,
+    tx: mpsc::Sender<Message>,
+) {
+    spawn_batch([prf], amdt, profile, mode, run, cancel, tx).await;
+}
+
+/// Like [`spawn_jobs`] for several sector packages, e.g. EDGG, EDMM and EDWW,
+/// downloading the AIXM datasets once for all of them. The packages are
+/// updated one after the other, a failing one does not stop the rest.
+pub async fn spawn_batch<P: AsRef<Path>>(
+    prfs: impl IntoIterator<Item = P>,
+    amdt: u32,
+    profile: Profile,
+    mode: RunMode,
+    run: Uuid,
+    cancel: CancelToken,
+    tx: mpsc::Sender<Message>,
+) {
+    let started = Instant::now();
+    let monitor = ChannelMonitor::start(&tx);
+    let prfs = prfs.into_iter().collect::<Vec<_>>();
+    if let Err(e) = tx
+        .send(Message::info(format!(
+            "Run {run} of {}",
+            prfs.iter()
+                .map(|prf| prf.as_ref().display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )))
+        .await
+    {
+        error!("{e}");
+    }
+
+    let mut summaries = vec![];
+    if let Some(shared) = load_shared_aixm(amdt, &profile, &cancel, &tx).await {
+        for prf in &prfs {
+            if cancel.is_cancelled() {
+                break;
+            }
+            if prfs.len() > 1 {
+                if let Err(e) = tx
+                    .send(Message::info(format!(
+                        "Updating {}",
+                        prf.as_ref().display()
+                    )))
+                    .await
+                {
+                    error!("{e}");
+                }
+            }
+            update_package(
+                prf.as_ref(),
+                &shared,
+                amdt,
+                &profile,
+                mode,
+                run,
+                &cancel,
+                &tx,
+                &mut summaries,
+            )
+            .await;
+        }
+    }
+    if !summaries.is_empty() {
+        if let Err(e) = tx.send(Message::summary(summaries)).await {
+            error!("{e}");
+        }
+    }
+
+    // comparable between the GUI and the command line to spot the pipeline
+    // waiting for progress messages to be taken
+    if let Err(e) = tx
+        .send(Message::info(format!(
+            "Finished processing in {:.1?}",
+            started.elapsed()
+        )))
+        .await
+    {
+        error!("{e}");
+    }
+    if let Some(metrics) = monitor.finish().await {
+        if let Err(e) = tx.send(Message::debug(metrics.to_string())).await {
+            error!("{e}");
+        }
+    }
+}
+
+/// AIXM data of a run, loaded and indexed once for all of its packages.
+struct SharedAixm {
+    aixm: Arc<AixmIndex>,
+    options: Arc<CombineOptions>,
+    /// Content of the temporary airspace overlay, see
+    /// [`Profile::temporary_airspace`]
+    overlay: Option<Vec<u8>>,
+    /// Content of the traffic circuit overlay, see
+    /// [`Profile::traffic_circuits`]
+    circuits: Option<Vec<u8>>,
+    /// Content of the ILS overlay, see [`Profile::ils_overlay`]
+    ils: Option<Vec<u8>>,
+    /// Conflicts between the datasets, listed for every package
+    follow_ups: Vec<FollowUp>,
+}
+
+/// Downloads (or reads) and indexes the AIXM datasets of `profile`, `None`
+/// after reporting the failure.
+async fn load_shared_aixm(
+    amdt: u32,
+    profile: &Profile,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> Option<SharedAixm> {
+    // checked before anything is downloaded
+    let options = match CombineOptions::from_profile(profile) {
+        Ok(options) => Arc::new(options),
+        Err(e) => {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+            return None;
+        }
+    };
+
+    let amdts = Amendments::from(amdt).with_overrides(profile.dataset_amdts.clone());
+    let load = async {
+        try_join!(
+            async {
+                match &profile.aixm_dir {
+                    Some(dir) => {
+                        load_local_aixm_files(dir, &profile.datasets, cancel, tx.clone()).await
+                    }
+                    None => {
+                        profile
+                            .provider
+                            .load(
+                                amdts.clone(),
+                                &profile.datasets,
+                                profile.aixm_release,
+                                tx.clone(),
+                            )
+                            .await
+                    }
+                }
+            },
+            async {
+                match &profile.temporary_airspace {
+                    Some(dataset) => load_temporary_airspaces(
+                        amdts.of(dataset),
+                        dataset,
+                        profile.aixm_release,
+                        tx.clone(),
+                    )
+                    .await
+                    .map(Some),
+                    None => Ok(None),
+                }
+            },
+            async {
+                match &profile.cross_check {
+                    Some(cross_check) => cross_check.load_reference(tx.clone()).await.map(Some),
+                    None => Ok(None),
+                }
+            },
+            async {
+                match &profile.traffic_circuits {
+                    Some(circuits) => load_traffic_circuits(
+                        amdts.of(&circuits.dataset),
+                        circuits,
+                        profile.aixm_release,
+                        tx.clone(),
+                    )
+                    .await
+                    .map(Some),
+                    None => Ok(None),
+                }
+            }
+        )
+    };
+    // dropping `load` aborts the download tasks
+    let loaded = tokio::select! {
+        loaded = load => loaded,
+        () = cancel.cancelled() => CancelledSnafu.fail(),
+    };
+    let (aixm, temporary_airspaces, reference, circuits) = match loaded {
+        Ok(ok) => ok,
+        Err(e) => {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+            return None;
+        }
+    };
+
+    let blocking_tx = tx.clone();
+    let cross_check = profile.cross_check.clone();
+    let ils_overlay = profile.ils_overlay;
+    let (
+        airport_reference,
+        default_ctr_class,
+        match_airports_without_icao,
+        entity_types,
+        military_entities,
+    ) = (
+        profile.airport_reference.clone(),
+        profile.default_ctr_class.clone(),
+        profile.match_airports_without_icao,
+        profile.entity_types,
+        profile.military_entities,
+    );
+    let indexed = spawn_blocking(move || {
+        let aixm = AixmIndex::new(aixm)
+            .with_airport_reference(airport_reference)
+            .with_default_ctr_class(default_ctr_class)
+            .with_airports_without_icao(match_airports_without_icao)
+            .with_entity_types(entity_types)
+            .with_military_entities(military_entities);
+        if let Err(e) = blocking_tx.blocking_send(Message::debug(format!(
+            "Indexed {} AIXM members",
+            aixm.len()
+        ))) {
+            error!("{e}");
+        }
+        let mut follow_ups = vec![];
+        for duplicate in aixm.duplicates() {
+            if let Err(e) = blocking_tx.blocking_send(Message::warn(duplicate.to_string())) {
+                error!("{e}");
+            }
+            follow_ups.push(FollowUp::new(
+                FollowUpReason::AixmConflict,
+                None,
+                duplicate.kind,
+                &duplicate.key,
+                format!(
+                    "defined {} times, using the one from {}",
+                    duplicate.count, duplicate.dataset
+                ),
+            ));
+        }
+
+        let military = aixm.military();
+        if !military.is_empty() {
+            let verb = if military_entities == MilitaryEntities::Exclude {
+                "Excluded"
+            } else {
+                "Found"
+            };
+            if let Err(e) = blocking_tx.blocking_send(Message::info(format!(
+                "{verb} {} military-only AIXM entities",
+                military.len()
+            ))) {
+                error!("{e}");
+            }
+        }
+        if military_entities == MilitaryEntities::Tag {
+            follow_ups.extend(military.into_iter().map(|(kind, designator)| {
+                FollowUp::new(
+                    FollowUpReason::Military,
+                    None,
+                    kind,
+                    designator,
+                    "military only in AIXM",
+                )
+            }));
+        }
+
+        if let (Some(cross_check), Some(reference)) = (&cross_check, &reference) {
+            let disagreements = cross_check.check(&aixm, reference);
+            for disagreement in &disagreements {
+                if let Err(e) = blocking_tx.blocking_send(Message::warn(disagreement.to_string())) {
+                    error!("{e}");
+                }
+            }
+            if let Err(e) = blocking_tx.blocking_send(Message::info(format!(
+                "Cross-checked AIXM positions against ourairports, {} disagreements",
+                disagreements.len()
+            ))) {
+                error!("{e}");
+            }
+        }
+
+        let overlay = temporary_airspaces.map(|airspaces| render_overlay(&airspaces));
+        let circuits = circuits.map(|circuits| render_circuits(&circuits));
+        let ils = ils_overlay.then(|| {
+            if let Err(e) = blocking_tx.blocking_send(Message::info(format!(
+                "Found {} ILS localizers in AIXM",
+                aixm.localizers().len()
+            ))) {
+                error!("{e}");
+            }
+            render_ils(aixm.localizers())
+        });
+        SharedAixm {
+            aixm: Arc::new(aixm),
+            options,
+            overlay,
+            circuits,
+            ils,
+            follow_ups,
+        }
+    })
+    .await;
+    match indexed {
+        Ok(shared) => Some(shared),
+        Err(e) => {
+            error!("{e}");
+            None
+        }
+    }
+}
+
+/// Updates the files referenced by `prf` from the AIXM data of the run.
+async fn update_package(
+    prf: &Path,
+    shared: &SharedAixm,
+    amdt: u32,
+    profile: &Profile,
+    mode: RunMode,
+    run: Uuid,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+    summaries: &mut Vec<FileSummary>,
+) {
+    let load = load_euroscope_files(
+        prf,
+        &profile.path_remaps,
+        &profile.passthrough_sections,
+        profile.salvage_sct,
+        tx.clone(),
+    );
+    let loaded = tokio::select! {
+        loaded = load => loaded,
+        () = cancel.cancelled() => CancelledSnafu.fail(),
+    };
+    let es_files = match loaded {
+        Ok(es_files) => es_files,
+        Err(e) => {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+            return;
+        }
+    };
+
+    let blocking_tx = tx.clone();
+    let (aixm, options) = (Arc::clone(&shared.aixm), Arc::clone(&shared.options));
+    let (overlay, circuits, ils) = (
+        shared.overlay.clone(),
+        shared.circuits.clone(),
+        shared.ils.clone(),
+    );
+    let sct_writer = profile.sct_writer;
+    let prepared = spawn_blocking(move || {
+        let mut es_files = es_files;
+        let renames = EuroscopeFile::runway_renames(&es_files, &aixm, &options);
+        for rename in &renames {
+            if let Err(e) =
+                blocking_tx.blocking_send(Message::warn(format!("Runway re-designated: {rename}")))
+            {
+                error!("{e}");
+            }
+        }
+        for es_file in &mut es_files {
+            es_file.apply_runway_renames(&renames);
+            es_file.set_sct_writer(sct_writer);
+        }
+
+        let overlays = [
+            (overlay, overlay_path as fn(&Path) -> PathBuf),
+            (circuits, circuits_path),
+            (ils, ils_path),
+        ]
+        .into_iter()
+        .filter_map(|(content, overlay_path)| {
+            let content = content?;
+            es_files.iter().find_map(|es_file| match es_file {
+                EuroscopeFile::Sct { path, .. } => Some(EuroscopeFile::Generated {
+                    path: overlay_path(path),
+                    content: content.clone(),
+                }),
+                _ => None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+        (aixm, es_files, overlays)
+    })
+    .await;
+    let (aixm, es_files, overlays) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+    let mut follow_ups = shared.follow_ups.clone();
+
+    // The index is not modified anymore, so the files can be combined
+    // concurrently, each on its own blocking thread.
+    let navaid_names = Arc::new(profile.navaid_names.clone());
+    let (new_entities, stale_entities) = (profile.new_entities, profile.stale_entities);
+    let verify_tolerance = (mode == RunMode::Verify).then_some(profile.verify_tolerance);
+    let combined = try_join_all(es_files.into_iter().map(|es_file| {
+        let (aixm, options, navaid_names, cancel, tx) = (
+            Arc::clone(&aixm),
+            Arc::clone(&shared.options),
+            Arc::clone(&navaid_names),
+            cancel.clone(),
+            tx.clone(),
+        );
+        spawn_blocking(move || {
+            combine_file(
+                es_file,
+                &aixm,
+                &options,
+                new_entities,
+                stale_entities,
+                navaid_names.as_ref().as_ref(),
+                verify_tolerance,
+                &cancel,
+                &tx,
+            )
+        })
+    }))
+    .await;
+    let combined = match combined {
+        Ok(combined) => combined.into_iter().collect::<AiracUpdaterResult<Vec<_>>>(),
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+    let mut report = ChangeReport::default();
+    report.set_run(run);
+    if let Some(cycle) = aixm.cycle() {
+        report.set_cycle(cycle);
+    }
+    let mut quality = QualityCounts::default();
+    let mut files = match combined {
+        Ok(combined) => {
+            let mut files = vec![];
+            for combined in combined {
+                quality += combined.quality.unwrap_or_default();
+                if !combined.changes.is_empty() {
+                    report.add(&combined.path, combined.changes);
+                }
+                files.extend(combined.files);
+                follow_ups.extend(combined.follow_ups);
+            }
+            files.extend(overlays);
+            files
+        }
+        Err(e) => {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+            return;
+        }
+    };
+    if mode == RunMode::Verify {
+        for file in &files {
+            if let Err(e) = tx
+                .send(Message::stage(file.path().display(), Stage::Done))
+                .await
+            {
+                error!("{e}");
+            }
+        }
+        let score = QualityScore {
+            time: Utc::now(),
+            run,
+            amdt,
+            score: quality.score(),
+            counts: quality,
+        };
+        if let Err(e) = record_quality_score(prf, &score, tx).await {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+        }
+        return;
+    }
+    if profile.review_changes && mode == RunMode::Write && !report.is_empty() {
+        let Some(rejected) = review_changes(&report, cancel, tx).await else {
+            if let Err(e) = tx
+                .send(Message::warn(
+                    "Review cancelled, no files were written".to_string(),
+                ))
+                .await
+            {
+                error!("{e}");
+            }
+            return;
+        };
+        for (path, changes) in &rejected {
+            for file in files.iter_mut().filter(|file| file.path() == path) {
+                file.reject_changes(changes);
+            }
+            report.reject(path, changes);
+            follow_ups.extend(changes.iter().map(|change| {
+                FollowUp::new(
+                    FollowUpReason::Rejected,
+                    Some(path),
+                    change.kind,
+                    &change.designator,
+                    change.action.to_string(),
+                )
+            }));
+        }
+    }
+
+    if profile.validation.enabled {
+        let anomalies = profile.validation.check(&report, &files);
+        if !anomalies.is_empty() && mode == RunMode::Write {
+            if !confirm_anomalies(anomalies, cancel, tx).await {
+                if let Err(e) = tx
+                    .send(Message::warn(
+                        "Suspicious results not confirmed, no files were written".to_string(),
+                    ))
+                    .await
+                {
+                    error!("{e}");
+                }
+                return;
+            }
+        } else {
+            for anomaly in anomalies {
+                if let Err(e) = tx.send(Message::warn(anomaly.to_string())).await {
+                    error!("{e}");
+                }
+            }
+        }
+    }
+
+    if profile.aurora_output {
+        let aurora = files
+            .iter()
+            .filter_map(|file| match file {
+                EuroscopeFile::Sct { path, content, .. } => {
+                    Some(render_aurora(path, content, aixm.airspaces()))
+                }
+                _ => None,
+            })
+            .flatten()
+            .collect::<Vec<_>>();
+        files.extend(aurora);
+    }
+    if profile.topsky_output {
+        let topsky = files
+            .iter()
+            .filter_map(|file| match file {
+                EuroscopeFile::Sct { path, .. } => Some(topsky_maps(path, aixm.airspaces())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        files.extend(topsky);
+    }
+
+    let package_dir = prf
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let procedure_waypoints = files
+        .iter()
+        .flat_map(EuroscopeFile::procedure_waypoints)
+        .collect();
+    retain_referenced(&mut follow_ups, &procedure_waypoints);
+    let summary = summarize(&report, &files, &follow_ups);
+
+    send_file_diffs(&files, tx).await;
+
+    let store = ArtifactStore::open();
+    let mut unchanged = vec![];
+    if mode == RunMode::Write {
+        if let Some(store) = &store {
+            (files, unchanged) = split_unchanged(store, prf, files, tx).await;
+        }
+    }
+
+    if mode == RunMode::DryRun {
+        for file in &files {
+            if let Err(e) = tx
+                .send(Message::stage(file.path().display(), Stage::Done))
+                .await
+            {
+                error!("{e}");
+            }
+        }
+        if let Err(e) = tx
+            .send(Message::info(format!(
+                "Preview only, no files were written, {} entities would need manual attention",
+                follow_ups.len()
+            )))
+            .await
+        {
+            error!("{e}");
+        }
+        if let Some(format) = profile.change_report {
+            write_change_report(prf, &report, format, tx).await;
+        }
+        summaries.extend(summary);
+        return;
+    }
+
+    let plan = match WritePlan::new(&files, profile.write_mode, run).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+            for file in &files {
+                if let Err(e) = tx
+                    .send(Message::stage(file.path().display(), Stage::Failed))
+                    .await
+                {
+                    error!("{e}");
+                }
+            }
+            return;
+        }
+    };
+    if profile.confirm_writes
+        && !plan.files.is_empty()
+        && !confirm_writes(plan.clone(), cancel, tx).await
+    {
+        if let Err(e) = tx
+            .send(Message::warn(
+                "Writing not confirmed, no files were written".to_string(),
+            ))
+            .await
+        {
+            error!("{e}");
+        }
+        return;
+    }
+    if let Err(e) = write_files(
+        package_dir,
+        &plan,
+        profile.allow_external_symlinks,
+        cancel,
+        tx.clone(),
+    )
+    .await
+    {
+        if let Err(e) = tx.send(Message::failure(&e)).await {
+            error!("{e}");
+        }
+        for file in &files {
+            if let Err(e) = tx
+                .send(Message::stage(file.path().display(), Stage::Failed))
+                .await
+            {
+                error!("{e}");
+            }
+        }
+    } else {
+        if let Some(store) = &store {
+            match store
+                .record(run, prf, amdt, files.iter().chain(&unchanged))
+                .await
+            {
+                Ok(record) => {
+                    if let Err(e) = tx
+                        .send(Message::debug(format!(
+                            "Stored {} files of run {run} in the artifact store",
+                            record.files.len()
+                        )))
+                        .await
+                    {
+                        error!("{e}");
+                    }
+                }
+                Err(e) => {
+                    if let Err(e) = tx.send(Message::warn(e.to_string())).await {
+                        error!("{e}");
+                    }
+                }
+            }
+        }
+        if let Some(geo_export) = profile.geo_export {
+            if let Err(e) = write_geo_exports(&files, geo_export, tx.clone()).await {
+                if let Err(e) = tx.send(Message::failure(&e)).await {
+                    error!("{e}");
+                }
+            }
+        }
+        if let Err(e) = write_follow_ups(prf, &mut follow_ups, tx.clone()).await {
+            if let Err(e) = tx.send(Message::failure(&e)).await {
+                error!("{e}");
+            }
+        }
+        if let Some(format) = profile.change_report {
+            write_change_report(prf, &report, format, tx).await;
+        }
+        summaries.extend(summary);
+    }
+}
+
+/// Splits off the files the last run of `prf` wrote with the same content and
+/// that were not modified since, as writing them again changes nothing.
+async fn split_unchanged(
+    store: &ArtifactStore,
+    prf: &Path,
+    files: Vec<EuroscopeFile>,
+    tx: &mpsc::Sender<Message>,
+) -> (Vec<EuroscopeFile>, Vec<EuroscopeFile>) {
+    let (mut changed, mut unchanged) = (vec![], vec![]);
+    for file in files {
+        let is_unchanged = match file.serialize() {
+            Some(content) => store.is_unchanged(prf, file.path(), &content).await,
+            None => false,
+        };
+        if !is_unchanged {
+            changed.push(file);
+            continue;
+        }
+        for message in [
+            Message::info(format!(
+                "{}: unchanged since the last run, not rewritten",
+                file.path().display()
+            )),
+            Message::stage(file.path().display(), Stage::Done),
+        ] {
+            if let Err(e) = tx.send(message).await {
+                error!("{e}");
+            }
+        }
+        unchanged.push(file);
+    }
+    (changed, unchanged)
+}
+
+/// Sends the differences of the files patched on write, i.e. the .sct and
+/// isec.txt, between their content as read and as rendered.
+async fn send_file_diffs(files: &[EuroscopeFile], tx: &mpsc::Sender<Message>) {
+    for file in files {
+        let Some(original) = file.original() else {
+            continue;
+        };
+        let Some(rendered) = file.serialize() else {
+            continue;
+        };
+        let (path, original) = (file.path().to_path_buf(), original.to_string());
+        let diff = spawn_blocking(move || {
+            FileDiff::new(&path, &original, &String::from_utf8_lossy(&rendered))
+        })
+        .await;
+        match diff {
+            Ok(diff) => {
+                if let Err(e) = tx.send(Message::diff(diff)).await {
+                    error!("{e}");
+                }
+            }
+            Err(e) => error!("{e}"),
+        }
+    }
+}
+
+/// Waits for the changes of `report` to be reviewed, `None` if the review was
+/// dismissed or the run cancelled.
+async fn review_changes(
+    report: &ChangeReport,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> Option<Rejected> {
+    let (reply, answer) = oneshot::channel();
+    let request = ReviewRequest {
+        files: report.files().to_vec(),
+        reply,
+    };
+    if let Err(e) = tx.send(Message::review(request)).await {
+        error!("{e}");
+        return None;
+    }
+    let rejected = tokio::select! {
+        answer = answer => answer.ok()?,
+        () = cancel.cancelled() => return None,
+    };
+    let count = rejected
+        .iter()
+        .map(|(_, changes)| changes.len())
+        .sum::<usize>();
+    if let Err(e) = tx
+        .send(Message::info(format!("Rejected {count} changes in review")))
+        .await
+    {
+        error!("{e}");
+    }
+    Some(rejected)
+}
+
+/// Lists `anomalies` as warnings and waits for them to be confirmed, `false`
+/// if they were not or the run was cancelled.
+async fn confirm_anomalies(
+    anomalies: Vec<Anomaly>,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> bool {
+    for anomaly in &anomalies {
+        if let Err(e) = tx.send(Message::warn(anomaly.to_string())).await {
+            error!("{e}");
+        }
+    }
+    let (reply, answer) = oneshot::channel();
+    let request = AnomalyConfirmation { anomalies, reply };
+    if let Err(e) = tx.send(Message::anomalies(request)).await {
+        error!("{e}");
+        return false;
+    }
+    tokio::select! {
+        answer = answer => answer.unwrap_or(false),
+        () = cancel.cancelled() => false,
+    }
+}
+
+/// Lists the files of `plan` and waits for them to be confirmed, `false` if
+/// they were not or the run was cancelled.
+async fn confirm_writes(plan: WritePlan, cancel: &CancelToken, tx: &mpsc::Sender<Message>) -> bool {
+    let (reply, answer) = oneshot::channel();
+    let request = WriteConfirmation { plan, reply };
+    if let Err(e) = tx.send(Message::writes(request)).await {
+        error!("{e}");
+        return false;
+    }
+    tokio::select! {
+        answer = answer => answer.unwrap_or(false),
+        () = cancel.cancelled() => false,
+    }
+}
+
+/// Writes the change report next to the .prf, see [`Profile::change_report`].
+async fn write_change_report(
+    prf: &Path,
+    report: &ChangeReport,
+    format: ReportFormat,
+    tx: &mpsc::Sender<Message>,
+) {
+    let path = format.path_for(prf);
+    let msg = match report.write(&path, format).await {
+        Ok(()) => Message::info(format!("Wrote change report {}", path.display())),
+        Err(e) => Message::failure(&e),
+    };
+    if let Err(e) = tx.send(msg).await {
+        error!("{e}");
+    }
+}
+
+/// Result of combining a single file.
+struct Combined {
+    path: PathBuf,
+    /// The combined file followed by the auxiliary files generated from it
+    files: Vec<EuroscopeFile>,
+    changes: Vec<Change>,
+    /// Entities left for the maintainer. Excluded fixes are listed regardless
+    /// of whether a procedure uses them, see [`retain_referenced`].
+    follow_ups: Vec<FollowUp>,
+    /// Comparison against AIXM when verifying a .sct
+    quality: Option<QualityCounts>,
+}
+
+/// Combines a single file with the AIXM data, unless cancelled in between.
+/// With `verify_tolerance`, the file is compared against AIXM as well.
+fn combine_file(
+    es_file: EuroscopeFile,
+    aixm: &AixmIndex,
+    options: &CombineOptions,
+    new_entities: NewEntities,
+    stale_entities: StaleEntities,
+    navaid_names: Option<&NavaidNames>,
+    verify_tolerance: Option<f64>,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Combined> {
+    cancel.check()?;
+    if let Err(e) = tx.blocking_send(Message::stage(es_file.path().display(), Stage::Combining)) {
+        error!("{e}");
+    }
+    let mut changes = vec![];
+    let mut es_file = es_file.combine_with_aixm(aixm, options, &mut changes, tx.clone());
+    let quality =
+        verify_tolerance.and_then(|tolerance| es_file.quality_counts(aixm, &changes, tolerance));
+    cancel.check()?;
+    let path = es_file.path().to_path_buf();
+    let excluded = es_file.exclude_fixes(&options.fix_exclude, &mut changes);
+    if !excluded.is_empty() {
+        if let Err(e) = tx.blocking_send(Message::info(format!(
+            "{}: skipped {} new fixes matching fix-exclude",
+            path.display(),
+            excluded.len()
+        ))) {
+            error!("{e}");
+        }
+    }
+    let mut follow_ups = excluded
+        .into_iter()
+        .map(|(designator, _)| {
+            FollowUp::new(
+                FollowUpReason::ExcludedButReferenced,
+                Some(&path),
+                EntityKind::Fix,
+                designator,
+                "matches fix-exclude",
+            )
+        })
+        .collect::<Vec<_>>();
+    if new_entities == NewEntities::Candidate {
+        es_file.demote_additions(&mut changes);
+    }
+    if stale_entities != StaleEntities::Keep {
+        let kept = es_file.handle_stale_entities(
+            aixm,
+            stale_entities,
+            &options.transforms,
+            &mut changes,
+            tx,
+        );
+        follow_ups.extend(kept.into_iter().map(|(kind, designator, _)| {
+            FollowUp::new(
+                FollowUpReason::Stale,
+                Some(&path),
+                kind,
+                designator,
+                "missing in AIXM",
+            )
+        }));
+    }
+    follow_ups.extend(
+        changes
+            .iter()
+            .filter(|change| change.action == ChangeAction::Candidate)
+            .map(|change| {
+                FollowUp::new(
+                    FollowUpReason::Candidate,
+                    Some(&path),
+                    change.kind,
+                    &change.designator,
+                    format!("from {}", change.dataset),
+                )
+            }),
+    );
+    follow_ups.extend(es_file.ambiguous_frequencies(aixm).into_iter().map(
+        |(name, call_sign, frequencies)| {
+            FollowUp::new(
+                FollowUpReason::AmbiguousFrequency,
+                Some(&path),
+                "Position",
+                name,
+                format!("{call_sign}: {frequencies}"),
+            )
+        },
+    ));
+    let unknown_waypoints = es_file.unknown_waypoints(aixm);
+    if !unknown_waypoints.is_empty() {
+        if let Err(e) = tx.blocking_send(Message::warn(format!(
+            "{} SID/STAR waypoints in {} not found in AIXM: {}",
+            unknown_waypoints.len(),
+            path.display(),
+            unknown_waypoints
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))) {
+            error!("{e}");
+        }
+    }
+    follow_ups.extend(unknown_waypoints.into_iter().map(|waypoint| {
+        FollowUp::new(
+            FollowUpReason::UnknownWaypoint,
+            Some(&path),
+            "Waypoint",
+            waypoint,
+            "not found in AIXM",
+        )
+    }));
+    if let Some(entities) = es_file.entity_count() {
+        let count = |action: fn(&ChangeAction) -> bool| {
+            changes
+                .iter()
+                .filter(|change| action(&change.action))
+                .count()
+        };
+        let inserted = count(|action| *action == ChangeAction::Added);
+        let candidates = count(|action| *action == ChangeAction::Candidate);
+        let moved = count(|action| matches!(action, ChangeAction::Moved { .. }));
+        let removed = count(|action| *action == ChangeAction::Removed);
+        // candidates and removed entities are not part of the file
+        let unchanged = entities.saturating_sub(inserted + moved);
+        if let Err(e) = tx.blocking_send(Message::info(format!(
+            "{}: {} added, {moved} moved, {removed} removed, {unchanged} unchanged",
+            es_file.path().display(),
+            inserted + candidates
+        ))) {
+            error!("{e}");
+        }
+    }
+    if !changes.is_empty() {
+        if let Err(e) = tx.blocking_send(Message::changes(es_file.path(), changes.clone())) {
+            error!("{e}");
+        }
+    }
+    let mut generated = es_file
+        .generate_auxiliary(aixm)
+        .into_iter()
+        .collect::<Vec<_>>();
+    if let Some(navaid_names) = navaid_names {
+        let (labelled, sidecar) = es_file.add_navaid_names(aixm, navaid_names);
+        if labelled > 0 {
+            if let Err(e) = tx.blocking_send(Message::info(format!(
+                "{}: added or moved {labelled} navaid name labels",
+                es_file.path().display()
+            ))) {
+                error!("{e}");
+            }
+        }
+        generated.extend(sidecar);
+    }
+    cancel.check()?;
+    Ok(Combined {
+        path,
+        files: std::iter::once(es_file).chain(generated).collect(),
+        changes,
+        follow_ups,
+        quality,
+    })
+}
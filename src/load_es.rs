@@ -1,20 +1,207 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use snafu::ResultExt as _;
-use std::path::Path;
-use tokio::{fs::File, io::AsyncReadExt as _, sync::mpsc, task::JoinSet};
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+use tokio::{
+    fs::File,
+    io::AsyncReadExt as _,
+    sync::mpsc,
+    task::{JoinSet, spawn_blocking},
+};
 use tracing::{debug, error};
 use vatsim_parser::isec::parse_isec_txt;
 use vatsim_parser::prf::Prf;
 use vatsim_parser::{ese::Ese, sct::Sct};
 
 use crate::error::{
-    AiracUpdaterResult, OpenEseSnafu, OpenIsecSnafu, OpenPrfSnafu, OpenSctSnafu, ParseEseSnafu,
-    ParseIsecSnafu, ParsePrfSnafu, ParseSctSnafu, ReadEseSnafu, ReadIsecSnafu, ReadPrfSnafu,
-    ReadSctSnafu,
+    AiracUpdaterResult, OpenEseSnafu, OpenIsecSnafu, OpenPrfSnafu, OpenRwySnafu, OpenSctSnafu,
+    ParseEseSnafu, ParseIsecSnafu, ParsePrfSnafu, ParseSctSnafu, ReadEseSnafu, ReadIsecSnafu,
+    ReadPrfSnafu, ReadRwySnafu, ReadSctSnafu,
 };
-use crate::{Message, aixm_combine::EuroscopeFile};
+use crate::{
+    Message,
+    aixm_combine::{EuroscopeFile, passthrough_sections, positions_lines},
+    config::SctWriter,
+    pipeline::Stage,
+    sct_salvage,
+};
+
+static PRF_SECTOR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^settings\s+sector\s+(.+?)\s*$").expect("valid regex"));
+static PRF_ISEC_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?im)^\S+\s+\S+\s+(.*isec\.txt)\s*$").expect("valid regex"));
+
+/// Replaces the `from` prefix of paths in the .prf with `to`, e.g. to process
+/// a package copied from a controller's machine without editing the .prf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PathRemap {
+    /// Prefix as written in the .prf, compared case-insensitively and
+    /// regardless of the separator, e.g. `C:\EuroScope\EDMM`
+    pub from: String,
+    /// Replacement, relative to the .prf directory unless absolute
+    pub to: PathBuf,
+}
+
+impl PathRemap {
+    fn apply(&self, prf_dir: &Path, path: &Path) -> Option<PathBuf> {
+        let normalize = |value: &str| value.replace('\\', "/").to_ascii_lowercase();
+        let from = normalize(self.from.trim_end_matches(['\\', '/']));
+        // foreign absolute paths (`C:\...`) are relative to the .prf elsewhere
+        let original = path.strip_prefix(prf_dir).unwrap_or(path).to_string_lossy();
+        let normalized = normalize(&original);
+        let rest = normalized.strip_prefix(&from)?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+
+        // the rest of the original, to keep its capitalization
+        let rest = original[original.len() - rest.len()..].trim_start_matches(['\\', '/']);
+        Some(resolve_prf_path(&prf_dir.join(&self.to), rest))
+    }
+}
+
+/// Sector file paths referenced by a .prf.
+struct SectorPaths {
+    sct: PathBuf,
+    ese: PathBuf,
+    isec: Option<PathBuf>,
+}
+
+impl SectorPaths {
+    fn from_prf(prf: &Prf) -> Self {
+        Self {
+            sct: prf.sct_path().to_path_buf(),
+            ese: prf.ese_path().to_path_buf(),
+            isec: Some(prf.isec_path().to_path_buf()),
+        }
+    }
+
+    /// Extracts only the sector and isec.txt paths by pattern, for .prf files
+    /// the full parser rejects (unusual key capitalization, unknown settings).
+    fn from_prf_fallback(prf_path: &Path, contents: &[u8]) -> Option<Self> {
+        let contents = String::from_utf8_lossy(contents);
+        let prf_dir = prf_path.parent().unwrap_or(Path::new(""));
+        let sct = resolve_prf_path(prf_dir, &PRF_SECTOR_RE.captures(&contents)?[1]);
+        let isec = PRF_ISEC_RE
+            .captures(&contents)
+            .map(|captures| resolve_prf_path(prf_dir, &captures[1]));
+
+        Some(Self {
+            ese: sct.with_extension("ese"),
+            sct,
+            isec,
+        })
+    }
 
-pub(crate) async fn load_euroscope_files(
+    /// Applies the first matching remap rule to each path.
+    fn remap(&mut self, prf_dir: &Path, remaps: &[PathRemap]) -> Vec<(PathBuf, PathBuf)> {
+        let mut remapped = vec![];
+        for path in [&mut self.sct, &mut self.ese]
+            .into_iter()
+            .chain(self.isec.as_mut())
+        {
+            if let Some(new_path) = remaps.iter().find_map(|remap| remap.apply(prf_dir, path)) {
+                remapped.push((std::mem::replace(path, new_path.clone()), new_path));
+            }
+        }
+        remapped
+    }
+}
+
+/// Resolves a path as written by EuroScope, i.e. absolute or relative to the
+/// .prf directory with backslashes as separators.
+fn resolve_prf_path(prf_dir: &Path, value: &str) -> PathBuf {
+    if Path::new(value).is_absolute() {
+        return PathBuf::from(value);
+    }
+
+    value
+        .split(['\\', '/'])
+        .filter(|component| !component.is_empty())
+        .fold(prf_dir.to_path_buf(), |path, component| {
+            path.join(component)
+        })
+}
+
+/// Sector file referenced by the .prf, `None` if it cannot be read.
+fn prf_sector_file(prf_path: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read(prf_path).ok()?;
+    let paths = match Prf::parse(prf_path, &contents) {
+        Ok(prf) => SectorPaths::from_prf(&prf),
+        Err(_) => SectorPaths::from_prf_fallback(prf_path, &contents)?,
+    };
+    Some(paths.sct)
+}
+
+/// Trailing number of the file name, e.g. `2503` of `EDMM_2503.sct`, with
+/// its number of digits.
+fn sector_version(path: &Path) -> Option<(usize, u64)> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = stem
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .find(|part| !part.is_empty())?;
+    Some((digits.len(), digits.parse().ok()?))
+}
+
+/// Compares the version numbers in the names if both have one of the same
+/// format, otherwise the modification times.
+fn is_newer_sector(candidate: &Path, current: &Path) -> bool {
+    match (sector_version(candidate), sector_version(current)) {
+        (Some((digits, candidate)), Some((current_digits, current)))
+            if digits == current_digits =>
+        {
+            candidate > current
+        }
+        _ => {
+            let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+            matches!(
+                (modified(candidate), modified(current)),
+                (Some(candidate), Some(current)) if candidate > current
+            )
+        }
+    }
+}
+
+/// Another .prf in the folder of `prf_path` and its sector file, if that looks
+/// newer than the one of `prf_path`, i.e. an outdated profile of the package
+/// may have been picked.
+pub fn newer_sibling_sector(prf_path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let own = prf_sector_file(prf_path)?;
+    let dir = prf_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name() != prf_path.file_name()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("prf"))
+        })
+        .filter_map(|prf| Some((prf_sector_file(&prf)?, prf)))
+        .filter(|(sct, _)| *sct != own && sct.is_file() && is_newer_sector(sct, &own))
+        .reduce(|newest, candidate| {
+            if is_newer_sector(&candidate.0, &newest.0) {
+                candidate
+            } else {
+                newest
+            }
+        })
+        .map(|(sct, prf)| (prf, sct))
+}
+
+pub async fn load_euroscope_files(
     prf_path: &Path,
+    remaps: &[PathRemap],
+    passthrough: &[String],
+    salvage: bool,
     tx: mpsc::Sender<Message>,
 ) -> AiracUpdaterResult<Vec<EuroscopeFile>> {
     let mut prf_contents = vec![];
@@ -24,12 +211,61 @@ pub(crate) async fn load_euroscope_files(
         .read_to_end(&mut prf_contents)
         .await
         .context(ReadPrfSnafu { filename: prf_path })?;
-    let prf = Prf::parse(prf_path, &prf_contents).context(ParsePrfSnafu { filename: prf_path })?;
+    let mut paths = match Prf::parse(prf_path, &prf_contents) {
+        Ok(prf) => SectorPaths::from_prf(&prf),
+        Err(source) => {
+            let Some(paths) = SectorPaths::from_prf_fallback(prf_path, &prf_contents) else {
+                return Err(source).context(ParsePrfSnafu { filename: prf_path });
+            };
+            tx.send(Message::warn(format!(
+                "Could not fully parse .prf ({source}), only using the sector file paths: {}",
+                paths.sct.display()
+            )))
+            .await?;
+            paths
+        }
+    };
+    let prf_dir = prf_path.parent().unwrap_or(Path::new(""));
+    for (from, to) in paths.remap(prf_dir, remaps) {
+        tx.send(Message::info(format!(
+            "Remapped {} to {}",
+            from.display(),
+            to.display()
+        )))
+        .await?;
+    }
     let mut join_handle = JoinSet::new();
 
-    join_handle.spawn(handle_sct(prf.sct_path(), tx.clone()));
-    join_handle.spawn(handle_ese(prf.ese_path(), tx.clone()));
-    join_handle.spawn(handle_isec(prf.isec_path(), tx.clone()));
+    join_handle.spawn(track_failure(
+        paths.sct.clone(),
+        handle_sct(paths.sct.clone(), passthrough.to_vec(), salvage, tx.clone()),
+        tx.clone(),
+    ));
+    join_handle.spawn(track_failure(
+        paths.ese.clone(),
+        handle_ese(paths.ese, tx.clone()),
+        tx.clone(),
+    ));
+    if let Some(isec_path) = paths.isec {
+        join_handle.spawn(track_failure(
+            isec_path.clone(),
+            handle_isec(isec_path, tx.clone()),
+            tx.clone(),
+        ));
+    } else {
+        tx.send(Message::warn(
+            "No isec.txt found in .prf, skipping isec.txt".to_string(),
+        ))
+        .await?;
+    }
+    let rwy_path = paths.sct.with_extension("rwy");
+    if rwy_path.exists() {
+        join_handle.spawn(track_failure(
+            rwy_path.clone(),
+            handle_rwy(rwy_path, tx.clone()),
+            tx.clone(),
+        ));
+    }
 
     Ok(join_handle
         .join_all()
@@ -37,7 +273,7 @@ pub(crate) async fn load_euroscope_files(
         .into_iter()
         .filter_map(|res| match res {
             Err(e) => {
-                if let Err(e) = tx.blocking_send(Message::error(e.to_string())) {
+                if let Err(e) = tx.blocking_send(Message::failure(&e)) {
                     error!("{e}");
                 }
                 None
@@ -47,6 +283,21 @@ pub(crate) async fn load_euroscope_files(
         .collect())
 }
 
+async fn track_failure(
+    filename: PathBuf,
+    load: impl Future<Output = AiracUpdaterResult<EuroscopeFile>>,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<EuroscopeFile> {
+    tx.send(Message::stage(filename.display(), Stage::Parsing))
+        .await?;
+    let result = load.await;
+    if result.is_err() {
+        tx.send(Message::stage(filename.display(), Stage::Failed))
+            .await?;
+    }
+    result
+}
+
 async fn handle_ese(
     filename: impl AsRef<Path>,
     tx: mpsc::Sender<Message>,
@@ -81,11 +332,16 @@ async fn handle_ese(
     Ok(EuroscopeFile::Ese {
         path: filename.to_path_buf(),
         content: Box::new(ese),
+        positions: positions_lines(&buf),
+        raw: buf,
+        changed: false,
     })
 }
 
 async fn handle_sct(
     filename: impl AsRef<Path>,
+    passthrough: Vec<String>,
+    salvage: bool,
     tx: mpsc::Sender<Message>,
 ) -> AiracUpdaterResult<EuroscopeFile> {
     let filename = filename.as_ref();
@@ -110,7 +366,36 @@ async fn handle_sct(
         filename.display()
     )))
     .await?;
-    let sct = Sct::parse(&buf).context(ParseSctSnafu { filename })?;
+    let original = String::from_utf8_lossy(&buf).into_owned();
+    let (sct, salvaged) = match Sct::parse(&buf) {
+        Ok(sct) => (sct, vec![]),
+        Err(source) if salvage => {
+            tx.send(Message::warn(format!(
+                "Could not parse .sct ({}): {source}, isolating the malformed lines",
+                filename.display()
+            )))
+            .await?;
+            let salvaged = spawn_blocking({
+                let original = original.clone();
+                move || sct_salvage::salvage(&original)
+            })
+            .await?;
+            let Some((sct, salvaged)) = salvaged else {
+                return Err(source).context(ParseSctSnafu { filename });
+            };
+            for &line in &salvaged {
+                tx.send(Message::warn(format!(
+                    "{}:{}: malformed line kept as it is: {}",
+                    filename.display(),
+                    line + 1,
+                    original.lines().nth(line).unwrap_or_default().trim_end()
+                )))
+                .await?;
+            }
+            (sct, salvaged)
+        }
+        Err(source) => return Err(source).context(ParseSctSnafu { filename }),
+    };
     tx.send(Message::info(format!(
         "Parsing .sct complete: {} ({})",
         filename.display(),
@@ -118,9 +403,26 @@ async fn handle_sct(
     )))
     .await?;
 
+    let (passthrough, dropped) = passthrough_sections(&original, &passthrough);
+    if !dropped.is_empty() {
+        tx.send(Message::warn(format!(
+            "Sections of {} unknown to the parser are lost when it is written, unless listed in passthrough-sections: {}",
+            filename.display(),
+            dropped.join(", ")
+        )))
+        .await?;
+    }
+
     Ok(EuroscopeFile::Sct {
         path: filename.to_path_buf(),
         content: Box::new(sct),
+        candidates: vec![],
+        sections: vec![],
+        passthrough,
+        removed: vec![],
+        original,
+        writer: SctWriter::default(),
+        salvaged,
     })
 }
 
@@ -151,6 +453,7 @@ async fn handle_isec(
     )))
     .await?;
     let isec = parse_isec_txt(&buf).context(ParseIsecSnafu { filename })?;
+    let original = String::from_utf8_lossy(&buf).into_owned();
     tx.send(Message::info(format!(
         "Parsing isec.txt complete: {}",
         filename.display(),
@@ -160,5 +463,34 @@ async fn handle_isec(
     Ok(EuroscopeFile::Isec {
         path: filename.to_path_buf(),
         content: Box::new(isec),
+        original,
+    })
+}
+
+async fn handle_rwy(
+    filename: impl AsRef<Path>,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<EuroscopeFile> {
+    let filename = filename.as_ref();
+    let mut buf = vec![];
+
+    debug!("Opening .rwy: {}", filename.display());
+    let mut f = File::open(filename)
+        .await
+        .context(OpenRwySnafu { filename })?;
+
+    tx.send(Message::info(format!(
+        "Reading .rwy: {}",
+        filename.display()
+    )))
+    .await?;
+    f.read_to_end(&mut buf)
+        .await
+        .context(ReadRwySnafu { filename })?;
+
+    Ok(EuroscopeFile::Rwy {
+        path: filename.to_path_buf(),
+        content: buf,
+        changed: false,
     })
 }
@@ -0,0 +1,98 @@
+//! Times and numbers as shown in the GUI, in the locale and time zone of the
+//! user. Files, logs and reports keep UTC and plain numbers.
+
+use chrono::{DateTime, Local, Locale, NaiveDateTime, Utc};
+
+/// Languages writing `1.234,5` instead of `1,234.5`
+const COMMA_DECIMAL_LANGUAGES: &[&str] = &[
+    "cs", "da", "de", "el", "es", "fi", "fr", "hr", "hu", "it", "nb", "nl", "nn", "pl", "pt", "ro",
+    "ru", "sk", "sl", "sv", "tr",
+];
+/// Of those, the ones grouping thousands with a space instead of a dot
+const SPACE_GROUPING_LANGUAGES: &[&str] =
+    &["cs", "fi", "fr", "hu", "nb", "nn", "pl", "ru", "sk", "sv"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayLocale {
+    locale: Locale,
+    decimal_separator: char,
+    thousands_separator: char,
+}
+
+impl Default for DisplayLocale {
+    fn default() -> Self {
+        Self::new("en-US")
+    }
+}
+
+impl DisplayLocale {
+    /// Locale of a BCP 47 language tag, e.g. `de-DE`, falling back to the
+    /// language alone and then to POSIX for unknown ones.
+    pub fn new(tag: &str) -> Self {
+        let name = tag.split('.').next().unwrap_or_default().replace('-', "_");
+        let language = name
+            .split('_')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let locale = Locale::try_from(name.as_str())
+            .or_else(|_| Locale::try_from(language.as_str()))
+            .unwrap_or(Locale::POSIX);
+        let (decimal_separator, thousands_separator) =
+            if !COMMA_DECIMAL_LANGUAGES.contains(&language.as_str()) {
+                ('.', ',')
+            } else if SPACE_GROUPING_LANGUAGES.contains(&language.as_str()) {
+                (',', '\u{202f}')
+            } else {
+                (',', '.')
+            };
+        Self {
+            locale,
+            decimal_separator,
+            thousands_separator,
+        }
+    }
+
+    /// Locale of the user, the default one if it cannot be determined.
+    pub fn detect() -> Self {
+        sys_locale::get_locale().map_or_else(Self::default, |tag| Self::new(&tag))
+    }
+
+    /// Date and time in the local time zone
+    pub fn date_time(&self, time: DateTime<Utc>) -> String {
+        time.with_timezone(&Local)
+            .format_localized("%x %X", self.locale)
+            .to_string()
+    }
+
+    /// Date and time of a UTC time without zone, e.g. of a backup file name,
+    /// in the local time zone
+    pub fn naive_date_time(&self, time: NaiveDateTime) -> String {
+        self.date_time(time.and_utc())
+    }
+
+    /// `value` rounded to `decimals`, with grouped thousands
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.decimals$}", value.abs());
+        let (integer, fraction) = formatted
+            .split_once('.')
+            .map_or((formatted.as_str(), None), |(integer, fraction)| {
+                (integer, Some(fraction))
+            });
+        let mut grouped = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push(self.thousands_separator);
+            }
+            grouped.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            grouped.push(self.decimal_separator);
+            grouped.push_str(fraction);
+        }
+        if value.is_sign_negative() && formatted.chars().any(|digit| digit != '0' && digit != '.') {
+            grouped.insert(0, '-');
+        }
+        grouped
+    }
+}
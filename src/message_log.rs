@@ -0,0 +1,68 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use chrono::SecondsFormat;
+use serde::Serialize;
+use snafu::ResultExt as _;
+use uuid::Uuid;
+
+use crate::{
+    Message,
+    error::{AiracUpdaterResult, ErrorContext, OpenMessageLogSnafu, WriteMessageLogSnafu},
+};
+
+#[derive(Debug, Serialize)]
+struct LogLine<'a> {
+    time: String,
+    level: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a ErrorContext>,
+}
+
+/// Appends every message of a run as a JSON line, e.g. for log collection on
+/// unattended machines. Lines are tagged with the unique id of the current
+/// run, see [`Self::set_run`].
+#[derive(Debug)]
+pub struct MessageLog {
+    path: PathBuf,
+    file: File,
+    run: Option<Uuid>,
+}
+
+impl MessageLog {
+    pub fn open(path: &Path) -> AiracUpdaterResult<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(OpenMessageLogSnafu { path })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            run: None,
+        })
+    }
+
+    pub fn set_run(&mut self, run: Uuid) {
+        self.run = Some(run);
+    }
+
+    pub fn write(&mut self, msg: &Message) -> AiracUpdaterResult {
+        let line = LogLine {
+            time: msg.time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            level: msg.level.as_str(),
+            message: &msg.content,
+            run: self.run,
+            error: msg.error.as_ref(),
+        };
+        // serializing plain strings and options cannot fail
+        let json = serde_json::to_string(&line).unwrap_or_default();
+        writeln!(self.file, "{json}").context(WriteMessageLogSnafu { path: &self.path })
+    }
+}
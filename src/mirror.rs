@@ -0,0 +1,230 @@
+use std::{
+    net::SocketAddr,
+    path::{Component, Path, PathBuf},
+};
+
+use serde_json::Value;
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::{
+    fs,
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::spawn_blocking,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    Message,
+    aixm::fetch_dataset,
+    aixm_dfs::{AixmRelease, DfsAmdts, dfs_base_url, fetch_dfs_datasets_raw, get_dataset_path},
+    aixm_parse::check_well_formed,
+    error::{
+        AcceptMirrorSnafu, AiracUpdaterResult, BindMirrorSnafu, CreateMirrorDirSnafu,
+        DatasetNotFoundSnafu, DeserializeDfsDatasetsSnafu, SerializeDatasetListSnafu,
+        WriteMirrorSnafu,
+    },
+};
+
+const MIRROR_PREFIX: &str = "/datasets/rest/";
+const INDEX_FILE: &str = "index.json";
+
+/// Downloads the current datasets into `dir`, in the layout served by
+/// [`serve_mirror`], together with the DFS dataset list reduced to what was
+/// mirrored. Datasets mirrored before are only downloaded again if they are
+/// not well-formed.
+pub async fn sync_mirror(
+    dir: &Path,
+    datasets: &[String],
+    release: AixmRelease,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let raw_index = fetch_dfs_datasets_raw().await?;
+    let amdts: DfsAmdts = serde_json::from_str(&raw_index).context(DeserializeDfsDatasetsSnafu)?;
+
+    for dataset in datasets {
+        let dataset_path =
+            get_dataset_path(&amdts, 0, dataset, release).context(DatasetNotFoundSnafu {
+                dataset: dataset.clone(),
+            })?;
+        let target = dir.join(&dataset_path);
+        if let Ok(existing) = fs::read(&target).await {
+            match spawn_blocking(move || check_well_formed(&existing)).await? {
+                Ok(()) => {
+                    info!("Already mirrored: {dataset} ({})", target.display());
+                    continue;
+                }
+                Err(reason) => warn!(
+                    "Mirrored {dataset} ({}) is broken, downloading it again: {reason}",
+                    target.display()
+                ),
+            }
+        }
+
+        info!("Mirroring {dataset} to {}", target.display());
+        let url = format!("{}{dataset_path}", dfs_base_url());
+        let data = fetch_dataset(url, dataset, tx.clone()).await?;
+        write_atomic(&target, &data).await?;
+    }
+
+    let index = mirrored_index(&raw_index, datasets, release)?;
+    write_atomic(&dir.join(INDEX_FILE), index.as_bytes()).await
+}
+
+/// Writes next to `path` first, so an interrupted sync never leaves a
+/// truncated dataset behind.
+async fn write_atomic(path: &Path, data: &[u8]) -> AiracUpdaterResult {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .context(CreateMirrorDirSnafu { path: parent })?;
+    }
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    fs::write(&partial, data)
+        .await
+        .context(WriteMirrorSnafu { path: &partial })?;
+    fs::rename(&partial, path)
+        .await
+        .context(WriteMirrorSnafu { path })
+}
+
+/// The DFS dataset list with amendment 0 only, and in it only the `release`
+/// of `datasets`, so clients never select anything the mirror does not have.
+fn mirrored_index(
+    raw_index: &str,
+    datasets: &[String],
+    release: AixmRelease,
+) -> AiracUpdaterResult<String> {
+    /// Keeps the mirrored leaves of a group or leaf, `false` if nothing is
+    /// left of it.
+    fn retain_mirrored(item: &mut Value, datasets: &[String], release_type: &str) -> bool {
+        match item.get("type").and_then(Value::as_str) {
+            Some("group") => match item.get_mut("items").and_then(Value::as_array_mut) {
+                Some(items) => {
+                    items.retain_mut(|item| retain_mirrored(item, datasets, release_type));
+                    !items.is_empty()
+                }
+                None => false,
+            },
+            Some("leaf") => {
+                let is_mirrored = item
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .is_some_and(|name| datasets.iter().any(|dataset| dataset == name));
+                match item.get_mut("releases").and_then(Value::as_array_mut) {
+                    Some(releases) if is_mirrored => {
+                        releases.retain(|release| {
+                            release.get("type").and_then(Value::as_str) == Some(release_type)
+                        });
+                        !releases.is_empty()
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    let mut index: Value = serde_json::from_str(raw_index).context(DeserializeDfsDatasetsSnafu)?;
+    if let Some(amdts) = index.get_mut("Amdts").and_then(Value::as_array_mut) {
+        amdts.retain(|amdt| amdt.get("Amdt").and_then(Value::as_u64) == Some(0));
+        for amdt in amdts {
+            if let Some(items) = amdt
+                .pointer_mut("/Metadata/datasets")
+                .and_then(Value::as_array_mut)
+            {
+                items.retain_mut(|item| retain_mirrored(item, datasets, release.release_type()));
+            }
+        }
+    }
+    serde_json::to_string(&index).context(SerializeDatasetListSnafu)
+}
+
+/// Serves `dir` in the same REST shape as aip.dfs.de, so clients can use it by
+/// setting `AIRAC_UPDATER_DFS_URL=http://<host>:<port>/datasets/rest/`.
+pub async fn serve_mirror(dir: PathBuf, bind: SocketAddr) -> AiracUpdaterResult {
+    let listener = TcpListener::bind(bind)
+        .await
+        .context(BindMirrorSnafu { addr: bind })?;
+    info!(
+        "Serving AIXM mirror of {} on http://{bind}{MIRROR_PREFIX}",
+        dir.display()
+    );
+
+    loop {
+        let (stream, peer) = listener.accept().await.context(AcceptMirrorSnafu)?;
+        let dir = dir.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &dir).await {
+                warn!("Mirror request from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+fn resolve_request_path(dir: &Path, target: &str) -> Option<PathBuf> {
+    let target = target.split(['?', '#']).next()?;
+    let relative = target.strip_prefix(MIRROR_PREFIX)?;
+    if relative.is_empty() {
+        return Some(dir.join(INDEX_FILE));
+    }
+
+    let relative = Path::new(relative);
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then(|| dir.join(relative))
+}
+
+async fn handle_connection(stream: TcpStream, dir: &Path) -> std::io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    loop {
+        let mut header = String::new();
+        if stream.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => {
+            debug!("Mirror request: {target}");
+            match resolve_request_path(dir, target) {
+                Some(path) => match fs::read(&path).await {
+                    Ok(body) => {
+                        let content_type = if path.extension().is_some_and(|ext| ext == "json") {
+                            "application/json"
+                        } else {
+                            "application/xml"
+                        };
+                        ("200 OK", content_type, body)
+                    }
+                    Err(_) => ("404 Not Found", "text/plain", b"not found".to_vec()),
+                },
+                None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+            }
+        }
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            b"method not allowed".to_vec(),
+        ),
+    };
+
+    let stream = stream.get_mut();
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .as_bytes(),
+        )
+        .await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await
+}
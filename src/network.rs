@@ -0,0 +1,102 @@
+use std::{fs::OpenOptions, io::Write as _, path::PathBuf, sync::OnceLock, time::Duration};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt as _, ensure};
+use tracing::debug;
+
+use crate::error::{AiracUpdaterResult, HostNotAllowedSnafu, WriteAuditLogSnafu};
+
+/// Restrictions for outbound HTTP, e.g. on locked-down ANSP machines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct NetworkConfig {
+    /// Hosts requests may go to, any if unset
+    pub allowed_hosts: Option<Vec<String>>,
+    /// File every request URL is appended to
+    pub audit_log: Option<PathBuf>,
+    pub retry: RetryConfig,
+}
+
+/// Retries of dataset downloads failing with a connection or server error,
+/// waiting twice as long before every further attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RetryConfig {
+    /// Attempts per download, `1` to never retry
+    pub attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 4,
+            initial_delay_ms: 2000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay after the `attempt`th failed attempt, starting at `1`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1_u64 << attempt.saturating_sub(1).min(16);
+        Duration::from_millis(
+            self.initial_delay_ms
+                .saturating_mul(factor)
+                .min(self.max_delay_ms),
+        )
+    }
+}
+
+static NETWORK: OnceLock<NetworkConfig> = OnceLock::new();
+
+/// Sets the restrictions for the rest of the process, before any request.
+pub fn init(config: NetworkConfig) {
+    if NETWORK.set(config).is_err() {
+        debug!("network config already set");
+    }
+}
+
+/// Retries of the downloads, as configured by [`init`].
+pub fn retry() -> RetryConfig {
+    NETWORK.get_or_init(NetworkConfig::default).retry
+}
+
+/// Checks the host of `url` against the allowlist and records the request in
+/// the audit log, to be called before every outbound request.
+pub fn check_request(url: &str) -> AiracUpdaterResult {
+    let config = NETWORK.get_or_init(NetworkConfig::default);
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string));
+    let allowed = config.allowed_hosts.as_ref().is_none_or(|allowed_hosts| {
+        host.as_ref().is_some_and(|host| {
+            allowed_hosts
+                .iter()
+                .any(|allowed_host| allowed_host.eq_ignore_ascii_case(host))
+        })
+    });
+    let verdict = if allowed { "allowed" } else { "denied" };
+
+    debug!("Request {verdict}: {url}");
+    if let Some(audit_log) = &config.audit_log {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(audit_log)
+            .and_then(|mut file| {
+                writeln!(
+                    file,
+                    "{} {verdict} {url}",
+                    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+                )
+            })
+            .context(WriteAuditLogSnafu { path: audit_log })?;
+    }
+    ensure!(allowed, HostNotAllowedSnafu { url });
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Writers turning the combined files back into bytes. The built-in ones cover
+//! the files read from the package (.sct, .ese, isec.txt, .rwy) and the TopSky
+//! maps generated next to the .sct; writers of
+//! other crates are added with [`register`] before [`spawn_jobs`] runs, and
+//! take precedence over the built-in ones for the files they match.
+//!
+//! [`spawn_jobs`]: crate::spawn_jobs
+
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use snafu::ensure;
+use tracing::error;
+
+use crate::{
+    aixm_combine::EuroscopeFile,
+    error::{AiracUpdaterResult, IncompatibleOutputWriterSnafu},
+};
+
+/// Version of the [`OutputWriter`] interface. The major version changes with
+/// every breaking change, the minor one with additions writers may rely on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ApiVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl ApiVersion {
+    /// Whether a writer built against `self` works with `current`
+    pub fn is_compatible_with(self, current: Self) -> bool {
+        self.major == current.major && self.minor <= current.minor
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+pub const OUTPUT_WRITER_API: ApiVersion = ApiVersion { major: 1, minor: 0 };
+
+pub trait OutputWriter: Send + Sync {
+    /// Name shown in the log
+    fn name(&self) -> &str;
+
+    /// Interface version the writer was built against, usually
+    /// [`OUTPUT_WRITER_API`] of the version of this crate it depends on.
+    fn api_version(&self) -> ApiVersion;
+
+    /// Whether the writer handles the file at `path`
+    fn matches(&self, path: &Path) -> bool;
+
+    /// Content of the combined file, `None` to leave the file untouched.
+    fn write(&self, file: &EuroscopeFile) -> Option<Vec<u8>>;
+}
+
+/// Built-in writer of the files with one of `extensions` (or file names, for
+/// `isec.txt`), rendered by [`EuroscopeFile::render`].
+struct BuiltinWriter {
+    name: &'static str,
+    extensions: &'static [&'static str],
+}
+
+impl OutputWriter for BuiltinWriter {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn api_version(&self) -> ApiVersion {
+        OUTPUT_WRITER_API
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        self.extensions
+            .iter()
+            .any(|extension| file_name.ends_with(extension))
+    }
+
+    fn write(&self, file: &EuroscopeFile) -> Option<Vec<u8>> {
+        file.render()
+    }
+}
+
+const BUILTIN_WRITERS: [BuiltinWriter; 5] = [
+    BuiltinWriter {
+        name: "sct",
+        extensions: &[".sct"],
+    },
+    BuiltinWriter {
+        name: "ese",
+        extensions: &[".ese"],
+    },
+    BuiltinWriter {
+        name: "isec",
+        extensions: &["isec.txt"],
+    },
+    BuiltinWriter {
+        name: "rwy",
+        extensions: &[".rwy"],
+    },
+    BuiltinWriter {
+        name: "topsky",
+        extensions: &[".aau_topsky_maps.txt"],
+    },
+];
+
+static REGISTERED: RwLock<Vec<Arc<dyn OutputWriter>>> = RwLock::new(vec![]);
+
+/// Adds a writer for the rest of the process, checked before the ones
+/// registered earlier and the built-in ones.
+pub fn register(writer: impl OutputWriter + 'static) -> AiracUpdaterResult {
+    ensure!(
+        writer.api_version().is_compatible_with(OUTPUT_WRITER_API),
+        IncompatibleOutputWriterSnafu {
+            name: writer.name(),
+            version: writer.api_version().to_string(),
+            current: OUTPUT_WRITER_API.to_string(),
+        }
+    );
+    match REGISTERED.write() {
+        Ok(mut registered) => registered.insert(0, Arc::new(writer)),
+        Err(e) => error!("{e}"),
+    }
+    Ok(())
+}
+
+/// Names of the registered writers followed by the built-in ones.
+pub fn writer_names() -> Vec<String> {
+    let registered = REGISTERED
+        .read()
+        .map(|registered| {
+            registered
+                .iter()
+                .map(|writer| writer.name().to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    registered
+        .into_iter()
+        .chain(BUILTIN_WRITERS.iter().map(|writer| writer.name.to_string()))
+        .collect()
+}
+
+/// Content of `file` by the first writer matching its path. Generated files
+/// are already rendered, and files no writer matches keep the built-in
+/// rendering.
+pub fn serialize(file: &EuroscopeFile) -> Option<Vec<u8>> {
+    if let EuroscopeFile::Generated { content, .. } = file {
+        return Some(content.clone());
+    }
+    let path = file.path();
+    let registered = REGISTERED.read().ok().and_then(|registered| {
+        registered
+            .iter()
+            .find(|writer| writer.matches(path))
+            .cloned()
+    });
+    if let Some(writer) = registered {
+        return writer.write(file);
+    }
+    BUILTIN_WRITERS
+        .iter()
+        .find(|writer| writer.matches(path))
+        .map_or_else(|| file.render(), |writer| writer.write(file))
+}
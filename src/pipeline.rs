@@ -0,0 +1,103 @@
+use std::fmt;
+
+#[cfg(feature = "gui")]
+use egui::{Grid, RichText, Ui};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Queued,
+    Downloading,
+    Parsing,
+    Combining,
+    Writing,
+    Done,
+    Failed,
+}
+
+#[cfg(feature = "gui")]
+const FLOW: &[Stage] = &[
+    Stage::Queued,
+    Stage::Downloading,
+    Stage::Parsing,
+    Stage::Combining,
+    Stage::Writing,
+    Stage::Done,
+];
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Queued => write!(f, "queued"),
+            Stage::Downloading => write!(f, "downloading"),
+            Stage::Parsing => write!(f, "parsing"),
+            Stage::Combining => write!(f, "combining"),
+            Stage::Writing => write!(f, "writing"),
+            Stage::Done => write!(f, "done"),
+            Stage::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Current stage per dataset and file, in the order they first appeared.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    items: Vec<(String, Stage)>,
+}
+
+impl Pipeline {
+    pub fn update(&mut self, item: &str, stage: Stage) {
+        if let Some((_, current)) = self.items.iter_mut().find(|(name, _)| name == item) {
+            *current = stage;
+        } else {
+            self.items.push((item.to_string(), stage));
+        }
+    }
+
+    /// Items that ended up failed.
+    pub fn failed(&self) -> impl Iterator<Item = &str> {
+        self.items
+            .iter()
+            .filter(|(_, stage)| *stage == Stage::Failed)
+            .map(|(item, _)| item.as_str())
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(feature = "gui")]
+pub fn show_pipeline(ui: &mut Ui, pipeline: &Pipeline) {
+    let visuals = ui.style().visuals.clone();
+    Grid::new("pipeline").striped(true).show(ui, |ui| {
+        for (item, stage) in &pipeline.items {
+            ui.monospace(item);
+            ui.horizontal(|ui| {
+                if *stage == Stage::Failed {
+                    ui.label(RichText::new(stage.to_string()).color(visuals.error_fg_color));
+                    return;
+                }
+                for (i, flow_stage) in FLOW.iter().enumerate() {
+                    if i > 0 {
+                        ui.weak("→");
+                    }
+                    let text = RichText::new(flow_stage.to_string());
+                    ui.label(match flow_stage.cmp(stage) {
+                        std::cmp::Ordering::Less => text,
+                        std::cmp::Ordering::Equal => text.strong().color(visuals.hyperlink_color),
+                        std::cmp::Ordering::Greater => text.weak(),
+                    });
+                }
+            });
+            ui.end_row();
+        }
+    });
+}
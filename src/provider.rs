@@ -0,0 +1,310 @@
+use std::{collections::BTreeMap, future::Future, sync::LazyLock};
+
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::sync::mpsc;
+use tracing::trace;
+
+use crate::{
+    Message,
+    airac::AiracCycle,
+    aixm::{AixmDataset, load_aixm_files},
+    aixm_dfs::{AixmRelease, fetch_dfs_datasets, get_dataset_url},
+    error::{AiracUpdaterResult, DatasetNotFoundSnafu, FetchDatasetIndexSnafu},
+    network,
+};
+
+/// Amendment to load the datasets from, `0` being the current one, unless
+/// overridden for a dataset, e.g. to take a known bad publication from
+/// another amendment.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Amendments {
+    pub amdt: u32,
+    pub overrides: BTreeMap<String, u32>,
+}
+
+impl Amendments {
+    #[must_use]
+    pub fn with_overrides(mut self, overrides: BTreeMap<String, u32>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Amendment of `dataset`
+    pub fn of(&self, dataset: &str) -> u32 {
+        self.overrides.get(dataset).copied().unwrap_or(self.amdt)
+    }
+
+    /// The datasets grouped by their amendment, to resolve their URLs per
+    /// amendment.
+    pub fn group(&self, datasets: &[String]) -> BTreeMap<u32, Vec<String>> {
+        let mut groups = BTreeMap::<u32, Vec<String>>::new();
+        for dataset in datasets {
+            groups
+                .entry(self.of(dataset))
+                .or_default()
+                .push(dataset.clone());
+        }
+        groups
+    }
+}
+
+impl From<u32> for Amendments {
+    fn from(amdt: u32) -> Self {
+        Self {
+            amdt,
+            overrides: BTreeMap::new(),
+        }
+    }
+}
+
+/// Source of the AIXM datasets of an amendment.
+pub trait DatasetProvider {
+    /// Name shown in the log and UI
+    fn name(&self) -> &str;
+
+    /// Download URLs of `datasets` (name and URL) for amendment `amdt`, `0`
+    /// being the current one.
+    fn dataset_urls(
+        &self,
+        amdt: u32,
+        datasets: &[String],
+    ) -> impl Future<Output = AiracUpdaterResult<Vec<(String, String)>>> + Send;
+}
+
+/// DFS datasets listed by aip.dfs.de, or a mirror of it, see
+/// [`dfs_base_url`](crate::aixm_dfs::dfs_base_url).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dfs {
+    pub release: AixmRelease,
+}
+
+impl DatasetProvider for Dfs {
+    fn name(&self) -> &str {
+        "DFS"
+    }
+
+    async fn dataset_urls(
+        &self,
+        amdt: u32,
+        datasets: &[String],
+    ) -> AiracUpdaterResult<Vec<(String, String)>> {
+        let metadata = fetch_dfs_datasets().await?;
+        datasets
+            .iter()
+            .map(|dataset| {
+                let url = get_dataset_url(&metadata, amdt, dataset, self.release)
+                    .context(DatasetNotFoundSnafu { dataset })?;
+                Ok((dataset.clone(), url))
+            })
+            .collect()
+    }
+}
+
+/// AIXM 5.1 exports of another AIS published under a fixed URL scheme, given
+/// entirely by the user: there are no presets, neither URLs nor dataset names
+/// of a particular AIS are known. `{dataset}` and `{airac}` (e.g. `2503`) in
+/// `url` are replaced, amendment `n` being the `n`th cycle after the current
+/// one.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UrlTemplate {
+    pub name: String,
+    pub url: String,
+}
+
+impl DatasetProvider for UrlTemplate {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn dataset_urls(
+        &self,
+        amdt: u32,
+        datasets: &[String],
+    ) -> AiracUpdaterResult<Vec<(String, String)>> {
+        let airac = AiracCycle::current().offset(i64::from(amdt)).ident();
+        Ok(datasets
+            .iter()
+            .map(|dataset| {
+                (
+                    dataset.clone(),
+                    self.url
+                        .replace("{airac}", &airac)
+                        .replace("{dataset}", dataset),
+                )
+            })
+            .collect())
+    }
+}
+
+const AUSTRO_CONTROL_INDEX_URL: &str = "https://eaip.austrocontrol.at/aixm/";
+
+/// Links on an index page.
+static HREF_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"href\s*=\s*["']([^"'#?]+)"#).expect("valid regex"));
+
+/// AIXM exports an AIS lists on an index page, e.g. the directory listing of
+/// its download server. The export of a dataset is the link whose file name
+/// contains the dataset name and the effective date of the cycle, e.g.
+/// `LO_Navaids_2025-03-20.xml` or `LKAA-Navaids-20250320.zip` for `Navaids`,
+/// amendment `n` being the `n`th cycle after the current one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AisIndex {
+    pub name: String,
+    pub index_url: String,
+}
+
+impl AisIndex {
+    /// Austro Control, publishing the AIXM exports of LOVV. The index can be
+    /// pointed to a mirror via `AIRAC_UPDATER_ACG_URL`.
+    pub fn austro_control() -> Self {
+        Self {
+            name: "Austro Control".to_string(),
+            index_url: std::env::var("AIRAC_UPDATER_ACG_URL")
+                .unwrap_or_else(|_| AUSTRO_CONTROL_INDEX_URL.to_string()),
+        }
+    }
+
+    async fn fetch_links(&self) -> AiracUpdaterResult<Vec<String>> {
+        let url = &self.index_url;
+        network::check_request(url)?;
+        let page = reqwest::get(url)
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context(FetchDatasetIndexSnafu { url })?
+            .text()
+            .await
+            .context(FetchDatasetIndexSnafu { url })?;
+        trace!("{page}");
+        Ok(links(url, &page))
+    }
+}
+
+impl Default for AisIndex {
+    fn default() -> Self {
+        Self::austro_control()
+    }
+}
+
+/// Absolute URLs of the links on the page at `url`.
+fn links(url: &str, page: &str) -> Vec<String> {
+    let Ok(base) = Url::parse(url) else {
+        return vec![];
+    };
+    HREF_RE
+        .captures_iter(page)
+        .filter_map(|captures| base.join(&captures[1]).ok())
+        .map(String::from)
+        .collect()
+}
+
+/// Export of `dataset` effective with `cycle` among `links`, the last one
+/// by file name if there are several, e.g. revisions.
+fn find_export<'a>(links: &'a [String], dataset: &str, cycle: AiracCycle) -> Option<&'a String> {
+    let normalize = |name: &str| name.to_lowercase().replace(['_', '-', ' '], "");
+    let dataset = normalize(dataset);
+    let effective = cycle.effective();
+    let dates = [
+        effective.format("%Y-%m-%d").to_string(),
+        effective.format("%Y%m%d").to_string(),
+    ];
+    links
+        .iter()
+        .filter_map(|link| Some((link, link.rsplit('/').next()?)))
+        .filter(|(_, file_name)| {
+            let lower = file_name.to_lowercase();
+            (lower.ends_with(".xml") || lower.ends_with(".zip"))
+                && normalize(file_name).contains(&dataset)
+                && dates.iter().any(|date| file_name.contains(date.as_str()))
+        })
+        .max_by_key(|(_, file_name)| *file_name)
+        .map(|(link, _)| link)
+}
+
+impl DatasetProvider for AisIndex {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn dataset_urls(
+        &self,
+        amdt: u32,
+        datasets: &[String],
+    ) -> AiracUpdaterResult<Vec<(String, String)>> {
+        let cycle = AiracCycle::current().offset(i64::from(amdt));
+        let links = self.fetch_links().await?;
+        datasets
+            .iter()
+            .map(|dataset| {
+                let url = find_export(&links, dataset, cycle)
+                    .context(DatasetNotFoundSnafu { dataset })?;
+                Ok((dataset.clone(), url.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Provider of a profile.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum DataProvider {
+    #[default]
+    Dfs,
+    Url(UrlTemplate),
+    Index(AisIndex),
+}
+
+impl DataProvider {
+    pub fn name(&self) -> &str {
+        match self {
+            DataProvider::Dfs => Dfs::default().name(),
+            DataProvider::Url(template) => template.name(),
+            DataProvider::Index(index) => index.name(),
+        }
+    }
+
+    /// Loads the given datasets of their amendments, see [`load_aixm_files`].
+    /// `release` only applies to DFS, other providers have a single format.
+    pub async fn load(
+        &self,
+        amdt: Amendments,
+        datasets: &[String],
+        release: AixmRelease,
+        tx: mpsc::Sender<Message>,
+    ) -> AiracUpdaterResult<Vec<AixmDataset>> {
+        match self {
+            DataProvider::Dfs => load_aixm_files(&Dfs { release }, amdt, datasets, tx).await,
+            DataProvider::Url(template) => load_aixm_files(template, amdt, datasets, tx).await,
+            DataProvider::Index(index) => load_aixm_files(index, amdt, datasets, tx).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn export_is_found_by_dataset_and_effective_date() {
+        let page = r#"<a href="LO_Navaids_2025-02-20.xml">
+<a href="LO_Navaids_2025-03-20.xml"><a href="LO_Navaids_2025-03-20_rev1.xml">
+<a href='/aixm/LO-Waypoints-20250320.zip'><a href="?C=M;O=A">"#;
+        let links = links("https://ais.example.org/aixm/", page);
+        let cycle = AiracCycle::containing(NaiveDate::from_ymd_opt(2025, 3, 20).unwrap());
+        assert_eq!(
+            find_export(&links, "Navaids", cycle).map(String::as_str),
+            Some("https://ais.example.org/aixm/LO_Navaids_2025-03-20_rev1.xml")
+        );
+        assert_eq!(
+            find_export(&links, "Waypoints", cycle).map(String::as_str),
+            Some("https://ais.example.org/aixm/LO-Waypoints-20250320.zip")
+        );
+        assert_eq!(find_export(&links, "Airports", cycle), None);
+    }
+}
@@ -0,0 +1,714 @@
+#[cfg(feature = "gui")]
+use std::collections::{HashMap, HashSet};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "gui")]
+use egui::{CollapsingHeader, Grid, RichText, Ui};
+
+#[cfg(feature = "gui")]
+use crate::locale::DisplayLocale;
+use geo::Point;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt as _;
+use uuid::Uuid;
+
+use crate::{
+    airac::AiracCycle,
+    aixm_combine::EntityKind,
+    error::{
+        AiracUpdaterResult, SerializeChangeReportCsvSnafu, SerializeChangeReportSnafu,
+        WriteChangeReportSnafu,
+    },
+};
+
+/// Version of the machine-readable report, see
+/// `schema/change-report.v1.schema.json`. Only incremented for incompatible
+/// changes, new optional fields may be added within a version.
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Positions closer than this are considered unchanged, to ignore rounding
+/// differences between the sources.
+pub const MOVED_THRESHOLD: f64 = 1.0;
+
+/// Upper bounds in metres of the displacement histogram buckets, the last
+/// bucket taking everything above
+pub const DISPLACEMENT_BUCKETS: [f64; 4] = [10.0, 100.0, 1_000.0, 10_000.0];
+
+/// Median displacement above which the entities of a kind were most likely
+/// shifted as a whole, e.g. by a datum or parsing problem, rather than
+/// corrected one by one
+pub const SYSTEMATIC_SHIFT_THRESHOLD: f64 = 100.0;
+
+/// Fewest moved entities of a kind for the median to say anything
+const SYSTEMATIC_SHIFT_MIN_COUNT: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeAction {
+    Added,
+    /// New, but only written as a commented-out line for review
+    Candidate,
+    Moved {
+        from: Point,
+        distance: f64,
+    },
+    /// Missing in AIXM, removed from the file
+    Removed,
+}
+
+/// Change of a single entity in a sector file, with the AIXM dataset it came
+/// from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: EntityKind,
+    pub designator: String,
+    pub position: Point,
+    pub action: ChangeAction,
+    pub dataset: String,
+}
+
+impl Change {
+    pub fn added(
+        kind: EntityKind,
+        designator: impl Into<String>,
+        position: Point,
+        dataset: &str,
+    ) -> Self {
+        Self {
+            kind,
+            designator: designator.into(),
+            position,
+            action: ChangeAction::Added,
+            dataset: dataset.to_string(),
+        }
+    }
+
+    pub fn removed(kind: EntityKind, designator: impl Into<String>, position: Point) -> Self {
+        Self {
+            kind,
+            designator: designator.into(),
+            position,
+            action: ChangeAction::Removed,
+            dataset: String::new(),
+        }
+    }
+
+    pub fn moved(
+        kind: EntityKind,
+        designator: impl Into<String>,
+        from: Point,
+        to: Point,
+        distance: f64,
+        dataset: &str,
+    ) -> Self {
+        Self {
+            kind,
+            designator: designator.into(),
+            position: to,
+            action: ChangeAction::Moved { from, distance },
+            dataset: dataset.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ChangeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeAction::Added => write!(f, "added"),
+            ChangeAction::Candidate => write!(f, "added as candidate"),
+            ChangeAction::Moved { distance, .. } => write!(f, "moved {distance:.0} m"),
+            ChangeAction::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.kind, self.designator, self.action)?;
+        if let ChangeAction::Moved { from, .. } = self.action {
+            write!(
+                f,
+                " ({:.6} {:.6} -> {:.6} {:.6})",
+                from.y(),
+                from.x(),
+                self.position.y(),
+                self.position.x()
+            )?;
+        }
+        if self.dataset.is_empty() {
+            return Ok(());
+        }
+        write!(f, " [{}]", self.dataset)
+    }
+}
+
+/// File format of the change report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReportFormat {
+    /// Following `schema/change-report.v1.schema.json`
+    #[default]
+    Json,
+    /// One row per change, for reviewing in a spreadsheet
+    Csv,
+}
+
+impl ReportFormat {
+    /// CSV for `.csv` files, JSON otherwise
+    pub fn from_path(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+        {
+            ReportFormat::Csv
+        } else {
+            ReportFormat::Json
+        }
+    }
+
+    /// `<name>.prf.aau_changes.json` or `.csv` next to the .prf
+    pub fn path_for(self, prf: &Path) -> PathBuf {
+        let mut file_name = prf.file_name().unwrap_or_default().to_os_string();
+        file_name.push(match self {
+            ReportFormat::Json => ".aau_changes.json",
+            ReportFormat::Csv => ".aau_changes.csv",
+        });
+        prf.with_file_name(file_name)
+    }
+}
+
+/// Changes of the current run per file, in the order the files were combined.
+#[derive(Debug, Default)]
+pub struct ChangeReport {
+    files: Vec<(PathBuf, Vec<Change>)>,
+    run: Option<Uuid>,
+    cycle: Option<AiracCycle>,
+}
+
+#[derive(Serialize)]
+struct ReportDocument<'a> {
+    schema_version: u32,
+    generator: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    run_id: Option<Uuid>,
+    /// Cycle of the AIXM data used, if their file names tell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    airac: Option<String>,
+    files: Vec<FileDocument<'a>>,
+}
+
+#[derive(Serialize)]
+struct FileDocument<'a> {
+    path: &'a Path,
+    changes: Vec<ChangeDocument<'a>>,
+}
+
+#[derive(Serialize)]
+struct ChangeDocument<'a> {
+    kind: EntityKind,
+    designator: &'a str,
+    action: &'static str,
+    position: Coordinate,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<Coordinate>,
+    /// Metres
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+    dataset: &'a str,
+    /// Added as a commented-out candidate line only
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    candidate: bool,
+}
+
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    file: &'a Path,
+    kind: EntityKind,
+    designator: &'a str,
+    action: &'static str,
+    old_lat: Option<f64>,
+    old_lon: Option<f64>,
+    new_lat: Option<f64>,
+    new_lon: Option<f64>,
+    /// Metres
+    distance: Option<f64>,
+    dataset: &'a str,
+    candidate: bool,
+}
+
+impl<'a> CsvRow<'a> {
+    fn new(file: &'a Path, change: &'a Change) -> Self {
+        let (action, old, new, distance) = match change.action {
+            ChangeAction::Added | ChangeAction::Candidate => {
+                ("added", None, Some(change.position), None)
+            }
+            ChangeAction::Moved { from, distance } => {
+                ("moved", Some(from), Some(change.position), Some(distance))
+            }
+            ChangeAction::Removed => ("removed", Some(change.position), None, None),
+        };
+        Self {
+            file,
+            kind: change.kind,
+            designator: &change.designator,
+            action,
+            old_lat: old.map(|point| point.y()),
+            old_lon: old.map(|point| point.x()),
+            new_lat: new.map(|point| point.y()),
+            new_lon: new.map(|point| point.x()),
+            distance,
+            dataset: &change.dataset,
+            candidate: change.action == ChangeAction::Candidate,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Coordinate {
+    lat: f64,
+    lon: f64,
+}
+
+impl From<Point> for Coordinate {
+    fn from(point: Point) -> Self {
+        Self {
+            lat: point.y(),
+            lon: point.x(),
+        }
+    }
+}
+
+impl<'a> From<&'a Change> for ChangeDocument<'a> {
+    fn from(change: &'a Change) -> Self {
+        let (action, from, distance) = match change.action {
+            ChangeAction::Added | ChangeAction::Candidate => ("added", None, None),
+            ChangeAction::Moved { from, distance } => ("moved", Some(from.into()), Some(distance)),
+            ChangeAction::Removed => ("removed", None, None),
+        };
+        Self {
+            kind: change.kind,
+            designator: &change.designator,
+            action,
+            position: change.position.into(),
+            from,
+            distance,
+            dataset: &change.dataset,
+            candidate: change.action == ChangeAction::Candidate,
+        }
+    }
+}
+
+impl ChangeReport {
+    /// Unique id of the run the changes belong to
+    pub fn set_run(&mut self, run: Uuid) {
+        self.run = Some(run);
+    }
+
+    /// AIRAC cycle of the AIXM data the changes come from
+    pub fn set_cycle(&mut self, cycle: AiracCycle) {
+        self.cycle = Some(cycle);
+    }
+
+    pub fn files(&self) -> &[(PathBuf, Vec<Change>)] {
+        &self.files
+    }
+
+    /// Drops the `rejected` changes of `file`, e.g. after a review.
+    pub fn reject(&mut self, file: &Path, rejected: &[Change]) {
+        if let Some((_, changes)) = self.files.iter_mut().find(|(path, _)| path == file) {
+            changes.retain(|change| !rejected.contains(change));
+        }
+    }
+
+    pub fn add(&mut self, file: &Path, changes: Vec<Change>) {
+        if let Some((_, existing)) = self.files.iter_mut().find(|(path, _)| path == file) {
+            existing.extend(changes);
+        } else {
+            self.files.push((file.to_path_buf(), changes));
+        }
+    }
+
+    /// Machine-readable report, following [`REPORT_SCHEMA_VERSION`].
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&ReportDocument {
+            schema_version: REPORT_SCHEMA_VERSION,
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            run_id: self.run,
+            airac: self.cycle.map(AiracCycle::ident),
+            files: self
+                .files
+                .iter()
+                .map(|(path, changes)| FileDocument {
+                    path,
+                    changes: changes.iter().map(ChangeDocument::from).collect(),
+                })
+                .collect(),
+        })
+    }
+
+    /// One row per change with the old and new coordinates.
+    pub fn to_csv(&self) -> csv::Result<Vec<u8>> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        for (path, changes) in &self.files {
+            for change in changes {
+                writer.serialize(CsvRow::new(path, change))?;
+            }
+        }
+        writer
+            .into_inner()
+            .map_err(|e| csv::Error::from(e.into_error()))
+    }
+
+    pub async fn write(&self, path: &Path, format: ReportFormat) -> AiracUpdaterResult {
+        let content = match format {
+            ReportFormat::Json => self
+                .to_json()
+                .context(SerializeChangeReportSnafu)?
+                .into_bytes(),
+            ReportFormat::Csv => self.to_csv().context(SerializeChangeReportCsvSnafu)?,
+        };
+        tokio::fs::write(path, content)
+            .await
+            .context(WriteChangeReportSnafu { path })
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.run = None;
+        self.cycle = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.iter().all(|(_, changes)| changes.is_empty())
+    }
+
+    /// Entities of a file changed by more than one dataset, i.e. where the
+    /// datasets disagree and the last one won.
+    #[cfg(feature = "gui")]
+    fn conflicts(changes: &[Change]) -> HashSet<(EntityKind, &str)> {
+        let mut datasets = HashMap::<_, HashSet<&str>>::new();
+        for change in changes {
+            datasets
+                .entry((change.kind, change.designator.as_str()))
+                .or_default()
+                .insert(&change.dataset);
+        }
+        datasets
+            .into_iter()
+            .filter(|(_, datasets)| datasets.len() > 1)
+            .map(|(entity, _)| entity)
+            .collect()
+    }
+}
+
+/// Distribution of the distances the entities of one kind were moved by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplacementStats {
+    pub kind: EntityKind,
+    pub count: usize,
+    /// Metres
+    pub median: f64,
+    /// Metres
+    pub p95: f64,
+    /// Metres
+    pub max: f64,
+    /// Moved entities per bucket of [`DISPLACEMENT_BUCKETS`]
+    pub histogram: [usize; DISPLACEMENT_BUCKETS.len() + 1],
+}
+
+impl DisplacementStats {
+    fn new(kind: EntityKind, mut distances: Vec<f64>) -> Option<Self> {
+        distances.sort_by(f64::total_cmp);
+        let max = *distances.last()?;
+        // nearest rank
+        let percentile = |p: f64| {
+            let rank = (p * distances.len() as f64).ceil() as usize;
+            distances[rank.clamp(1, distances.len()) - 1]
+        };
+        let mut histogram = [0; DISPLACEMENT_BUCKETS.len() + 1];
+        for distance in &distances {
+            let bucket = DISPLACEMENT_BUCKETS
+                .iter()
+                .position(|bound| distance < bound)
+                .unwrap_or(DISPLACEMENT_BUCKETS.len());
+            histogram[bucket] += 1;
+        }
+        Some(Self {
+            kind,
+            count: distances.len(),
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            max,
+            histogram,
+        })
+    }
+
+    /// Whether most entities moved far, hinting at a systematic shift
+    pub fn is_systematic(&self) -> bool {
+        self.count >= SYSTEMATIC_SHIFT_MIN_COUNT && self.median > SYSTEMATIC_SHIFT_THRESHOLD
+    }
+}
+
+impl fmt::Display for DisplacementStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} moved, median {:.0} m, p95 {:.0} m, max {:.0} m",
+            self.kind, self.count, self.median, self.p95, self.max
+        )
+    }
+}
+
+impl ChangeReport {
+    /// Displacement statistics of the moved entities of all files, per kind.
+    pub fn displacement_stats(&self) -> Vec<DisplacementStats> {
+        [
+            EntityKind::Airport,
+            EntityKind::Vor,
+            EntityKind::Ndb,
+            EntityKind::Fix,
+        ]
+        .into_iter()
+        .filter_map(|kind| {
+            let distances = self
+                .files
+                .iter()
+                .flat_map(|(_, changes)| changes)
+                .filter(|change| change.kind == kind)
+                .filter_map(|change| match change.action {
+                    ChangeAction::Moved { distance, .. } => Some(distance),
+                    _ => None,
+                })
+                .collect();
+            DisplacementStats::new(kind, distances)
+        })
+        .collect()
+    }
+}
+
+#[cfg(feature = "gui")]
+fn show_displacement_stats(ui: &mut Ui, stats: &[DisplacementStats], locale: &DisplayLocale) {
+    let visuals = ui.style().visuals.clone();
+    let metres = |distance: f64| {
+        if distance < 1_000.0 {
+            format!("{} m", locale.number(distance, 0))
+        } else {
+            format!("{} km", locale.number(distance / 1_000.0, 0))
+        }
+    };
+    CollapsingHeader::new("Displacement statistics")
+        .id_salt("displacement")
+        .show(ui, |ui| {
+            Grid::new("displacement").striped(true).show(ui, |ui| {
+                ui.strong("Type");
+                ui.strong("Moved");
+                ui.strong("Median");
+                ui.strong("p95");
+                ui.strong("Max");
+                for bound in DISPLACEMENT_BUCKETS {
+                    ui.strong(format!("< {}", metres(bound)));
+                }
+                ui.strong(format!(
+                    "≥ {}",
+                    metres(DISPLACEMENT_BUCKETS[DISPLACEMENT_BUCKETS.len() - 1])
+                ));
+                ui.end_row();
+                for stats in stats {
+                    let text = |text: String| {
+                        if stats.is_systematic() {
+                            RichText::new(text).color(visuals.warn_fg_color)
+                        } else {
+                            RichText::new(text)
+                        }
+                    };
+                    ui.label(text(stats.kind.to_string())).on_hover_text(
+                        if stats.is_systematic() {
+                            "Most entities moved far, check the datum and parsing of the sources"
+                        } else {
+                            "Distances the entities of this type were moved by"
+                        },
+                    );
+                    ui.label(text(stats.count.to_string()));
+                    ui.label(text(metres(stats.median)));
+                    ui.label(text(metres(stats.p95)));
+                    ui.label(text(metres(stats.max)));
+                    for count in stats.histogram {
+                        ui.label(text(count.to_string()));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+}
+
+#[cfg(feature = "gui")]
+pub fn show_report(ui: &mut Ui, report: &ChangeReport, locale: &DisplayLocale) {
+    let visuals = ui.style().visuals.clone();
+    let stats = report.displacement_stats();
+    if !stats.is_empty() {
+        show_displacement_stats(ui, &stats, locale);
+    }
+    for (file, changes) in &report.files {
+        if changes.is_empty() {
+            continue;
+        }
+        let conflicts = ChangeReport::conflicts(changes);
+        let title = if conflicts.is_empty() {
+            format!("{} ({} changes)", file.display(), changes.len())
+        } else {
+            format!(
+                "{} ({} changes, {} conflicting)",
+                file.display(),
+                changes.len(),
+                conflicts.len()
+            )
+        };
+        CollapsingHeader::new(title).id_salt(file).show(ui, |ui| {
+            Grid::new(file).striped(true).show(ui, |ui| {
+                ui.strong("Type");
+                ui.strong("Designator");
+                ui.strong("Change");
+                ui.strong("Dataset");
+                ui.end_row();
+                for change in changes {
+                    let conflicting =
+                        conflicts.contains(&(change.kind, change.designator.as_str()));
+                    let text = |text: String| {
+                        if conflicting {
+                            RichText::new(text).color(visuals.warn_fg_color)
+                        } else {
+                            RichText::new(text)
+                        }
+                    };
+                    ui.label(text(change.kind.to_string()));
+                    ui.monospace(text(change.designator.clone()));
+                    ui.label(text(match change.action {
+                        ChangeAction::Moved { distance, .. } => {
+                            format!("moved {} m", locale.number(distance, 0))
+                        }
+                        _ => change.action.to_string(),
+                    }));
+                    ui.label(text(change.dataset.clone()));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    const SCHEMA: &str = include_str!("../schema/change-report.v1.schema.json");
+
+    /// Checks the subset of JSON Schema the report schema uses, failing on
+    /// properties the schema does not declare as well.
+    fn validate(schema: &Value, root: &Value, value: &Value, at: &str) {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/$defs/");
+            return validate(&root["$defs"][name], root, value, at);
+        }
+        if let Some(expected) = schema.get("const") {
+            assert_eq!(value, expected, "{at}");
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            assert!(allowed.contains(value), "{at}: {value} not in {allowed:?}");
+        }
+        match schema["type"].as_str() {
+            Some("object") => assert!(value.is_object(), "{at}"),
+            Some("array") => assert!(value.is_array(), "{at}"),
+            Some("string") => assert!(value.is_string(), "{at}"),
+            Some("number") => assert!(value.is_number(), "{at}"),
+            _ => (),
+        }
+        if let Some(minimum) = schema["minimum"].as_f64() {
+            assert!(value.as_f64().unwrap() >= minimum, "{at}");
+        }
+        if let Some(maximum) = schema["maximum"].as_f64() {
+            assert!(value.as_f64().unwrap() <= maximum, "{at}");
+        }
+        if let Some(pattern) = schema["pattern"].as_str() {
+            assert!(
+                regex::Regex::new(pattern)
+                    .unwrap()
+                    .is_match(value.as_str().unwrap()),
+                "{at}"
+            );
+        }
+        if let Some(items) = schema.get("items") {
+            for (i, item) in value.as_array().unwrap().iter().enumerate() {
+                validate(items, root, item, &format!("{at}[{i}]"));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            let mut required = schema["required"].as_array().cloned().unwrap_or_default();
+            if let (Some(condition), Some(then)) = (schema.get("if"), schema.get("then")) {
+                let matches = condition["properties"]
+                    .as_object()
+                    .unwrap()
+                    .iter()
+                    .all(|(key, property)| object.get(key) == property.get("const"));
+                if matches {
+                    required.extend(then["required"].as_array().cloned().unwrap_or_default());
+                }
+            }
+            for key in &required {
+                let key = key.as_str().unwrap();
+                assert!(object.contains_key(key), "{at}: {key} missing");
+            }
+            for (key, property) in object {
+                let property_schema = &schema["properties"][key];
+                assert!(!property_schema.is_null(), "{at}: {key} not in the schema");
+                validate(property_schema, root, property, &format!("{at}.{key}"));
+            }
+        }
+    }
+
+    #[test]
+    fn json_report_follows_schema() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            REPORT_SCHEMA_VERSION
+        );
+
+        let mut report = ChangeReport::default();
+        report.set_run(Uuid::nil());
+        report.set_cycle(AiracCycle::parse("2504").unwrap());
+        report.add(
+            Path::new("EDMM/EDMM.sct"),
+            vec![
+                Change::added(
+                    EntityKind::Fix,
+                    "KIRDI",
+                    Point::new(11.5, 48.5),
+                    "ED Waypoints",
+                ),
+                Change {
+                    action: ChangeAction::Candidate,
+                    ..Change::added(EntityKind::Ndb, "MNW", Point::new(11.6, 48.1), "ED Navaids")
+                },
+                Change::moved(
+                    EntityKind::Vor,
+                    "MBG",
+                    Point::new(11.1, 48.3),
+                    Point::new(11.1001, 48.3),
+                    7.4,
+                    "ED Navaids",
+                ),
+                Change::removed(EntityKind::Airport, "EDMX", Point::new(11.0, 48.0)),
+            ],
+        );
+        let document: Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+
+        assert_eq!(document["schema_version"], REPORT_SCHEMA_VERSION);
+        assert_eq!(document["airac"], "2504");
+        validate(&schema, &schema, &document, "$");
+    }
+}
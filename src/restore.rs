@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "gui")]
+use airac_aixm_updater_core::locale::DisplayLocale;
+use airac_aixm_updater_core::{
+    Message,
+    error::{AiracUpdaterResult, CopyBackupSnafu},
+    staging::{
+        BACKUP_MARKER, backup_path, next_run_id, parse_backup_file_name, rename, run_suffix,
+    },
+};
+use chrono::NaiveDateTime;
+#[cfg(feature = "gui")]
+use egui::{Button, CollapsingHeader, Grid, Ui};
+use snafu::ResultExt as _;
+use tokio::sync::mpsc;
+use tracing::error;
+use uuid::Uuid;
+
+/// Backup written by a previous run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Backup {
+    pub(crate) path: PathBuf,
+    pub(crate) timestamp: NaiveDateTime,
+    pub(crate) run_id: Option<u32>,
+    /// Unique id of the run, to find its log lines and reports
+    pub(crate) run: Option<Uuid>,
+}
+
+/// Backups of a single file, newest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileBackups {
+    pub(crate) file: PathBuf,
+    pub(crate) backups: Vec<Backup>,
+}
+
+/// Lists the backups in the folder of the .prf and its direct subfolders, the
+/// same places the sector files are written to.
+pub(crate) fn find_backups(prf_path: &Path) -> Vec<FileBackups> {
+    let Some(package_dir) = prf_path.parent() else {
+        return vec![];
+    };
+    let Ok(entries) = fs::read_dir(package_dir) else {
+        return vec![];
+    };
+
+    let mut files = BTreeMap::<PathBuf, Vec<Backup>>::new();
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        let paths = if path.is_dir() {
+            fs::read_dir(&path)
+                .map(|sub_entries| {
+                    sub_entries
+                        .filter_map(Result::ok)
+                        .map(|sub_entry| sub_entry.path())
+                        .collect()
+                })
+                .unwrap_or_default()
+        } else {
+            vec![path]
+        };
+        for (file, backup) in paths.into_iter().filter_map(backup_of) {
+            files.entry(file).or_default().push(backup);
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(file, mut backups)| {
+            backups.sort_by_key(|backup| std::cmp::Reverse((backup.timestamp, backup.run_id)));
+            FileBackups { file, backups }
+        })
+        .collect()
+}
+
+/// File left missing or empty by a run that crashed after moving it aside,
+/// with the newest non-empty backup to recover it from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OrphanedBackup {
+    pub(crate) file: PathBuf,
+    pub(crate) backup: PathBuf,
+    /// The file exists, but is empty
+    pub(crate) empty: bool,
+}
+
+impl OrphanedBackup {
+    pub(crate) fn state(&self) -> &'static str {
+        if self.empty { "empty" } else { "missing" }
+    }
+}
+
+/// Files of `files` that are missing or empty although they have a backup.
+pub(crate) fn find_orphaned(files: &[FileBackups]) -> Vec<OrphanedBackup> {
+    files
+        .iter()
+        .filter_map(|FileBackups { file, backups }| {
+            let empty = match fs::metadata(file) {
+                Ok(metadata) if metadata.len() == 0 => true,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+                _ => return None,
+            };
+            let backup = backups.iter().find(|backup| {
+                fs::metadata(&backup.path).is_ok_and(|metadata| metadata.len() > 0)
+            })?;
+            Some(OrphanedBackup {
+                file: file.clone(),
+                backup: backup.path.clone(),
+                empty,
+            })
+        })
+        .collect()
+}
+
+/// Original file and backup, if `path` is a backup.
+fn backup_of(path: PathBuf) -> Option<(PathBuf, Backup)> {
+    let file_name = path.file_name()?.to_str()?;
+    let (original, _) = file_name.rsplit_once(BACKUP_MARKER)?;
+    let (timestamp, run_id, run) = parse_backup_file_name(file_name)?;
+    Some((
+        path.with_file_name(original),
+        Backup {
+            path,
+            timestamp,
+            run_id,
+            run,
+        },
+    ))
+}
+
+/// Replaces `file` with a copy of `backup`. The current file is moved aside
+/// to a new backup first, so a restore can be undone the same way, and the
+/// copy is made next to `file` beforehand, so `file` is only missing between
+/// two renames.
+pub(crate) async fn restore_backup(
+    file: PathBuf,
+    backup: PathBuf,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let mut restored_name = file.file_name().unwrap_or_default().to_os_string();
+    restored_name.push(".aau_restore");
+    let restored = file.with_file_name(restored_name);
+    tx.send(Message::info(format!(
+        "Copying {} to {}",
+        backup.display(),
+        restored.display()
+    )))
+    .await?;
+    tokio::fs::copy(&backup, &restored)
+        .await
+        .context(CopyBackupSnafu {
+            from: &backup,
+            to: &restored,
+        })?;
+
+    let aside = if file.exists() {
+        let run_id = next_run_id(file.parent()).await;
+        let aside = backup_path(&file, &run_suffix(run_id, Uuid::new_v4()));
+        tx.send(Message::info(format!(
+            "Moving {} to {}",
+            file.display(),
+            aside.display()
+        )))
+        .await?;
+        if let Err(e) = rename(&file, &aside).await {
+            remove_restored(&restored).await;
+            return Err(e);
+        }
+        Some(aside)
+    } else {
+        None
+    };
+
+    if let Err(e) = rename(&restored, &file).await {
+        if let Some(aside) = aside {
+            rename(&aside, &file).await?;
+        }
+        remove_restored(&restored).await;
+        return Err(e);
+    }
+
+    tx.send(Message::info(format!(
+        "Restored {} from {}",
+        file.display(),
+        backup.display()
+    )))
+    .await?;
+    Ok(())
+}
+
+async fn remove_restored(restored: &Path) {
+    if let Err(e) = tokio::fs::remove_file(restored).await {
+        error!("Could not remove {}: {e}", restored.display());
+    }
+}
+
+/// Lists the backups per file, returning the file and backup to restore if
+/// one was clicked.
+#[cfg(feature = "gui")]
+pub(crate) fn show_backups(
+    ui: &mut Ui,
+    files: &[FileBackups],
+    enabled: bool,
+    locale: &DisplayLocale,
+) -> Option<(PathBuf, PathBuf)> {
+    let mut clicked = None;
+    for FileBackups { file, backups } in files {
+        CollapsingHeader::new(format!("{} ({} backups)", file.display(), backups.len()))
+            .id_salt(("backups", file))
+            .show(ui, |ui| {
+                Grid::new(("backup-list", file))
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Written");
+                        ui.strong("Run");
+                        ui.strong("Backup");
+                        ui.end_row();
+                        for backup in backups {
+                            ui.label(locale.naive_date_time(backup.timestamp));
+                            let run_label = ui.label(
+                                backup
+                                    .run_id
+                                    .map(|run_id| run_id.to_string())
+                                    .unwrap_or_default(),
+                            );
+                            if let Some(run) = backup.run {
+                                run_label.on_hover_text(run.to_string());
+                            }
+                            ui.monospace(
+                                backup
+                                    .path
+                                    .file_name()
+                                    .unwrap_or_default()
+                                    .to_string_lossy(),
+                            );
+                            if ui
+                                .add_enabled(enabled, Button::new("Restore"))
+                                .on_hover_text(
+                                    "Replace the current file, keeping it as a new backup",
+                                )
+                                .clicked()
+                            {
+                                clicked = Some((file.clone(), backup.path.clone()));
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+    clicked
+}
@@ -0,0 +1,17 @@
+use std::path::PathBuf;
+
+use tokio::sync::oneshot;
+
+use crate::report::Change;
+
+/// Rejected changes per file.
+pub type Rejected = Vec<(PathBuf, Vec<Change>)>;
+
+/// Changes of a run waiting to be accepted or rejected before anything is
+/// written, see [`Profile::review_changes`](crate::config::Profile::review_changes).
+#[derive(Debug)]
+pub struct ReviewRequest {
+    pub files: Vec<(PathBuf, Vec<Change>)>,
+    /// Answered with the rejected changes, dropping it cancels the run
+    pub reply: oneshot::Sender<Rejected>,
+}
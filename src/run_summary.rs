@@ -0,0 +1,169 @@
+//! What a run did per file and entity kind, sent once all packages are
+//! updated.
+
+use std::{fmt, path::PathBuf};
+
+#[cfg(feature = "gui")]
+use egui::{Grid, Ui};
+use vatsim_parser::sct::Sct;
+
+use crate::{
+    EuroscopeFile,
+    aixm_combine::EntityKind,
+    follow_up::{FollowUp, FollowUpReason},
+    report::{ChangeAction, ChangeReport},
+};
+
+const KINDS: [EntityKind; 4] = [
+    EntityKind::Airport,
+    EntityKind::Vor,
+    EntityKind::Ndb,
+    EntityKind::Fix,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KindCounts {
+    pub added: usize,
+    pub moved: usize,
+    pub removed: usize,
+    /// Left out for a maintainer to decide, e.g. candidates, rejected or
+    /// military entities
+    pub skipped: usize,
+    /// In the written file and not changed by the run
+    pub unchanged: usize,
+}
+
+impl fmt::Display for KindCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} moved, {} removed, {} skipped, {} unchanged",
+            self.added, self.moved, self.removed, self.skipped, self.unchanged
+        )
+    }
+}
+
+/// Counts of a .sct per entity kind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSummary {
+    pub path: PathBuf,
+    pub counts: Vec<(EntityKind, KindCounts)>,
+}
+
+impl FileSummary {
+    fn new(path: PathBuf, content: &Sct, report: &ChangeReport, follow_ups: &[FollowUp]) -> Self {
+        let changes = report
+            .files()
+            .iter()
+            .find(|(file, _)| *file == path)
+            .map(|(_, changes)| changes.as_slice())
+            .unwrap_or_default();
+        let file = path.display().to_string();
+        let counts = KINDS
+            .into_iter()
+            .map(|kind| {
+                let mut counts = KindCounts::default();
+                for change in changes.iter().filter(|change| change.kind == kind) {
+                    match change.action {
+                        ChangeAction::Added => counts.added += 1,
+                        ChangeAction::Moved { .. } => counts.moved += 1,
+                        ChangeAction::Removed => counts.removed += 1,
+                        // listed as follow-up as well
+                        ChangeAction::Candidate => (),
+                    }
+                }
+                let name = kind.to_string();
+                counts.skipped = follow_ups
+                    .iter()
+                    .filter(|follow_up| follow_up.file == file && follow_up.kind == name)
+                    .filter(|follow_up| {
+                        matches!(
+                            follow_up.reason,
+                            FollowUpReason::Candidate
+                                | FollowUpReason::Rejected
+                                | FollowUpReason::Military
+                                | FollowUpReason::ExcludedButReferenced
+                        )
+                    })
+                    .count();
+                let total = match kind {
+                    EntityKind::Airport => content.airports.len(),
+                    EntityKind::Vor => content.vors.len(),
+                    EntityKind::Ndb => content.ndbs.len(),
+                    EntityKind::Fix => content.fixes.len(),
+                };
+                counts.unchanged = total.saturating_sub(counts.added + counts.moved);
+                (kind, counts)
+            })
+            .collect();
+        Self { path, counts }
+    }
+}
+
+impl fmt::Display for FileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.path.display())?;
+        for (kind, counts) in &self.counts {
+            write!(f, " {kind} {counts};")?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary of every .sct of `files` combined by a run.
+pub fn summarize(
+    report: &ChangeReport,
+    files: &[EuroscopeFile],
+    follow_ups: &[FollowUp],
+) -> Vec<FileSummary> {
+    files
+        .iter()
+        .filter_map(|file| match file {
+            EuroscopeFile::Sct { path, content, .. } => {
+                Some(FileSummary::new(path.clone(), content, report, follow_ups))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(feature = "gui")]
+pub fn show_run_summary(ui: &mut Ui, summaries: &[FileSummary]) {
+    Grid::new("run-summary").striped(true).show(ui, |ui| {
+        for header in [
+            "File",
+            "Type",
+            "Added",
+            "Moved",
+            "Removed",
+            "Skipped",
+            "Unchanged",
+        ] {
+            ui.strong(header);
+        }
+        ui.end_row();
+        for summary in summaries {
+            for (kind, counts) in &summary.counts {
+                ui.label(
+                    summary
+                        .path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy(),
+                )
+                .on_hover_text(summary.path.display().to_string());
+                ui.label(kind.to_string());
+                for count in [
+                    counts.added,
+                    counts.moved,
+                    counts.removed,
+                    counts.skipped,
+                    counts.unchanged,
+                ] {
+                    ui.label(count.to_string());
+                }
+                ui.end_row();
+            }
+        }
+    });
+}
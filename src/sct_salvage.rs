@@ -0,0 +1,85 @@
+//! .sct files the parser rejects because of a few malformed lines: the lines
+//! are isolated by parsing ever shorter prefixes of the file, commented out
+//! for the parser and written back as they were.
+
+use std::borrow::Cow;
+
+use vatsim_parser::sct::Sct;
+
+/// Malformed lines isolated at most before the file is given up on
+pub const MAX_SALVAGED_LINES: usize = 50;
+
+/// Prefix commenting out a salvaged line for the parser and the patch
+const MARKER: &str = ";aau-salvaged;";
+
+/// Parses `original` without the lines the parser rejects, returned as
+/// 0-based line indices. `None` if the `[INFO]` section itself is broken or
+/// more than [`MAX_SALVAGED_LINES`] lines would have to be left out.
+pub fn salvage(original: &str) -> Option<(Sct, Vec<usize>)> {
+    let mut lines = original.lines().map(Cow::Borrowed).collect::<Vec<_>>();
+    let parse = |lines: &[Cow<'_, str>]| Sct::parse(lines.join("\n").as_bytes()).ok();
+    // prefixes ending within [INFO] fail for its missing lines, not a
+    // malformed one
+    let header_end = lines
+        .iter()
+        .position(|line| line.trim().eq_ignore_ascii_case("[INFO]"))
+        .and_then(|info| {
+            lines[info + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map(|next| info + 1 + next)
+        })
+        .unwrap_or(lines.len());
+
+    let mut salvaged = vec![];
+    loop {
+        if let Some(sct) = parse(&lines) {
+            return Some((sct, salvaged));
+        }
+        if salvaged.len() == MAX_SALVAGED_LINES
+            || (salvaged.is_empty() && parse(&lines[..header_end]).is_none())
+        {
+            return None;
+        }
+        // the last line of the shortest failing prefix is the malformed one
+        let (mut good, mut bad) = (header_end, lines.len());
+        while bad - good > 1 {
+            let mid = good + (bad - good) / 2;
+            if parse(&lines[..mid]).is_some() {
+                good = mid;
+            } else {
+                bad = mid;
+            }
+        }
+        let malformed = bad - 1;
+        if salvaged.contains(&malformed) {
+            return None;
+        }
+        lines[malformed] = Cow::Owned(format!("{MARKER}{}", lines[malformed]));
+        salvaged.push(malformed);
+    }
+}
+
+/// `original` with the `salvaged` lines commented out, so they are kept as
+/// comments when patched.
+pub fn mark(original: &str, salvaged: &[usize]) -> String {
+    original
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            if salvaged.contains(&i) {
+                Cow::Owned(format!("{MARKER}{line}"))
+            } else {
+                Cow::Borrowed(line)
+            }
+        })
+        .collect()
+}
+
+/// Reverts [`mark`], restoring the salvaged lines as they were.
+pub fn unmark(patched: &str) -> String {
+    patched
+        .split_inclusive('\n')
+        .map(|line| line.strip_prefix(MARKER).unwrap_or(line))
+        .collect()
+}
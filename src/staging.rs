@@ -0,0 +1,631 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use snafu::{ResultExt as _, ensure};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+    sync::{mpsc, oneshot},
+};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    Message,
+    aixm_combine::EuroscopeFile,
+    cancel::CancelToken,
+    config::{SctWriter, WriteMode},
+    error::{
+        AiracUpdaterResult, CancelledSnafu, ChangedSincePlannedSnafu, CreateNewSnafu,
+        CreateStagingDirSnafu, Error, RenameSnafu, ResolveSymlinkSnafu, SymlinkOutsidePackageSnafu,
+        WriteNewSnafu,
+    },
+    pipeline::Stage,
+};
+
+pub const BACKUP_MARKER: &str = ".aau_bkp";
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+/// Suffix of the backups written by run `run_id` with the unique id `run`,
+/// after the backup marker.
+pub fn run_suffix(run_id: u32, run: Uuid) -> String {
+    format!(
+        "{}_r{run_id}_{}",
+        Utc::now().format(BACKUP_TIMESTAMP_FORMAT),
+        run.simple()
+    )
+}
+
+/// Backup next to `target` for the run with `run_suffix`.
+pub fn backup_path(target: &Path, run_suffix: &str) -> PathBuf {
+    let mut bkp_file_name = target.file_name().unwrap_or_default().to_os_string();
+    bkp_file_name.push(format!("{BACKUP_MARKER}{run_suffix}"));
+    target.with_file_name(bkp_file_name)
+}
+
+/// Timestamp, run id and unique run id of a backup file name
+/// (`<name>.aau_bkp<%Y%m%d_%H%M%S>_r<run id>_<uuid>`), backups from older
+/// versions have no (unique) run id.
+pub fn parse_backup_file_name(
+    file_name: &str,
+) -> Option<(NaiveDateTime, Option<u32>, Option<Uuid>)> {
+    let (_, suffix) = file_name.rsplit_once(BACKUP_MARKER)?;
+    let (suffix, run) = match suffix
+        .rsplit_once('_')
+        .and_then(|(rest, run)| Some((rest, Uuid::try_parse(run).ok()?)))
+    {
+        Some((rest, run)) => (rest, Some(run)),
+        None => (suffix, None),
+    };
+    let (timestamp, run_id) = match suffix.rsplit_once("_r") {
+        Some((timestamp, run_id)) => (timestamp, Some(run_id.parse().ok()?)),
+        None => (suffix, None),
+    };
+    Some((
+        NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT).ok()?,
+        run_id,
+        run,
+    ))
+}
+
+/// Run id following the highest one of the backups in `dirs`, so runs stay
+/// ordered even if the clocks of machines sharing the package disagree.
+pub async fn next_run_id(dirs: impl IntoIterator<Item = &Path>) -> u32 {
+    let mut max_run_id = 0;
+    for dir in dirs {
+        let Ok(mut entries) = fs::read_dir(dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Some((_, Some(run_id), _)) =
+                entry.file_name().to_str().and_then(parse_backup_file_name)
+            {
+                max_run_id = max_run_id.max(run_id);
+            }
+        }
+    }
+    max_run_id + 1
+}
+
+/// File a run is about to replace or create.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedWrite {
+    /// Path as referenced by the package
+    pub path: PathBuf,
+    /// Real file written, differs from `path` for symlinks
+    pub target: PathBuf,
+    /// `None` for files that do not exist yet
+    pub backup: Option<PathBuf>,
+    pub write_mode: WriteMode,
+    /// How a .sct is written, `None` for other files
+    pub sct_writer: Option<SctWriter>,
+    /// Serialized file, written as planned
+    content: Arc<[u8]>,
+}
+
+impl fmt::Display for PlannedWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if self.target != self.path {
+            write!(f, " (symlink to {})", self.target.display())?;
+        }
+        match (&self.backup, self.write_mode) {
+            (None, _) => write!(f, ": new file")?,
+            (Some(backup), WriteMode::Backup) => write!(f, ": backup {}", backup.display())?,
+            (Some(_), WriteMode::Overwrite) => write!(f, ": overwritten without backup")?,
+        }
+        match self.sct_writer {
+            Some(SctWriter::Full) => write!(f, ", rewritten in full"),
+            Some(SctWriter::MinimalEdit) => write!(f, ", minimal edit"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Files a run writes with [`write_files`], planned up front so they can be
+/// confirmed before anything is modified, and written exactly as confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WritePlan {
+    pub run_id: u32,
+    pub run: Uuid,
+    pub write_mode: WriteMode,
+    pub files: Vec<PlannedWrite>,
+    /// Files that are not modified, only reported as done
+    unchanged: Vec<PathBuf>,
+    /// Suffix of the backups, see [`run_suffix`]
+    run_suffix: String,
+}
+
+impl WritePlan {
+    /// Plans writing the `files` that are modified, serializing them once.
+    /// Symlinks are resolved without checking their target, [`write_files`]
+    /// refuses the ones outside of the package.
+    pub async fn new(
+        files: &[EuroscopeFile],
+        write_mode: WriteMode,
+        run: Uuid,
+    ) -> AiracUpdaterResult<Self> {
+        let run_id = next_run_id(files.iter().filter_map(|file| file.path().parent())).await;
+        let run_suffix = run_suffix(run_id, run);
+        let mut planned = vec![];
+        let mut unchanged = vec![];
+        for file in files {
+            let path = file.path().to_path_buf();
+            let Some(content) = file.serialize() else {
+                unchanged.push(path);
+                continue;
+            };
+            file.verify_preserved()?;
+            let target = real_path(&path).await.unwrap_or_else(|_| path.clone());
+            planned.push(PlannedWrite {
+                backup: target.exists().then(|| backup_path(&target, &run_suffix)),
+                path,
+                target,
+                write_mode,
+                sct_writer: file.sct_writer(),
+                content: content.into(),
+            });
+        }
+        Ok(Self {
+            run_id,
+            run,
+            write_mode,
+            files: planned,
+            unchanged,
+            run_suffix,
+        })
+    }
+}
+
+/// Files of a run waiting to be confirmed before they are written, see
+/// [`Profile::confirm_writes`](crate::config::Profile::confirm_writes).
+#[derive(Debug)]
+pub struct WriteConfirmation {
+    pub plan: WritePlan,
+    /// Answered with whether to write, dropping it cancels the run
+    pub reply: oneshot::Sender<bool>,
+}
+
+struct StagedFile {
+    /// Path as referenced by the package, for progress reporting
+    path: PathBuf,
+    /// Real file to replace, i.e. the symlink target if `path` is a symlink
+    target: PathBuf,
+    staged: PathBuf,
+    /// `None` for files that did not exist before
+    backup: Option<PathBuf>,
+}
+
+/// Writes all files of a run as planned in two phases: every file is first written to a
+/// staging directory next to its target, and only once all of them succeeded
+/// the originals are moved to their backups and replaced by the staged files.
+///
+/// Symlinked files are written to their real target, so the links stay
+/// intact. Targets outside of `package_dir` are refused unless
+/// `allow_external_symlinks` is set.
+///
+/// If a symlink was retargeted or a target created or removed since the plan
+/// was made, nothing is modified.
+///
+/// If `cancel` is cancelled while staging, nothing is modified, while moving
+/// the files into place, the files already moved are rolled back.
+pub async fn write_files(
+    package_dir: &Path,
+    plan: &WritePlan,
+    allow_external_symlinks: bool,
+    cancel: &CancelToken,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let package_dir = fs::canonicalize(package_dir)
+        .await
+        .context(ResolveSymlinkSnafu { path: package_dir })?;
+    tx.send(Message::info(format!(
+        "Writing files of run {} ({})",
+        plan.run_id, plan.run
+    )))
+    .await?;
+    let mut staging_dirs = HashMap::new();
+
+    let result = match stage_files(
+        plan,
+        &package_dir,
+        allow_external_symlinks,
+        &mut staging_dirs,
+        cancel,
+        &tx,
+    )
+    .await
+    {
+        Ok(staged) => {
+            let result = commit_files(&staged, cancel, &tx).await;
+            if result.is_ok() && plan.write_mode == WriteMode::Overwrite {
+                remove_backups(&staged, &tx).await;
+            }
+            result
+        }
+        Err(e) => {
+            let reason = if matches!(e, Error::Cancelled) {
+                "Cancelled"
+            } else {
+                "Staging failed"
+            };
+            tx.send(Message::error(format!(
+                "{reason}, no files have been modified"
+            )))
+            .await?;
+            Err(e)
+        }
+    };
+
+    for staging_dir in staging_dirs.values() {
+        if let Err(e) = fs::remove_dir_all(staging_dir).await {
+            error!(
+                "Could not remove staging directory {}: {e}",
+                staging_dir.display()
+            );
+        }
+    }
+
+    result
+}
+
+/// File that is actually written for `path`, its target if it is a symlink.
+async fn real_path(path: &Path) -> std::io::Result<PathBuf> {
+    let is_symlink = fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.file_type().is_symlink());
+    if is_symlink {
+        fs::canonicalize(path).await
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+/// Makes sure `planned` is still written where it was planned, and that a
+/// symlinked file is written inside of the package.
+async fn check_target(
+    planned: &PlannedWrite,
+    package_dir: &Path,
+    allow_external_symlinks: bool,
+    tx: &mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let path = &planned.path;
+    let target = real_path(path)
+        .await
+        .context(ResolveSymlinkSnafu { path })?;
+    ensure!(
+        target == planned.target && target.exists() == planned.backup.is_some(),
+        ChangedSincePlannedSnafu { path }
+    );
+    if target == *path {
+        return Ok(());
+    }
+
+    ensure!(
+        allow_external_symlinks || target.starts_with(package_dir),
+        SymlinkOutsidePackageSnafu {
+            path,
+            target: &target
+        }
+    );
+    tx.send(Message::warn(format!(
+        "{} is a symlink, writing to {} instead",
+        path.display(),
+        target.display()
+    )))
+    .await?;
+    Ok(())
+}
+
+async fn stage_files(
+    plan: &WritePlan,
+    package_dir: &Path,
+    allow_external_symlinks: bool,
+    staging_dirs: &mut HashMap<PathBuf, PathBuf>,
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<StagedFile>> {
+    for path in &plan.unchanged {
+        tx.send(Message::stage(path.display(), Stage::Done)).await?;
+    }
+    let mut staged_files = vec![];
+    for planned in &plan.files {
+        cancel.check()?;
+        check_target(planned, package_dir, allow_external_symlinks, tx).await?;
+        let target = &planned.target;
+        let (Some(parent), Some(file_name)) = (target.parent(), target.file_name()) else {
+            continue;
+        };
+
+        let staging_dir = if let Some(staging_dir) = staging_dirs.get(parent) {
+            staging_dir.clone()
+        } else {
+            let staging_dir = parent.join(format!(".aau_staging{}", plan.run_suffix));
+            fs::create_dir(&staging_dir)
+                .await
+                .context(CreateStagingDirSnafu {
+                    path: staging_dir.clone(),
+                })?;
+            staging_dirs.insert(parent.to_path_buf(), staging_dir.clone());
+            staging_dir
+        };
+
+        let staged = staging_dir.join(file_name);
+        tx.send(Message::stage(planned.path.display(), Stage::Writing))
+            .await?;
+        tx.send(Message::info(format!(
+            "Staging new {} in {}",
+            target.display(),
+            staged.display()
+        )))
+        .await?;
+        write_new(&staged, &planned.content).await?;
+
+        staged_files.push(StagedFile {
+            path: planned.path.clone(),
+            target: target.clone(),
+            staged,
+            backup: planned.backup.clone(),
+        });
+    }
+
+    Ok(staged_files)
+}
+
+async fn write_new(path: &Path, content: &[u8]) -> AiracUpdaterResult {
+    OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(path)
+        .await
+        .context(CreateNewSnafu { path })?
+        .write_all(content)
+        .await
+        .context(WriteNewSnafu { path })
+}
+
+async fn commit_files(
+    staged_files: &[StagedFile],
+    cancel: &CancelToken,
+    tx: &mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    for (i, staged_file) in staged_files.iter().enumerate() {
+        if cancel.is_cancelled() {
+            progress(tx, Message::error("Cancelled, rolling back".to_string())).await;
+            rollback(&staged_files[..i], tx).await;
+            return CancelledSnafu.fail();
+        }
+        if let Err(e) = commit_file(staged_file, tx).await {
+            progress(
+                tx,
+                Message::error(format!(
+                    "Could not move {} into place, rolling back",
+                    staged_file.target.display()
+                )),
+            )
+            .await;
+            rollback(&staged_files[..i], tx).await;
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn commit_file(staged_file: &StagedFile, tx: &mpsc::Sender<Message>) -> AiracUpdaterResult {
+    if let Some(backup) = &staged_file.backup {
+        progress(
+            tx,
+            Message::info(format!(
+                "Moving {} to {}",
+                staged_file.target.display(),
+                backup.display()
+            )),
+        )
+        .await;
+        rename(&staged_file.target, backup).await?;
+    }
+
+    progress(
+        tx,
+        Message::info(format!("Writing new {}", staged_file.target.display())),
+    )
+    .await;
+    if let Err(e) = rename(&staged_file.staged, &staged_file.target).await {
+        // the original error is why the write failed, a failed restore is
+        // only reported
+        if let Some(backup) = &staged_file.backup {
+            if let Err(restore_err) = rename(backup, &staged_file.target).await {
+                progress(tx, Message::failure(&restore_err)).await;
+            }
+        }
+        return Err(e);
+    }
+
+    progress(
+        tx,
+        Message::info(format!("Finished writing {}", staged_file.target.display())),
+    )
+    .await;
+    progress(tx, Message::stage(staged_file.path.display(), Stage::Done)).await;
+    Ok(())
+}
+
+async fn rollback(committed: &[StagedFile], tx: &mpsc::Sender<Message>) {
+    for staged_file in committed.iter().rev() {
+        let Some(backup) = &staged_file.backup else {
+            progress(
+                tx,
+                Message::info(format!("Removing new {}", staged_file.target.display())),
+            )
+            .await;
+            if let Err(e) = fs::remove_file(&staged_file.target).await {
+                progress(
+                    tx,
+                    Message::error(format!(
+                        "Could not remove {}: {e}",
+                        staged_file.target.display()
+                    )),
+                )
+                .await;
+            }
+            continue;
+        };
+
+        progress(
+            tx,
+            Message::info(format!(
+                "Restoring {} from {}",
+                staged_file.target.display(),
+                backup.display()
+            )),
+        )
+        .await;
+        if let Err(e) = rename(backup, &staged_file.target).await {
+            progress(tx, Message::failure(&e)).await;
+        }
+    }
+}
+
+async fn remove_backups(staged_files: &[StagedFile], tx: &mpsc::Sender<Message>) {
+    for backup in staged_files.iter().filter_map(|f| f.backup.as_ref()) {
+        progress(
+            tx,
+            Message::debug(format!("Removing backup {}", backup.display())),
+        )
+        .await;
+        if let Err(e) = fs::remove_file(backup).await {
+            progress(
+                tx,
+                Message::error(format!("Could not remove backup {}: {e}", backup.display())),
+            )
+            .await;
+        }
+    }
+}
+
+/// Sends progress while files are moved into place or back, never
+/// interrupting the renames if the receiver is gone.
+async fn progress(tx: &mpsc::Sender<Message>, msg: Message) {
+    if let Err(e) = tx.send(msg).await {
+        error!("{e}");
+    }
+}
+
+pub async fn rename(from: &Path, to: &Path) -> AiracUpdaterResult {
+    fs::rename(from, to).await.context(RenameSnafu { from, to })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory of its own in the system temp directory.
+    fn test_dir() -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("aau_staging_test_{}", Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn backup_file_names_round_trip() {
+        let run = Uuid::new_v4();
+        let backup = backup_path(Path::new("package/EDMM.sct"), &run_suffix(7, run));
+        let file_name = backup.file_name().unwrap().to_str().unwrap();
+
+        let (timestamp, run_id, parsed_run) = parse_backup_file_name(file_name).unwrap();
+        assert!((Utc::now().naive_utc() - timestamp).num_seconds() < 60);
+        assert_eq!((run_id, parsed_run), (Some(7), Some(run)));
+        assert_eq!(backup.parent(), Some(Path::new("package")));
+    }
+
+    #[test]
+    fn backup_file_names_of_older_versions_are_parsed() {
+        let timestamp =
+            NaiveDateTime::parse_from_str("20250101_120000", BACKUP_TIMESTAMP_FORMAT).unwrap();
+        assert_eq!(
+            parse_backup_file_name("EDMM.sct.aau_bkp20250101_120000_r3"),
+            Some((timestamp, Some(3), None))
+        );
+        assert_eq!(
+            parse_backup_file_name("EDMM.sct.aau_bkp20250101_120000"),
+            Some((timestamp, None, None))
+        );
+    }
+
+    #[test]
+    fn other_file_names_are_no_backups() {
+        assert_eq!(parse_backup_file_name("EDMM.sct"), None);
+        assert_eq!(parse_backup_file_name("EDMM.sct.aau_bkp"), None);
+        assert_eq!(
+            parse_backup_file_name("EDMM.sct.aau_bkp20250101_120000_rx"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_commit_rolls_back_committed_files() {
+        let dir = test_dir();
+        let staging_dir = dir.join(".aau_staging");
+        std::fs::create_dir(&staging_dir).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(dir.join(name), format!("old {name}")).unwrap();
+        }
+        std::fs::write(staging_dir.join("a.txt"), "new a.txt").unwrap();
+        let staged_files = ["a.txt", "b.txt"].map(|name| StagedFile {
+            path: dir.join(name),
+            target: dir.join(name),
+            // b.txt was never staged, so moving it into place fails
+            staged: staging_dir.join(name),
+            backup: Some(dir.join(format!("{name}.bkp"))),
+        });
+        let (tx, _rx) = mpsc::channel(100);
+
+        let result = commit_files(&staged_files, &CancelToken::default(), &tx).await;
+
+        assert!(
+            matches!(result, Err(Error::Rename { from, .. }) if from == staging_dir.join("b.txt"))
+        );
+        for name in ["a.txt", "b.txt"] {
+            assert_eq!(
+                std::fs::read_to_string(dir.join(name)).unwrap(),
+                format!("old {name}")
+            );
+            assert!(!dir.join(format!("{name}.bkp")).exists());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_target_is_written_without_backup() {
+        let dir = test_dir();
+        let path = dir.join("new.txt");
+        let files = [EuroscopeFile::Generated {
+            path: path.clone(),
+            content: b"new".to_vec(),
+        }];
+        let plan = WritePlan::new(&files, WriteMode::Backup, Uuid::new_v4())
+            .await
+            .unwrap();
+        assert_eq!(plan.files[0].backup, None);
+        let (tx, _rx) = mpsc::channel(100);
+
+        write_files(&dir, &plan, false, &CancelToken::default(), tx)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        let entries = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(entries, ["new.txt"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use geo::Point;
+use quick_xml::DeError;
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::{sync::mpsc, task::spawn_blocking};
+
+use crate::{
+    Message,
+    aixm::fetch_dataset,
+    aixm_airspaces::{AirspaceMessage, TEMPDELTA, TimePosition},
+    aixm_dfs::{AixmRelease, fetch_dfs_datasets, get_dataset_url},
+    aixm_parse::normalize_prefixes,
+    error::{AiracUpdaterResult, DatasetNotFoundSnafu, DeserializeDatasetSnafu},
+    geometry::to_sct_coordinates,
+    pipeline::Stage,
+};
+
+/// Temporary airspace (e.g. an ED-R activation) published as AIXM TEMPDELTA.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporaryAirspace {
+    pub designator: String,
+    pub name: Option<String>,
+    pub airspace_type: Option<String>,
+    pub valid_from: Option<String>,
+    pub valid_until: Option<String>,
+    pub outlines: Vec<Vec<Point>>,
+}
+
+/// Collects the TEMPDELTA airspace time slices with a horizontal projection,
+/// returning the number of skipped ones with unsupported geometry as well.
+fn parse_temporary_airspaces(data: &[u8]) -> Result<(Vec<TemporaryAirspace>, usize), DeError> {
+    let xml = String::from_utf8_lossy(data);
+    let message = quick_xml::de::from_str::<AirspaceMessage>(&normalize_prefixes(&xml))?;
+    let mut skipped = 0;
+    let airspaces = message
+        .members
+        .into_iter()
+        .filter_map(|member| member.airspace)
+        .flat_map(|airspace| airspace.time_slices)
+        .map(|time_slice| time_slice.airspace)
+        .filter(|time_slice| time_slice.interpretation == TEMPDELTA)
+        .filter_map(|time_slice| {
+            let outlines = time_slice.outlines();
+            if outlines.is_empty() {
+                skipped += 1;
+                return None;
+            }
+
+            let (valid_from, valid_until) = time_slice
+                .valid_time
+                .map(|valid_time| {
+                    let position = |position: Option<TimePosition>| {
+                        position
+                            .map(|position| position.value)
+                            .filter(|value| !value.is_empty())
+                    };
+                    (
+                        position(valid_time.period.begin),
+                        position(valid_time.period.end),
+                    )
+                })
+                .unwrap_or_default();
+            Some(TemporaryAirspace {
+                designator: time_slice
+                    .designator
+                    .or_else(|| time_slice.name.clone())
+                    .unwrap_or_else(|| "UNNAMED".to_string()),
+                name: time_slice.name,
+                airspace_type: time_slice.airspace_type,
+                valid_from,
+                valid_until,
+                outlines,
+            })
+        })
+        .collect();
+
+    Ok((airspaces, skipped))
+}
+
+/// Loads the temporary airspaces of an amendment from `dataset`.
+pub async fn load_temporary_airspaces(
+    amdt: u32,
+    dataset: &str,
+    release: AixmRelease,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<TemporaryAirspace>> {
+    let dataset_metadata = fetch_dfs_datasets().await?;
+    let dataset_url = get_dataset_url(&dataset_metadata, amdt, dataset, release)
+        .context(DatasetNotFoundSnafu { dataset })?;
+    tx.send(Message::stage(dataset, Stage::Queued)).await?;
+    let result = async {
+        let data = fetch_dataset(dataset_url, dataset, tx.clone()).await?;
+        tx.send(Message::stage(dataset, Stage::Parsing)).await?;
+        spawn_blocking(move || parse_temporary_airspaces(&data))
+            .await?
+            .context(DeserializeDatasetSnafu { dataset })
+    }
+    .await;
+    tx.send(Message::stage(
+        dataset,
+        if result.is_ok() {
+            Stage::Done
+        } else {
+            Stage::Failed
+        },
+    ))
+    .await?;
+
+    let (airspaces, skipped) = result?;
+    tx.send(Message::info(format!(
+        "Found {} temporary airspaces in {dataset}",
+        airspaces.len()
+    )))
+    .await?;
+    if skipped > 0 {
+        tx.send(Message::warn(format!(
+            "Skipped {skipped} temporary airspaces in {dataset} without supported geometry"
+        )))
+        .await?;
+    }
+    Ok(airspaces)
+}
+
+/// Overlay next to the .sct, e.g. `EDMM.sct.aau_temporary.sct`.
+pub fn overlay_path(sct_path: &Path) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_temporary.sct");
+    sct_path.with_file_name(file_name)
+}
+
+/// Renders the airspaces as `[ARTCC LOW]` outlines, each prefixed with
+/// `TEMP` and its validity, for loading alongside the base .sct.
+pub fn render_overlay(airspaces: &[TemporaryAirspace]) -> Vec<u8> {
+    let mut content = format!(
+        "; TEMPORARY airspaces (AIXM TEMPDELTA), generated by {} {}\r\n\
+         ; Only valid for the periods given below, do not merge into the base .sct\r\n\
+         \r\n\
+         [ARTCC LOW]\r\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    for airspace in airspaces {
+        content.push_str(&format!(
+            "; {}{}{} valid {} until {}\r\n",
+            airspace.designator,
+            airspace
+                .name
+                .as_ref()
+                .map(|name| format!(" {name}"))
+                .unwrap_or_default(),
+            airspace
+                .airspace_type
+                .as_ref()
+                .map(|airspace_type| format!(" ({airspace_type})"))
+                .unwrap_or_default(),
+            airspace.valid_from.as_deref().unwrap_or("<unknown>"),
+            airspace.valid_until.as_deref().unwrap_or("further notice"),
+        ));
+        for outline in &airspace.outlines {
+            for (from, to) in outline.iter().zip(outline.iter().skip(1)) {
+                let (from_lat, from_lon) = to_sct_coordinates(*from);
+                let (to_lat, to_lon) = to_sct_coordinates(*to);
+                content.push_str(&format!(
+                    "TEMP {} {from_lat} {from_lon} {to_lat} {to_lon}\r\n",
+                    airspace.designator,
+                ));
+            }
+        }
+    }
+
+    content.into_bytes()
+}
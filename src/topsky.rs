@@ -0,0 +1,95 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    aixm_airspaces::AixmAirspace, aixm_combine::EuroscopeFile, geometry::to_sct_coordinates,
+};
+
+/// Map name of airspaces without a type
+const DEFAULT_MAP: &str = "AIRSPACE";
+
+/// Path of the TopSky maps next to the .sct, e.g.
+/// `EDMM.sct.aau_topsky_maps.txt`.
+fn topsky_maps_path(sct_path: &Path) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_topsky_maps.txt");
+    sct_path.with_file_name(file_name)
+}
+
+/// TopSky maps of the outlines of `airspaces` next to the .sct at
+/// `sct_path`, so controllers using the TopSky plugin get the AIXM airspaces
+/// as maps. Rendered by the `topsky` output writer.
+pub fn topsky_maps(sct_path: &Path, airspaces: &[AixmAirspace]) -> EuroscopeFile {
+    EuroscopeFile::TopSkyMaps {
+        path: topsky_maps_path(sct_path),
+        airspaces: airspaces.to_vec(),
+    }
+}
+
+/// One TopSky map per airspace type (e.g. `AIXM CTR`) in the `AIXM` folder,
+/// each outline drawn as a line through its points, to be merged into
+/// `TopSkyMaps.txt`. Colours are left to the map defaults.
+pub fn render_maps(airspaces: &[AixmAirspace]) -> String {
+    let mut by_type = BTreeMap::<&str, Vec<&AixmAirspace>>::new();
+    for airspace in airspaces {
+        by_type
+            .entry(airspace.airspace_type.as_deref().unwrap_or(DEFAULT_MAP))
+            .or_default()
+            .push(airspace);
+    }
+
+    let mut maps = String::from("// AIXM airspaces, regenerated on every run\n");
+    for (airspace_type, airspaces) in by_type {
+        maps.push_str(&format!("\nMAP:AIXM {airspace_type}\nFOLDER:AIXM\n"));
+        for airspace in airspaces {
+            maps.push_str(&format!("// {}\n", airspace.designator));
+            for outline in &airspace.outlines {
+                for point in outline {
+                    let (lat, lon) = to_sct_coordinates(*point);
+                    maps.push_str(&format!("COORD:{lat}:{lon}\n"));
+                }
+                maps.push_str("COORDLINE\n");
+            }
+        }
+    }
+    maps
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Point;
+
+    use super::*;
+
+    #[test]
+    fn airspaces_are_grouped_into_maps_by_type() {
+        let airspace = |designator: &str, airspace_type: Option<&str>| AixmAirspace {
+            designator: designator.to_string(),
+            airspace_type: airspace_type.map(str::to_string),
+            class: None,
+            outlines: vec![vec![
+                Point::new(11.0, 48.0),
+                Point::new(12.0, 48.5),
+                Point::new(11.0, 48.0),
+            ]],
+        };
+        let maps = render_maps(&[
+            airspace("EDDM", Some("CTR")),
+            airspace("EDDN", Some("CTR")),
+            airspace("ED-R1", None),
+        ]);
+        assert_eq!(maps.matches("MAP:AIXM CTR\n").count(), 1);
+        assert_eq!(maps.matches("MAP:AIXM AIRSPACE\n").count(), 1);
+        assert!(maps.contains(
+            "// EDDM\nCOORD:N048.00.00.000:E011.00.00.000\nCOORD:N048.30.00.000:E012.00.00.000\nCOORD:N048.00.00.000:E011.00.00.000\nCOORDLINE\n"
+        ));
+    }
+
+    #[test]
+    fn maps_are_written_next_to_the_sct() {
+        let file = topsky_maps(Path::new("EDMM/EDMM.sct"), &[]);
+        assert_eq!(file.path(), Path::new("EDMM/EDMM.sct.aau_topsky_maps.txt"));
+    }
+}
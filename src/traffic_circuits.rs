@@ -0,0 +1,197 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use geo::Point;
+use quick_xml::DeError;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt as _, ResultExt as _};
+use tokio::{sync::mpsc, task::spawn_blocking};
+
+use crate::{
+    Message,
+    aixm::fetch_dataset,
+    aixm_airspaces::{AirspaceMessage, AirspaceTimeSlice},
+    aixm_dfs::{AixmRelease, fetch_dfs_datasets, get_dataset_url},
+    aixm_parse::normalize_prefixes,
+    error::{AiracUpdaterResult, DatasetNotFoundSnafu, DeserializeDatasetSnafu},
+    geometry::to_sct_coordinates,
+    pipeline::Stage,
+};
+
+/// Colour of the circuits in the overlay, green
+const CIRCUIT_COLOR: u32 = 0x00_ff_00;
+
+static ICAO_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b[A-Z]{4}\b").expect("valid regex"));
+
+/// Aerodrome traffic circuits drawn into a GEO overlay next to the .sct, e.g.
+/// for a VFR package.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TrafficCircuits {
+    pub dataset: String,
+    /// ICAO codes of the aerodromes to draw, all if empty
+    pub aerodromes: Vec<String>,
+}
+
+/// Traffic circuit of an aerodrome. AIXM 5.1 has no feature of its own for
+/// them, they are published as airspaces of type `OTHER:TRAFFIC_CIRCUIT` or
+/// named as such.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrafficCircuit {
+    pub aerodrome: String,
+    pub name: String,
+    pub outlines: Vec<Vec<Point>>,
+}
+
+fn is_traffic_circuit(time_slice: &AirspaceTimeSlice) -> bool {
+    [&time_slice.airspace_type, &time_slice.name]
+        .into_iter()
+        .flatten()
+        .map(|value| value.to_ascii_uppercase().replace('_', " "))
+        .any(|value| {
+            value.contains("CIRCUIT")
+                || value.contains("TRAFFIC PATTERN")
+                || value.contains("PLATZRUNDE")
+        })
+}
+
+/// Aerodrome of a circuit, by the first ICAO code in its designator or name.
+fn aerodrome(time_slice: &AirspaceTimeSlice) -> Option<String> {
+    [&time_slice.designator, &time_slice.name]
+        .into_iter()
+        .flatten()
+        .find_map(|value| ICAO_RE.find(value))
+        .map(|icao| icao.as_str().to_string())
+}
+
+/// Collects the baseline traffic circuits of the `aerodromes` (all if empty),
+/// returning the number of skipped ones without aerodrome or geometry as
+/// well.
+fn parse_traffic_circuits(
+    data: &[u8],
+    aerodromes: &[String],
+) -> Result<(Vec<TrafficCircuit>, usize), DeError> {
+    let xml = String::from_utf8_lossy(data);
+    let message = quick_xml::de::from_str::<AirspaceMessage>(&normalize_prefixes(&xml))?;
+    let mut skipped = 0;
+    let circuits = message
+        .members
+        .into_iter()
+        .filter_map(|member| member.airspace)
+        .flat_map(|airspace| airspace.time_slices)
+        .map(|time_slice| time_slice.airspace)
+        .filter(|time_slice| {
+            time_slice.interpretation == "BASELINE" && is_traffic_circuit(time_slice)
+        })
+        .filter_map(|time_slice| {
+            let outlines = time_slice.outlines();
+            let Some(aerodrome) = aerodrome(&time_slice).filter(|_| !outlines.is_empty()) else {
+                skipped += 1;
+                return None;
+            };
+            if !aerodromes.is_empty()
+                && !aerodromes
+                    .iter()
+                    .any(|selected| selected.eq_ignore_ascii_case(&aerodrome))
+            {
+                return None;
+            }
+            Some(TrafficCircuit {
+                name: time_slice
+                    .name
+                    .or(time_slice.designator)
+                    .unwrap_or_else(|| aerodrome.clone()),
+                aerodrome,
+                outlines,
+            })
+        })
+        .collect();
+
+    Ok((circuits, skipped))
+}
+
+/// Loads the traffic circuits of an amendment from the configured dataset.
+pub async fn load_traffic_circuits(
+    amdt: u32,
+    config: &TrafficCircuits,
+    release: AixmRelease,
+    tx: mpsc::Sender<Message>,
+) -> AiracUpdaterResult<Vec<TrafficCircuit>> {
+    let dataset = config.dataset.as_str();
+    let dataset_metadata = fetch_dfs_datasets().await?;
+    let dataset_url = get_dataset_url(&dataset_metadata, amdt, dataset, release)
+        .context(DatasetNotFoundSnafu { dataset })?;
+    tx.send(Message::stage(dataset, Stage::Queued)).await?;
+    let aerodromes = config.aerodromes.clone();
+    let result = async {
+        let data = fetch_dataset(dataset_url, dataset, tx.clone()).await?;
+        tx.send(Message::stage(dataset, Stage::Parsing)).await?;
+        spawn_blocking(move || parse_traffic_circuits(&data, &aerodromes))
+            .await?
+            .context(DeserializeDatasetSnafu { dataset })
+    }
+    .await;
+    tx.send(Message::stage(
+        dataset,
+        if result.is_ok() {
+            Stage::Done
+        } else {
+            Stage::Failed
+        },
+    ))
+    .await?;
+
+    let (circuits, skipped) = result?;
+    tx.send(Message::info(format!(
+        "Found {} traffic circuits in {dataset}",
+        circuits.len()
+    )))
+    .await?;
+    if skipped > 0 {
+        tx.send(Message::warn(format!(
+            "Skipped {skipped} traffic circuits in {dataset} without aerodrome or supported geometry"
+        )))
+        .await?;
+    }
+    Ok(circuits)
+}
+
+/// Overlay next to the .sct, e.g. `EDMM.sct.aau_circuits.sct`.
+pub fn circuits_path(sct_path: &Path) -> PathBuf {
+    let mut file_name = sct_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_circuits.sct");
+    sct_path.with_file_name(file_name)
+}
+
+/// Renders the circuits as `[GEO]` lines, one group per aerodrome, for
+/// loading alongside the base .sct.
+pub fn render_circuits(circuits: &[TrafficCircuit]) -> Vec<u8> {
+    let mut content = format!(
+        "; Aerodrome traffic circuits, generated by {} {}\r\n\
+         \r\n\
+         #define COLOR_Circuit {CIRCUIT_COLOR}\r\n\
+         \r\n\
+         [GEO]\r\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    );
+    for circuit in circuits {
+        content.push_str(&format!("; {}\r\n", circuit.name));
+        for outline in &circuit.outlines {
+            for (from, to) in outline.iter().zip(outline.iter().skip(1)) {
+                let (from_lat, from_lon) = to_sct_coordinates(*from);
+                let (to_lat, to_lon) = to_sct_coordinates(*to);
+                content.push_str(&format!(
+                    "{} {from_lat} {from_lon} {to_lat} {to_lon} COLOR_Circuit\r\n",
+                    circuit.aerodrome,
+                ));
+            }
+        }
+    }
+
+    content.into_bytes()
+}
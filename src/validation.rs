@@ -0,0 +1,209 @@
+//! Checks of the combined files for results that are more likely a problem of
+//! the sources than a real change, confirmed before anything is written.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::PathBuf,
+};
+
+use geo::Point;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::{
+    EuroscopeFile,
+    aixm_combine::EntityKind,
+    report::{ChangeAction, ChangeReport},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Validation {
+    pub enabled: bool,
+    /// Distance in kilometres above which a moved navaid or fix is suspicious
+    pub max_move_km: f64,
+    /// Area new and moved entities are expected in
+    pub bounds: Bounds,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_move_km: 5.0,
+            bounds: Bounds::default(),
+        }
+    }
+}
+
+/// Bounding box in degrees, by default around the German FIRs (EDWW, EDGG,
+/// EDMM) with some margin.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Bounds {
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min_lat: 47.0,
+            max_lat: 55.5,
+            min_lon: 5.5,
+            max_lon: 15.5,
+        }
+    }
+}
+
+impl Bounds {
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min_lat..=self.max_lat).contains(&point.y())
+            && (self.min_lon..=self.max_lon).contains(&point.x())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnomalyKind {
+    /// Moved further than [`Validation::max_move_km`], distance in metres
+    LargeMove {
+        distance: f64,
+    },
+    /// Designator of a changed entity used by several entities of its kind
+    Duplicate {
+        count: usize,
+    },
+    OutsideBounds,
+}
+
+/// Suspicious result of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub path: PathBuf,
+    pub kind: EntityKind,
+    pub designator: String,
+    pub position: Point,
+    pub anomaly: AnomalyKind,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {} ",
+            self.path.display(),
+            self.kind,
+            self.designator
+        )?;
+        match self.anomaly {
+            AnomalyKind::LargeMove { distance } => {
+                write!(f, "moved {:.1} km", distance / 1000.)
+            }
+            AnomalyKind::Duplicate { count } => write!(f, "exists {count} times"),
+            AnomalyKind::OutsideBounds => write!(
+                f,
+                "outside of the expected area at {:.6} {:.6}",
+                self.position.y(),
+                self.position.x()
+            ),
+        }
+    }
+}
+
+/// Anomalies of a run waiting to be confirmed before anything is written,
+/// see [`Profile::validation`](crate::config::Profile::validation).
+#[derive(Debug)]
+pub struct AnomalyConfirmation {
+    pub anomalies: Vec<Anomaly>,
+    /// Answered with whether to write anyway, dropping it cancels the run
+    pub reply: oneshot::Sender<bool>,
+}
+
+impl Validation {
+    /// Checks the changes of `report` and the combined `files` they were
+    /// made to. Removed entities are not checked.
+    pub fn check(&self, report: &ChangeReport, files: &[EuroscopeFile]) -> Vec<Anomaly> {
+        let mut anomalies = vec![];
+        for (path, changes) in report.files() {
+            let changes = changes
+                .iter()
+                .filter(|change| change.action != ChangeAction::Removed);
+            let mut changed = vec![];
+            for change in changes {
+                let anomaly = |anomaly| Anomaly {
+                    path: path.clone(),
+                    kind: change.kind,
+                    designator: change.designator.clone(),
+                    position: change.position,
+                    anomaly,
+                };
+                if let ChangeAction::Moved { distance, .. } = change.action {
+                    if change.kind != EntityKind::Airport && distance > self.max_move_km * 1000. {
+                        anomalies.push(anomaly(AnomalyKind::LargeMove { distance }));
+                    }
+                }
+                if !self.bounds.contains(change.position) {
+                    anomalies.push(anomaly(AnomalyKind::OutsideBounds));
+                }
+                changed.push(change);
+            }
+
+            let Some(EuroscopeFile::Sct { content, .. }) = files
+                .iter()
+                .find(|file| matches!(file, EuroscopeFile::Sct { .. }) && file.path() == path)
+            else {
+                continue;
+            };
+            let mut counts = HashMap::<(EntityKind, &str), usize>::new();
+            let designators = content
+                .airports
+                .iter()
+                .map(|ad| (EntityKind::Airport, ad.designator.as_str()))
+                .chain(
+                    content
+                        .vors
+                        .iter()
+                        .map(|vor| (EntityKind::Vor, vor.designator.as_str())),
+                )
+                .chain(
+                    content
+                        .ndbs
+                        .iter()
+                        .map(|ndb| (EntityKind::Ndb, ndb.designator.as_str())),
+                )
+                .chain(
+                    content
+                        .fixes
+                        .iter()
+                        .map(|fix| (EntityKind::Fix, fix.designator.as_str())),
+                );
+            for designator in designators {
+                *counts.entry(designator).or_default() += 1;
+            }
+            // reported once per designator, not for each of its changes
+            let mut reported = HashSet::new();
+            for change in changed {
+                if !reported.insert((change.kind, change.designator.as_str())) {
+                    continue;
+                }
+                let count = counts
+                    .get(&(change.kind, change.designator.as_str()))
+                    .copied()
+                    .unwrap_or_default();
+                if count > 1 {
+                    anomalies.push(Anomaly {
+                        path: path.clone(),
+                        kind: change.kind,
+                        designator: change.designator.clone(),
+                        position: change.position,
+                        anomaly: AnomalyKind::Duplicate { count },
+                    });
+                }
+            }
+        }
+        anomalies
+    }
+}
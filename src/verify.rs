@@ -0,0 +1,140 @@
+use std::{
+    fmt,
+    ops::AddAssign,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt as _;
+use tokio::{fs, io::AsyncWriteExt as _, sync::mpsc};
+use uuid::Uuid;
+
+use crate::{
+    Message,
+    error::{AiracUpdaterResult, WriteQualityHistorySnafu},
+};
+
+/// Distance in metres up to which an entity of the .sct counts as in its AIXM
+/// position, unless configured otherwise.
+pub const DEFAULT_TOLERANCE: f64 = 100.0;
+
+/// Airports, navaids and fixes of the .sct files compared against AIXM.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QualityCounts {
+    /// Entities of the .sct also in AIXM
+    pub checked: usize,
+    /// Checked entities at most the tolerance away from their AIXM position
+    pub within_tolerance: usize,
+    /// AIXM entities the .sct is missing
+    pub missing: usize,
+    /// Entities of the .sct missing in AIXM
+    pub stale: usize,
+}
+
+impl QualityCounts {
+    /// Percentage of all entities of the .sct and AIXM that agree, `100` if
+    /// there are none.
+    pub fn score(&self) -> f64 {
+        let total = self.checked + self.missing + self.stale;
+        if total == 0 {
+            return 100.0;
+        }
+        100.0 * self.within_tolerance as f64 / total as f64
+    }
+}
+
+impl AddAssign for QualityCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.checked += other.checked;
+        self.within_tolerance += other.within_tolerance;
+        self.missing += other.missing;
+        self.stale += other.stale;
+    }
+}
+
+impl fmt::Display for QualityCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}% ({} of {} checked entities within tolerance, {} missing, {} stale)",
+            self.score(),
+            self.within_tolerance,
+            self.checked,
+            self.missing,
+            self.stale
+        )
+    }
+}
+
+/// Score of a verification run, one JSON line per run in the history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct QualityScore {
+    pub time: DateTime<Utc>,
+    pub run: Uuid,
+    pub amdt: u32,
+    pub score: f64,
+    #[serde(flatten)]
+    pub counts: QualityCounts,
+}
+
+/// `<name>.prf.aau_quality.jsonl` next to the .prf
+pub fn quality_history_path(prf: &Path) -> PathBuf {
+    let mut file_name = prf.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".aau_quality.jsonl");
+    prf.with_file_name(file_name)
+}
+
+/// Scores of the previous verification runs of the package, oldest first.
+/// Unreadable lines are skipped.
+pub async fn read_quality_history(prf: &Path) -> Vec<QualityScore> {
+    let Ok(history) = fs::read_to_string(quality_history_path(prf)).await else {
+        return vec![];
+    };
+    history
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Appends the score to the history of the package and reports it along with
+/// the difference to the previous run.
+pub async fn record_quality_score(
+    prf: &Path,
+    score: &QualityScore,
+    tx: &mpsc::Sender<Message>,
+) -> AiracUpdaterResult {
+    let previous = read_quality_history(prf).await.pop();
+    let path = quality_history_path(prf);
+    let mut line = serde_json::to_vec(score)
+        .map_err(std::io::Error::from)
+        .context(WriteQualityHistorySnafu { path: &path })?;
+    line.push(b'\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .context(WriteQualityHistorySnafu { path: &path })?;
+    file.write_all(&line)
+        .await
+        .context(WriteQualityHistorySnafu { path: &path })?;
+
+    let trend = previous.map_or_else(String::new, |previous| {
+        format!(
+            ", {:+.1} since {}",
+            score.score - previous.score,
+            previous.time.format("%Y-%m-%d")
+        )
+    });
+    tx.send(Message::info(format!(
+        "Quality score {}{trend}, see {}",
+        score.counts,
+        path.display()
+    )))
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::{fmt, fs, path::Path};
+
+use airac_aixm_updater_core::{airac::AiracCycle, staging::parse_backup_file_name};
+use chrono::NaiveDateTime;
+use egui::{Margin, Stroke, Ui};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Warning {
+    NoNetwork,
+    AmendmentAlreadyApplied,
+    WaypointsDatasetMissing,
+    BackupsDisabled,
+    ClockSkew,
+    NewerSiblingSector,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WarningFix {
+    RetryNetworkCheck,
+    NextAmendment,
+    AddWaypointsDataset,
+    EnableBackups,
+    PickNewerSibling,
+    Dismiss(Warning),
+}
+
+impl Warning {
+    fn fix(self) -> (&'static str, WarningFix) {
+        match self {
+            Warning::NoNetwork => ("Retry", WarningFix::RetryNetworkCheck),
+            Warning::AmendmentAlreadyApplied => ("Use next amendment", WarningFix::NextAmendment),
+            Warning::WaypointsDatasetMissing => {
+                ("Add ED Waypoints", WarningFix::AddWaypointsDataset)
+            }
+            Warning::BackupsDisabled => ("Enable backups", WarningFix::EnableBackups),
+            Warning::ClockSkew => ("Check again", WarningFix::RetryNetworkCheck),
+            Warning::NewerSiblingSector => ("Use newer .prf", WarningFix::PickNewerSibling),
+        }
+    }
+
+    /// Whether the warning may be ignored, as the package may be updated as
+    /// intended regardless.
+    fn dismissable(self) -> bool {
+        matches!(
+            self,
+            Warning::AmendmentAlreadyApplied | Warning::ClockSkew | Warning::NewerSiblingSector
+        )
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::NoNetwork => write!(f, "The DFS dataset server aip.dfs.de is not reachable."),
+            Warning::AmendmentAlreadyApplied => write!(
+                f,
+                "Backups from the selected AIRAC cycle were found, the package seems to be updated already."
+            ),
+            Warning::WaypointsDatasetMissing => write!(
+                f,
+                "The selected profile does not load ED Waypoints, fixes will not be updated."
+            ),
+            Warning::BackupsDisabled => write!(
+                f,
+                "The selected profile overwrites files without keeping a backup."
+            ),
+            Warning::ClockSkew => write!(
+                f,
+                "The system clock differs from the DFS server time, backup timestamps may be misleading."
+            ),
+            Warning::NewerSiblingSector => write!(
+                f,
+                "Another .prf in this folder references a newer sector file, the selected one may be outdated."
+            ),
+        }
+    }
+}
+
+/// Shows a banner per warning, returning the fix the user clicked on, if any.
+pub(crate) fn show_warnings(ui: &mut Ui, warnings: &[Warning]) -> Option<WarningFix> {
+    let mut clicked = None;
+    let warn_color = ui.style().visuals.warn_fg_color;
+    for warning in warnings {
+        egui::Frame::new()
+            .fill(warn_color.gamma_multiply(0.1))
+            .stroke(Stroke::new(1., warn_color))
+            .inner_margin(Margin::same(6))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(warn_color, warning.to_string());
+                    let (label, fix) = warning.fix();
+                    if ui.button(label).clicked() {
+                        clicked = Some(fix);
+                    }
+                    if warning.dismissable() && ui.button("Dismiss").clicked() {
+                        clicked = Some(WarningFix::Dismiss(*warning));
+                    }
+                });
+            });
+        ui.add_space(4.);
+    }
+
+    clicked
+}
+
+/// Checks the folder of the .prf and its direct subfolders for backups written
+/// since `cycle` became effective, e.g. the cycle selected for the update.
+pub(crate) fn has_backups_from_cycle(prf_path: &Path, cycle: AiracCycle) -> bool {
+    let Some(package_dir) = prf_path.parent() else {
+        return false;
+    };
+    let airac_start = cycle.effective().and_hms_opt(0, 0, 0).expect("valid time");
+
+    let Ok(entries) = fs::read_dir(package_dir) else {
+        return false;
+    };
+    entries.filter_map(Result::ok).any(|entry| {
+        if entry.path().is_dir() {
+            fs::read_dir(entry.path()).is_ok_and(|sub_entries| {
+                sub_entries
+                    .filter_map(Result::ok)
+                    .any(|sub_entry| is_backup_since(&sub_entry.path(), airac_start))
+            })
+        } else {
+            is_backup_since(&entry.path(), airac_start)
+        }
+    })
+}
+
+fn is_backup_since(path: &Path, since: NaiveDateTime) -> bool {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .and_then(parse_backup_file_name)
+        .is_some_and(|(timestamp, ..)| timestamp >= since)
+}